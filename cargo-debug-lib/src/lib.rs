@@ -0,0 +1,200 @@
+//! The parts of cargo-debug that other tools can reuse without shelling out:
+//! finding the binary cargo builds for a target, and the command line that
+//! debugs it.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let bin = cargo_debug_lib::discover_binary(Path::new("Cargo.toml"), "foo", "dev")?;
+//! let status = cargo_debug_lib::debugger_command("gdb", &bin, &[]).status()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::borrow::Cow;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::MetadataCommand;
+
+/// The directory under the target directory a profile's output goes into.
+///
+/// The built-in profiles share `debug` and `release`, custom profiles get a
+/// directory of their own.
+pub fn profile_dir(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        profile => profile,
+    }
+}
+
+/// Where cargo puts the binary for the target `name`.
+///
+/// `triple` is the `--target` the build is for, if any.
+pub fn binary_path(
+    target_directory: &Utf8Path,
+    triple: Option<&str>,
+    profile: &str,
+    name: &str,
+    example: bool,
+) -> Utf8PathBuf {
+    let mut path = target_directory.to_path_buf();
+    if let Some(triple) = triple {
+        path = path.join(triple);
+    }
+
+    path = path.join(profile_dir(profile));
+    if example {
+        path = path.join("examples");
+    }
+
+    path.join(format!("{name}{}", env::consts::EXE_SUFFIX))
+}
+
+/// Find the binary cargo built for the bin or example `target_name` of the
+/// workspace at `manifest_path`, with the cargo `profile` (e.g. `dev`).
+pub fn discover_binary(manifest_path: &Path, target_name: &str, profile: &str) -> Result<PathBuf> {
+    let metadata = MetadataCommand::new()
+        .manifest_path(manifest_path)
+        .no_deps()
+        .exec()?;
+
+    let target = metadata
+        .workspace_packages()
+        .into_iter()
+        .flat_map(|p| p.targets.iter())
+        .find(|t| t.name == target_name && t.kind.iter().any(|k| k == "bin" || k == "example"));
+    let Some(target) = target else {
+        bail!("Could not find a bin or example target {target_name}");
+    };
+
+    let example = target.kind.iter().any(|k| k == "example");
+    let triple = env::var("CARGO_BUILD_TARGET").ok();
+    let path = binary_path(
+        &metadata.target_directory,
+        triple.as_deref(),
+        profile,
+        target_name,
+        example,
+    );
+    if !path.is_file() {
+        bail!("{target_name} has not been built, expected it at {path}");
+    }
+
+    Ok(path.into_std_path_buf())
+}
+
+/// Quote an argument for lldb's command interpreter, which splits arguments
+/// itself and expands backticks and `$` in double quotes.
+pub fn quote_lldb(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return Cow::Borrowed(arg);
+    }
+
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '\\' | '`' | '$') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+
+    Cow::Owned(quoted)
+}
+
+/// The command debugging `binary` with `options` in `debugger`.
+///
+/// gdb and lldb are told which arguments belong to the program, and any
+/// other debugger is run as `<debugger> <binary> <options>`. lldb gets them
+/// as a setting on the target, so they're in place whether a later command
+/// launches the process or that's left to the prompt.
+pub fn debugger_command(debugger: &str, binary: &Path, options: &[String]) -> Command {
+    let mut command = Command::new(debugger);
+
+    match Path::new(debugger).file_stem().and_then(|s| s.to_str()) {
+        Some("gdb" | "rust-gdb") => {
+            if !options.is_empty() {
+                command.arg("--args");
+            }
+            command.arg(binary).args(options);
+        }
+        Some("lldb" | "rust-lldb") => {
+            command.arg("--file").arg(binary);
+            if !options.is_empty() {
+                let mut run_args = "settings set target.run-args".to_string();
+                for option in options {
+                    run_args.push(' ');
+                    run_args.push_str(&quote_lldb(option));
+                }
+                command.arg("--one-line").arg(run_args);
+            }
+        }
+        _ => {
+            command.arg(binary).args(options);
+        }
+    }
+
+    command
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn binary_paths() {
+        let target = Utf8Path::new("/foo/target");
+        let exe = env::consts::EXE_SUFFIX;
+
+        assert_eq!(
+            binary_path(target, None, "dev", "foo", false).as_str(),
+            format!("/foo/target/debug/foo{exe}")
+        );
+        assert_eq!(
+            binary_path(
+                target,
+                Some("aarch64-unknown-linux-gnu"),
+                "profiling",
+                "bar",
+                true
+            )
+            .as_str(),
+            format!("/foo/target/aarch64-unknown-linux-gnu/profiling/examples/bar{exe}")
+        );
+    }
+
+    #[test]
+    fn debugger_commands() {
+        let options = ["--bar".to_string(), "a b".to_string()];
+        let args = |c: &Command| {
+            c.get_args()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let gdb = debugger_command("gdb", Path::new("/foo"), &options);
+        assert_eq!(args(&gdb), ["--args", "/foo", "--bar", "a b"]);
+        let gdb = debugger_command("gdb", Path::new("/foo"), &[]);
+        assert_eq!(args(&gdb), ["/foo"]);
+
+        let lldb = debugger_command("/usr/bin/lldb", Path::new("/foo"), &options);
+        assert_eq!(
+            args(&lldb),
+            [
+                "--file",
+                "/foo",
+                "--one-line",
+                "settings set target.run-args --bar \"a b\""
+            ]
+        );
+
+        let rr = debugger_command("rr", Path::new("/foo"), &options);
+        assert_eq!(rr.get_program(), "rr");
+        assert_eq!(args(&rr), ["/foo", "--bar", "a b"]);
+    }
+}
@@ -0,0 +1,133 @@
+//! Building every target at once (`--all-targets`): the binaries and
+//! examples, and the tests and benches as their harnesses build them, so
+//! any of them can be picked to debug from the one build.
+//!
+//! A binary and its unit tests are both `bin` targets of the same name;
+//! the test profile tells them apart.
+
+use std::io::IsTerminal;
+
+use anyhow::{bail, Result};
+use cargo_metadata::Artifact;
+
+use crate::picker;
+
+/// What `artifact` is, e.g. "example foo" or "bin foo (unit tests)".
+pub fn describe(artifact: &Artifact) -> String {
+    let kind = artifact
+        .target
+        .kind
+        .first()
+        .map(String::as_str)
+        .unwrap_or("target");
+    let kind = match kind {
+        "rlib" | "dylib" | "cdylib" | "staticlib" | "proc-macro" => "lib",
+        kind => kind,
+    };
+    let name = &artifact.target.name;
+
+    // Integration tests and benches only ever build as harnesses
+    if artifact.profile.test && !matches!(kind, "test" | "bench") {
+        format!("{kind} {name} (unit tests)")
+    } else {
+        format!("{kind} {name}")
+    }
+}
+
+/// The executables of `artifacts`, with their descriptions, in order.
+fn index(artifacts: Vec<Artifact>) -> Vec<(String, Artifact)> {
+    let mut index = artifacts
+        .into_iter()
+        .filter(|a| a.executable.is_some())
+        .map(|a| (describe(&a), a))
+        .collect::<Vec<_>>();
+    index.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    index
+}
+
+/// The artifact to debug, out of everything an `--all-targets` build
+/// produced, asking which when there's more than one. With `list`, the
+/// executables are printed first, and nothing is picked without a terminal
+/// to ask on.
+pub fn select(artifacts: Vec<Artifact>, list: bool) -> Result<Option<Artifact>> {
+    let mut index = index(artifacts);
+    if list {
+        for (description, artifact) in &index {
+            let exe = artifact
+                .executable
+                .as_ref()
+                .expect("indexed artifacts are executable");
+            println!("{description}\t{exe}");
+        }
+        if !std::io::stdin().is_terminal() {
+            return Ok(None);
+        }
+    }
+
+    let picked = match index.len() {
+        0 => bail!("The build produced no executables"),
+        1 => 0,
+        n => {
+            let choices = index.iter().map(|(d, _)| d.clone()).collect::<Vec<_>>();
+            picker::pick(&format!("the build produced {n} executables"), &choices)?
+        }
+    };
+
+    Ok(Some(index.swap_remove(picked).1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::artifact;
+
+    #[test]
+    fn combined_index() {
+        let mut unit = artifact(
+            "foo",
+            "bin",
+            Some("/foo/target/debug/deps/foo-0123456789abcdef"),
+        );
+        unit.profile.test = true;
+        let mut it = artifact(
+            "it",
+            "test",
+            Some("/foo/target/debug/deps/it-0123456789abcdef"),
+        );
+        it.profile.test = true;
+        let mut lib = artifact(
+            "foo",
+            "lib",
+            Some("/foo/target/debug/deps/foo-fedcba9876543210"),
+        );
+        lib.profile.test = true;
+        let artifacts = vec![
+            artifact("foo", "bin", Some("/foo/target/debug/foo")),
+            artifact("foo", "lib", None),
+            unit,
+            artifact("baz", "example", Some("/foo/target/debug/examples/baz")),
+            it,
+            lib,
+        ];
+
+        let descriptions = index(artifacts.clone())
+            .into_iter()
+            .map(|(d, _)| d)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            descriptions,
+            [
+                "bin foo",
+                "bin foo (unit tests)",
+                "example baz",
+                "lib foo (unit tests)",
+                "test it"
+            ]
+        );
+
+        let only = select(artifacts[..2].to_vec(), false).unwrap().unwrap();
+        assert_eq!(only.executable.unwrap().as_str(), "/foo/target/debug/foo");
+        assert!(select(artifacts[1..2].to_vec(), false).is_err());
+    }
+}
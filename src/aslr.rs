@@ -0,0 +1,214 @@
+//! Running the session without address space layout randomization, so the
+//! program's addresses are the same from one run to the next.
+//!
+//! gdb and lldb already turn it off for the programs they launch, unless
+//! told otherwise, so they're told explicitly. On Linux the debugger starts
+//! with the `ADDR_NO_RANDOMIZE` personality, which every debugger and the
+//! program inherit. Windows decides per image, from the PE header's
+//! `DYNAMIC_BASE` flag, which is cleared for the session, with the header's
+//! checksum to match, and set again however it ends. macOS can only opt out when the process is spawned, which
+//! lldb does.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+
+use crate::Debugger;
+
+/// The image can be relocated at load time.
+const DYNAMIC_BASE: u16 = 0x0040;
+/// ... anywhere in the 64-bit address space.
+const HIGH_ENTROPY_VA: u16 = 0x0020;
+
+/// Where the optional header's `DllCharacteristics` is in a PE image; it's
+/// at the same offset in PE32 and PE32+ headers, 6 bytes after `CheckSum`.
+fn characteristics_offset(image: &[u8]) -> Result<usize> {
+    if !image.starts_with(b"MZ") || image.len() < 0x40 {
+        bail!("not a PE image");
+    }
+    let pe = u32::from_le_bytes(image[0x3c..0x40].try_into().unwrap()) as usize;
+    if image.get(pe..pe + 4) != Some(b"PE\0\0") {
+        bail!("not a PE image");
+    }
+
+    // The signature, then the 20 byte file header
+    let offset = pe + 4 + 20 + 70;
+    if image.len() < offset + 2 {
+        bail!("truncated PE header");
+    }
+
+    Ok(offset)
+}
+
+/// The PE checksum of `image`, whose own checksum is at `field`: its 16-bit
+/// words summed with the carries folded back in, plus its length.
+fn checksum(image: &[u8], field: usize) -> u32 {
+    let mut sum = 0u32;
+    for (i, word) in image.chunks(2).enumerate() {
+        if i * 2 == field || i * 2 == field + 2 {
+            continue;
+        }
+        sum += u16::from_le_bytes([word[0], word.get(1).copied().unwrap_or(0)]) as u32;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    sum + image.len() as u32
+}
+
+/// Clear `DYNAMIC_BASE` in `image`, returning the characteristics and
+/// checksum it had. A checksum is updated to match, unless it's unset, as
+/// linkers leave it by default.
+fn clear_dynamic_base(image: &mut [u8]) -> Result<(u16, [u8; 4])> {
+    let offset = characteristics_offset(image)?;
+    let original = u16::from_le_bytes([image[offset], image[offset + 1]]);
+    let fixed = original & !(DYNAMIC_BASE | HIGH_ENTROPY_VA);
+    image[offset..offset + 2].copy_from_slice(&fixed.to_le_bytes());
+
+    let field = offset - 6;
+    let original_checksum: [u8; 4] = image[field..field + 4].try_into().unwrap();
+    if original_checksum != [0; 4] {
+        let sum = checksum(image, field);
+        image[field..field + 4].copy_from_slice(&sum.to_le_bytes());
+    }
+
+    Ok((original, original_checksum))
+}
+
+/// A Windows executable loaded at its preferred address until [`restore`].
+///
+/// [`restore`]: FixedImage::restore
+pub struct FixedImage {
+    path: PathBuf,
+    original: u16,
+    original_checksum: [u8; 4],
+}
+
+impl FixedImage {
+    /// Clear `DYNAMIC_BASE` in the executable at `path`, if it's set.
+    pub fn new(path: &Path) -> Result<Option<FixedImage>> {
+        let mut image =
+            fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+        let (original, original_checksum) = clear_dynamic_base(&mut image)
+            .with_context(|| format!("Could not disable ASLR for {}", path.display()))?;
+        if original & DYNAMIC_BASE == 0 {
+            return Ok(None);
+        }
+        fs::write(path, image).with_context(|| format!("Could not write {}", path.display()))?;
+        info!("ASLR disabled for {}", path.display());
+
+        Ok(Some(FixedImage {
+            path: path.to_path_buf(),
+            original,
+            original_checksum,
+        }))
+    }
+
+    /// Put the executable's characteristics back.
+    pub fn restore(self) {
+        let restored = fs::read(&self.path)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut image| {
+                let offset = characteristics_offset(&image)?;
+                image[offset..offset + 2].copy_from_slice(&self.original.to_le_bytes());
+                image[offset - 6..offset - 2].copy_from_slice(&self.original_checksum);
+                Ok(fs::write(&self.path, image)?)
+            });
+        if let Err(e) = restored {
+            warn!(
+                "could not re-enable ASLR for {} ({e:#}), rebuild it to do so",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// The startup commands keeping ASLR off for the program `debugger` runs.
+pub fn commands(debugger: &Debugger) -> Vec<String> {
+    match debugger {
+        Debugger::Gdb => vec!["set disable-randomization on".to_string()],
+        Debugger::Lldb => vec!["settings set target.disable-aslr true".to_string()],
+        _ => vec![],
+    }
+}
+
+/// Start the debugger, and so the program, without ASLR.
+#[cfg(target_os = "linux")]
+pub fn disable(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: the hook only calls personality, a plain system call
+    unsafe {
+        cmd.pre_exec(|| {
+            // 0xffffffff reads the personality without changing it
+            let current = libc::personality(0xffff_ffff);
+            if current == -1
+                || libc::personality((current | libc::ADDR_NO_RANDOMIZE) as libc::c_ulong) == -1
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn disable(_cmd: &mut Command) {
+    if cfg!(target_os = "macos") {
+        info!("ASLR can only be disabled by lldb on macOS");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The headers of a PE32+ image, as far as `DllCharacteristics`.
+    fn image(characteristics: u16) -> Vec<u8> {
+        let mut image = vec![0; 0x80 + 4 + 20 + 72];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        image[0x80..0x84].copy_from_slice(b"PE\0\0");
+        image[0x80 + 24..0x80 + 26].copy_from_slice(&0x20bu16.to_le_bytes());
+        let offset = 0x80 + 24 + 70;
+        image[offset..offset + 2].copy_from_slice(&characteristics.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn pe_dynamic_base() {
+        // DYNAMIC_BASE | HIGH_ENTROPY_VA | NX_COMPAT | TERMINAL_SERVER_AWARE
+        let mut pe = image(0x8160);
+        assert_eq!(clear_dynamic_base(&mut pe).unwrap(), (0x8160, [0; 4]));
+        assert_eq!(pe, image(0x8100));
+
+        // A checksum that's set is kept valid
+        let field = 0x80 + 24 + 64;
+        let mut pe = image(0x8160);
+        let sum = checksum(&pe, field);
+        pe[field..field + 4].copy_from_slice(&sum.to_le_bytes());
+        let (_, original_checksum) = clear_dynamic_base(&mut pe).unwrap();
+        assert_eq!(original_checksum, sum.to_le_bytes());
+        let updated = u32::from_le_bytes(pe[field..field + 4].try_into().unwrap());
+        assert_eq!(updated, checksum(&pe, field));
+        assert_ne!(updated, sum);
+
+        assert!(clear_dynamic_base(&mut b"\x7fELF".to_vec()).is_err());
+        let mut truncated = image(0x8160);
+        truncated.truncate(0x80 + 24 + 71);
+        assert!(clear_dynamic_base(&mut truncated).is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("cargo-debug-aslr-{}.exe", std::process::id()));
+        fs::write(&path, image(0x8160)).unwrap();
+        let fixed = FixedImage::new(&path).unwrap().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), image(0x8100));
+        assert!(FixedImage::new(&path).unwrap().is_none());
+        fixed.restore();
+        assert_eq!(fs::read(&path).unwrap(), image(0x8160));
+        fs::remove_file(&path).unwrap();
+    }
+}
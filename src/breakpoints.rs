@@ -0,0 +1,172 @@
+//! Keeping the breakpoints of one session for the next
+//! (`--save-breakpoints`), in `target/cargo-debug/breakpoints`, a file per
+//! target.
+//!
+//! gdb saves them with `save breakpoints` from a `hook-quit`, and sources
+//! them back. lldb can't hook quitting, so a stop hook writes them out with
+//! `breakpoint write` at every stop, and `breakpoint read` restores them.
+//! Both leave a breakpoint whose line has moved away pending rather than
+//! failing to load it.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+
+use crate::{quote, utf8_path, Debugger};
+
+/// Where `target`'s breakpoints are kept in `dir`, for `debugger`.
+fn file(debugger: &Debugger, dir: &Path, target: &str) -> Result<PathBuf> {
+    let extension = match debugger {
+        Debugger::Gdb => "gdb",
+        Debugger::Lldb => "json",
+        other => bail!(
+            "--save-breakpoints is only supported by gdb and lldb, not {}",
+            other.name()
+        ),
+    };
+
+    Ok(dir.join(format!("{target}.{extension}")))
+}
+
+/// A gdb `hook-quit` saving the breakpoints to `file`, or removing it once
+/// there are none, as `save breakpoints` then fails rather than writing an
+/// empty file, and a failing hook would keep gdb from quitting.
+fn gdb_hook(file: &str) -> String {
+    format!(
+        "define hook-quit\n\
+         \x20 python\n\
+         import os\n\
+         try:\n\
+         \x20   gdb.execute(\"save breakpoints \" + {file:?}, to_string=True)\n\
+         except gdb.error:\n\
+         \x20   if os.path.exists({file:?}):\n\
+         \x20       os.remove({file:?})\n\
+         \x20 end\n\
+         end\n"
+    )
+}
+
+/// The commands restoring the breakpoints saved in `file`, if it exists,
+/// and saving them there again.
+fn commands_for(debugger: &Debugger, file: &str, hook_file: &Path) -> Result<Vec<String>> {
+    let saved = Path::new(file).is_file();
+    let mut commands = vec![];
+    match debugger {
+        Debugger::Gdb => {
+            // A definition spans lines, so it needs a file of its own
+            std::fs::write(hook_file, gdb_hook(file))
+                .with_context(|| format!("Could not write {}", hook_file.display()))?;
+            commands.push(format!("source {}", hook_file.display()));
+            if saved {
+                commands.push("set breakpoint pending on".to_string());
+                commands.push(format!("source {file}"));
+            }
+        }
+        Debugger::Lldb => {
+            if saved {
+                commands.push(format!("breakpoint read --file {}", quote::lldb(file)));
+            }
+            let write = format!("breakpoint write --file {}", quote::lldb(file));
+            commands.push(format!(
+                "target stop-hook add --one-liner {}",
+                quote::lldb(&write)
+            ));
+        }
+        _ => unreachable!("file() only names gdb's and lldb's"),
+    }
+
+    Ok(commands)
+}
+
+/// The debugger commands restoring `target`'s breakpoints from `dir` and
+/// saving them there again. Breakpoints saved before the executable was
+/// `built` may be on lines that have since moved, which is warned about.
+pub fn commands(
+    debugger: &Debugger,
+    dir: &Path,
+    target: &str,
+    built: Option<SystemTime>,
+) -> Result<Vec<String>> {
+    let path = file(debugger, dir, target)?;
+    std::fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    let file = utf8_path(&path, "The breakpoints file")?;
+
+    let saved = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+    if let Some(saved) = saved {
+        info!("restoring the breakpoints saved in {file}");
+        if built.is_some_and(|built| built > saved) {
+            warn!(
+                "{target} was rebuilt since its breakpoints were saved, \
+                 those on lines that moved will be pending"
+            );
+        }
+    }
+
+    let hook_file = std::env::temp_dir().join(format!(
+        "cargo-debug-breakpoints-{}.gdb",
+        std::process::id()
+    ));
+    commands_for(debugger, file, &hook_file)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn saved_breakpoints() {
+        assert_eq!(
+            gdb_hook("/foo/target/cargo-debug/breakpoints/foo.gdb"),
+            r#"define hook-quit
+  python
+import os
+try:
+    gdb.execute("save breakpoints " + "/foo/target/cargo-debug/breakpoints/foo.gdb", to_string=True)
+except gdb.error:
+    if os.path.exists("/foo/target/cargo-debug/breakpoints/foo.gdb"):
+        os.remove("/foo/target/cargo-debug/breakpoints/foo.gdb")
+  end
+end
+"#
+        );
+
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-debug-saved-breakpoints-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let hook = dir.join("hook.gdb");
+        let gdb = dir.join("foo.gdb");
+        let gdb = gdb.to_str().unwrap();
+        assert_eq!(
+            commands_for(&Debugger::Gdb, gdb, &hook).unwrap(),
+            [format!("source {}", hook.display())]
+        );
+        std::fs::write(gdb, "break src/main.rs:12\n").unwrap();
+        assert_eq!(
+            commands_for(&Debugger::Gdb, gdb, &hook).unwrap(),
+            [
+                format!("source {}", hook.display()),
+                "set breakpoint pending on".to_string(),
+                format!("source {gdb}"),
+            ]
+        );
+        assert_eq!(std::fs::read_to_string(&hook).unwrap(), gdb_hook(gdb));
+
+        let lldb = dir.join("foo.json");
+        let lldb = lldb.to_str().unwrap();
+        std::fs::write(lldb, "[]").unwrap();
+        assert_eq!(
+            commands_for(&Debugger::Lldb, lldb, &hook).unwrap(),
+            [
+                format!("breakpoint read --file {lldb}"),
+                format!("target stop-hook add --one-liner \"breakpoint write --file {lldb}\""),
+            ]
+        );
+        assert!(file(&Debugger::Windbg, &dir, "foo").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+//! Capturing and restoring the inputs of a debug build, so a teammate can
+//! reproduce exactly the same debuggable binary.
+//!
+//! What is captured:
+//! - the build flags (`--release`, `--manifest-path`, `--bin`, `--example`, `--target`)
+//! - environment variables that change how cargo and rustc build, see [`captured`]
+//! - the `rustc -V` version string, which is checked (not enforced) on restore
+//!
+//! What is not captured: the toolchain itself, `.cargo/config.toml` files,
+//! `Cargo.lock`, and the source tree. Paths are stored as they were given.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::BuildArgs;
+
+/// Variables captured by exact name.
+const VARS: &[&str] = &[
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "RUSTC",
+    "RUSTC_WRAPPER",
+    "RUSTC_WORKSPACE_WRAPPER",
+    "RUSTUP_TOOLCHAIN",
+    "CARGO_INCREMENTAL",
+    "CC",
+    "CXX",
+    "AR",
+    "CFLAGS",
+    "CXXFLAGS",
+];
+
+/// Variables captured by prefix, i.e. cargo's config-via-environment.
+const PREFIXES: &[&str] = &["CARGO_BUILD_", "CARGO_PROFILE_", "CARGO_TARGET_"];
+
+/// Whether an environment variable is part of a captured build environment.
+fn captured(name: &str) -> bool {
+    VARS.contains(&name) || PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+fn rustc_version() -> Option<String> {
+    let rustc = env::var("RUSTC").unwrap_or(String::from("rustc"));
+    let output = Command::new(rustc).arg("-V").output().ok()?;
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct BuildEnv {
+    rustc: Option<String>,
+    release: bool,
+    manifest: Option<String>,
+    bin: Option<String>,
+    example: Option<String>,
+    target: Option<String>,
+    env: BTreeMap<String, String>,
+}
+
+impl BuildEnv {
+    /// Capture the current environment along with the given build flags.
+    pub fn capture(args: &BuildArgs) -> Self {
+        if args.manifests.len() > 1 {
+            warn!("only the first --manifest-path is saved with the build environment");
+        }
+
+        Self {
+            rustc: rustc_version(),
+            release: args.release,
+            manifest: args.manifests.first().cloned(),
+            bin: args.bin.clone(),
+            example: args.example.clone(),
+            target: args.target.clone(),
+            env: env::vars()
+                .filter(|(k, _)| captured(k))
+                .chain(
+                    args.rustc_wrapper
+                        .clone()
+                        .map(|w| ("RUSTC_WRAPPER".to_string(), w)),
+                )
+                .collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Could not write build environment to {}", path.display()))?;
+
+        info!("saved build environment to {}", path.display());
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read build environment {}", path.display()))?;
+
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Replace the build flags and captured environment of this process with
+    /// the saved ones, so cargo (and the debugger) inherit them.
+    pub fn restore(&self, args: &mut BuildArgs) {
+        if let (Some(saved), Some(current)) = (&self.rustc, rustc_version()) {
+            if *saved != current {
+                warn!("build environment was saved with {saved}, but building with {current}");
+            }
+        }
+
+        args.release = self.release;
+        args.manifests = self.manifest.clone().into_iter().collect();
+        args.bin = self.bin.clone();
+        args.example = self.example.clone();
+        args.target = self.target.clone();
+        args.rustc_wrapper = None;
+
+        for (name, _) in env::vars().filter(|(k, _)| captured(k)) {
+            if !self.env.contains_key(&name) {
+                env::remove_var(name);
+            }
+        }
+
+        for (name, value) in &self.env {
+            env::set_var(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn captured_vars() {
+        assert!(captured("RUSTFLAGS"));
+        assert!(captured("CARGO_PROFILE_DEV_DEBUG"));
+        assert!(captured("CARGO_TARGET_DIR"));
+        assert!(!captured("CARGO"));
+        assert!(!captured("HOME"));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let saved = BuildEnv {
+            rustc: Some("rustc 1.75.0".to_string()),
+            release: true,
+            manifest: Some("foo/Cargo.toml".to_string()),
+            bin: Some("foo".to_string()),
+            example: None,
+            target: None,
+            env: BTreeMap::from([("RUSTFLAGS".to_string(), "-C debuginfo=2".to_string())]),
+        };
+
+        let path = env::temp_dir().join("cargo-debug-save-and-load.json");
+        saved.save(&path).unwrap();
+        let loaded = BuildEnv::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved, loaded);
+    }
+}
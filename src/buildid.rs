@@ -0,0 +1,104 @@
+//! The IDs linkers give binaries (GNU build ID, Mach-O UUID or PDB GUID and
+//! age), and picking the artifact with a given one (`--bin-id`), such as
+//! the one a crash report names.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use cargo_metadata::Artifact;
+use object::Object;
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The ID the linker gave the binary, if it has one.
+pub fn of(file: &object::File) -> Option<String> {
+    if let Ok(Some(id)) = file.build_id() {
+        return Some(hex(id));
+    }
+    if let Ok(Some(uuid)) = file.mach_uuid() {
+        return Some(hex(&uuid));
+    }
+    if let Ok(Some(codeview)) = file.pdb_info() {
+        return Some(format!("{}{:x}", hex(&codeview.guid()), codeview.age()));
+    }
+
+    None
+}
+
+/// The ID of the binary at `path`, if it has one.
+fn read(path: &Path) -> Result<Option<String>> {
+    let contents = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+    let file = object::File::parse(contents.as_slice())
+        .with_context(|| format!("Could not parse {}", path.display()))?;
+
+    Ok(of(&file))
+}
+
+/// Whether `id` is the one `wanted` gives, in full or as a prefix, in either
+/// case and with or without the dashes UUIDs are usually written with.
+fn matches(id: &str, wanted: &str) -> bool {
+    let wanted = wanted.replace('-', "").to_ascii_lowercase();
+    !wanted.is_empty() && id.starts_with(&wanted)
+}
+
+/// The one of `binaries` whose build ID is `wanted`.
+pub fn select(binaries: Vec<Artifact>, wanted: &str) -> Result<Artifact> {
+    let mut seen = vec![];
+    let mut found = vec![];
+    for artifact in binaries {
+        let Some(path) = &artifact.executable else {
+            continue;
+        };
+        let id = read(path.as_std_path())?;
+        match id {
+            Some(id) if matches(&id, wanted) => found.push(artifact),
+            Some(id) => seen.push(format!("{} {id}", artifact.target.name)),
+            None => seen.push(format!("{} (no build ID)", artifact.target.name)),
+        }
+    }
+
+    match found.len() {
+        1 => Ok(found.remove(0)),
+        0 if seen.is_empty() => {
+            bail!("The build produced no binaries to match --bin-id {wanted} against")
+        }
+        0 => bail!(
+            "No binary has the build ID {wanted}, the build produced:\n  {}",
+            seen.join("\n  ")
+        ),
+        _ => {
+            let names = found
+                .iter()
+                .map(|a| a.target.name.as_str())
+                .collect::<Vec<_>>();
+            bail!(
+                "More than one binary has a build ID starting with {wanted} ({}), give more of it",
+                names.join(", ")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::artifact;
+
+    #[test]
+    fn match_build_id() {
+        let id = "a1b2c3d4e5f60718293a4b5c6d7e8f90";
+        assert!(matches(id, id));
+        assert!(matches(id, "A1B2C3D4"));
+        assert!(matches(id, "A1B2C3D4-E5F6-0718-293A-4B5C6D7E8F90"));
+        assert!(!matches(id, "b2c3"));
+        assert!(!matches(id, ""));
+
+        let missing = artifact("foo", "bin", Some("/nonexistent/cargo-debug/foo"));
+        assert!(select(vec![missing], id).is_err());
+        let err = select(vec![artifact("foo", "bin", None)], id).unwrap_err();
+        assert!(err.to_string().contains("no binaries"));
+    }
+}
@@ -0,0 +1,257 @@
+//! An on-disk cache of the environment probes that cost a process on every
+//! run: the toolchain's sysroot, Visual Studio's install (from vswhere) and
+//! the `rust-gdb`/`rust-lldb` wrapper a debugger is run through.
+//!
+//! Each probe's result is a path, kept with a stamp of what it depends on
+//! (the probed tools' paths and modification times, and the variables
+//! picking a toolchain). It's probed again when the stamp changes or the
+//! path no longer exists. `--no-cache` bypasses the cache.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::find_executable;
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Probe without the cache, for `--no-cache`.
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// The platform's cache directory for cargo-debug.
+fn dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        Path::new(&env::var_os("HOME")?).join("Library/Caches")
+    } else {
+        match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => Path::new(&env::var_os("HOME")?).join(".cache"),
+        }
+    };
+
+    Some(base.join("cargo-debug"))
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Entry {
+    stamp: String,
+    path: PathBuf,
+}
+
+/// The cached probes, by key.
+struct Cache {
+    file: PathBuf,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Cache {
+    /// The cache in `file`, empty if there's none or it can't be read.
+    fn load(file: PathBuf) -> Self {
+        let entries = fs::read_to_string(&file)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        Cache { file, entries }
+    }
+
+    /// The path probed for `key` when the stamp was `stamp`, if it still
+    /// exists.
+    fn get(&self, key: &str, stamp: &str) -> Option<&Path> {
+        let entry = self.entries.get(key)?;
+        (entry.stamp == stamp && entry.path.exists()).then_some(entry.path.as_path())
+    }
+
+    fn insert(&mut self, key: &str, stamp: &str, path: &Path) {
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                stamp: stamp.to_string(),
+                path: path.to_path_buf(),
+            },
+        );
+    }
+
+    /// Write the cache out, through a temporary file so that concurrent runs
+    /// never read half of it.
+    fn save(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        let temporary = self
+            .file
+            .with_extension(format!("json.{}", std::process::id()));
+        fs::write(&temporary, json)?;
+        fs::rename(&temporary, &self.file)
+    }
+}
+
+/// A file's path and modification time, or a dash for the time when it
+/// doesn't exist.
+fn file_stamp(path: &Path) -> String {
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok());
+    match modified {
+        Some(time) => format!("{}@{}", path.display(), time.as_nanos()),
+        None => format!("{}@-", path.display()),
+    }
+}
+
+/// The stamp of a probe depending on `files` and the variables `vars`.
+pub fn stamp(files: &[&Path], vars: &[&str]) -> String {
+    let files = files.iter().map(|path| file_stamp(path));
+    let vars = vars.iter().map(|name| {
+        let value = env::var_os(name).unwrap_or_default();
+        format!("{name}={}", value.to_string_lossy())
+    });
+
+    files.chain(vars).collect::<Vec<_>>().join(";")
+}
+
+/// The stamp of a probe of the toolchain rustup picks in the current
+/// directory: rustc, the nearest `rust-toolchain` file and rustup's
+/// settings, which hold directory overrides.
+pub fn toolchain_stamp() -> String {
+    let rustc = env::var_os("RUSTC").unwrap_or("rustc".into());
+    let rustc = find_executable(Path::new(&rustc)).unwrap_or(PathBuf::from(rustc));
+    let cwd = env::current_dir().unwrap_or_default();
+    let toolchain_file = cwd
+        .ancestors()
+        .flat_map(|dir| [dir.join("rust-toolchain.toml"), dir.join("rust-toolchain")])
+        .find(|path| path.is_file())
+        .unwrap_or_default();
+    let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    let rustup_home = env::var_os("RUSTUP_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| Path::new(&home).join(".rustup")))
+        .unwrap_or_default();
+
+    stamp(
+        &[&rustc, &toolchain_file, &rustup_home.join("settings.toml")],
+        &["RUSTC", "RUSTUP_TOOLCHAIN"],
+    )
+}
+
+/// The stamp of a probe of Visual Studio's installs: vswhere and the
+/// installer's record of what's installed.
+#[cfg(windows)]
+pub fn visual_studio_stamp() -> String {
+    let program_files = env::var_os("ProgramFiles(x86)").unwrap_or_default();
+    let program_data = env::var_os("ProgramData").unwrap_or_default();
+    let vswhere = Path::new(&program_files).join(r"Microsoft Visual Studio\Installer\vswhere.exe");
+    let instances = Path::new(&program_data).join(r"Microsoft\VisualStudio\Packages\_Instances");
+
+    stamp(&[&vswhere, &instances], &[])
+}
+
+/// `probe` through the cache in `file`.
+fn probe_in(
+    file: PathBuf,
+    key: &str,
+    stamp: &str,
+    probe: impl FnOnce() -> Option<PathBuf>,
+) -> Option<PathBuf> {
+    let mut cache = Cache::load(file);
+    if let Some(path) = cache.get(key, stamp) {
+        debug!("{key}: {} (cached)", path.display());
+        return Some(path.to_path_buf());
+    }
+
+    let path = probe()?;
+    cache.insert(key, stamp, &path);
+    if let Err(e) = cache.save() {
+        debug!("could not write {}: {e}", cache.file.display());
+    }
+
+    Some(path)
+}
+
+/// The path `probe` finds for `key`, from the cache when it was probed with
+/// the same `stamp`. Nothing is cached when it finds nothing.
+pub fn probe(key: &str, stamp: &str, probe: impl FnOnce() -> Option<PathBuf>) -> Option<PathBuf> {
+    match dir() {
+        Some(dir) if !DISABLED.load(Ordering::Relaxed) => {
+            probe_in(dir.join("probes.json"), key, stamp, probe)
+        }
+        _ => probe(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cache_invalidation() {
+        let dir = env::temp_dir().join(format!("cargo-debug-cache-{}", std::process::id()));
+        let file = dir.join("probes.json");
+        let found = dir.join("sysroot");
+        fs::create_dir_all(&found).unwrap();
+
+        let probed = |path: &Path| {
+            let path = path.to_path_buf();
+            move || Some(path)
+        };
+        let unreachable = || -> Option<PathBuf> { panic!("probed again") };
+
+        // Probed once, then cached for the same stamp
+        let path = probe_in(file.clone(), "sysroot", "a", probed(&found));
+        assert_eq!(path.as_deref(), Some(found.as_path()));
+        let path = probe_in(file.clone(), "sysroot", "a", unreachable);
+        assert_eq!(path.as_deref(), Some(found.as_path()));
+
+        // A new stamp probes again
+        let other = dir.join("other");
+        fs::create_dir_all(&other).unwrap();
+        let path = probe_in(file.clone(), "sysroot", "b", probed(&other));
+        assert_eq!(path.as_deref(), Some(other.as_path()));
+
+        // As does the cached path disappearing
+        fs::remove_dir(&other).unwrap();
+        let path = probe_in(file.clone(), "sysroot", "b", probed(&found));
+        assert_eq!(path.as_deref(), Some(found.as_path()));
+
+        // Finding nothing leaves the entry alone, and other keys are kept
+        assert_eq!(probe_in(file.clone(), "devenv", "b", || None), None);
+        let cache = Cache::load(file.clone());
+        assert_eq!(cache.entries.keys().collect::<Vec<_>>(), ["sysroot"]);
+
+        // A corrupt cache is as good as none
+        fs::write(&file, "{").unwrap();
+        assert!(Cache::load(file.clone()).entries.is_empty());
+        let path = probe_in(file.clone(), "sysroot", "b", probed(&found));
+        assert_eq!(path.as_deref(), Some(found.as_path()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn probe_stamp() {
+        let dir = env::temp_dir().join(format!("cargo-debug-stamp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let tool = dir.join("tool");
+
+        let missing = stamp(&[&tool], &[]);
+        assert!(missing.ends_with("tool@-"));
+        fs::write(&tool, "").unwrap();
+        let present = stamp(&[&tool], &[]);
+        assert_ne!(present, missing);
+        assert_eq!(stamp(&[&tool], &[]), present);
+
+        assert!(stamp(&[], &["CARGO_DEBUG_UNSET_VARIABLE"]).ends_with("UNSET_VARIABLE="));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+//! Checking that this process holds the Linux capabilities a debugger needs.
+
+use anyhow::{bail, Context, Result};
+use caps::{Capability, CapsHashSet};
+use log::{info, warn};
+
+use crate::Debugger;
+
+/// Parse the effective capability mask out of `/proc/<pid>/status`.
+fn effective_mask(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix("CapEff:"))
+        .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+}
+
+fn decode(mask: u64) -> CapsHashSet {
+    caps::all()
+        .into_iter()
+        .filter(|c| mask & (1 << c.index()) != 0)
+        .collect()
+}
+
+/// Yama's ptrace restrictions, see `Documentation/admin-guide/LSM/Yama.rst`.
+fn ptrace_scope() -> Option<u32> {
+    std::fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// The capabilities `debugger` needs to launch a program.
+fn required(debugger: &Debugger) -> Result<Vec<Capability>> {
+    match debugger {
+        Debugger::Gdb | Debugger::Gdbserver | Debugger::Lldb | Debugger::Frida => {
+            match ptrace_scope() {
+                // Tracing a child we spawned is allowed without any capability
+                None | Some(0) | Some(1) => Ok(vec![]),
+                Some(2) => Ok(vec![Capability::CAP_SYS_PTRACE]),
+                Some(_) => {
+                    bail!("ptrace is disabled on this system (kernel.yama.ptrace_scope = 3)")
+                }
+            }
+        }
+        Debugger::Devenv | Debugger::Windbg | Debugger::Perf | Debugger::Custom(_) => Ok(vec![]),
+    }
+}
+
+/// Check that the debugger will be able to run, reporting any missing capabilities.
+pub fn check(debugger: &Debugger) -> Result<()> {
+    let status =
+        std::fs::read_to_string("/proc/self/status").context("Could not read /proc/self/status")?;
+    let mask = effective_mask(&status).context("No CapEff field in /proc/self/status")?;
+    let effective = decode(mask);
+
+    let missing = required(debugger)?
+        .into_iter()
+        .filter(|c| !effective.contains(c))
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        info!("all capabilities required by {debugger:?} are available");
+        return Ok(());
+    }
+
+    let names = missing
+        .iter()
+        .map(|c| c.to_string().to_lowercase())
+        .collect::<Vec<_>>();
+
+    for name in &names {
+        warn!("missing capability {}", name.to_uppercase());
+    }
+
+    bail!(
+        "{debugger:?} is missing capabilities, run with `sudo` or grant them to the debugger with \
+         `sudo setcap {}=eip <path to debugger>`",
+        names.join(",")
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_effective_mask() {
+        let status = "Name:\tcat\nCapInh:\t0000000000000000\nCapEff:\t0000000000080000\n";
+        let mask = effective_mask(status).unwrap();
+
+        assert_eq!(mask, 1 << 19);
+        assert!(decode(mask).contains(&Capability::CAP_SYS_PTRACE));
+        assert!(!decode(mask).contains(&Capability::CAP_NET_RAW));
+    }
+}
@@ -0,0 +1,250 @@
+//! Resolving settings from cargo's configuration layers.
+//!
+//! A `[profile]` setting can come from the workspace's manifest, from any
+//! `.cargo/config.toml` between the current directory and the root, from
+//! `$CARGO_HOME/config.toml`, or from a `CARGO_PROFILE_<NAME>_<KEY>`
+//! variable, each overriding the ones before. `cargo config get` reports
+//! the merged configuration with where each value came from, but only on
+//! nightly; elsewhere the files are read in the same order.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::trace;
+
+use crate::profile::manifest_settings;
+
+/// An effective setting and where it was set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Setting {
+    pub value: String,
+    /// A file, or the environment variable
+    pub source: String,
+}
+
+/// The cargo config files applying in `dir`, most specific first, ending
+/// with the one in `cargo_home`.
+pub fn files(dir: &Path, cargo_home: Option<&Path>) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let mut dirs = dir
+        .ancestors()
+        .map(|d| d.join(".cargo"))
+        .collect::<Vec<_>>();
+    dirs.extend(cargo_home.map(Path::to_path_buf));
+
+    for dir in dirs {
+        for name in ["config.toml", "config"] {
+            let file = dir.join(name);
+            if file.is_file() && !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+
+    files
+}
+
+/// The config files applying in the current directory.
+pub fn current_files() -> Vec<PathBuf> {
+    let cwd = env::current_dir().unwrap_or_default();
+    let cargo_home = env::var_os("CARGO_HOME").map(PathBuf::from);
+    files(&cwd, cargo_home.as_deref())
+}
+
+/// The `CARGO_PROFILE_<NAME>_<KEY>` settings of `profile` among `vars`.
+fn env_settings(
+    profile: &str,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> BTreeMap<String, Setting> {
+    let prefix = format!(
+        "CARGO_PROFILE_{}_",
+        profile.to_uppercase().replace('-', "_")
+    );
+    vars.into_iter()
+        .filter_map(|(var, value)| {
+            let key = var.strip_prefix(&prefix)?.to_lowercase().replace('_', "-");
+            Some((key, Setting { value, source: var }))
+        })
+        .collect()
+}
+
+/// `profile`'s settings from the config `files` (most specific first) and
+/// the environment `vars`, as cargo merges them.
+fn from_files(
+    profile: &str,
+    files: &[PathBuf],
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> BTreeMap<String, Setting> {
+    let mut settings = BTreeMap::new();
+    for file in files.iter().rev() {
+        let Ok(config) = fs::read_to_string(file) else {
+            continue;
+        };
+        for (key, value) in manifest_settings(&config, profile) {
+            let source = file.to_string_lossy().into_owned();
+            settings.insert(key, Setting { value, source });
+        }
+    }
+    settings.extend(env_settings(profile, vars));
+
+    settings
+}
+
+/// The settings in `cargo config get --show-origin`'s output, lines like
+/// `profile.dev.debug = "line-tables-only" # /foo/.cargo/config.toml`.
+fn parse_show_origin(profile: &str, output: &str) -> BTreeMap<String, Setting> {
+    let prefix = format!("profile.{profile}.");
+    output
+        .lines()
+        .filter_map(|line| {
+            let (setting, source) = line.split_once(" # ")?;
+            let (key, value) = setting.split_once('=')?;
+            let key = key.trim().strip_prefix(&prefix)?;
+            let value = value.trim().trim_matches('"');
+            // e.g. "environment variable `CARGO_PROFILE_DEV_DEBUG`"
+            let source = source.trim().trim_start_matches("environment variable ");
+            Some((
+                key.to_string(),
+                Setting {
+                    value: value.to_string(),
+                    source: source.trim_matches('`').to_string(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// `profile`'s settings as `cargo config get` reports them, where it can.
+fn from_cargo(profile: &str) -> Option<BTreeMap<String, Setting>> {
+    let cargo = env::var("CARGO").unwrap_or(String::from("cargo"));
+    let output = Command::new(cargo)
+        .args(["-Z", "unstable-options", "config", "get", "--show-origin"])
+        .arg(format!("profile.{profile}"))
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+
+    Some(parse_show_origin(
+        profile,
+        &String::from_utf8_lossy(&output.stdout),
+    ))
+}
+
+/// `profile`'s settings in the manifest at `path`.
+fn manifest_layer(profile: &str, path: &Path, manifest: &str) -> BTreeMap<String, Setting> {
+    manifest_settings(manifest, profile)
+        .into_iter()
+        .map(|(key, value)| {
+            let source = path.to_string_lossy().into_owned();
+            (key, Setting { value, source })
+        })
+        .collect()
+}
+
+/// The effective settings of `profile`: the `manifest`'s, overridden by the
+/// config files' and the environment's.
+pub fn profile(profile: &str, manifest: Option<&Path>) -> BTreeMap<String, Setting> {
+    let mut settings = manifest
+        .and_then(|path| Some((path, fs::read_to_string(path).ok()?)))
+        .map(|(path, contents)| manifest_layer(profile, path, &contents))
+        .unwrap_or_default();
+
+    let configured = from_cargo(profile).unwrap_or_else(|| {
+        trace!("`cargo config get` is unavailable, reading the config files");
+        from_files(profile, &current_files(), env::vars())
+    });
+    settings.extend(configured);
+
+    settings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/cargo-config")
+    }
+
+    #[test]
+    fn config_layers() {
+        let root = fixture();
+        let workspace = root.join("workspace");
+        let home = root.join("home");
+
+        // Anything above the fixture isn't ours to assert on
+        let found = files(&workspace.join("src"), Some(&home))
+            .into_iter()
+            .filter(|f| f.starts_with(&root))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                workspace.join(".cargo/config.toml"),
+                root.join(".cargo/config"),
+                home.join("config.toml"),
+            ]
+        );
+
+        let vars = [
+            ("CARGO_PROFILE_DEV_OPT_LEVEL".to_string(), "1".to_string()),
+            ("CARGO_PROFILE_RELEASE_LTO".to_string(), "fat".to_string()),
+        ];
+        let mut settings = manifest_layer(
+            "dev",
+            &workspace.join("Cargo.toml"),
+            &fs::read_to_string(workspace.join("Cargo.toml")).unwrap(),
+        );
+        settings.extend(from_files("dev", &found, vars));
+
+        let setting = |key: &str| {
+            settings
+                .get(key)
+                .map(|s| (s.value.as_str(), s.source.clone()))
+        };
+        let source = |path: PathBuf| path.to_string_lossy().into_owned();
+        assert_eq!(
+            setting("debug"),
+            Some((
+                "line-tables-only",
+                source(workspace.join(".cargo/config.toml"))
+            ))
+        );
+        assert_eq!(
+            setting("lto"),
+            Some(("thin", source(root.join(".cargo/config"))))
+        );
+        assert_eq!(
+            setting("panic"),
+            Some(("abort", source(home.join("config.toml"))))
+        );
+        assert_eq!(
+            setting("strip"),
+            Some(("true", source(workspace.join("Cargo.toml"))))
+        );
+        assert_eq!(
+            setting("opt-level"),
+            Some(("1", "CARGO_PROFILE_DEV_OPT_LEVEL".to_string()))
+        );
+    }
+
+    #[test]
+    fn cargo_config_origins() {
+        let output = "profile.dev.debug = \"line-tables-only\" # /foo/.cargo/config.toml\n\
+                      profile.dev.opt-level = 1 # environment variable `CARGO_PROFILE_DEV_OPT_LEVEL`\n\
+                      profile.dev.package.foo.opt-level = 3 # /foo/.cargo/config.toml\n";
+        let settings = parse_show_origin("dev", output);
+        assert_eq!(
+            settings["debug"],
+            Setting {
+                value: "line-tables-only".to_string(),
+                source: "/foo/.cargo/config.toml".to_string()
+            }
+        );
+        assert_eq!(settings["opt-level"].source, "CARGO_PROFILE_DEV_OPT_LEVEL");
+        assert_eq!(settings.len(), 3);
+    }
+}
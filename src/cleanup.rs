@@ -0,0 +1,73 @@
+//! Undoing what a session changes outside of itself, such as the system's
+//! core pattern, however the session ends: when its [`Guard`] is dropped,
+//! on the way out of an error, or in [`exit`], which Ctrl+C's handler and
+//! the sessions ending with the program's status go through, as exiting
+//! skips destructors.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+type Undo = Box<dyn FnOnce() + Send>;
+
+/// The changes not undone yet, by their guard's id.
+static PENDING: Mutex<Vec<(usize, Undo)>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Undoes a change when dropped, unless [`exit`] did first.
+pub struct Guard {
+    id: usize,
+}
+
+/// Run `undo` once the returned guard is dropped or the process exits
+/// through [`exit`].
+pub fn defer(undo: impl FnOnce() + Send + 'static) -> Guard {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((id, Box::new(undo)));
+
+    Guard { id }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let undo = {
+            let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+            let index = pending.iter().position(|(id, _)| *id == self.id);
+            index.map(|index| pending.remove(index).1)
+        };
+        if let Some(undo) = undo {
+            undo();
+        }
+    }
+}
+
+/// Undo every pending change, the latest first, and exit with `code`.
+pub fn exit(code: i32) -> ! {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    for (_, undo) in pending.into_iter().rev() {
+        undo();
+    }
+
+    std::process::exit(code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn undone_once() {
+        let undone = Arc::new(AtomicUsize::new(0));
+        let counter = undone.clone();
+        let guard = defer(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(undone.load(Ordering::SeqCst), 0);
+        drop(guard);
+        assert_eq!(undone.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,127 @@
+//! Signing macOS executables so lldb may debug them (`--codesign`).
+//!
+//! macOS only lets a debugger take control of a process whose signature
+//! grants it the `com.apple.security.get-task-allow` entitlement, short of
+//! the debugger being authorized for every process, which prompts for a
+//! password, and fails outright on hardened binaries. The linker's ad-hoc
+//! signature has no entitlements, so the executable is signed again, ad
+//! hoc, with that one.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use log::{info, trace};
+
+use crate::find_executable;
+
+const ENTITLEMENT: &str = "com.apple.security.get-task-allow";
+
+/// The entitlements plist granting [`ENTITLEMENT`].
+fn entitlements_plist() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>{ENTITLEMENT}</key>
+    <true/>
+</dict>
+</plist>
+"#
+    )
+}
+
+/// Whether the entitlements `codesign -d --entitlements - --xml` printed
+/// grant [`ENTITLEMENT`].
+fn allows_debugging(entitlements: &str) -> bool {
+    let key = format!("<key>{ENTITLEMENT}</key>");
+    entitlements
+        .split_once(&key)
+        .is_some_and(|(_, rest)| rest.trim_start().starts_with("<true/>"))
+}
+
+/// Whether `exe` is where System Integrity Protection keeps it from being
+/// modified, let alone signed again.
+fn protected(exe: &Path) -> bool {
+    [
+        "/System",
+        "/usr/bin",
+        "/usr/sbin",
+        "/usr/lib",
+        "/bin",
+        "/sbin",
+    ]
+    .iter()
+    .any(|dir| exe.starts_with(dir))
+}
+
+/// Sign `exe` ad hoc with the entitlement letting lldb debug it, unless its
+/// signature already has it.
+pub fn ensure(exe: &Path) -> Result<()> {
+    let Some(codesign) = find_executable(Path::new("codesign")) else {
+        bail!(
+            "codesign was not found; install the Xcode command line tools with \
+             `xcode-select --install`, or leave out --codesign"
+        );
+    };
+
+    let shown = Command::new(&codesign)
+        .args(["-d", "--entitlements", "-", "--xml"])
+        .arg(exe)
+        .output()
+        .with_context(|| format!("Could not run {}", codesign.display()))?;
+    if shown.status.success() && allows_debugging(&String::from_utf8_lossy(&shown.stdout)) {
+        trace!("{} already allows debugging", exe.display());
+        return Ok(());
+    }
+    if protected(exe) {
+        bail!(
+            "{} is protected by System Integrity Protection and can't be signed again; \
+             copy it elsewhere to debug it",
+            exe.display()
+        );
+    }
+
+    let plist = std::env::temp_dir().join(format!(
+        "cargo-debug-entitlements-{}.plist",
+        std::process::id()
+    ));
+    std::fs::write(&plist, entitlements_plist())
+        .with_context(|| format!("Could not write {}", plist.display()))?;
+    let signed = Command::new(&codesign)
+        .args(["--force", "--sign", "-", "--entitlements"])
+        .arg(&plist)
+        .arg(exe)
+        .output();
+    let _ = std::fs::remove_file(&plist);
+    let signed = signed.with_context(|| format!("Could not run {}", codesign.display()))?;
+    if !signed.status.success() {
+        bail!(
+            "codesign failed to sign {}: {}\n\
+             if lldb still can't debug it, enable developer mode with \
+             `sudo DevToolsSecurity -enable`",
+            exe.display(),
+            String::from_utf8_lossy(&signed.stderr).trim()
+        );
+    }
+    info!("signed {} with {ENTITLEMENT}", exe.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn get_task_allow() {
+        assert!(allows_debugging(&entitlements_plist()));
+        let denied = entitlements_plist().replace("<true/>", "<false/>");
+        assert!(!allows_debugging(&denied));
+        assert!(!allows_debugging(""));
+
+        assert!(protected(Path::new("/usr/bin/true")));
+        assert!(!protected(Path::new("/Users/me/foo/target/debug/foo")));
+    }
+}
@@ -0,0 +1,291 @@
+//! Passing gdb's startup commands in a command file.
+//!
+//! Each `-ex` is another argument on the debugger's command line, which
+//! grows with every flag adding a startup command. Launched in this
+//! terminal, gdb reads the settings leading them from one temporary file
+//! instead, sourced in their place, and the file is removed once the session
+//! ends.
+//!
+//! gdb gives up on the rest of a command file at its first failing command,
+//! where each `-ex` runs whatever happened to the ones before. So the file
+//! only holds settings that take any value, and everything from the first
+//! other command on (a breakpoint on a missing symbol, `run`, ...) stays an
+//! `-ex` of its own, as does `--command-file`'s script.
+//!
+//! gdb runs whatever the file says, `shell` included, so it's created anew,
+//! readable by this user only, in a directory of theirs that no one else can
+//! write to, rather than at a name in the shared temporary directory that
+//! someone else could create first.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use log::{trace, warn};
+
+use crate::{utf8_path, DebugSetup};
+
+/// The gdb settings that take any value, so setting one can't fail.
+const SETTINGS: [&str; 15] = [
+    "args",
+    "auto-load",
+    "breakpoint",
+    "confirm",
+    "cwd",
+    "debug-file-directory",
+    "detach-on-fork",
+    "disable-randomization",
+    "environment",
+    "follow-exec-mode",
+    "follow-fork-mode",
+    "pagination",
+    "solib-search-path",
+    "substitute-path",
+    "sysroot",
+];
+
+/// Whether gdb can run `command` without it failing.
+fn cannot_fail(command: &str) -> bool {
+    let mut words = command.split_whitespace();
+    match words.next() {
+        // A missing directory is only warned about
+        Some("directory") => true,
+        Some("set") => words.next().is_some_and(|name| SETTINGS.contains(&name)),
+        _ => false,
+    }
+}
+
+/// The contents of a command file running `commands` in order.
+fn render(commands: &[String]) -> String {
+    let mut script = String::from("# generated by cargo-debug\n");
+    for command in commands {
+        script.push_str(command);
+        script.push('\n');
+    }
+
+    script
+}
+
+/// A command file, removed when dropped.
+pub struct CommandFile {
+    path: PathBuf,
+}
+
+impl CommandFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CommandFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("could not remove {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// This user's private directory for command files in `temp`, created if
+/// it's missing. One that isn't theirs alone is refused.
+fn private_dir(temp: &Path) -> Result<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+        // SAFETY: geteuid has no preconditions
+        let uid = unsafe { libc::geteuid() };
+        let dir = temp.join(format!("cargo-debug-{uid}"));
+        match fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e).with_context(|| format!("Could not create {}", dir.display())),
+        }
+        // Not followed if it's a symlink, which is refused as not a directory
+        let metadata = fs::symlink_metadata(&dir)
+            .with_context(|| format!("Could not read {}", dir.display()))?;
+        if !metadata.is_dir() || metadata.uid() != uid || metadata.permissions().mode() & 0o077 != 0
+        {
+            bail!(
+                "{} is not a directory only this user can access, not writing gdb's startup commands there",
+                dir.display()
+            );
+        }
+
+        Ok(dir)
+    }
+    #[cfg(not(unix))]
+    {
+        // Windows' temporary directory is the user's own already
+        let dir = temp.join("cargo-debug");
+        fs::create_dir_all(&dir).with_context(|| format!("Could not create {}", dir.display()))?;
+        Ok(dir)
+    }
+}
+
+/// Create `path`, which mustn't exist, readable and writable by this user
+/// only, with `contents`.
+fn create_private(path: &Path, contents: &str) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Could not create {}", path.display()))?;
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// Move the settings leading `setup`'s startup commands into a command file,
+/// returning the setup that sources it instead, or `None` if no command
+/// leads that can't fail.
+pub fn write(setup: &DebugSetup) -> Result<Option<(DebugSetup, CommandFile)>> {
+    let settings = setup.commands.iter().take_while(|c| cannot_fail(c)).count();
+    if settings == 0 {
+        return Ok(None);
+    }
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let name = format!("cargo_debug_{}_{nanos:08x}.gdb", std::process::id());
+    let path = private_dir(&env::temp_dir())?.join(name);
+    create_private(&path, &render(&setup.commands[..settings]))?;
+    trace!("wrote startup commands to {}", path.display());
+
+    // Still after the user's script, as the settings would be on the command
+    // line
+    let mut commands = vec![format!("source {}", utf8_path(&path, "The command file")?)];
+    commands.extend(setup.commands[settings..].iter().cloned());
+    let setup = DebugSetup {
+        program: setup.program.clone(),
+        flags: setup.flags.clone(),
+        commands,
+        command_file: setup.command_file.clone(),
+    };
+
+    Ok(Some((setup, CommandFile { path })))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{stdin_commands, watch_range_command, Debugger};
+
+    #[test]
+    fn command_file() {
+        // --break-on-panic --asan-focus 0x1000-0x1008 --stdin-file in.txt -- -v
+        let commands = [
+            "break rust_panic".to_string(),
+            watch_range_command((0x1000, 0x1008)),
+        ]
+        .into_iter()
+        .chain(
+            stdin_commands(
+                &Debugger::Gdb,
+                Path::new("/foo/in.txt"),
+                &["-v".to_string()],
+            )
+            .unwrap(),
+        )
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            render(&commands),
+            "# generated by cargo-debug\n\
+             break rust_panic\n\
+             watch *(char(*)[8])0x1000\n\
+             set args -v < /foo/in.txt\n"
+        );
+
+        // Nothing leads that can't fail
+        let setup = DebugSetup {
+            program: None,
+            flags: vec!["-iex".to_string(), "set pagination off".to_string()],
+            commands,
+            command_file: None,
+        };
+        assert!(write(&setup).unwrap().is_none());
+
+        // --env A=1 --cwd /foo --break-on-panic --command-file breakpoints.gdb --run
+        let commands = [
+            "set environment A = 1",
+            "set cwd /foo",
+            "break rust_panic",
+            "set args -v",
+            "run",
+        ]
+        .map(String::from);
+        let setup = DebugSetup {
+            commands: commands.to_vec(),
+            command_file: Some("/foo/breakpoints.gdb".to_string()),
+            ..setup
+        };
+        let (sourced, file) = write(&setup).unwrap().unwrap();
+        assert_eq!(sourced.flags, setup.flags);
+        assert_eq!(
+            fs::read_to_string(file.path()).unwrap(),
+            render(&commands[..2])
+        );
+        // A breakpoint gdb can't set doesn't keep the program from running:
+        // each command after it is an `-ex` of its own
+        let source = format!("source {}", file.path().display());
+        assert_eq!(
+            sourced.startup_args(&Debugger::Gdb),
+            [
+                "--command",
+                "/foo/breakpoints.gdb",
+                "-ex",
+                &source,
+                "-ex",
+                "break rust_panic",
+                "-ex",
+                "set args -v",
+                "-ex",
+                "run"
+            ]
+        );
+
+        let path = file.path().to_path_buf();
+        drop(file);
+        assert!(!path.exists());
+
+        assert!(cannot_fail("set substitute-path /rustc/abc /foo/src"));
+        assert!(cannot_fail("directory /foo/src"));
+        assert!(!cannot_fail("set logging enabled on"));
+        assert!(!cannot_fail("set var x = 1"));
+        assert!(!cannot_fail("source /foo/breakpoints.gdb"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_command_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = env::temp_dir().join(format!("cargo-debug-private-{}", std::process::id()));
+        fs::create_dir_all(&temp).unwrap();
+
+        let dir = private_dir(&temp).unwrap();
+        assert_eq!(dir.metadata().unwrap().permissions().mode() & 0o777, 0o700);
+        let path = dir.join("startup.gdb");
+        create_private(&path, "break main\n").unwrap();
+        assert_eq!(path.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+        // Never written through one that's there already
+        assert!(create_private(&path, "shell id\n").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "break main\n");
+
+        // Nor in a directory others can write to, or a link to one
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(private_dir(&temp).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+        std::os::unix::fs::symlink("/tmp", &dir).unwrap();
+        assert!(private_dir(&temp).is_err());
+
+        fs::remove_dir_all(&temp).unwrap();
+    }
+}
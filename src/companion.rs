@@ -0,0 +1,265 @@
+//! Running other binaries of the workspace alongside the session
+//! (`--with`), e.g. a client driving the server being debugged.
+//!
+//! Companions start once the program is running: on Linux, when a process
+//! under cargo-debug is running its executable, and elsewhere after
+//! `--with-delay`. They're killed when the session ends.
+
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use cargo_metadata::Artifact;
+use log::{info, warn};
+
+use crate::events::Events;
+use crate::{build, select_binary, BuildArgs};
+
+/// How often the program is looked for.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A binary to run alongside the session, and its arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Companion {
+    pub bin: String,
+    pub args: Vec<String>,
+}
+
+/// Parse `BIN[:ARGS]`, the arguments separated by whitespace.
+pub fn parse(s: &str) -> Result<Companion, String> {
+    let (bin, args) = s.split_once(':').unwrap_or((s, ""));
+    if bin.is_empty() {
+        return Err(format!("expected BIN[:ARGS], got `{s}`"));
+    }
+
+    Ok(Companion {
+        bin: bin.to_string(),
+        args: args.split_whitespace().map(String::from).collect(),
+    })
+}
+
+/// Where the companions' output goes.
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// To a file each, `target/cargo-debug/with/BIN.log`
+    Log,
+    /// To this terminal, along with the debugger's
+    Inherit,
+}
+
+/// The executable of each of `companions`: one the build already produced,
+/// or else one built for it.
+pub fn executables(
+    args: &BuildArgs,
+    companions: &[Companion],
+    built: &[Artifact],
+) -> Result<Vec<PathBuf>> {
+    companions
+        .iter()
+        .map(|companion| {
+            let found = built.iter().find(|a| {
+                a.target.name == companion.bin
+                    && a.target.kind.iter().any(|k| k == "bin")
+                    && !a.profile.test
+            });
+            let artifact = match found {
+                Some(artifact) => artifact.clone(),
+                None => {
+                    let mut args = args.clone();
+                    args.bin = Some(companion.bin.clone());
+                    args.example = None;
+                    args.last_failed = false;
+                    args.all_targets = false;
+                    info!("building --with {}", companion.bin);
+                    let artifacts = build(&args, false, &mut Events::default()).artifacts;
+                    select_binary(&args, artifacts)?
+                }
+            };
+            let exe = artifact
+                .executable
+                .expect("selected binaries are executable");
+            Ok(exe.into_std_path_buf())
+        })
+        .collect()
+}
+
+/// The parent pid in a `/proc/PID/stat` line, which follows the
+/// parenthesized command name, itself possibly containing parentheses.
+#[cfg(target_os = "linux")]
+fn parent_pid(stat: &str) -> Option<u32> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Whether `pid` is `ancestor` or one of its descendants.
+#[cfg(target_os = "linux")]
+fn descends(mut pid: u32, ancestor: u32, parents: &HashMap<u32, u32>) -> bool {
+    while pid != ancestor {
+        match parents.get(&pid) {
+            Some(&parent) if parent != pid => pid = parent,
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+/// Whether a process under this one is running `exe`, if that can be told.
+#[cfg(target_os = "linux")]
+fn running(exe: &Path) -> Option<bool> {
+    let mut parents = HashMap::new();
+    let mut running = vec![];
+    for entry in fs::read_dir("/proc").ok()?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse().ok()) else {
+            continue;
+        };
+        let stat = fs::read_to_string(entry.path().join("stat")).ok();
+        if let Some(parent) = stat.as_deref().and_then(parent_pid) {
+            parents.insert(pid, parent);
+        }
+        if fs::read_link(entry.path().join("exe")).is_ok_and(|path| path == exe) {
+            running.push(pid);
+        }
+    }
+
+    let me = std::process::id();
+    Some(running.into_iter().any(|pid| descends(pid, me, &parents)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn running(_exe: &Path) -> Option<bool> {
+    None
+}
+
+fn spawn(companion: &Companion, exe: &Path, output: &Output, log_dir: &Path) -> Result<Child> {
+    let mut cmd = Command::new(exe);
+    cmd.args(&companion.args).stdin(Stdio::null());
+    if *output == Output::Log {
+        fs::create_dir_all(log_dir)
+            .with_context(|| format!("Could not create {}", log_dir.display()))?;
+        let path = log_dir.join(format!("{}.log", companion.bin));
+        let log =
+            File::create(&path).with_context(|| format!("Could not create {}", path.display()))?;
+        cmd.stdout(log.try_clone()?).stderr(log);
+        info!("--with {} logs to {}", companion.bin, path.display());
+    }
+
+    cmd.spawn()
+        .with_context(|| format!("Could not run {}", exe.display()))
+}
+
+/// The companions of a session, started in the background.
+pub struct Companions {
+    stop: Arc<AtomicBool>,
+    starter: JoinHandle<Vec<(String, Child)>>,
+}
+
+impl Companions {
+    /// Start `companions`, each running its executable, once `program` is
+    /// running, or after `delay` when that can't be told.
+    pub fn start(
+        companions: Vec<(Companion, PathBuf)>,
+        program: &Path,
+        delay: Duration,
+        output: Output,
+        log_dir: PathBuf,
+    ) -> Companions {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let program = program.canonicalize().unwrap_or(program.to_path_buf());
+
+        let starter = thread::spawn(move || {
+            let started = Instant::now();
+            loop {
+                if stopped.load(Ordering::SeqCst) {
+                    return vec![];
+                }
+                match running(&program) {
+                    Some(true) => break,
+                    Some(false) => {}
+                    None if started.elapsed() >= delay => break,
+                    None => {}
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+
+            companions
+                .iter()
+                .filter_map(
+                    |(companion, exe)| match spawn(companion, exe, &output, &log_dir) {
+                        Ok(child) => {
+                            eprintln!("cargo-debug: started --with {}", companion.bin);
+                            Some((companion.bin.clone(), child))
+                        }
+                        Err(e) => {
+                            warn!("{e:#}");
+                            None
+                        }
+                    },
+                )
+                .collect()
+        });
+
+        Companions { stop, starter }
+    }
+
+    /// Kill the companions, those that were started.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let Ok(children) = self.starter.join() else {
+            return;
+        };
+        for (bin, mut child) in children {
+            match child.try_wait() {
+                Ok(Some(status)) => info!("--with {bin} had already exited ({status})"),
+                _ => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn companion_spec() {
+        assert_eq!(
+            parse("client:--rate 100  --host localhost").unwrap(),
+            Companion {
+                bin: "client".to_string(),
+                args: ["--rate", "100", "--host", "localhost"]
+                    .map(String::from)
+                    .to_vec(),
+            }
+        );
+        assert_eq!(parse("client").unwrap().args, Vec::<String>::new());
+        assert!(parse(":--rate 100").is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn process_tree() {
+        assert_eq!(parent_pid("42 (a (b) c) S 7 42 42 0"), Some(7));
+        let parents = HashMap::from([(30, 20), (20, 10), (10, 1), (1, 0)]);
+        assert!(descends(30, 10, &parents));
+        assert!(descends(10, 10, &parents));
+        assert!(!descends(10, 20, &parents));
+        assert!(!descends(99, 10, &parents));
+
+        // This test is running its own executable
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(running(&exe), Some(true));
+        assert_eq!(running(Path::new("/nonexistent")), Some(false));
+    }
+}
@@ -0,0 +1,148 @@
+//! Comparing the selected binary with another build of it.
+//!
+//! Only what can be read from the two files is compared: their size, whether
+//! they're byte-for-byte identical, their build IDs (GNU build ID, Mach-O
+//! UUID or PDB GUID and age) and the names of the symbols they define. Code
+//! that changed without renaming anything shows up as a size difference at
+//! most, so this tells whether two builds differ rather than how.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use object::{Object, ObjectSymbol};
+
+use crate::buildid;
+
+/// How many added or removed symbols are listed, the rest are counted.
+const MAX_LISTED: usize = 20;
+
+/// What's compared of a binary.
+#[derive(Debug)]
+struct Summary {
+    path: PathBuf,
+    size: u64,
+    contents: Vec<u8>,
+    build_id: Option<String>,
+    symbols: BTreeSet<String>,
+}
+
+fn summarize(path: &Path) -> Result<Summary> {
+    let contents = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+    let file = object::File::parse(contents.as_slice())
+        .with_context(|| format!("Could not parse {}", path.display()))?;
+
+    let build_id = buildid::of(&file);
+    let symbols = file
+        .symbols()
+        .filter(|s| s.is_definition())
+        .filter_map(|s| s.name().ok().filter(|n| !n.is_empty()).map(String::from))
+        .collect();
+
+    Ok(Summary {
+        path: path.to_path_buf(),
+        size: contents.len() as u64,
+        contents,
+        build_id,
+        symbols,
+    })
+}
+
+/// The symbols in `a` and not in `b`, as lines of a report.
+fn symbol_lines(sign: char, a: &BTreeSet<String>, b: &BTreeSet<String>) -> Vec<String> {
+    let only = a.difference(b).collect::<Vec<_>>();
+    let mut lines = only
+        .iter()
+        .take(MAX_LISTED)
+        .map(|s| format!("  {sign} {s}"))
+        .collect::<Vec<_>>();
+    if only.len() > MAX_LISTED {
+        lines.push(format!("  ... and {} more", only.len() - MAX_LISTED));
+    }
+
+    lines
+}
+
+/// A report on how `theirs` differs from `ours`, and whether it does.
+fn render(ours: &Summary, theirs: &Summary) -> (String, bool) {
+    let differ = ours.contents != theirs.contents;
+    let id = |s: &Summary| s.build_id.clone().unwrap_or_else(|| "none".to_string());
+    let mut lines = vec![
+        format!("a: {}", ours.path.display()),
+        format!("b: {}", theirs.path.display()),
+        if differ {
+            "the binaries differ".to_string()
+        } else {
+            "the binaries are identical".to_string()
+        },
+        format!(
+            "size: {} -> {} bytes ({:+})",
+            ours.size,
+            theirs.size,
+            theirs.size as i64 - ours.size as i64
+        ),
+        format!("build id: {} -> {}", id(ours), id(theirs)),
+        format!(
+            "symbols: {} -> {}",
+            ours.symbols.len(),
+            theirs.symbols.len()
+        ),
+    ];
+    lines.extend(symbol_lines('-', &ours.symbols, &theirs.symbols));
+    lines.extend(symbol_lines('+', &theirs.symbols, &ours.symbols));
+
+    (lines.join("\n"), differ)
+}
+
+/// Print how `theirs` differs from `ours`, returning whether it does.
+pub fn report(ours: &Path, theirs: &Path) -> Result<bool> {
+    let (report, differ) = render(&summarize(ours)?, &summarize(theirs)?);
+    println!("{report}");
+
+    Ok(differ)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn summary(path: &str, contents: &[u8], symbols: &[&str]) -> Summary {
+        Summary {
+            path: PathBuf::from(path),
+            size: contents.len() as u64,
+            contents: contents.to_vec(),
+            build_id: Some(buildid::hex(contents)),
+            symbols: symbols.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn compare_binaries() {
+        let ours = summary("target/debug/foo", b"\x01\x02", &["main", "foo::old"]);
+        let theirs = summary("/tmp/foo", b"\x01\x02\x03", &["main", "foo::new"]);
+
+        let (report, differ) = render(&ours, &theirs);
+        assert!(differ);
+        assert_eq!(
+            report,
+            "a: target/debug/foo\n\
+             b: /tmp/foo\n\
+             the binaries differ\n\
+             size: 2 -> 3 bytes (+1)\n\
+             build id: 0102 -> 010203\n\
+             symbols: 2 -> 2\n  \
+             - foo::old\n  \
+             + foo::new"
+        );
+
+        let (report, differ) = render(&ours, &ours);
+        assert!(!differ);
+        assert!(report.contains("the binaries are identical"));
+
+        let many = (0..25).map(|i| format!("f{i}")).collect::<BTreeSet<_>>();
+        let lines = symbol_lines('+', &many, &BTreeSet::new());
+        assert_eq!(lines.len(), MAX_LISTED + 1);
+        assert_eq!(lines[MAX_LISTED], "  ... and 5 more");
+    }
+}
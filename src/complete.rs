@@ -0,0 +1,65 @@
+//! Target names for shell completion of `--bin`, `--example` and friends.
+
+use cargo_metadata::{Metadata, Target};
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum TargetKind {
+    Bin,
+    Example,
+    Test,
+    Bench,
+}
+
+impl TargetKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TargetKind::Bin => "bin",
+            TargetKind::Example => "example",
+            TargetKind::Test => "test",
+            TargetKind::Bench => "bench",
+        }
+    }
+}
+
+/// The sorted, deduplicated names of the targets of the given kind.
+fn names<'a>(targets: impl Iterator<Item = &'a Target>, kind: &TargetKind) -> Vec<String> {
+    let mut names = targets
+        .filter(|t| t.kind.iter().any(|k| k == kind.name()))
+        .map(|t| t.name.clone())
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    names
+}
+
+/// The names of the workspace's targets of the given kind.
+pub fn target_names(metadata: &Metadata, kind: &TargetKind) -> Vec<String> {
+    names(
+        metadata
+            .workspace_packages()
+            .into_iter()
+            .flat_map(|p| p.targets.iter()),
+        kind,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::artifact;
+
+    #[test]
+    fn bin_names() {
+        let targets = [
+            artifact("foo", "bin", Some("/foo/target/debug/foo")).target,
+            artifact("foo", "lib", None).target,
+            artifact("bar", "bin", Some("/foo/target/debug/bar")).target,
+            artifact("baz", "example", Some("/foo/target/debug/examples/baz")).target,
+        ];
+
+        assert_eq!(names(targets.iter(), &TargetKind::Bin), ["bar", "foo"]);
+        assert_eq!(names(targets.iter(), &TargetKind::Example), ["baz"]);
+        assert!(names(targets.iter(), &TargetKind::Test).is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+//! Resolving the effective configuration and where each value came from.
+//!
+//! Settings are taken from command line flags first, then environment
+//! variables, then defaults.
+
+use std::env;
+use std::fmt::Write;
+
+use crate::{Args, Debugger, ALLOW_PROFILE_ENV, DEFAULT_MAX_ARTIFACTS};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Source {
+    Cli,
+    Environment(&'static str),
+    Default,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Bool(bool),
+    Integer(usize),
+}
+
+struct Setting {
+    key: &'static str,
+    value: Option<(Value, Source)>,
+}
+
+fn setting(key: &'static str, value: Option<(Value, Source)>) -> Setting {
+    Setting { key, value }
+}
+
+/// A string flag, falling back to an environment variable.
+fn string(cli: &Option<String>, var: Option<&'static str>) -> Option<(Value, Source)> {
+    if let Some(value) = cli {
+        return Some((Value::String(value.clone()), Source::Cli));
+    }
+
+    let var = var?;
+    let value = env::var(var).ok()?;
+    Some((Value::String(value), Source::Environment(var)))
+}
+
+fn flag(cli: bool) -> Option<(Value, Source)> {
+    let source = if cli { Source::Cli } else { Source::Default };
+    Some((Value::Bool(cli), source))
+}
+
+/// A list flag, falling back to a comma-separated environment variable.
+fn list(cli: &[String], var: &'static str) -> Option<(Value, Source)> {
+    if !cli.is_empty() {
+        return Some((Value::String(cli.join(",")), Source::Cli));
+    }
+
+    let value = env::var(var).ok()?;
+    Some((Value::String(value), Source::Environment(var)))
+}
+
+/// A program that is run by name unless overridden by an environment variable.
+fn program(var: &'static str, default: &str) -> Option<(Value, Source)> {
+    match env::var(var) {
+        Ok(value) => Some((Value::String(value), Source::Environment(var))),
+        Err(_) => Some((Value::String(default.to_string()), Source::Default)),
+    }
+}
+
+fn resolve(args: &Args) -> Vec<(&'static str, Vec<Setting>)> {
+    let debugger = match &args.debugger {
+        Some(debugger) => (Value::String(debugger.name().to_string()), Source::Cli),
+        None => (
+            Value::String(Debugger::default().name().to_string()),
+            Source::Default,
+        ),
+    };
+
+    let max_artifacts = match args.build.max_artifacts {
+        Some(max) => (Value::Integer(max), Source::Cli),
+        None => (Value::Integer(DEFAULT_MAX_ARTIFACTS), Source::Default),
+    };
+
+    let build = &args.build;
+    vec![
+        (
+            "debug",
+            vec![
+                setting("debugger", Some(debugger)),
+                setting("pretty", flag(args.pretty)),
+                setting("tui", flag(args.tui)),
+                setting(
+                    "allow-profile",
+                    list(&args.allow_profile, ALLOW_PROFILE_ENV),
+                ),
+            ],
+        ),
+        (
+            "build",
+            vec![
+                setting("release", flag(build.release)),
+                setting(
+                    "manifest-path",
+                    string(
+                        &(!build.manifests.is_empty()).then(|| build.manifests.join(", ")),
+                        None,
+                    ),
+                ),
+                setting("bin", string(&build.bin, None)),
+                setting("example", string(&build.example, None)),
+                setting("target", string(&build.target, Some("CARGO_BUILD_TARGET"))),
+                setting(
+                    "target-dir",
+                    string(
+                        &build.target_dir.as_ref().map(|d| d.display().to_string()),
+                        Some("CARGO_TARGET_DIR"),
+                    ),
+                ),
+                setting(
+                    "rustc-wrapper",
+                    string(&build.rustc_wrapper, Some("RUSTC_WRAPPER")),
+                ),
+                setting("max-artifacts", Some(max_artifacts)),
+                setting(
+                    "opt-debug",
+                    flag(build.opt_debug || !build.opt_debug_config.is_empty()),
+                ),
+                setting("cargo", program("CARGO", "cargo")),
+                setting("rustc", program("RUSTC", "rustc")),
+            ],
+        ),
+    ]
+}
+
+/// Quote a TOML basic string.
+fn toml_string(s: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+fn render(sections: &[(&str, Vec<Setting>)]) -> String {
+    let mut toml = String::new();
+
+    for (i, (name, settings)) in sections.iter().enumerate() {
+        if i != 0 {
+            toml.push('\n');
+        }
+        writeln!(toml, "[{name}]").unwrap();
+
+        for setting in settings {
+            let Some((value, source)) = &setting.value else {
+                writeln!(toml, "# {} is not set", setting.key).unwrap();
+                continue;
+            };
+
+            let value = match value {
+                Value::String(s) => toml_string(s),
+                Value::Bool(b) => b.to_string(),
+                Value::Integer(n) => n.to_string(),
+            };
+            let source = match source {
+                Source::Cli => "from CLI".to_string(),
+                Source::Environment(var) => format!("from environment ({var})"),
+                Source::Default => "default".to_string(),
+            };
+
+            writeln!(toml, "{} = {value}  # {source}", setting.key).unwrap();
+        }
+    }
+
+    toml
+}
+
+/// The resolved configuration as TOML, with each value commented with its source.
+pub fn dump(args: &Args) -> String {
+    render(&resolve(args))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_sources() {
+        let sections = vec![(
+            "build",
+            vec![
+                setting("release", flag(true)),
+                setting("bin", None),
+                setting(
+                    "target",
+                    Some((
+                        Value::String("x86_64-unknown-linux-musl".to_string()),
+                        Source::Environment("CARGO_BUILD_TARGET"),
+                    )),
+                ),
+                setting("max-artifacts", Some((Value::Integer(32), Source::Default))),
+                setting(
+                    "manifest-path",
+                    string(&Some(r#"C:\my "dir"\Cargo.toml"#.to_string()), None),
+                ),
+            ],
+        )];
+
+        assert_eq!(
+            render(&sections),
+            r#"[build]
+release = true  # from CLI
+# bin is not set
+target = "x86_64-unknown-linux-musl"  # from environment (CARGO_BUILD_TARGET)
+max-artifacts = 32  # default
+manifest-path = "C:\\my \"dir\"\\Cargo.toml"  # from CLI
+"#
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Asking before a launch that's privileged or expensive (`--confirm`).
+//!
+//! Running as another user asks even without `--confirm`, and `--yes` or
+//! stdin not being a terminal skip the question, so scripts aren't stuck.
+
+use std::io::{BufRead, IsTerminal, Write};
+
+use anyhow::{bail, Result};
+
+/// From how many runs `--repeat` and `--run-until-crash` are worth asking
+/// about.
+const MANY_RUNS: u32 = 100;
+
+/// What's about to be launched.
+pub struct Launch<'a> {
+    pub debugger: &'a str,
+    pub name: &'a str,
+    pub user: Option<&'a str>,
+    pub remote: Option<&'a str>,
+    pub repeat: Option<u32>,
+    pub run_until_crash: Option<u32>,
+}
+
+/// What's worth knowing about `launch` before it happens, a line each, and
+/// whether any of it is privileged.
+fn risks(launch: &Launch) -> (Vec<String>, bool) {
+    let name = launch.name;
+    let mut lines = vec![];
+    if let Some(user) = launch.user {
+        lines.push(format!(
+            "{} and {name} run as {user} through sudo",
+            launch.debugger
+        ));
+    }
+    if let Some(remote) = launch.remote {
+        lines.push(format!("{name} is copied to {remote} and debugged there"));
+    }
+    match launch.repeat {
+        Some(n) if n >= MANY_RUNS => lines.push(format!("{name} runs up to {n} times")),
+        _ => {}
+    }
+    match launch.run_until_crash {
+        Some(0) => lines.push(format!(
+            "{name} runs until it fails, however long that takes"
+        )),
+        Some(n) if n >= MANY_RUNS => {
+            lines.push(format!("{name} runs up to {n} times until it fails"))
+        }
+        _ => {}
+    }
+
+    (lines, launch.user.is_some())
+}
+
+/// Ask whether to go ahead with `launch` when `confirm` is given or it's
+/// privileged, failing if the answer isn't yes. `yes` answers for the user.
+pub fn ask(launch: &Launch, confirm: bool, yes: bool) -> Result<()> {
+    let (lines, privileged) = risks(launch);
+    if yes || !(confirm || privileged) || !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+
+    eprintln!(
+        "cargo-debug: about to launch {} for {}",
+        launch.debugger, launch.name
+    );
+    for line in &lines {
+        eprintln!("  - {line}");
+    }
+    eprint!("cargo-debug: go ahead? [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    if !matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+        bail!("Not launching {}", launch.debugger);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn launch_risks() {
+        let quiet = Launch {
+            debugger: "gdb",
+            name: "foo",
+            user: None,
+            remote: None,
+            repeat: Some(10),
+            run_until_crash: None,
+        };
+        assert_eq!(risks(&quiet), (vec![], false));
+
+        let loud = Launch {
+            user: Some("nobody"),
+            remote: Some("me@box"),
+            repeat: Some(500),
+            run_until_crash: Some(0),
+            ..quiet
+        };
+        let (lines, privileged) = risks(&loud);
+        assert!(privileged);
+        assert_eq!(
+            lines,
+            [
+                "gdb and foo run as nobody through sudo",
+                "foo is copied to me@box and debugged there",
+                "foo runs up to 500 times",
+                "foo runs until it fails, however long that takes"
+            ]
+        );
+    }
+}
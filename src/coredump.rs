@@ -0,0 +1,439 @@
+//! Configuring where the kernel writes core dumps during the session.
+//!
+//! The pattern is system-wide and needs root to change, so failing to set it
+//! only warns, and the previous pattern is restored afterwards.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+
+use crate::{DebugSetup, Debugger};
+
+#[cfg(target_os = "linux")]
+const CORE_PATTERN: &str = "/proc/sys/kernel/core_pattern";
+
+/// Translate Linux's `%p` (pid), `%e` (executable) and `%t` (time) to
+/// macOS's `kern.corefile` equivalents, which has no time.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn macos_corefile(pattern: &str) -> String {
+    let mut corefile = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            corefile.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('p') => corefile.push_str("%P"),
+            Some('e') => corefile.push_str("%N"),
+            Some('t') => warn!("kern.corefile has no %t, leaving the time out"),
+            Some(other) => {
+                corefile.push('%');
+                corefile.push(other);
+            }
+            None => corefile.push('%'),
+        }
+    }
+
+    corefile
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sysctl(args: &[&str]) -> Result<String> {
+    let output = Command::new("sysctl")
+        .args(args)
+        .output()
+        .context("Could not run sysctl")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn read_pattern() -> Result<String> {
+    #[cfg(target_os = "linux")]
+    return Ok(std::fs::read_to_string(CORE_PATTERN)
+        .with_context(|| format!("Could not read {CORE_PATTERN}"))?
+        .trim_end()
+        .to_string());
+
+    #[cfg(not(target_os = "linux"))]
+    sysctl(&["-n", "kern.corefile"])
+}
+
+fn write_pattern(pattern: &str) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    return std::fs::write(CORE_PATTERN, pattern)
+        .with_context(|| format!("Could not write {CORE_PATTERN}"));
+
+    #[cfg(not(target_os = "linux"))]
+    sysctl(&["-w", &format!("kern.corefile={pattern}")]).map(|_| ())
+}
+
+/// Set the core dump pattern, returning the pattern it replaced so it can
+/// be restored, or `None` if it couldn't be changed.
+pub fn set_pattern(pattern: &str) -> Option<String> {
+    if !cfg!(any(target_os = "linux", target_os = "macos")) {
+        warn!("--core-pattern is only supported on Linux and macOS, ignoring it");
+        return None;
+    }
+
+    let pattern = if cfg!(target_os = "macos") {
+        macos_corefile(pattern)
+    } else {
+        pattern.to_string()
+    };
+
+    let previous = match read_pattern() {
+        Ok(previous) => previous,
+        Err(e) => {
+            warn!("not setting the core pattern: {e:#}");
+            return None;
+        }
+    };
+    if let Err(e) = write_pattern(&pattern) {
+        let command = if cfg!(target_os = "macos") {
+            format!("sudo sysctl -w kern.corefile='{pattern}'")
+        } else {
+            format!("sudo sysctl -w kernel.core_pattern='{pattern}'")
+        };
+        warn!("could not set the core pattern ({e:#}), it needs root: try `{command}`");
+        return None;
+    }
+
+    info!("core pattern: {previous} -> {pattern}");
+
+    Some(previous)
+}
+
+/// The pattern `--enable-core-dumps` sets: cores named after the
+/// executable, pid and time, in `dir`.
+pub fn default_pattern(dir: &Path) -> String {
+    format!("{}/core.%e.%p.%t", dir.display())
+}
+
+/// Let the program dump core of any size: the soft limit is raised to the
+/// hard one, and on macOS, launchd's limit is lifted too.
+pub fn enable() -> Result<()> {
+    #[cfg(unix)]
+    crate::ulimit::set(crate::ulimit::Resource::Core, |limit| {
+        limit.rlim_cur = limit.rlim_max
+    })
+    .context("Could not raise the core size limit")?;
+
+    #[cfg(target_os = "macos")]
+    match Command::new("launchctl")
+        .args(["limit", "core", "unlimited"])
+        .status()
+    {
+        Ok(status) if status.success() => {}
+        _ => {
+            warn!("could not lift launchd's core limit, try `sudo launchctl limit core unlimited`")
+        }
+    }
+
+    #[cfg(not(unix))]
+    warn!("--enable-core-dumps only sets up core dumps on Linux and macOS");
+
+    Ok(())
+}
+
+/// The newest file in `dir` written since `since`.
+fn newest(dir: &Path, since: SystemTime) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .filter(|(modified, _)| *modified >= since)
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// Where a core dump goes with `pattern`, for when it isn't one of ours.
+fn hint(pattern: &str) -> String {
+    if pattern.contains("systemd-coredump") {
+        "cores are handled by systemd-coredump, see `coredumpctl list`".to_string()
+    } else if pattern.contains("apport") {
+        "cores are handled by apport, see /var/crash".to_string()
+    } else if let Some(handler) = pattern.strip_prefix('|') {
+        format!("cores are piped to `{handler}`")
+    } else if pattern.starts_with('/') {
+        format!("cores are written to `{pattern}`")
+    } else {
+        format!("cores are written to `{pattern}`, relative to the program's directory")
+    }
+}
+
+/// The core size limit `--unlimit-core` gives the program: unlimited, or
+/// the hard limit if that's lower, as only root can raise it.
+#[cfg(unix)]
+pub fn unlimited() -> Result<Option<crate::ulimit::Limit>> {
+    let mut hard = libc::RLIM_INFINITY;
+    crate::ulimit::set(crate::ulimit::Resource::Core, |limit| hard = limit.rlim_max)
+        .context("Could not read the core size limit")?;
+    if hard != libc::RLIM_INFINITY {
+        warn!("the hard core size limit is {hard} bytes, cores may be truncated");
+    }
+
+    // rlim_t is narrower on some 32-bit platforms
+    #[allow(clippy::unnecessary_cast)]
+    Ok(Some(crate::ulimit::Limit {
+        resource: crate::ulimit::Resource::Core,
+        soft: (hard != libc::RLIM_INFINITY).then_some(hard as u64),
+        hard: None,
+    }))
+}
+
+#[cfg(not(unix))]
+pub fn unlimited() -> Result<Option<crate::ulimit::Limit>> {
+    warn!("--unlimit-core is only supported on Unix, ignoring it");
+    Ok(None)
+}
+
+/// Print where a core dump of the program will go.
+pub fn destination() {
+    match read_pattern() {
+        Ok(pattern) => eprintln!("core dumps enabled, {}", hint(&pattern)),
+        Err(e) => warn!("core dumps enabled, but the core pattern can't be read: {e:#}"),
+    }
+}
+
+/// Print where the core dump written since `since` is, if there is one in
+/// `dir`, or where to look for it otherwise.
+pub fn report(dir: &Path, since: SystemTime, crashed: bool) {
+    if let Some(core) = newest(dir, since) {
+        eprintln!("core dumped to {}", core.display());
+    } else if crashed {
+        match read_pattern() {
+            Ok(pattern) => eprintln!(
+                "the program crashed, but not into {}; {}",
+                dir.display(),
+                hint(&pattern)
+            ),
+            Err(e) => warn!("the program crashed, and the core pattern can't be read: {e:#}"),
+        }
+    }
+}
+
+/// `pattern` for the process `pid` of `exe` killed by `signal`, or `None`
+/// if it has a specifier that can't be known afterwards, like the time.
+fn expand(pattern: &str, pid: u32, exe: &str, signal: i32) -> Option<String> {
+    let mut path = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            path.push(c);
+            continue;
+        }
+
+        // Linux's specifiers, and macOS's in upper case
+        match chars.next()? {
+            'p' | 'P' => path.push_str(&pid.to_string()),
+            // The command name, which the kernel truncates to 15 bytes
+            'e' | 'N' => path.extend(exe.chars().take(15)),
+            's' => path.push_str(&signal.to_string()),
+            '%' => path.push('%'),
+            _ => return None,
+        }
+    }
+
+    Some(path)
+}
+
+/// Whether Linux appends the pid to cores whose pattern doesn't have it.
+fn core_uses_pid() -> bool {
+    fs::read_to_string("/proc/sys/kernel/core_uses_pid").is_ok_and(|v| v.trim() == "1")
+}
+
+/// Extract the core of the process `pid` from systemd-coredump's journal,
+/// which has it a moment after the process died.
+fn coredumpctl(pid: u32, dir: &Path) -> Result<PathBuf> {
+    let core = dir.join(format!("core.{pid}"));
+    for _ in 0..10 {
+        let status = Command::new("coredumpctl")
+            .args(["--quiet", "dump", &pid.to_string(), "--output"])
+            .arg(&core)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .context("Could not run coredumpctl")?;
+        if status.success() {
+            return Ok(core);
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+
+    bail!("coredumpctl has no core for pid {pid}, see `coredumpctl list`");
+}
+
+/// The core dump of the process `pid` of `exe`, run in `cwd` at `since` and
+/// killed by `signal`. Cores piped to systemd-coredump are extracted into
+/// `dir`.
+pub fn locate(
+    pid: u32,
+    exe: &str,
+    signal: i32,
+    cwd: &Path,
+    since: SystemTime,
+    dir: &Path,
+) -> Result<Option<PathBuf>> {
+    let pattern = read_pattern()?;
+    if pattern.starts_with('|') {
+        if pattern.contains("systemd-coredump") {
+            warn!("cores are piped to systemd-coredump, extracting this one with coredumpctl");
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create {}", dir.display()))?;
+            return coredumpctl(pid, dir).map(Some);
+        }
+        warn!("{}, which cargo-debug can't get cores from", hint(&pattern));
+        return Ok(None);
+    }
+
+    let Some(mut core) = expand(&pattern, pid, exe, signal) else {
+        // The newest file where cores go will do
+        let pattern = cwd.join(&pattern);
+        return Ok(pattern.parent().and_then(|dir| newest(dir, since)));
+    };
+    if !pattern.contains("%p") && core_uses_pid() {
+        core.push_str(&format!(".{pid}"));
+    }
+    let core = cwd.join(core);
+
+    Ok(core.is_file().then_some(core))
+}
+
+/// The debugger invocation opening `core` with symbols for `bin`. The core
+/// is passed as it's named, whether or not that's UTF-8.
+pub fn open_command(
+    debugger: &Debugger,
+    core: &Path,
+    bin: &str,
+    setup: &DebugSetup,
+) -> Result<(PathBuf, Vec<OsString>)> {
+    let mut args = setup.flags.iter().map(OsString::from).collect::<Vec<_>>();
+    let program = match debugger {
+        Debugger::Gdb => {
+            args.extend(setup.startup_args(debugger).into_iter().map(OsString::from));
+            args.extend([bin.into(), core.into()]);
+            "gdb"
+        }
+        Debugger::Lldb => {
+            args.extend(["--file".into(), bin.into(), "--core".into(), core.into()]);
+            args.extend(setup.startup_args(debugger).into_iter().map(OsString::from));
+            "lldb"
+        }
+        other => bail!(
+            "--collect-core opens cores with gdb or lldb, not {}",
+            other.name()
+        ),
+    };
+
+    Ok((
+        setup.program.clone().unwrap_or(PathBuf::from(program)),
+        args,
+    ))
+}
+
+/// Put back the pattern [`set_pattern`] replaced.
+pub fn restore_pattern(previous: &str) {
+    if let Err(e) = write_pattern(previous) {
+        warn!("could not restore the core pattern to {previous}: {e:#}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn open_core() {
+        let setup = DebugSetup {
+            commands: vec!["bt".to_string()],
+            ..Default::default()
+        };
+        let core = Path::new("core.42");
+
+        let (program, args) =
+            open_command(&Debugger::Gdb, core, "target/debug/foo", &setup).unwrap();
+        assert_eq!(program, PathBuf::from("gdb"));
+        assert_eq!(args, ["-ex", "bt", "target/debug/foo", "core.42"]);
+
+        let (program, args) =
+            open_command(&Debugger::Lldb, core, "target/debug/foo", &setup).unwrap();
+        assert_eq!(program, PathBuf::from("lldb"));
+        assert_eq!(
+            args,
+            [
+                "--file",
+                "target/debug/foo",
+                "--core",
+                "core.42",
+                "--one-line",
+                "bt"
+            ]
+        );
+        assert!(open_command(&Debugger::Windbg, core, "foo", &setup).is_err());
+    }
+
+    #[test]
+    fn macos_pattern() {
+        assert_eq!(macos_corefile("/cores/core.%e.%p"), "/cores/core.%N.%P");
+        assert_eq!(macos_corefile("core.%e.%p.%t"), "core.%N.%P.");
+        assert_eq!(macos_corefile("100%%"), "100%%");
+    }
+
+    #[test]
+    fn locate_cores() {
+        let dir = std::env::temp_dir().join(format!("cargo-debug-cores-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(
+            default_pattern(&dir),
+            format!("{}/core.%e.%p.%t", dir.display())
+        );
+
+        let since = SystemTime::now() - std::time::Duration::from_secs(1);
+        assert_eq!(newest(&dir, since), None);
+        fs::write(dir.join("core.foo.42.1700000000"), "").unwrap();
+        assert_eq!(
+            newest(&dir, since),
+            Some(dir.join("core.foo.42.1700000000"))
+        );
+        assert_eq!(
+            newest(&dir, SystemTime::now() + std::time::Duration::from_secs(60)),
+            None
+        );
+
+        assert!(hint("|/usr/lib/systemd/systemd-coredump %P").contains("coredumpctl"));
+        assert_eq!(
+            hint("core"),
+            "cores are written to `core`, relative to the program's directory"
+        );
+        assert_eq!(
+            expand(
+                "/var/cores/core.%e.%p.%s",
+                42,
+                "a-very-long-program-name",
+                11
+            )
+            .unwrap(),
+            "/var/cores/core.a-very-long-pro.42.11"
+        );
+        assert_eq!(
+            expand("/cores/core.%P", 42, "foo", 6).unwrap(),
+            "/cores/core.42"
+        );
+        assert_eq!(expand("core.%t", 42, "foo", 6), None);
+        assert_eq!(
+            hint("/var/cores/core.%e.%p"),
+            "cores are written to `/var/cores/core.%e.%p`"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+//! Collecting LLVM source-based coverage from a debug session.
+//!
+//! The binary is built with `-C instrument-coverage` and writes a `.profraw`
+//! profile on exit, which is merged and exported with the LLVM tools shipped
+//! in rustup's `llvm-tools` component.
+
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use log::{info, trace, warn};
+
+use crate::{find_executable, host_triple, sysroot};
+
+/// Coverage of dependencies and the standard library is rarely interesting.
+const IGNORE_FILENAMES: &str = r"[\\/]\.cargo[\\/]registry[\\/]|[\\/]rustc[\\/]";
+
+/// Append a rustc flag to a `RUSTFLAGS`-style value.
+pub fn append_flag(flags: Option<String>, separator: char, flag: &str) -> String {
+    match flags {
+        Some(flags) if !flags.is_empty() => format!("{flags}{separator}{flag}"),
+        _ => flag.to_string(),
+    }
+}
+
+fn profiles(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut profiles = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() == Some(OsStr::new("profraw")) {
+            profiles.push(path);
+        }
+    }
+
+    Ok(profiles)
+}
+
+/// Build with coverage instrumentation, writing profiles into `dir`.
+///
+/// This changes the environment of this process, so cargo and the debugged
+/// binary inherit it. Profiles left over from earlier sessions are removed.
+pub fn instrument(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    for profile in profiles(dir)? {
+        std::fs::remove_file(profile)?;
+    }
+
+    // cargo ignores RUSTFLAGS when the encoded form is set
+    if let Ok(flags) = env::var("CARGO_ENCODED_RUSTFLAGS") {
+        let flags = append_flag(Some(flags), '\x1f', "-Cinstrument-coverage");
+        env::set_var("CARGO_ENCODED_RUSTFLAGS", flags);
+    } else {
+        let flags = append_flag(env::var("RUSTFLAGS").ok(), ' ', "-Cinstrument-coverage");
+        env::set_var("RUSTFLAGS", flags);
+    }
+
+    env::set_var("LLVM_PROFILE_FILE", dir.join("cargo-debug-%p-%m.profraw"));
+
+    Ok(())
+}
+
+/// Locate one of the LLVM tools, preferring `PATH` over rustup's `llvm-tools`.
+pub fn llvm_tool(name: &str) -> Result<PathBuf> {
+    if let Some(path) = find_executable(Path::new(name)) {
+        return Ok(path);
+    }
+
+    let path = sysroot()?
+        .join("lib/rustlib")
+        .join(host_triple()?)
+        .join("bin")
+        .join(format!("{name}{}", env::consts::EXE_SUFFIX));
+    if !path.is_file() {
+        bail!("Could not find {name}, install it with `rustup component add llvm-tools`");
+    }
+
+    Ok(path)
+}
+
+fn run(cmd: &mut Command) -> Result<()> {
+    trace!("running {:?}", cmd);
+
+    let status = cmd.status()?;
+    if !status.success() {
+        bail!("{:?} failed: {status}", cmd.get_program());
+    }
+
+    Ok(())
+}
+
+/// Merge the profiles written to `dir` while debugging `bin`, export them as
+/// `lcov.info` and print a per-file summary, or write an HTML report.
+pub fn report(dir: &Path, bin: &str, html: bool) -> Result<()> {
+    let profiles = profiles(dir)?;
+    if profiles.is_empty() {
+        warn!("no coverage profiles were written, did the program exit normally?");
+        return Ok(());
+    }
+
+    let profdata = dir.join("cargo-debug.profdata");
+    run(Command::new(llvm_tool("llvm-profdata")?)
+        .args(["merge", "-sparse", "-o"])
+        .arg(&profdata)
+        .args(&profiles))?;
+
+    let llvm_cov = llvm_tool("llvm-cov")?;
+    let cov_args = [
+        format!("-instr-profile={}", profdata.display()),
+        format!("-ignore-filename-regex={IGNORE_FILENAMES}"),
+        bin.to_string(),
+    ];
+
+    let lcov = Command::new(&llvm_cov)
+        .args(["export", "-format=lcov"])
+        .args(&cov_args)
+        .output()?;
+    if !lcov.status.success() {
+        bail!(
+            "llvm-cov export failed: {}",
+            String::from_utf8_lossy(&lcov.stderr)
+        );
+    }
+
+    let lcov_path = dir.join("lcov.info");
+    std::fs::write(&lcov_path, lcov.stdout)?;
+    info!("wrote coverage data to {}", lcov_path.display());
+
+    if html {
+        let html_dir = dir.join("html");
+        run(Command::new(&llvm_cov)
+            .args(["show", "-format=html"])
+            .arg(format!("-output-dir={}", html_dir.display()))
+            .args(&cov_args))?;
+
+        println!("Coverage report: {}", html_dir.join("index.html").display());
+    } else {
+        run(Command::new(&llvm_cov).arg("report").args(&cov_args))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn append_rustflags() {
+        assert_eq!(append_flag(None, ' ', "-Cfoo"), "-Cfoo");
+        assert_eq!(append_flag(Some(String::new()), ' ', "-Cfoo"), "-Cfoo");
+        assert_eq!(
+            append_flag(Some("-Cbar".to_string()), ' ', "-Cfoo"),
+            "-Cbar -Cfoo"
+        );
+        assert_eq!(
+            append_flag(Some("-Cbar".to_string()), '\x1f', "-Cfoo"),
+            "-Cbar\x1f-Cfoo"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Running the program without a debugger until it fails, for crashes too
+//! rare to wait for in a debugger.
+//!
+//! Each run's output goes to files, so once a run fails, its output is what
+//! they hold. cargo-debug then runs the program once more in the debugger,
+//! with the same arguments and environment. `--on-failure` is the same with
+//! a single run, whose output is printed before the debugger starts.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::signal;
+
+/// How `name`'s run ended, e.g. "foo was terminated by SIGSEGV (...)".
+fn outcome(name: &str, status: &ExitStatus) -> String {
+    signal::report(name, status).unwrap_or_else(|| format!("{name} exited with {status}"))
+}
+
+/// The files in `dir` a run's stdout and stderr go to, once it exists.
+fn output_files(dir: &Path) -> Result<(PathBuf, PathBuf)> {
+    fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    Ok((dir.join("stdout.txt"), dir.join("stderr.txt")))
+}
+
+/// Run `cmd` once, with an empty stdin and its output in `stdout` and
+/// `stderr`.
+fn run_to(cmd: &mut Command, stdout: &Path, stderr: &Path) -> Result<ExitStatus> {
+    let create = |path: &Path| {
+        File::create(path).with_context(|| format!("Could not create {}", path.display()))
+    };
+    cmd.stdin(Stdio::null())
+        .stdout(create(stdout)?)
+        .stderr(create(stderr)?)
+        .status()
+        .with_context(|| format!("Could not run {}", cmd.get_program().to_string_lossy()))
+}
+
+/// Run `cmd` until it fails, `max` times at most (0 for no limit), with
+/// its output in `dir`, returning the run that failed.
+pub fn run(cmd: &mut Command, max: u32, dir: &Path, name: &str) -> Result<Option<u32>> {
+    let (stdout, stderr) = output_files(dir)?;
+    let of = if max == 0 {
+        String::new()
+    } else {
+        format!(" of {max}")
+    };
+
+    for iteration in (1..).take_while(|i| max == 0 || *i <= max) {
+        let status = run_to(cmd, &stdout, &stderr)?;
+
+        // Rewrite the line in place, runs can be quick
+        eprint!("\rcargo-debug: run {iteration}{of}: {status}");
+        std::io::stderr().flush()?;
+        if !status.success() {
+            eprintln!();
+            eprintln!(
+                "cargo-debug: run {iteration} failed, {}; its output is in {} and {}",
+                outcome(name, &status),
+                stdout.display(),
+                stderr.display()
+            );
+            return Ok(Some(iteration));
+        }
+    }
+
+    eprintln!();
+    eprintln!("cargo-debug: {name} didn't fail in {max} runs");
+
+    Ok(None)
+}
+
+/// Run `cmd` once, with its output in `dir`, and if it fails, print that
+/// output, for context before the debugger runs it again.
+pub fn once(cmd: &mut Command, dir: &Path, name: &str) -> Result<ExitStatus> {
+    let (stdout, stderr) = output_files(dir)?;
+    let status = run_to(cmd, &stdout, &stderr)?;
+    if status.success() {
+        return Ok(status);
+    }
+
+    eprintln!("cargo-debug: {}", outcome(name, &status));
+    for (stream, path) in [("stdout", &stdout), ("stderr", &stderr)] {
+        let output =
+            fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+        if output.is_empty() {
+            continue;
+        }
+        eprintln!("cargo-debug: {name}'s {stream} ({}):", path.display());
+        std::io::stderr().write_all(&output)?;
+        if !output.ends_with(b"\n") {
+            eprintln!();
+        }
+    }
+
+    Ok(status)
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn until_failure() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-debug-crashloop-{}", std::process::id()));
+        let marker = dir.join("ran");
+
+        // Fails on its second run
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(format!(
+            "echo out; echo err >&2; [ -e {0} ] && exit 3; touch {0}",
+            marker.display()
+        ));
+        assert_eq!(run(&mut cmd, 5, &dir, "sh").unwrap(), Some(2));
+        assert_eq!(fs::read_to_string(dir.join("stdout.txt")).unwrap(), "out\n");
+        assert_eq!(fs::read_to_string(dir.join("stderr.txt")).unwrap(), "err\n");
+
+        assert_eq!(
+            run(&mut Command::new("true"), 3, &dir, "true").unwrap(),
+            None
+        );
+        let status = Command::new("sh").args(["-c", "exit 3"]).status().unwrap();
+        assert_eq!(outcome("sh", &status), "sh exited with exit status: 3");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn single_run() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-debug-on-failure-{}", std::process::id()));
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo out; echo err >&2; exit 3"]);
+        let status = once(&mut cmd, &dir, "sh").unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(fs::read_to_string(dir.join("stderr.txt")).unwrap(), "err\n");
+
+        assert!(once(&mut Command::new("true"), &dir, "true")
+            .unwrap()
+            .success());
+        assert_eq!(fs::read_to_string(dir.join("stdout.txt")).unwrap(), "");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
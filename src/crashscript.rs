@@ -0,0 +1,105 @@
+//! Sourcing a script of debugger commands when the program crashes, e.g.
+//! `bt` and `info registers`, for an analysis that doesn't wait on anyone.
+//!
+//! gdb gets a `hook-stop` that sources the script when the stop is for a
+//! crash signal. lldb's stop hooks can't tell why the process stopped
+//! without a Python class, so its hook sources the script at every stop.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::quote;
+use crate::{utf8_path, Debugger};
+
+/// The signals that mean the program crashed.
+#[cfg(unix)]
+const CRASH_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+];
+
+/// A gdb `hook-stop` sourcing `script` when the program stops on one of
+/// the crash signals.
+#[cfg(unix)]
+fn gdb_hook(script: &Path) -> String {
+    let crashed = CRASH_SIGNALS
+        .iter()
+        .map(|signal| format!("$_siginfo.si_signo == {signal}"))
+        .collect::<Vec<_>>()
+        .join(" || ");
+
+    format!(
+        "define hook-stop\n\
+         \x20 if {crashed}\n\
+         \x20   source {}\n\
+         \x20 end\n\
+         end\n",
+        script.display()
+    )
+}
+
+/// The debugger commands sourcing `script` when the program crashes.
+pub fn commands(debugger: &Debugger, script: &Path) -> Result<Vec<String>> {
+    // The debugger may run somewhere else than we do
+    let path = script
+        .canonicalize()
+        .with_context(|| format!("Could not find {}", script.display()))?;
+    let script = utf8_path(&path, "--post-crash-script")?;
+
+    match debugger {
+        #[cfg(unix)]
+        Debugger::Gdb => {
+            // A definition spans lines, so it needs a file of its own
+            let path = std::env::temp_dir()
+                .join(format!("cargo-debug-crash-hook-{}.gdb", std::process::id()));
+            std::fs::write(&path, gdb_hook(Path::new(script)))
+                .with_context(|| format!("Could not write {}", path.display()))?;
+            Ok(vec![format!("source {}", path.display())])
+        }
+        Debugger::Lldb => {
+            let source = format!("command source {}", quote::lldb(script));
+            Ok(vec![format!(
+                "target stop-hook add --one-liner {}",
+                quote::lldb(&source)
+            )])
+        }
+        _ => bail!("--post-crash-script is only supported by gdb on Unix, and lldb"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn gdb_crash_hook() {
+        let hook = gdb_hook(Path::new("/tmp/crash.gdb"));
+        assert!(hook.starts_with("define hook-stop\n  if $_siginfo.si_signo == 11 || "));
+        assert!(hook.ends_with("\n    source /tmp/crash.gdb\n  end\nend\n"));
+    }
+
+    #[test]
+    fn lldb_stop_hook() {
+        let script = std::env::temp_dir().join("cargo-debug crash.lldb");
+        std::fs::write(&script, "bt\n").unwrap();
+        let path = script.canonicalize().unwrap();
+
+        let hook = commands(&Debugger::Lldb, &script).unwrap();
+        assert_eq!(
+            hook,
+            [format!(
+                "target stop-hook add --one-liner \"command source \\\"{}\\\"\"",
+                path.display()
+            )]
+        );
+        assert!(commands(&Debugger::Windbg, &script).is_err());
+
+        std::fs::remove_file(&script).unwrap();
+        assert!(commands(&Debugger::Lldb, &script).is_err());
+    }
+}
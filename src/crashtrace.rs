@@ -0,0 +1,251 @@
+//! Running the program under the debugger with no one at it, e.g. in CI:
+//! if it crashes, every thread's backtrace is printed and the session ends.
+//!
+//! The debugger runs in batch mode, and its exit status says how the
+//! debugger did rather than the program, so its output is passed on a line
+//! at a time, looking for how the program ended. gdb reports that in lines
+//! of our own, from a script; lldb and cdb in their own words: lldb's
+//! `Process N exited with status = X`, and cdb's `.lastevent`.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+use crate::events::Events;
+use crate::{debug_command, DebugSetup, Debugger};
+
+/// The exit status when the program crashed, as a shell reports a program
+/// killed by SIGABRT.
+pub const CRASH_EXIT_CODE: i32 = 134;
+
+/// What gdb's script prints when the program exits, followed by its status.
+const GDB_EXITED: &str = "cargo-debug: exited with ";
+/// ... and when it stops instead.
+const GDB_CRASHED: &str = "cargo-debug: crashed";
+
+/// How the program's run ended.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Exited(i32),
+    Crashed,
+}
+
+/// Runs the program, then prints the backtraces if it stopped rather than
+/// exited: `$_exitcode` is only set once it has exited.
+fn gdb_script() -> String {
+    format!(
+        "run\n\
+         if $_isvoid($_exitcode)\n\
+         \x20 echo {GDB_CRASHED}\\n\n\
+         \x20 thread apply all bt\n\
+         else\n\
+         \x20 printf \"{GDB_EXITED}%d\\n\", $_exitcode\n\
+         end\n"
+    )
+}
+
+/// The batch invocation running `bin` and printing every thread's backtrace
+/// if it crashes.
+pub fn command(
+    debugger: &Debugger,
+    bin: &str,
+    options: &[String],
+    setup: &DebugSetup,
+) -> Result<(PathBuf, Vec<String>)> {
+    let mut flags = setup.flags.clone();
+    let mut commands = setup.commands.clone();
+
+    match debugger {
+        Debugger::Gdb => {
+            // An `if` spans lines, so it needs a file of its own
+            let path = std::env::temp_dir()
+                .join(format!("cargo-debug-backtrace-{}.gdb", std::process::id()));
+            std::fs::write(&path, gdb_script())
+                .with_context(|| format!("Could not write {}", path.display()))?;
+            flags.push("--batch".to_string());
+            commands.push(format!("source {}", path.display()));
+        }
+        Debugger::Lldb => {
+            // A crash ends the commands and runs the ones for crashes; a
+            // breakpoint, e.g. on `rust_panic`, goes on to the backtrace
+            flags.push("--batch".to_string());
+            for command in ["thread backtrace all", "process kill", "quit"] {
+                flags.push("--one-line-on-crash".to_string());
+                flags.push(command.to_string());
+            }
+            commands.push("run".to_string());
+            commands.push("thread backtrace all".to_string());
+        }
+        Debugger::Windbg => return Ok(cdb_command(PathBuf::from("cdb"), bin, options, setup)),
+        Debugger::Custom(path)
+            if path
+                .file_stem()
+                .is_some_and(|stem| stem.eq_ignore_ascii_case("cdb")) =>
+        {
+            return Ok(cdb_command(path.clone(), bin, options, setup));
+        }
+        other => bail!(
+            "--backtrace-on-crash is only supported by gdb, lldb and cdb, not {}",
+            other.name()
+        ),
+    }
+
+    let batch = DebugSetup {
+        program: setup.program.clone(),
+        flags,
+        commands,
+        command_file: None,
+    };
+    debug_command(debugger, bin, options, &batch)
+}
+
+/// cdb, WinDbg's console version, going on from the initial breakpoint to
+/// the crash or the exit, then analyzing it and quitting.
+fn cdb_command(
+    program: PathBuf,
+    bin: &str,
+    options: &[String],
+    setup: &DebugSetup,
+) -> (PathBuf, Vec<String>) {
+    let mut commands = setup.commands.clone();
+    commands.extend(["g", ".lastevent", "!analyze -v", "~*kb", "q"].map(String::from));
+
+    let mut args = setup.flags.clone();
+    args.extend(["-c".to_string(), commands.join("; ")]);
+    args.push(bin.to_string());
+    args.extend(options.iter().cloned());
+
+    (setup.program.clone().unwrap_or(program), args)
+}
+
+/// How the program ended, if `line` of the debugger's output says.
+fn outcome(line: &str) -> Option<Outcome> {
+    let line = line.trim();
+    if line == GDB_CRASHED {
+        return Some(Outcome::Crashed);
+    }
+    if let Some(code) = line.strip_prefix(GDB_EXITED) {
+        return code.parse().ok().map(Outcome::Exited);
+    }
+
+    // lldb: "Process 123 exited with status = 3 (0x00000003)"
+    if let Some(rest) = line.strip_prefix("Process ") {
+        if let Some((_, status)) = rest.split_once(" exited with status = ") {
+            let code = status.split_whitespace().next()?;
+            return code.parse().ok().map(Outcome::Exited);
+        }
+        if rest.ends_with(" stopped") {
+            return Some(Outcome::Crashed);
+        }
+    }
+
+    // cdb: "Last event: 1a2b.3c4d: Exit process 0:1a2b, code 3", in hex
+    if let Some(event) = line.strip_prefix("Last event: ") {
+        return Some(match event.split_once(", code ") {
+            Some((exit, code)) if exit.contains("Exit process") => {
+                // NTSTATUS codes don't fit an i32, exit statuses are the bits
+                let code = u32::from_str_radix(code.trim(), 16).ok()?;
+                Outcome::Exited(code as i32)
+            }
+            _ => Outcome::Crashed,
+        });
+    }
+
+    None
+}
+
+/// Run the batch session `cmd`, passing its output on to stdout, or stderr
+/// when stdout has events, and return how the program ended.
+pub fn run(cmd: &mut Command, events: &mut Events) -> Result<(Option<Outcome>, ExitStatus)> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Could not run {}", cmd.get_program().to_string_lossy()))?;
+    events.debugger_launched(child.id());
+
+    let mut out: Box<dyn Write> = if events.enabled() {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    };
+    let mut ended = None;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    for line in BufReader::new(stdout).split(b'\n') {
+        let line = String::from_utf8_lossy(&line?).into_owned();
+        writeln!(out, "{line}")?;
+        ended = outcome(&line).or(ended);
+    }
+
+    let status = child.wait()?;
+    events.debugger_exited(&status);
+
+    Ok((ended, status))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn batch_outcome() {
+        assert_eq!(outcome("cargo-debug: crashed"), Some(Outcome::Crashed));
+        assert_eq!(
+            outcome("cargo-debug: exited with 3"),
+            Some(Outcome::Exited(3))
+        );
+        assert_eq!(
+            outcome("Process 4242 exited with status = 0 (0x00000000) "),
+            Some(Outcome::Exited(0))
+        );
+        assert_eq!(outcome("Process 4242 stopped"), Some(Outcome::Crashed));
+        assert_eq!(
+            outcome("Last event: 1a2b.3c4d: Exit process 0:1a2b, code 1f"),
+            Some(Outcome::Exited(31))
+        );
+        assert_eq!(
+            outcome("Last event: 1a2b.3c4d: Access violation - code c0000005 (first chance)"),
+            Some(Outcome::Crashed)
+        );
+        assert_eq!(outcome("[Inferior 1 (process 4242) exited normally]"), None);
+
+        assert!(gdb_script().contains("  echo cargo-debug: crashed\\n\n  thread apply all bt\n"));
+
+        let setup = DebugSetup::default();
+        let (program, args) =
+            command(&Debugger::Windbg, "foo.exe", &["-v".to_string()], &setup).unwrap();
+        assert_eq!(program, PathBuf::from("cdb"));
+        assert_eq!(
+            args,
+            ["-c", "g; .lastevent; !analyze -v; ~*kb; q", "foo.exe", "-v"]
+        );
+        assert!(command(&Debugger::Perf, "foo", &[], &setup).is_err());
+    }
+
+    /// A program killed by SIGSEGV, under gdb if it's installed.
+    #[cfg(unix)]
+    #[test]
+    fn crashing_fixture() {
+        let installed = Command::new("gdb")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success());
+        if !installed {
+            return;
+        }
+
+        let setup = DebugSetup::default();
+        let run_gdb = |script: &str| {
+            let options = ["-c".to_string(), script.to_string()];
+            let (program, args) = command(&Debugger::Gdb, "/bin/sh", &options, &setup).unwrap();
+            run(Command::new(program).args(args), &mut Events::default())
+                .unwrap()
+                .0
+        };
+        assert_eq!(run_gdb("kill -SEGV $$"), Some(Outcome::Crashed));
+        assert_eq!(run_gdb("exit 3"), Some(Outcome::Exited(3)));
+    }
+}
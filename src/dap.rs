@@ -0,0 +1,215 @@
+//! Debug Adapter Protocol bridge, so editors can attach to a build without
+//! any editor-specific configuration.
+
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Child, Command};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::trace;
+use serde_json::{json, Value};
+
+use crate::find_executable;
+
+#[derive(clap::ValueEnum, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DapAdapter {
+    /// LLVM's `lldb-dap` (formerly `lldb-vscode`)
+    #[default]
+    LldbDap,
+    /// The adapter shipped with the CodeLLDB extension
+    Codelldb,
+    /// The adapter shipped with Microsoft's C/C++ extension
+    #[value(name = "opendebugad7")]
+    OpenDebugAd7,
+}
+
+impl DapAdapter {
+    fn program(&self) -> &'static str {
+        match self {
+            DapAdapter::LldbDap => "lldb-dap",
+            DapAdapter::Codelldb => "codelldb",
+            DapAdapter::OpenDebugAd7 => "OpenDebugAD7",
+        }
+    }
+
+    /// Arguments that make the adapter listen for a client on `port`.
+    fn args(&self, port: u16) -> Vec<String> {
+        match self {
+            DapAdapter::LldbDap | DapAdapter::Codelldb => {
+                vec!["--port".to_string(), port.to_string()]
+            }
+            DapAdapter::OpenDebugAd7 => vec![format!("--server={port}")],
+        }
+    }
+
+    /// The `arguments` of a `launch` request for the selected binary.
+    ///
+    /// None of the adapters accept the program on their command line, so the
+    /// client has to send these itself.
+    fn launch_arguments(&self, bin: &str, options: &[String], cwd: &Path) -> Value {
+        let mut arguments = json!({
+            "program": bin,
+            "args": options,
+            "cwd": cwd,
+        });
+
+        if *self == DapAdapter::OpenDebugAd7 {
+            let mode = if cfg!(target_os = "macos") {
+                "lldb"
+            } else {
+                "gdb"
+            };
+            arguments["MIMode"] = json!(mode);
+        }
+
+        arguments
+    }
+}
+
+/// Ask the OS for a free local port.
+fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    Ok(listener.local_addr()?.port())
+}
+
+fn spawn_adapter(adapter: &DapAdapter, port: u16) -> Result<Child> {
+    let program = adapter.program();
+    let path = find_executable(Path::new(program))
+        .with_context(|| format!("Could not find the {program} debug adapter"))?;
+
+    let mut adapter_cmd = Command::new(path);
+    adapter_cmd.args(adapter.args(port));
+
+    trace!("synthesized adapter command: {:?}", adapter_cmd);
+
+    Ok(adapter_cmd.spawn()?)
+}
+
+/// Start a debug adapter for the selected binary and wait for it to exit.
+///
+/// A single JSON line describing the session is written to stdout, including
+/// the arguments for the `launch` request the client should send.
+pub fn serve(adapter: &DapAdapter, bin: &str, options: &[String]) -> Result<()> {
+    let port = free_port()?;
+    let child = Arc::new(Mutex::new(spawn_adapter(adapter, port)?));
+
+    let cwd = std::env::current_dir()?;
+    let session = json!({
+        "port": port,
+        "program": bin,
+        "adapter": adapter.program(),
+        "launch": adapter.launch_arguments(bin, options, &cwd),
+    });
+
+    let mut stdout = std::io::stdout();
+    writeln!(stdout, "{session}")?;
+    stdout.flush()?;
+
+    // Don't leave the adapter behind if we're interrupted
+    let handler_child = child.clone();
+    ctrlc::set_handler(move || {
+        let _ = handler_child.lock().unwrap().kill();
+        std::process::exit(130);
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    let status = loop {
+        if let Some(status) = child.lock().unwrap().try_wait()? {
+            break status;
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    };
+
+    trace!("debug adapter exited: {status}");
+
+    if let Some(code) = status.code() {
+        if code != 0 {
+            std::process::exit(code);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpStream;
+
+    #[test]
+    fn opendebugad7_launch_arguments() {
+        let args = DapAdapter::OpenDebugAd7.launch_arguments(
+            "/foo/target/debug/foo",
+            &["--bar".to_string()],
+            Path::new("/foo"),
+        );
+
+        assert_eq!(args["program"], "/foo/target/debug/foo");
+        assert_eq!(args["args"][0], "--bar");
+        assert!(args["MIMode"].is_string());
+    }
+
+    #[test]
+    fn lldb_dap_initialize() {
+        if find_executable(Path::new("lldb-dap")).is_none() {
+            eprintln!("lldb-dap not found, skipping");
+            return;
+        }
+
+        let port = free_port().unwrap();
+        let mut child = spawn_adapter(&DapAdapter::LldbDap, port).unwrap();
+
+        // Give the adapter a moment to start listening
+        let mut stream = (0..50)
+            .find_map(|_| {
+                std::thread::sleep(Duration::from_millis(100));
+                TcpStream::connect(("127.0.0.1", port)).ok()
+            })
+            .expect("could not connect to lldb-dap");
+
+        let request = json!({
+            "seq": 1,
+            "type": "request",
+            "command": "initialize",
+            "arguments": { "adapterID": "cargo-debug" },
+        })
+        .to_string();
+        write!(
+            stream,
+            "Content-Length: {}\r\n\r\n{}",
+            request.len(),
+            request
+        )
+        .unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(len) = line.strip_prefix("Content-Length: ") {
+                length = len.parse().unwrap();
+            }
+        }
+
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let response: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response["command"], "initialize");
+        assert_eq!(response["success"], true);
+    }
+}
@@ -0,0 +1,339 @@
+//! Debuggers defined rather than built in, selected by name like the
+//! built-in ones. The manifest defines them with a program and arguments:
+//!
+//! ```toml
+//! [workspace.metadata.cargo-debug.debuggers.mygdb]
+//! program = "/opt/mygdb/bin/gdb"
+//! args = ["-q", "--args", "{bin}", "{args}"]
+//! ```
+//!
+//! or, like cargo's config files (`[debug.debuggers]`) and a
+//! `cargo-debug.toml` next to the workspace's manifest (`[debuggers]`), as a
+//! command line:
+//!
+//! ```toml
+//! [debug.debuggers]
+//! rr = "rr record {bin} {args}"
+//! ```
+//!
+//! `cargo debug mygdb` then runs `program` with `args`, whose placeholders
+//! are those of `--exec-template`, unquoted as there's no shell. A command
+//! line is split into words first, quoted as the platform's shell would.
+//! `--debugger-cmd` takes a command line for one session.
+//!
+//! A package's `[package.metadata.cargo-debug.debuggers]` override the
+//! workspace's, the config files override the manifest, the more specific
+//! ones the others, and `cargo-debug.toml` overrides them all. Built-in
+//! names can't be redefined.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use cargo_metadata::Metadata;
+
+use crate::template::{self, Values};
+use crate::{cargoconfig, quote, Debugger};
+
+/// The file next to the workspace's manifest defining debuggers.
+const LOCAL_FILE: &str = "cargo-debug.toml";
+
+/// A debugger defined in the manifest, a config file or `--debugger-cmd`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Defined {
+    pub name: String,
+    pub program: PathBuf,
+    args: Vec<String>,
+    /// Where it's defined
+    source: String,
+}
+
+impl Defined {
+    /// The debugger `name` that `line`, a command line with placeholders,
+    /// defines in `source`.
+    fn command_line(name: &str, line: &str, source: &str) -> Result<Self> {
+        let words = quote::words(line, cfg!(windows))
+            .with_context(|| format!("The {name} debugger in {source} has an unmatched quote"))?;
+        let Some((program, args)) = words.split_first() else {
+            bail!("The {name} debugger in {source} is empty");
+        };
+
+        Ok(Defined {
+            name: name.to_string(),
+            program: PathBuf::from(program),
+            args: args.to_vec(),
+            source: source.to_string(),
+        })
+    }
+
+    /// The command line debugging the binary `values` describe.
+    pub fn command(&self, values: &Values) -> Result<(PathBuf, Vec<String>)> {
+        let what = format!("the {} debugger in {}", self.name, self.source);
+        let args = template::expand_args(&self.args, values, &what)?;
+
+        Ok((self.program.clone(), args))
+    }
+}
+
+/// The debugger `--debugger-cmd` runs, named after its program.
+pub fn from_command_line(line: &str) -> Result<Defined> {
+    let mut defined = Defined::command_line("--debugger-cmd", line, "--debugger-cmd")?;
+    if let Some(name) = defined.program.file_stem() {
+        defined.name = name.to_string_lossy().into_owned();
+    }
+    template::expand_args(
+        &defined.args,
+        &Values {
+            bin: "",
+            args: &[],
+            cwd: "",
+            name: "",
+        },
+        "--debugger-cmd",
+    )?;
+
+    Ok(defined)
+}
+
+fn check_name(name: &str, source: &str) -> Result<()> {
+    if Debugger::KNOWN.iter().any(|d| d.name() == name) {
+        bail!("{name} in {source} is a built-in debugger, define it under another name");
+    }
+
+    Ok(())
+}
+
+/// The debuggers in the `debuggers` table of `metadata`'s `cargo-debug`
+/// table, which is in `source`.
+fn parse(metadata: &serde_json::Value, source: &str) -> Result<BTreeMap<String, Defined>> {
+    let Some(table) = metadata.get("cargo-debug").and_then(|m| m.get("debuggers")) else {
+        return Ok(BTreeMap::new());
+    };
+    let table = table
+        .as_object()
+        .with_context(|| format!("cargo-debug.debuggers in {source} is not a table"))?;
+
+    let mut defined = BTreeMap::new();
+    for (name, entry) in table {
+        check_name(name, source)?;
+        if let Some(line) = entry.as_str() {
+            defined.insert(name.clone(), Defined::command_line(name, line, source)?);
+            continue;
+        }
+        let program = entry
+            .get("program")
+            .and_then(|p| p.as_str())
+            .with_context(|| format!("The {name} debugger in {source} has no program"))?;
+        let args = match entry.get("args") {
+            Some(args) => args
+                .as_array()
+                .and_then(|args| args.iter().map(|a| a.as_str().map(String::from)).collect())
+                .with_context(|| {
+                    format!("The {name} debugger's args in {source} are not a list of strings")
+                })?,
+            None => vec!["{bin}".to_string(), "{args}".to_string()],
+        };
+        defined.insert(
+            name.clone(),
+            Defined {
+                name: name.clone(),
+                program: PathBuf::from(program),
+                args,
+                source: source.to_string(),
+            },
+        );
+    }
+
+    Ok(defined)
+}
+
+/// A TOML string value, basic (`"..."`, with escapes) or literal (`'...'`).
+fn toml_string(value: &str) -> Option<String> {
+    let mut chars = value.chars();
+    let quote = chars.next().filter(|&c| c == '"' || c == '\'')?;
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            c if c == quote => return Some(string),
+            '\\' if quote == '"' => string.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                c => c,
+            }),
+            c => string.push(c),
+        }
+    }
+}
+
+/// The string values in the TOML `table`, e.g. `debug.debuggers`, by their
+/// key in it: set under `[debug.debuggers]`, or as `debuggers.rr` under
+/// `[debug]`.
+fn toml_strings(toml: &str, table: &str) -> BTreeMap<String, String> {
+    let mut strings = BTreeMap::new();
+    let mut header = String::new();
+    for line in toml.lines().map(str::trim) {
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            header = name
+                .split(']')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = match header.as_str() {
+            "" => key.trim().to_string(),
+            header => format!("{header}.{}", key.trim()),
+        };
+        let Some(name) = key.strip_prefix(table).and_then(|k| k.strip_prefix('.')) else {
+            continue;
+        };
+        if let Some(value) = toml_string(value.trim()) {
+            strings.insert(name.trim_matches('"').to_string(), value);
+        }
+    }
+
+    strings
+}
+
+/// The debuggers the file at `path` defines in `table`, if it exists.
+fn from_file(path: &Path, table: &str) -> Result<BTreeMap<String, Defined>> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Ok(BTreeMap::new());
+    };
+
+    let source = path.display().to_string();
+    let mut defined = BTreeMap::new();
+    for (name, line) in toml_strings(&contents, table) {
+        check_name(&name, &source)?;
+        let debugger = Defined::command_line(&name, &line, &source)?;
+        defined.insert(name, debugger);
+    }
+
+    Ok(defined)
+}
+
+/// The debugger defined as `name` for `metadata`'s workspace, if any.
+pub fn find(metadata: &Metadata, name: &str) -> Result<Option<Defined>> {
+    let workspace = metadata.workspace_root.join("Cargo.toml");
+    let mut defined = parse(&metadata.workspace_metadata, workspace.as_str())?;
+    if let Some(package) = metadata.root_package() {
+        defined.extend(parse(&package.metadata, package.manifest_path.as_str())?);
+    }
+    // Most specific first
+    for file in cargoconfig::current_files().iter().rev() {
+        defined.extend(from_file(file, "debug.debuggers")?);
+    }
+    let local = metadata.workspace_root.join(LOCAL_FILE);
+    defined.extend(from_file(local.as_std_path(), "debuggers")?);
+
+    Ok(defined.remove(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn defined_debuggers() {
+        let metadata = serde_json::json!({
+            "cargo-debug": {
+                "debuggers": {
+                    "mygdb": {
+                        "program": "/opt/mygdb/bin/gdb",
+                        "args": ["-q", "--args", "{bin}", "{args}"]
+                    },
+                    "tracer": { "program": "tracer" }
+                }
+            }
+        });
+        let defined = parse(&metadata, "Cargo.toml").unwrap();
+        assert_eq!(defined.keys().collect::<Vec<_>>(), ["mygdb", "tracer"]);
+
+        let args = ["-v".to_string()];
+        let values = Values {
+            bin: "/foo/target/debug/foo",
+            args: &args,
+            cwd: "/foo",
+            name: "foo",
+        };
+        let (program, args) = defined["mygdb"].command(&values).unwrap();
+        assert_eq!(program, PathBuf::from("/opt/mygdb/bin/gdb"));
+        assert_eq!(args, ["-q", "--args", "/foo/target/debug/foo", "-v"]);
+        let (program, args) = defined["tracer"].command(&values).unwrap();
+        assert_eq!(program, PathBuf::from("tracer"));
+        assert_eq!(args, ["/foo/target/debug/foo", "-v"]);
+
+        assert!(parse(&serde_json::json!({}), "Cargo.toml")
+            .unwrap()
+            .is_empty());
+        let builtin =
+            serde_json::json!({"cargo-debug": {"debuggers": {"gdb": {"program": "gdb"}}}});
+        assert!(parse(&builtin, "Cargo.toml").is_err());
+        let no_program = serde_json::json!({"cargo-debug": {"debuggers": {"mygdb": {}}}});
+        assert!(parse(&no_program, "Cargo.toml").is_err());
+        let bad_args = serde_json::json!({"cargo-debug": {"debuggers": {"mygdb": {"program": "gdb", "args": "{bin}"}}}});
+        assert!(parse(&bad_args, "Cargo.toml").is_err());
+    }
+
+    #[test]
+    fn command_line_debuggers() {
+        let config = r#"
+[build]
+target-dir = "target"
+
+# debuggers.ignored = "not in [debug]"
+[debug]
+debuggers.rr = "rr record {bin} {args}"
+
+[debug.debuggers]
+cgdb = 'cgdb --args "{bin}" {args}'
+"arm-gdb" = "arm-none-eabi-gdb -ex \"target remote :3333\" {bin}"
+"#;
+        let strings = toml_strings(config, "debug.debuggers");
+        assert_eq!(
+            strings.keys().collect::<Vec<_>>(),
+            ["arm-gdb", "cgdb", "rr"]
+        );
+        assert_eq!(strings["cgdb"], r#"cgdb --args "{bin}" {args}"#);
+        assert_eq!(
+            toml_strings("[debuggers]\nrr = 'rr'\n", "debuggers")["rr"],
+            "rr"
+        );
+
+        let args = ["my case".to_string()];
+        let values = Values {
+            bin: "/my dir/target/debug/foo",
+            args: &args,
+            cwd: "/my dir",
+            name: "foo",
+        };
+        let arm = Defined::command_line("arm-gdb", &strings["arm-gdb"], "config.toml").unwrap();
+        let (program, arm_args) = arm.command(&values).unwrap();
+        assert_eq!(program, PathBuf::from("arm-none-eabi-gdb"));
+        assert_eq!(
+            arm_args,
+            ["-ex", "target remote :3333", "/my dir/target/debug/foo"]
+        );
+
+        // Metadata can define one with a command line too
+        let metadata = serde_json::json!({"cargo-debug": {"debuggers": {"rr": strings["rr"]}}});
+        let rr = &parse(&metadata, "Cargo.toml").unwrap()["rr"];
+        let (program, rr_args) = rr.command(&values).unwrap();
+        assert_eq!(program, PathBuf::from("rr"));
+        assert_eq!(rr_args, ["record", "/my dir/target/debug/foo", "my case"]);
+
+        let cli = from_command_line("rust-gdb --args {bin} {args}").unwrap();
+        assert_eq!(cli.name, "rust-gdb");
+        assert!(from_command_line("rr record").is_err());
+        assert!(from_command_line("rr record '{bin}").is_err());
+        assert!(from_command_line("").is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! Finding split debug info and pointing the debugger at it.
+//!
+//! With `split-debuginfo = "packed"` (or `"unpacked"`), the symbols are in a
+//! `.dSYM` bundle, a `.dwp` package or a PDB rather than the executable
+//! itself. Debuggers usually find them next to the executable, but not in
+//! every setup, so they are passed explicitly.
+//!
+//! On macOS, `"unpacked"` leaves the DWARF in the object files, and lldb
+//! only finds line info once `dsymutil` has gathered it into a `.dSYM`, so
+//! that's run first when the bundle is missing or out of date.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Instant, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use cargo_metadata::Artifact;
+use log::{info, trace, warn};
+
+use crate::{find_executable, Debugger};
+
+fn is_split_debuginfo(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("dwp" | "dSYM" | "pdb")
+    )
+}
+
+/// Where rustc puts split debug info for `exe`, on any platform.
+fn conventional(exe: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if let Some(name) = exe.file_name() {
+        let name = name.to_string_lossy();
+        paths.push(exe.with_file_name(format!("{name}.dwp")));
+        paths.push(exe.with_file_name(format!("{name}.dSYM")));
+    }
+    // PDBs are named after the crate, with underscores
+    if let Some(stem) = exe.file_stem() {
+        let stem = stem.to_string_lossy().replace('-', "_");
+        paths.push(exe.with_file_name(format!("{stem}.pdb")));
+    }
+
+    paths
+}
+
+/// The split debug info for the artifact: what cargo reported, which must
+/// exist, and anything else in the conventional places.
+fn locate(
+    filenames: &[PathBuf],
+    exe: &Path,
+    exists: impl Fn(&Path) -> bool,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let reported = filenames
+        .iter()
+        .filter(|f| is_split_debuginfo(f))
+        .cloned()
+        .collect::<Vec<_>>();
+    let (mut found, missing): (Vec<_>, Vec<_>) = reported.into_iter().partition(|f| exists(f));
+
+    for path in conventional(exe) {
+        if !found.contains(&path) && !missing.contains(&path) && exists(&path) {
+            found.push(path);
+        }
+    }
+
+    (found, missing)
+}
+
+/// Whether `header` is the start of a Mach-O file, thin or universal.
+fn is_macho(header: &[u8]) -> bool {
+    matches!(
+        header.get(..4),
+        Some(
+            [0xfe, 0xed, 0xfa, 0xce | 0xcf]
+                | [0xce | 0xcf, 0xfa, 0xed, 0xfe]
+                | [0xca, 0xfe, 0xba, 0xbe]
+        )
+    )
+}
+
+/// The DWARF inside the `.dSYM` bundle `dsymutil` writes for `exe`.
+fn dsym_dwarf(exe: &Path) -> Option<PathBuf> {
+    let name = exe.file_name()?.to_string_lossy();
+    Some(
+        exe.with_file_name(format!("{name}.dSYM"))
+            .join("Contents/Resources/DWARF")
+            .join(&*name),
+    )
+}
+
+/// Whether a `.dSYM` last written at `dsym` (if at all) predates the
+/// executable, built at `built`.
+fn dsym_stale(built: SystemTime, dsym: Option<SystemTime>) -> bool {
+    dsym.is_none_or(|dsym| dsym < built)
+}
+
+/// Run `dsymutil` on a Mach-O `exe` whose `.dSYM` is missing or older than
+/// it, so lldb can find its line info.
+pub fn dsymutil(exe: &Path) -> Result<()> {
+    let mut header = [0; 4];
+    let is_macho = fs::File::open(exe)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .is_ok_and(|()| is_macho(&header));
+    let Some(dwarf) = dsym_dwarf(exe).filter(|_| is_macho) else {
+        return Ok(());
+    };
+
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    let Some(built) = modified(exe) else {
+        return Ok(());
+    };
+    if !dsym_stale(built, modified(&dwarf)) {
+        trace!("{} is up to date", dwarf.display());
+        return Ok(());
+    }
+
+    let Some(dsymutil) = find_executable(Path::new("dsymutil")) else {
+        bail!(
+            "{} has no up-to-date .dSYM and dsymutil was not found; \
+             install the Xcode command line tools with `xcode-select --install`, \
+             or pass --no-dsymutil to debug without line info",
+            exe.display()
+        );
+    };
+
+    let start = Instant::now();
+    let status = Command::new(&dsymutil)
+        .arg(exe)
+        .status()
+        .with_context(|| format!("Could not run {}", dsymutil.display()))?;
+    if !status.success() {
+        bail!("dsymutil failed on {}", exe.display());
+    }
+    info!(
+        "ran dsymutil on {} in {:.1?}",
+        exe.display(),
+        start.elapsed()
+    );
+
+    Ok(())
+}
+
+/// Flags and startup commands loading `files` into the debugger.
+fn debugger_setup(debugger: &Debugger, files: &[PathBuf]) -> (Vec<String>, Vec<String>) {
+    let mut flags = vec![];
+    let mut commands = vec![];
+
+    for file in files {
+        let dir = file.parent().unwrap_or(Path::new(".")).display();
+        match debugger {
+            // gdb looks for `<exe>.dwp` in the debug file directories, before loading the binary
+            Debugger::Gdb if file.extension().is_some_and(|e| e == "dwp") => {
+                flags.push("-iex".to_string());
+                flags.push(format!("set debug-file-directory {dir}:/usr/lib/debug"));
+            }
+            Debugger::Lldb => commands.push(format!("target symbols add \"{}\"", file.display())),
+            Debugger::Windbg if file.extension().is_some_and(|e| e == "pdb") => {
+                flags.push("-y".to_string());
+                flags.push(dir.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    (flags, commands)
+}
+
+/// Flags and startup commands pointing the debugger at the artifact's split
+/// debug info, warning about any that cargo reported but can't be found.
+pub fn setup(debugger: &Debugger, artifact: &Artifact) -> (Vec<String>, Vec<String>) {
+    let Some(exe) = &artifact.executable else {
+        return (vec![], vec![]);
+    };
+    let filenames = artifact
+        .filenames
+        .iter()
+        .map(|f| f.as_std_path().to_path_buf())
+        .collect::<Vec<_>>();
+
+    let (found, missing) = locate(&filenames, exe.as_std_path(), Path::exists);
+    for path in missing {
+        warn!(
+            "cargo reported split debug info at {}, but it doesn't exist; \
+             the debugger may not find {}'s symbols",
+            path.display(),
+            artifact.target.name
+        );
+    }
+    for path in &found {
+        info!("split debug info: {}", path.display());
+    }
+
+    debugger_setup(debugger, &found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locate_split_debuginfo() {
+        let exe = Path::new("/foo/target/debug/foo-bar");
+        let filenames = [
+            exe.to_path_buf(),
+            PathBuf::from("/foo/target/debug/foo-bar.dwp"),
+            PathBuf::from("/foo/target/debug/foo-bar.dSYM"),
+        ];
+        let exists = |p: &Path| p.ends_with("foo-bar") || p.ends_with("foo-bar.dwp");
+
+        let (found, missing) = locate(&filenames, exe, exists);
+        assert_eq!(found, [PathBuf::from("/foo/target/debug/foo-bar.dwp")]);
+        assert_eq!(missing, [PathBuf::from("/foo/target/debug/foo-bar.dSYM")]);
+
+        // Found where rustc puts it, even if cargo didn't say so
+        let exists = |p: &Path| p.ends_with("foo_bar.pdb");
+        let (found, _) = locate(&[], exe, exists);
+        assert_eq!(found, [PathBuf::from("/foo/target/debug/foo_bar.pdb")]);
+
+        let (flags, commands) = debugger_setup(
+            &Debugger::Gdb,
+            &[PathBuf::from("/foo/target/debug/foo-bar.dwp")],
+        );
+        assert_eq!(
+            flags,
+            [
+                "-iex",
+                "set debug-file-directory /foo/target/debug:/usr/lib/debug"
+            ]
+        );
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn dsym_freshness() {
+        assert!(is_macho(&[0xcf, 0xfa, 0xed, 0xfe]));
+        assert!(is_macho(&[0xca, 0xfe, 0xba, 0xbe]));
+        assert!(!is_macho(b"\x7fELF"));
+        assert!(!is_macho(b"MZ"));
+
+        assert_eq!(
+            dsym_dwarf(Path::new("/foo/target/debug/foo")),
+            Some(PathBuf::from(
+                "/foo/target/debug/foo.dSYM/Contents/Resources/DWARF/foo"
+            ))
+        );
+
+        let built = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(100);
+        assert!(dsym_stale(built, None));
+        assert!(dsym_stale(built, Some(SystemTime::UNIX_EPOCH)));
+        assert!(!dsym_stale(built, Some(built)));
+    }
+}
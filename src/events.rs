@@ -0,0 +1,149 @@
+//! The event stream written with `--message-format json`.
+//!
+//! Like cargo's own `--message-format json`, each event is a JSON object on
+//! its own line of stdout, with a `reason` naming it:
+//!
+//! - `build-started`: the cargo `command` being run
+//! - `compiler-message`: the `level`, `message` and `rendered` form of each
+//!   diagnostic cargo reports
+//! - `build-finished`: whether it was a `success`, and how many `artifacts`
+//!   and `errors` it produced
+//! - `artifact-selected`: the `target` name and `executable` to debug
+//! - `debugger-resolved`: the `debugger`, and the `program` and `args` run
+//! - `debugger-launched`: the debugger's `pid`
+//! - `debugger-exited`: whether it was a `success`, and its exit `code`,
+//!   which is null if it was killed by a signal
+//!
+//! Every event carries the `schema_version` of [`crate::json`].
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use cargo_metadata::diagnostic::{Diagnostic, DiagnosticLevel};
+use cargo_metadata::Artifact;
+use serde_json::{json, Value};
+
+use crate::json::SCHEMA_VERSION;
+use crate::Debugger;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Human-readable output
+    #[default]
+    Human,
+    /// One JSON event per line on stdout
+    Json,
+}
+
+/// Where events go, if anywhere.
+#[derive(Default)]
+pub struct Events {
+    out: Option<Box<dyn Write>>,
+}
+
+fn level_name(level: &DiagnosticLevel) -> &'static str {
+    match level {
+        DiagnosticLevel::Ice => "error: internal compiler error",
+        DiagnosticLevel::Error => "error",
+        DiagnosticLevel::Warning => "warning",
+        DiagnosticLevel::FailureNote => "failure-note",
+        DiagnosticLevel::Note => "note",
+        _ => "help",
+    }
+}
+
+impl Events {
+    pub fn new(format: MessageFormat) -> Self {
+        match format {
+            MessageFormat::Human => Self::default(),
+            MessageFormat::Json => Self::to(Box::new(io::stdout())),
+        }
+    }
+
+    /// Write events to `out`.
+    pub fn to(out: Box<dyn Write>) -> Self {
+        Self { out: Some(out) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.out.is_some()
+    }
+
+    fn emit(&mut self, event: Value) {
+        let Some(out) = &mut self.out else {
+            return;
+        };
+
+        // A reader that went away shouldn't take the session down with it
+        let _ = writeln!(out, "{}", serde_json::to_string(&event).unwrap_or_default());
+        let _ = out.flush();
+    }
+
+    pub fn build_started(&mut self, cargo: &Command) {
+        let mut command = vec![cargo.get_program().to_string_lossy().into_owned()];
+        command.extend(cargo.get_args().map(|a| a.to_string_lossy().into_owned()));
+
+        self.emit(json!({
+            "reason": "build-started",
+            "schema_version": SCHEMA_VERSION,
+            "command": command,
+        }));
+    }
+
+    pub fn compiler_message(&mut self, diagnostic: &Diagnostic) {
+        self.emit(json!({
+            "reason": "compiler-message",
+            "schema_version": SCHEMA_VERSION,
+            "level": level_name(&diagnostic.level),
+            "message": diagnostic.message,
+            "rendered": diagnostic.rendered,
+        }));
+    }
+
+    pub fn build_finished(&mut self, success: bool, artifacts: usize, errors: usize) {
+        self.emit(json!({
+            "reason": "build-finished",
+            "schema_version": SCHEMA_VERSION,
+            "success": success,
+            "artifacts": artifacts,
+            "errors": errors,
+        }));
+    }
+
+    pub fn artifact_selected(&mut self, artifact: &Artifact) {
+        self.emit(json!({
+            "reason": "artifact-selected",
+            "schema_version": SCHEMA_VERSION,
+            "target": artifact.target.name,
+            "executable": artifact.executable,
+        }));
+    }
+
+    pub fn debugger_resolved(&mut self, debugger: &Debugger, program: &Path, args: &[String]) {
+        self.emit(json!({
+            "reason": "debugger-resolved",
+            "schema_version": SCHEMA_VERSION,
+            "debugger": debugger.name(),
+            "program": program,
+            "args": args,
+        }));
+    }
+
+    pub fn debugger_launched(&mut self, pid: u32) {
+        self.emit(json!({
+            "reason": "debugger-launched",
+            "schema_version": SCHEMA_VERSION,
+            "pid": pid,
+        }));
+    }
+
+    pub fn debugger_exited(&mut self, status: &ExitStatus) {
+        self.emit(json!({
+            "reason": "debugger-exited",
+            "schema_version": SCHEMA_VERSION,
+            "success": status.success(),
+            "code": status.code(),
+        }));
+    }
+}
@@ -0,0 +1,83 @@
+//! Falling back to lldb on macOS when gdb can't debug there.
+//!
+//! gdb has to be code-signed to control processes on macOS, which
+//! Homebrew's isn't, and it doesn't run arm64 programs at all, while lldb
+//! comes with the Xcode command line tools and just works. So gdb is tried
+//! on `/usr/bin/true` first, and lldb used instead if that fails.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use log::trace;
+
+use crate::{find_executable, Debugger};
+
+/// What gdb says when it can't run a program on macOS.
+const FAILURES: [&str; 3] = [
+    // Not code-signed
+    "please check gdb is codesigned",
+    "Unable to find Mach task port",
+    // Apple Silicon
+    "Don't know how to run",
+];
+
+/// Whether gdb's output from running a program says it couldn't.
+fn failed(output: &str) -> bool {
+    FAILURES.iter().any(|failure| output.contains(failure))
+}
+
+/// Whether gdb is installed and can run a program.
+fn gdb_works() -> bool {
+    if find_executable(Path::new("gdb")).is_none() {
+        return false;
+    }
+
+    let probe = Command::new("gdb")
+        .args(["-nx", "-batch", "-ex", "run", "/usr/bin/true"])
+        .stdin(Stdio::null())
+        .output();
+    match probe {
+        Ok(output) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            trace!("gdb probe: {text}");
+            !failed(&text)
+        }
+        Err(_) => false,
+    }
+}
+
+/// `debugger`, or lldb if it's gdb and gdb can't debug on this Mac.
+pub fn macos(debugger: Debugger) -> Debugger {
+    if debugger != Debugger::Gdb || gdb_works() {
+        return debugger;
+    }
+    if find_executable(Path::new("lldb")).is_none() {
+        eprintln!(
+            "cargo-debug: gdb can't debug on this Mac, install lldb with \
+             `xcode-select --install`"
+        );
+        return debugger;
+    }
+
+    eprintln!(
+        "cargo-debug: gdb can't debug on this Mac (missing, not code-signed, or on \
+         Apple Silicon), using lldb instead; pass --no-lldb-fallback to keep gdb"
+    );
+    Debugger::Lldb
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gdb_probe() {
+        assert!(failed(
+            "Unable to find Mach task port for process-id 4242: (os/kern) failure (0x5).\n \
+             (please check gdb is codesigned - see taskgated(8))"
+        ));
+        assert!(failed("Don't know how to run.  Try \"help target\"."));
+        assert!(!failed("[Inferior 1 (process 4242) exited normally]"));
+    }
+}
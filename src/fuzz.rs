@@ -0,0 +1,238 @@
+//! Debugging a cargo-fuzz target on an input it crashed with (`--fuzz`).
+//!
+//! cargo-fuzz keeps its targets in a `fuzz/` crate of their own, built on
+//! nightly for the host triple, with `RUSTFLAGS` adding libFuzzer's
+//! coverage instrumentation and a sanitizer. This builds them the same way,
+//! then runs the target on the one input with `-runs=1`, which is how
+//! libFuzzer replays a crash.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use log::info;
+
+use crate::coverage::append_flag;
+use crate::{host_triple, metadata, BuildArgs};
+
+/// What ASan does on a report, so the debugger stops at it rather than
+/// libFuzzer exiting.
+pub const ASAN_OPTIONS: &str = "abort_on_error=1";
+
+/// The sanitizers cargo-fuzz builds with.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sanitizer {
+    Address,
+    Leak,
+    Memory,
+    Thread,
+    None,
+}
+
+impl Sanitizer {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "address" => Some(Sanitizer::Address),
+            "leak" => Some(Sanitizer::Leak),
+            "memory" => Some(Sanitizer::Memory),
+            "thread" => Some(Sanitizer::Thread),
+            "none" => Some(Sanitizer::None),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Leak => "leak",
+            Sanitizer::Memory => "memory",
+            Sanitizer::Thread => "thread",
+            Sanitizer::None => "none",
+        }
+    }
+}
+
+/// The `fuzz/Cargo.toml` next to `manifest`, or else in the current
+/// directory or one of its parents.
+fn fuzz_manifest(manifest: Option<&str>) -> Result<PathBuf> {
+    let start = match manifest {
+        Some(manifest) => Path::new(manifest)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+        None => env::current_dir()?,
+    };
+
+    start
+        .ancestors()
+        .map(|dir| dir.join("fuzz").join("Cargo.toml"))
+        .find(|path| path.is_file())
+        .context("There is no fuzz/Cargo.toml here or in any parent directory, run `cargo fuzz init` first")
+}
+
+/// The rustflags cargo-fuzz builds with, for `sanitizer` on `triple`.
+fn rustflags(sanitizer: Sanitizer, triple: &str) -> Vec<String> {
+    let mut flags = vec![
+        "-Cpasses=sancov-module",
+        "-Cllvm-args=-sanitizer-coverage-level=4",
+        "-Cllvm-args=-sanitizer-coverage-inline-8bit-counters",
+        "-Cllvm-args=-sanitizer-coverage-pc-table",
+        "-Cllvm-args=-sanitizer-coverage-trace-compares",
+        "--cfg",
+        "fuzzing",
+        "-Cdebug-assertions",
+    ];
+    if triple.contains("-linux-") {
+        flags.push("-Cllvm-args=-sanitizer-coverage-stack-depth");
+    }
+    if sanitizer != Sanitizer::None {
+        flags.push(match sanitizer {
+            Sanitizer::Address => "-Zsanitizer=address",
+            Sanitizer::Leak => "-Zsanitizer=leak",
+            Sanitizer::Memory => "-Zsanitizer=memory",
+            _ => "-Zsanitizer=thread",
+        });
+    }
+    if sanitizer == Sanitizer::Memory {
+        flags.push("-Zsanitizer-memory-track-origins");
+    }
+
+    flags.into_iter().map(String::from).collect()
+}
+
+/// The sanitizer `[package.metadata.cargo-debug] fuzz-sanitizer` names in
+/// the fuzz crate's manifest, given as `metadata`.
+fn configured_sanitizer(metadata: &serde_json::Value) -> Result<Option<Sanitizer>> {
+    let Some(name) = metadata
+        .get("cargo-debug")
+        .and_then(|m| m.get("fuzz-sanitizer"))
+    else {
+        return Ok(None);
+    };
+    match name.as_str().and_then(Sanitizer::parse) {
+        Some(sanitizer) => Ok(Some(sanitizer)),
+        None => bail!(
+            "fuzz-sanitizer = {name} in fuzz/Cargo.toml is not one of address, leak, memory, thread or none"
+        ),
+    }
+}
+
+/// Set `build` up to build the fuzz target `target` the way cargo-fuzz
+/// does, returning the arguments replaying `input` with it. `sanitizer`
+/// overrides the fuzz crate's.
+///
+/// Like `--coverage-instrument`, this changes the environment of this
+/// process, so cargo inherits it.
+pub fn prepare(
+    build: &mut BuildArgs,
+    target: &str,
+    input: &Path,
+    sanitizer: Option<Sanitizer>,
+) -> Result<Vec<String>> {
+    if !input.is_file() {
+        bail!("--input {} does not exist", input.display());
+    }
+    let input = input
+        .to_str()
+        .context("The --input path is not valid UTF-8")?
+        .to_string();
+
+    let manifest = fuzz_manifest(build.manifests.first().map(String::as_str))?;
+    build.manifests = vec![manifest
+        .to_str()
+        .context("The fuzz/Cargo.toml path is not valid UTF-8")?
+        .to_string()];
+
+    let metadata = metadata(build)?;
+    let package = metadata
+        .root_package()
+        .with_context(|| format!("{} has no package", manifest.display()))?;
+    if !package.targets.iter().any(|t| t.name == target) {
+        let names = package
+            .targets
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        bail!(
+            "There is no fuzz target {target}, the targets are {}",
+            names.join(", ")
+        );
+    }
+    let sanitizer = match sanitizer {
+        Some(sanitizer) => sanitizer,
+        None => configured_sanitizer(&package.metadata)?.unwrap_or(Sanitizer::Address),
+    };
+
+    // The flags would reach build scripts and proc macros without a target
+    let triple = match &build.target {
+        Some(triple) => triple.clone(),
+        None => host_triple()?,
+    };
+    build.target = Some(triple.clone());
+    build.bin = Some(target.to_string());
+
+    info!(
+        "building fuzz target {target} for {triple} with the {} sanitizer",
+        sanitizer.name()
+    );
+    let flags = rustflags(sanitizer, &triple);
+    // cargo ignores RUSTFLAGS when the encoded form is set
+    if let Ok(existing) = env::var("CARGO_ENCODED_RUSTFLAGS") {
+        env::set_var(
+            "CARGO_ENCODED_RUSTFLAGS",
+            append_flag(Some(existing), '\x1f', &flags.join("\x1f")),
+        );
+    } else {
+        env::set_var(
+            "RUSTFLAGS",
+            append_flag(env::var("RUSTFLAGS").ok(), ' ', &flags.join(" ")),
+        );
+    }
+    // cargo-fuzz needs nightly, leave a toolchain that's already picked
+    if env::var_os("RUSTUP_TOOLCHAIN").is_none() {
+        env::set_var("RUSTUP_TOOLCHAIN", "nightly");
+    }
+
+    Ok(vec![input, "-runs=1".to_string()])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fuzz_build() {
+        let flags = rustflags(Sanitizer::Address, "x86_64-unknown-linux-gnu");
+        assert_eq!(
+            flags[..2],
+            [
+                "-Cpasses=sancov-module",
+                "-Cllvm-args=-sanitizer-coverage-level=4"
+            ]
+        );
+        assert!(flags.windows(2).any(|w| w == ["--cfg", "fuzzing"]));
+        assert!(flags.contains(&"-Cllvm-args=-sanitizer-coverage-stack-depth".to_string()));
+        assert_eq!(flags.last().unwrap(), "-Zsanitizer=address");
+
+        let flags = rustflags(Sanitizer::None, "aarch64-apple-darwin");
+        assert!(!flags.iter().any(|f| f.starts_with("-Zsanitizer")));
+        assert!(!flags.iter().any(|f| f.contains("stack-depth")));
+        assert!(
+            rustflags(Sanitizer::Memory, "x86_64-unknown-linux-gnu").ends_with(&[
+                "-Zsanitizer=memory".to_string(),
+                "-Zsanitizer-memory-track-origins".to_string()
+            ])
+        );
+
+        let metadata =
+            serde_json::json!({"cargo-fuzz": true, "cargo-debug": {"fuzz-sanitizer": "thread"}});
+        assert_eq!(
+            configured_sanitizer(&metadata).unwrap(),
+            Some(Sanitizer::Thread)
+        );
+        let metadata = serde_json::json!({"cargo-fuzz": true});
+        assert_eq!(configured_sanitizer(&metadata).unwrap(), None);
+        let metadata = serde_json::json!({"cargo-debug": {"fuzz-sanitizer": "undefined"}});
+        assert!(configured_sanitizer(&metadata).is_err());
+    }
+}
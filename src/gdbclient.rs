@@ -0,0 +1,135 @@
+//! `--attach`: gdbserver with a local gdb connected to it, in one session.
+//!
+//! gdbserver runs in a process group of its own, so that Ctrl+C reaches only
+//! gdb, which interrupts the program through gdbserver rather than losing
+//! it. gdb connects with `target remote`, reading symbols from the local
+//! binary, and with `tcp auto-retry` keeps retrying a refused connection
+//! until gdbserver listens, for up to `tcp connect-timeout` (15 seconds by
+//! default). Whichever of the two exits first takes the other down.
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use log::{info, trace, warn};
+
+use crate::find_executable;
+
+/// The address gdb connects to for gdbserver listening on `listen`: the
+/// wildcard addresses are reached through localhost.
+pub fn client_address(listen: &str) -> Result<String> {
+    let Some((host, port)) = listen.rsplit_once(':') else {
+        bail!("--attach can't connect to gdbserver on {listen}, gdb needs a [HOST:]PORT");
+    };
+
+    Ok(match host {
+        "" | "0.0.0.0" | "[::]" | "::" => format!("localhost:{port}"),
+        _ => listen.to_string(),
+    })
+}
+
+/// The gdb to connect with: `explicit`, or gdb-multiarch for a `foreign`
+/// architecture when it's installed.
+pub fn client_program(explicit: Option<&Path>, foreign: bool) -> PathBuf {
+    if let Some(path) = explicit {
+        return path.to_path_buf();
+    }
+    if foreign {
+        if find_executable(Path::new("gdb-multiarch")).is_some() {
+            return PathBuf::from("gdb-multiarch");
+        }
+        warn!("gdb-multiarch not found, connecting with gdb; pass --client-gdb for another");
+    }
+
+    PathBuf::from("gdb")
+}
+
+/// The startup commands connecting gdb to `address`, once gdbserver listens
+/// there.
+pub fn connect_commands(address: &str) -> [String; 2] {
+    [
+        "set tcp auto-retry on".to_string(),
+        format!("target remote {address}"),
+    ]
+}
+
+/// Ask `child` to exit, letting it restore the terminal.
+fn terminate(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: signals a process this tool started and hasn't reaped
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    #[cfg(not(unix))]
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Run `server_cmd`, gdbserver, and `client`, gdb, until either exits.
+pub fn debug(mut server_cmd: Command, client: (&Path, &[String])) -> Result<()> {
+    // Out of the terminal's foreground process group, which Ctrl+C signals
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut server_cmd, 0);
+    let mut gdbserver = server_cmd.spawn().with_context(|| {
+        format!(
+            "Could not run {}",
+            server_cmd.get_program().to_string_lossy()
+        )
+    })?;
+
+    // gdb handles ctrl+c, and gdbserver has to outlive it until gdb exits
+    ctrlc::set_handler(|| {}).expect("Error setting Ctrl-C handler");
+
+    trace!("synthesized gdb arguments: {:?}", client.1);
+    let mut gdb = match Command::new(client.0).args(client.1).spawn() {
+        Ok(gdb) => gdb,
+        Err(e) => {
+            let _ = gdbserver.kill();
+            let _ = gdbserver.wait();
+            return Err(e).with_context(|| format!("Could not run {}", client.0.display()));
+        }
+    };
+
+    loop {
+        if gdb.try_wait()?.is_some() {
+            info!("gdb exited, stopping gdbserver");
+            terminate(&mut gdbserver);
+            return Ok(());
+        }
+        if let Some(status) = gdbserver.try_wait()? {
+            warn!("gdbserver exited ({status}), closing gdb");
+            terminate(&mut gdb);
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn client_addresses() {
+        assert_eq!(client_address("0.0.0.0:2345").unwrap(), "localhost:2345");
+        assert_eq!(client_address(":2345").unwrap(), "localhost:2345");
+        assert_eq!(client_address("[::]:2345").unwrap(), "localhost:2345");
+        assert_eq!(
+            client_address("192.168.1.20:2345").unwrap(),
+            "192.168.1.20:2345"
+        );
+        assert!(client_address("/dev/ttyUSB0").is_err());
+
+        assert_eq!(
+            client_program(Some(Path::new("/opt/arm/bin/arm-none-eabi-gdb")), true),
+            PathBuf::from("/opt/arm/bin/arm-none-eabi-gdb")
+        );
+        assert_eq!(client_program(None, false), PathBuf::from("gdb"));
+        assert_eq!(
+            connect_commands("localhost:2345"),
+            ["set tcp auto-retry on", "target remote localhost:2345"]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Detecting and loading GDB extensions such as pwndbg and GEF.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{bail, Result};
+use log::{info, warn};
+
+/// Extensions we know of, by the name that shows up in their `source` lines.
+const KNOWN: &[&str] = &["pwndbg", "gef", "peda"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Extension {
+    Pwndbg,
+    Gef,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl Extension {
+    fn name(&self) -> &'static str {
+        match self {
+            Extension::Pwndbg => "pwndbg",
+            Extension::Gef => "gef",
+        }
+    }
+
+    /// Where the extension's init script is usually installed.
+    fn candidates(&self, home: Option<PathBuf>) -> Vec<PathBuf> {
+        match self {
+            Extension::Pwndbg => {
+                let mut candidates = vec![];
+                if let Some(home) = home {
+                    candidates.push(home.join("pwndbg/gdbinit.py"));
+                    candidates.push(home.join(".pwndbg/gdbinit.py"));
+                }
+                candidates.push(PathBuf::from("/usr/share/pwndbg/gdbinit.py"));
+                candidates.push(PathBuf::from("/opt/pwndbg/gdbinit.py"));
+                candidates
+            }
+            Extension::Gef => {
+                let mut candidates = vec![];
+                if let Some(home) = home {
+                    // Where GEF's install script puts it
+                    candidates.push(home.join(".gdbinit-gef.py"));
+                    candidates.push(home.join("gef/gef.py"));
+                }
+                candidates.push(PathBuf::from("/usr/share/gef/gef.py"));
+                candidates.push(PathBuf::from("/opt/gef/gef.py"));
+                candidates
+            }
+        }
+    }
+
+    /// Ask Python where a pip installed copy of the extension is.
+    fn pip_location(&self) -> Option<PathBuf> {
+        // GEF is a single script, not a package
+        if *self == Extension::Gef {
+            return None;
+        }
+
+        let script = format!(
+            "import os, {0}; print(os.path.dirname(os.path.dirname({0}.__file__)))",
+            self.name()
+        );
+        let output = Command::new("python3")
+            .args(["-c", &script])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let root = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        Some(root.join("gdbinit.py"))
+    }
+
+    /// Debugger commands selecting a color theme.
+    pub fn theme_commands(&self, theme: &Theme) -> Vec<String> {
+        match self {
+            Extension::Pwndbg => {
+                let style = match theme {
+                    Theme::Dark => "monokai",
+                    Theme::Light => "solarized-light",
+                };
+                vec![format!("set syntax-highlight-style {style}")]
+            }
+            Extension::Gef => vec![],
+        }
+    }
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// The known extensions sourced by a `.gdbinit`, ignoring comments.
+fn sourced(gdbinit: &str) -> Vec<&'static str> {
+    KNOWN
+        .iter()
+        .copied()
+        .filter(|name| {
+            gdbinit
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.starts_with('#'))
+                .any(|l| l.starts_with("source") && l.contains(name))
+        })
+        .collect()
+}
+
+/// Arguments that make gdb load `extension`, which are empty if `~/.gdbinit`
+/// already loads it.
+pub fn load(extension: Extension) -> Result<Vec<String>> {
+    let name = extension.name();
+    let gdbinit = home()
+        .and_then(|h| std::fs::read_to_string(h.join(".gdbinit")).ok())
+        .unwrap_or_default();
+
+    let sourced = sourced(&gdbinit);
+    let others = sourced
+        .iter()
+        .filter(|n| **n != name)
+        .copied()
+        .collect::<Vec<_>>();
+    if !others.is_empty() {
+        warn!(
+            "~/.gdbinit also loads {}, which may conflict with {name}; \
+             consider loading a single extension with gdb's --init-command instead",
+            others.join(", ")
+        );
+    }
+
+    if sourced.contains(&name) {
+        info!("{name} is already loaded by ~/.gdbinit");
+        return Ok(vec![]);
+    }
+
+    let init = extension
+        .candidates(home())
+        .into_iter()
+        .find(|p| p.is_file())
+        .or_else(|| extension.pip_location().filter(|p| p.is_file()));
+    let Some(init) = init else {
+        bail!("Could not find {name}, is it installed?");
+    };
+
+    info!("loading {name} from {}", init.display());
+    Ok(vec![format!("--init-command={}", init.display())])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sourced_extensions() {
+        let gdbinit = "set disassembly-flavor intel\n\
+                       source /home/me/pwndbg/gdbinit.py\n\
+                       # source ~/.gdbinit-gef.py\n";
+        assert_eq!(sourced(gdbinit), ["pwndbg"]);
+
+        let gdbinit = "source ~/pwndbg/gdbinit.py\nsource ~/peda/peda.py\n";
+        assert_eq!(sourced(gdbinit), ["pwndbg", "peda"]);
+    }
+
+    #[test]
+    fn pwndbg_theme() {
+        assert_eq!(
+            Extension::Pwndbg.theme_commands(&Theme::Light),
+            ["set syntax-highlight-style solarized-light"]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Adding a `.gdb_index` section so gdb loads big binaries quickly.
+//!
+//! Without an index gdb scans all of the DWARF every time it loads a binary,
+//! which takes tens of seconds for large statically linked ones. The index
+//! is added with `gdb-add-index` when it's installed, or with what it runs,
+//! `gdb -batch -ex "save gdb-index"` then `objcopy --add-section`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Instant, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use log::{info, trace};
+use object::Object;
+
+use crate::find_executable;
+
+/// Sections gdb reads an index from.
+const INDEX_SECTIONS: [&str; 2] = [".gdb_index", ".debug_names"];
+
+/// The file recording when `exe` was last indexed, so it isn't read again.
+fn stamp(exe: &Path) -> Option<PathBuf> {
+    let name = exe.file_name()?.to_string_lossy();
+    Some(exe.with_file_name(format!("{name}.gdb-index-stamp")))
+}
+
+/// Whether a stamp last written at `stamp` (if at all) is as recent as the
+/// executable, built at `built`.
+fn stamped(built: SystemTime, stamp: Option<SystemTime>) -> bool {
+    stamp.is_some_and(|stamp| stamp >= built)
+}
+
+/// Whether `exe` already has an index.
+fn has_index(exe: &Path) -> Result<bool> {
+    let data = fs::read(exe).with_context(|| format!("Could not read {}", exe.display()))?;
+    let file = object::File::parse(data.as_slice())
+        .with_context(|| format!("Could not parse {}", exe.display()))?;
+
+    Ok(INDEX_SECTIONS
+        .iter()
+        .any(|name| file.section_by_name(name).is_some()))
+}
+
+/// What `gdb-add-index` runs: gdb writes the index to `dir`, and objcopy
+/// adds it to `exe` in place.
+fn manual_commands(exe: &Path, dir: &Path) -> Vec<(PathBuf, Vec<String>)> {
+    let name = exe.file_name().unwrap_or_default().to_string_lossy();
+    let index = dir.join(format!("{name}.gdb-index"));
+    let exe = exe.to_string_lossy().into_owned();
+
+    vec![
+        (
+            PathBuf::from("gdb"),
+            vec![
+                "-batch".to_string(),
+                "-nx".to_string(),
+                "-iex".to_string(),
+                "set auto-load no".to_string(),
+                "-ex".to_string(),
+                format!("save gdb-index {}", dir.display()),
+                exe.clone(),
+            ],
+        ),
+        (
+            PathBuf::from("objcopy"),
+            vec![
+                "--add-section".to_string(),
+                format!(".gdb_index={}", index.display()),
+                "--set-section-flags".to_string(),
+                ".gdb_index=readonly".to_string(),
+                exe.clone(),
+                exe,
+            ],
+        ),
+    ]
+}
+
+fn run(program: &Path, args: &[String]) -> Result<()> {
+    trace!("running {} {:?}", program.display(), args);
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Could not run {}", program.display()))?;
+    if !status.success() {
+        bail!("{} failed ({status})", program.display());
+    }
+
+    Ok(())
+}
+
+/// Add a gdb index to `exe`, unless it has one already.
+pub fn add(exe: &Path) -> Result<()> {
+    let stamp = stamp(exe);
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    let Some(built) = modified(exe) else {
+        return Ok(());
+    };
+    if stamped(built, stamp.as_deref().and_then(modified)) || has_index(exe)? {
+        trace!("{} is already indexed", exe.display());
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    if let Some(script) = find_executable(Path::new("gdb-add-index")) {
+        run(&script, &[exe.to_string_lossy().into_owned()])?;
+    } else {
+        let dir = env::temp_dir().join(format!("cargo-debug-gdb-index-{}", std::process::id()));
+        fs::create_dir_all(&dir).with_context(|| format!("Could not create {}", dir.display()))?;
+        let result = manual_commands(exe, &dir)
+            .iter()
+            .try_for_each(|(program, args)| run(program, args));
+        let _ = fs::remove_dir_all(&dir);
+        result.context("Could not add a gdb index, is binutils' objcopy installed?")?;
+    }
+    info!(
+        "indexed {} in {:.1?}; gdb reads the index instead of scanning its DWARF from now on",
+        exe.display(),
+        start.elapsed()
+    );
+
+    if let Some(stamp) = stamp {
+        fs::write(&stamp, "").with_context(|| format!("Could not write {}", stamp.display()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn index_freshness() {
+        let built = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        assert!(!stamped(built, None));
+        assert!(!stamped(built, Some(built - Duration::from_secs(1))));
+        assert!(stamped(built, Some(built)));
+
+        assert_eq!(
+            stamp(Path::new("/foo/target/debug/foo")),
+            Some(PathBuf::from("/foo/target/debug/foo.gdb-index-stamp"))
+        );
+
+        let commands = manual_commands(Path::new("/foo/target/debug/foo"), Path::new("/tmp/idx"));
+        assert_eq!(
+            commands[0].1.last().map(String::as_str),
+            Some("/foo/target/debug/foo")
+        );
+        assert_eq!(commands[0].1[5], "save gdb-index /tmp/idx");
+        assert_eq!(commands[1].1[1], ".gdb_index=/tmp/idx/foo.gdb-index");
+    }
+}
@@ -0,0 +1,48 @@
+//! Machine-readable output shared by the JSON reporting flags.
+
+use cargo_metadata::Artifact;
+use serde_json::{json, Value};
+
+/// Bumped whenever a field is removed or changes meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Describe a selected artifact.
+pub fn artifact(artifact: &Artifact, profile: &str, triple: &str) -> Value {
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "package_id": artifact.package_id,
+        "target": {
+            "name": artifact.target.name,
+            "kind": artifact.target.kind,
+        },
+        "executable": artifact.executable,
+        "profile": {
+            "name": profile,
+            "debuginfo": artifact.profile.debuginfo,
+            "opt_level": artifact.profile.opt_level,
+        },
+        "features": artifact.features,
+        "target_triple": triple,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test::artifact as build_artifact;
+
+    #[test]
+    fn describe_artifact() {
+        let artifact = build_artifact("foo", "bin", Some("/foo/target/debug/foo"));
+        let json = super::artifact(&artifact, "dev", "x86_64-unknown-linux-gnu");
+
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["target"]["name"], "foo");
+        assert_eq!(json["target"]["kind"][0], "bin");
+        assert_eq!(json["executable"], "/foo/target/debug/foo");
+        assert_eq!(json["profile"]["name"], "dev");
+        assert_eq!(json["profile"]["debuginfo"], 2);
+        assert_eq!(json["target_triple"], "x86_64-unknown-linux-gnu");
+    }
+}
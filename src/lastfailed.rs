@@ -0,0 +1,257 @@
+//! Finding the test that failed, to debug it in its test binary.
+//!
+//! cargo doesn't keep the results of `cargo test`, so the test binaries
+//! `cargo test --no-run` built are run again, and the tests libtest reports
+//! as `FAILED` are the ones to pick from. With `--from-json`, they come from
+//! the JSON a previous run printed instead
+//! (`cargo test -- -Z unstable-options --format json`), which names the
+//! tests but not their binaries, so each binary's `--list` says which ones
+//! it has.
+
+use std::io::BufRead;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use cargo_metadata::Artifact;
+
+use crate::{picker, testbin};
+
+/// A failed test, and the index of the test binary it's in.
+#[derive(Debug, PartialEq, Eq)]
+struct Failure {
+    test: String,
+    binary: usize,
+}
+
+/// The names of the tests that failed in libtest's plain output.
+fn failed_tests(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.strip_prefix("test ")?.strip_suffix(" ... FAILED"))
+        // `#[should_panic]` tests are reported as `NAME - should panic`
+        .map(|name| name.split(" - ").next().unwrap_or(name).to_string())
+        .collect()
+}
+
+/// The names of the tests that failed in libtest's JSON output.
+fn failed_tests_json(reader: impl BufRead) -> Result<Vec<String>> {
+    let mut tests = vec![];
+    for line in reader.lines() {
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(&line?) else {
+            continue;
+        };
+        let field = |name: &str| event.get(name).and_then(|v| v.as_str());
+        if field("type") == Some("test") && field("event") == Some("failed") {
+            if let Some(name) = field("name").filter(|name| !tests.iter().any(|t| t == name)) {
+                tests.push(name.to_string());
+            }
+        }
+    }
+
+    Ok(tests)
+}
+
+/// The tests a binary has, from `--list --format terse`.
+fn listed_tests(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.strip_suffix(": test"))
+        .map(String::from)
+        .collect()
+}
+
+/// Each of `tests` in each of the binaries listing it, given the listing of
+/// every binary. A test a binary doesn't list, e.g. one since renamed,
+/// isn't there to debug.
+fn map_to_binaries(tests: &[String], listings: &[Vec<String>]) -> Vec<Failure> {
+    tests
+        .iter()
+        .flat_map(|test| {
+            listings
+                .iter()
+                .enumerate()
+                .filter(|(_, listed)| listed.contains(test))
+                .map(|(binary, _)| Failure {
+                    test: test.clone(),
+                    binary,
+                })
+        })
+        .collect()
+}
+
+/// Run a test binary as `cargo test` does, from its package's directory.
+fn run_test_binary(artifact: &Artifact, args: &[&str]) -> Result<String> {
+    let exe = artifact
+        .executable
+        .as_ref()
+        .expect("test binaries are executable");
+    let dir = artifact
+        .manifest_path
+        .parent()
+        .unwrap_or(&artifact.manifest_path);
+    let output = Command::new(exe)
+        .args(args)
+        .current_dir(dir)
+        .env("CARGO_MANIFEST_DIR", dir)
+        .output()
+        .with_context(|| format!("Could not run {exe}"))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The harness arguments running only `test`, one thread at a time.
+fn harness_args(test: &str, options: &[String]) -> Vec<String> {
+    let mut args = testbin::harness_args(Some(test), options);
+    if !options.iter().any(|o| o.starts_with("--test-threads")) {
+        args.push("--test-threads=1".to_string());
+    }
+
+    args
+}
+
+/// The test binary of the test that failed, out of `artifacts` of `cargo
+/// test --no-run`, with the arguments running only that test, then `options`.
+pub fn select(
+    artifacts: Vec<Artifact>,
+    from_json: Option<&Path>,
+    options: &[String],
+) -> Result<(Artifact, Vec<String>)> {
+    let mut binaries = artifacts
+        .into_iter()
+        .filter(|a| a.profile.test && a.executable.is_some())
+        .collect::<Vec<_>>();
+    if binaries.is_empty() {
+        bail!("The build produced no test binaries");
+    }
+
+    let failures = match from_json {
+        Some(path) => {
+            let tests = if path == Path::new("-") {
+                failed_tests_json(std::io::stdin().lock())?
+            } else {
+                let file = std::fs::File::open(path)
+                    .with_context(|| format!("Could not open {}", path.display()))?;
+                failed_tests_json(std::io::BufReader::new(file))?
+            };
+            let listings = binaries
+                .iter()
+                .map(|b| {
+                    Ok(listed_tests(&run_test_binary(
+                        b,
+                        &["--list", "--format", "terse"],
+                    )?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let failures = map_to_binaries(&tests, &listings);
+            if failures.is_empty() && !tests.is_empty() {
+                bail!(
+                    "None of the failed tests ({}) are in the test binaries built",
+                    tests.join(", ")
+                );
+            }
+            failures
+        }
+        None => {
+            let mut failures = vec![];
+            for (binary, artifact) in binaries.iter().enumerate() {
+                eprintln!("cargo-debug: running {}", artifact.target.name);
+                let output = run_test_binary(artifact, &[])?;
+                failures.extend(
+                    failed_tests(&output)
+                        .into_iter()
+                        .map(|test| Failure { test, binary }),
+                );
+            }
+            failures
+        }
+    };
+
+    let failure = match failures.len() {
+        0 => bail!("No test failed"),
+        1 => &failures[0],
+        n => {
+            let choices = failures
+                .iter()
+                .map(|f| format!("{} ({})", f.test, binaries[f.binary].target.name))
+                .collect::<Vec<_>>();
+            &failures[picker::pick(&format!("{n} tests failed"), &choices)?]
+        }
+    };
+    eprintln!(
+        "cargo-debug: debugging {} in {}",
+        failure.test, binaries[failure.binary].target.name
+    );
+
+    let args = harness_args(&failure.test, options);
+    Ok((binaries.swap_remove(failure.binary), args))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn failures_to_binaries() {
+        let output = "\
+running 3 tests
+test parse::empty ... ok
+test parse::nested ... FAILED
+test panics - should panic ... FAILED
+
+failures:
+
+---- parse::nested stdout ----
+
+test result: FAILED. 1 passed; 2 failed; 0 ignored; 0 measured; 0 filtered out
+";
+        assert_eq!(failed_tests(output), ["parse::nested", "panics"]);
+
+        let json = r#"{ "type": "suite", "event": "started", "test_count": 2 }
+{ "type": "test", "event": "started", "name": "parse::nested" }
+{ "type": "test", "name": "parse::nested", "event": "failed", "stdout": "" }
+{ "type": "test", "name": "it_works", "event": "ok" }
+not json
+{ "type": "test", "name": "parse::nested", "event": "failed", "stdout": "" }
+{ "type": "test", "name": "tests::shared", "event": "failed" }
+"#;
+        let tests = failed_tests_json(json.as_bytes()).unwrap();
+        assert_eq!(tests, ["parse::nested", "tests::shared"]);
+
+        let lib = listed_tests("parse::empty: test\nparse::nested: test\ntests::shared: test\n");
+        let bin = listed_tests("tests::shared: test\nbench_parse: benchmark\n");
+        assert_eq!(bin, ["tests::shared"]);
+        assert_eq!(
+            map_to_binaries(&tests, &[lib, bin]),
+            [
+                Failure {
+                    test: "parse::nested".to_string(),
+                    binary: 0
+                },
+                Failure {
+                    test: "tests::shared".to_string(),
+                    binary: 0
+                },
+                Failure {
+                    test: "tests::shared".to_string(),
+                    binary: 1
+                },
+            ]
+        );
+        assert!(map_to_binaries(&["gone".to_string()], &[vec![]]).is_empty());
+
+        assert_eq!(
+            harness_args("parse::nested", &[]),
+            [
+                "parse::nested",
+                "--exact",
+                "--nocapture",
+                "--test-threads=1"
+            ]
+        );
+        assert_eq!(
+            harness_args("a", &["--test-threads=4".to_string()]),
+            ["a", "--exact", "--nocapture", "--test-threads=4"]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Finding the dynamic libraries the workspace builds.
+//!
+//! A program that links or `dlopen`s a `cdylib` or `dylib` from the same
+//! workspace only finds it in `target/debug` when it's run from the right
+//! directory. The directories they're built into are added to the loader's
+//! search path, and to gdb's, so they load and get symbols from anywhere.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use cargo_metadata::Artifact;
+
+use crate::Debugger;
+
+/// The crate types built as dynamic libraries.
+const DYNAMIC_KINDS: [&str; 2] = ["cdylib", "dylib"];
+
+/// The variable the platform's loader searches for libraries.
+pub fn search_var() -> &'static str {
+    if cfg!(windows) {
+        "PATH"
+    } else if cfg!(target_os = "macos") {
+        "DYLD_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    }
+}
+
+/// The directories holding the dynamic libraries among `artifacts`, in the
+/// order they were built.
+pub fn dirs(artifacts: &[Artifact]) -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    for artifact in artifacts {
+        if !artifact
+            .target
+            .kind
+            .iter()
+            .any(|k| DYNAMIC_KINDS.contains(&k.as_str()))
+        {
+            continue;
+        }
+
+        let libraries = artifact
+            .filenames
+            .iter()
+            .filter(|f| matches!(f.extension(), Some("so" | "dylib" | "dll")));
+        for library in libraries {
+            let Some(dir) = library.parent().map(|d| d.as_std_path().to_path_buf()) else {
+                continue;
+            };
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+
+    dirs
+}
+
+/// The loader's search path with `dirs` in front of what's `existing`.
+pub fn environment(dirs: &[PathBuf], existing: Option<OsString>) -> Option<(String, String)> {
+    if dirs.is_empty() {
+        return None;
+    }
+
+    let existing = existing.unwrap_or_default();
+    let existing = env::split_paths(&existing).filter(|p| !p.as_os_str().is_empty());
+    let paths = dirs.iter().cloned().chain(existing);
+    let joined = env::join_paths(paths).ok()?;
+
+    Some((
+        search_var().to_string(),
+        joined.to_string_lossy().into_owned(),
+    ))
+}
+
+/// Commands pointing `debugger` at `dirs` for the libraries' symbols.
+pub fn commands(debugger: &Debugger, dirs: &[PathBuf]) -> Vec<String> {
+    if dirs.is_empty() {
+        return vec![];
+    }
+
+    match debugger {
+        Debugger::Gdb => {
+            let paths = env::join_paths(dirs).unwrap_or_default();
+            vec![format!("set solib-search-path {}", paths.to_string_lossy())]
+        }
+        // lldb finds them through the loader
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cargo_metadata::camino::Utf8PathBuf;
+
+    use crate::test::artifact;
+
+    fn library(name: &str, kind: &str, filenames: &[&str]) -> Artifact {
+        let mut artifact = artifact(name, kind, None);
+        artifact.filenames = filenames.iter().map(|f| Utf8PathBuf::from(*f)).collect();
+        artifact
+    }
+
+    #[test]
+    fn library_dirs() {
+        let artifacts = vec![
+            library("dep", "lib", &["/foo/target/debug/deps/libdep-1234.rlib"]),
+            library(
+                "plugin",
+                "cdylib",
+                &[
+                    "/foo/target/debug/libplugin.so",
+                    "/foo/target/debug/libplugin.d",
+                ],
+            ),
+            library("shared", "dylib", &["/foo/target/debug/libshared.so"]),
+            library("ffi", "cdylib", &["/foo/target/debug/deps/libffi.dylib"]),
+            artifact("foo", "bin", Some("/foo/target/debug/foo")),
+        ];
+
+        let dirs = dirs(&artifacts);
+        assert_eq!(
+            dirs,
+            [
+                PathBuf::from("/foo/target/debug"),
+                PathBuf::from("/foo/target/debug/deps")
+            ]
+        );
+
+        #[cfg(unix)]
+        {
+            let (var, value) =
+                environment(&dirs[..1], Some(OsString::from("/usr/local/lib"))).unwrap();
+            assert_eq!(var, search_var());
+            assert_eq!(value, "/foo/target/debug:/usr/local/lib");
+            assert_eq!(
+                commands(&Debugger::Gdb, &dirs),
+                ["set solib-search-path /foo/target/debug:/foo/target/debug/deps"]
+            );
+        }
+        assert_eq!(environment(&[], None), None);
+        assert!(commands(&Debugger::Lldb, &dirs).is_empty());
+    }
+}
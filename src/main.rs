@@ -1,22 +1,162 @@
+mod alltargets;
+mod aslr;
+mod breakpoints;
+mod buildenv;
+mod buildid;
+mod cache;
+#[cfg(target_os = "linux")]
+mod capabilities;
+mod cargoconfig;
+mod cleanup;
+mod codesign;
+mod commandfile;
+mod companion;
+mod compare;
+mod complete;
+mod config;
+mod confirm;
+mod coredump;
+mod coverage;
+mod crashloop;
+mod crashscript;
+mod crashtrace;
+mod dap;
+mod debuggee;
+mod debuggers;
+mod debuginfo;
+mod events;
+mod fallback;
+mod fuzz;
+mod gdbclient;
+mod gdbext;
+mod gdbindex;
+mod json;
+mod lastfailed;
+mod libpath;
+mod minidump;
+mod monitor;
+mod natvis;
+mod nextest;
+mod notify;
+#[cfg(target_os = "linux")]
+mod oom;
+mod pathmap;
+#[cfg(any(windows, test))]
+#[cfg_attr(not(windows), allow(dead_code))]
+mod pdb;
+mod perf;
+mod persistent;
+mod picker;
+mod prettyprinters;
+mod procmacro;
+mod profile;
+mod quote;
+mod remote;
+mod repeat;
+mod repro;
+#[cfg(unix)]
+mod runas;
+mod sanitizer;
+mod script;
+mod serve;
+#[cfg(any(windows, test))]
+#[cfg_attr(not(windows), allow(dead_code))]
+mod service;
+mod sharedlibs;
+mod signal;
+mod sources;
+mod targetfeatures;
+mod template;
+mod terminal;
+mod testbin;
+mod timings;
+mod tmux;
+mod transcript;
+mod ulimit;
+mod version;
+mod vscode;
+mod waitattach;
+mod watch;
+mod winarch;
+#[cfg(any(windows, test))]
+#[cfg_attr(not(windows), allow(dead_code))]
+mod windbg;
+mod x11;
+
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use clap::Parser;
-use log::{error, info, trace, warn};
+use log::{debug, error, info, trace, warn};
 
-use cargo_metadata::Message;
+use cargo_metadata::camino::Utf8PathBuf;
+use cargo_metadata::diagnostic::{Diagnostic, DiagnosticLevel};
+use cargo_metadata::{Artifact, Message, Metadata, MetadataCommand, Package};
 
-#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+use buildenv::BuildEnv;
+use complete::TargetKind;
+use dap::DapAdapter;
+use events::{Events, MessageFormat};
+use tmux::Tmux;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum Debugger {
     Gdb,
     Gdbserver,
     Lldb,
     Devenv,
     Windbg,
+    /// Profile with `perf record` (or `perf stat`) instead of debugging
+    Perf,
+    /// Instrument with Frida, optionally with a script, instead of debugging
+    Frida,
+    /// Any other debugger, run as `<debugger> <bin> <options>`
+    Custom(PathBuf),
+}
+
+impl Debugger {
+    const KNOWN: [Debugger; 7] = [
+        Debugger::Gdb,
+        Debugger::Gdbserver,
+        Debugger::Lldb,
+        Debugger::Devenv,
+        Debugger::Windbg,
+        Debugger::Perf,
+        Debugger::Frida,
+    ];
+
+    fn name(&self) -> &str {
+        match self {
+            Debugger::Gdb => "gdb",
+            Debugger::Gdbserver => "gdbserver",
+            Debugger::Lldb => "lldb",
+            Debugger::Devenv => "devenv",
+            Debugger::Windbg => "windbg",
+            Debugger::Perf => "perf",
+            Debugger::Frida => "frida",
+            Debugger::Custom(path) => path.to_str().unwrap_or("custom"),
+        }
+    }
+}
+
+/// Parse a known debugger's name, treating anything else as the path or
+/// name of a custom one.
+fn parse_debugger(s: &str) -> Result<Debugger, String> {
+    if s.is_empty() {
+        return Err("expected a debugger".to_string());
+    }
+
+    Ok(Debugger::KNOWN
+        .into_iter()
+        .find(|d| d.name() == s)
+        .unwrap_or_else(|| Debugger::Custom(PathBuf::from(s))))
 }
 
 impl std::default::Default for Debugger {
@@ -31,18 +171,786 @@ impl std::default::Default for Debugger {
     }
 }
 
-#[derive(clap::Args)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    debugger: Option<Debugger>,
+/// The layouts built into GDB's TUI.
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+enum TuiLayout {
+    Src,
+    Asm,
+    Split,
+    Regs,
+    Next,
+    Prev,
+}
+
+impl TuiLayout {
+    fn name(&self) -> &'static str {
+        match self {
+            TuiLayout::Src => "src",
+            TuiLayout::Asm => "asm",
+            TuiLayout::Split => "split",
+            TuiLayout::Regs => "regs",
+            TuiLayout::Next => "next",
+            TuiLayout::Prev => "prev",
+        }
+    }
+}
+
+/// When the other threads are stopped while one runs, as in gdb's
+/// `scheduler-locking`.
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+enum SchedulerLocking {
+    /// While stepping
+    Step,
+    /// Whenever the program runs
+    On,
+}
+
+/// How many binaries an unscoped build may produce before we assume a mistake.
+const DEFAULT_MAX_ARTIFACTS: usize = 32;
+
+#[derive(clap::Args, Clone)]
+struct BuildArgs {
     #[clap(long)]
     release: bool,
+    /// Repeat it to build crates of several workspaces, one after the other, and pick from all of them
     #[clap(long = "manifest-path")]
-    manifest: Option<String>,
+    manifests: Vec<String>,
     #[clap(long = "example")]
     example: Option<String>,
     #[clap(long = "bin")]
     bin: Option<String>,
+    /// Build the integration test NAME and debug it; a test filter and harness flags go after `--`
+    #[clap(long, value_name = "NAME", conflicts_with_all = ["bin", "example", "bench"])]
+    test: Option<String>,
+    /// Build the tests and debug the one test executable, with `--lib` the library's unit tests
+    #[clap(long, conflicts_with_all = ["bin", "example"])]
+    tests: bool,
+    /// Build the library's unit tests and debug them
+    #[clap(long, conflicts_with_all = ["bin", "example"])]
+    lib: bool,
+    /// Build the benchmark NAME and debug it, with the bench profile
+    #[clap(long, value_name = "NAME", conflicts_with_all = ["bin", "example"])]
+    bench: Option<String>,
+    /// Build only this package of the workspace
+    #[clap(long, short = 'p', value_name = "SPEC")]
+    package: Option<String>,
+    /// Features to enable, separated by commas or spaces (repeatable)
+    #[clap(long)]
+    features: Vec<String>,
+    /// Don't enable the default features
+    #[clap(long = "no-default-features")]
+    no_default_features: bool,
+    /// Enable every feature of the selected packages
+    #[clap(long = "all-features")]
+    all_features: bool,
+    /// Build with this cargo profile, e.g. a custom `dev-opt`
+    #[clap(long, value_name = "NAME", conflicts_with = "release")]
+    profile: Option<String>,
+    /// Doctests are built and run by rustdoc, which leaves no binary to debug; this only says so
+    #[clap(long)]
+    doc: bool,
+    /// Pick the binary whose build ID (GNU build ID, Mach-O UUID or PDB GUID and age) starts with this
+    #[clap(long = "bin-id", value_name = "HASH", conflicts_with_all = ["bin", "example"])]
+    bin_id: Option<String>,
+    /// Build the tests and debug the one that failed, found by running them again
+    #[clap(long = "last-failed")]
+    last_failed: bool,
+    /// Build every target, tests and benches included, and pick any of them to debug
+    #[clap(long = "all-targets")]
+    all_targets: bool,
+    /// Build for the given target triple
+    #[clap(long)]
+    target: Option<String>,
+    /// Build into this directory rather than the workspace's target directory
+    #[clap(long = "target-dir")]
+    target_dir: Option<PathBuf>,
+    /// Set or override RUSTC_WRAPPER for the build (an empty value disables it)
+    #[clap(long = "rustc-wrapper")]
+    rustc_wrapper: Option<String>,
+    /// Fail if an unscoped build produces more binaries than this (0 disables the check)
+    #[clap(long = "max-artifacts")]
+    max_artifacts: Option<usize>,
+    /// Build an optimized but debuggable binary, overriding the profile with `--config`
+    #[clap(long = "opt-debug")]
+    opt_debug: bool,
+    /// Change or add one of `--opt-debug`'s profile settings, e.g. `opt-level=2` (implies it)
+    #[clap(long = "opt-debug-config", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    opt_debug_config: Vec<(String, String)>,
+    /// Enable the features the `--bin` or `--example` target requires
+    #[clap(long = "enable-required-features")]
+    enable_required_features: bool,
+    /// Re-run a build that fails on the network or a lock, without compiler errors, up to this many times
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    retry: u32,
+    /// How long to wait between retries of the build, in milliseconds
+    #[clap(long = "retry-delay", value_name = "MS", default_value_t = 1000)]
+    retry_delay: u64,
+    /// Enable or disable CPU features for the build, e.g. `+avx2,-fma` (`-C target-feature`)
+    #[clap(
+        long = "target-features",
+        value_name = "FEATURES",
+        allow_hyphen_values = true
+    )]
+    target_features: Option<String>,
+    /// Build with AVX2 enabled (`+avx,+avx2`)
+    #[clap(long, conflicts_with = "no_avx")]
+    avx2: bool,
+    /// Build with AVX, and the features that imply it, disabled
+    #[clap(long = "no-avx")]
+    no_avx: bool,
+    /// Build with SSE4.2 enabled
+    #[clap(long = "sse4.2")]
+    sse4_2: bool,
+    /// Build with every x86 feature the host CPU has
+    #[clap(long = "native-features")]
+    native_features: bool,
+}
+
+/// The profile settings not to warn about when `--allow-profile` isn't given.
+const ALLOW_PROFILE_ENV: &str = "CARGO_DEBUG_ALLOW_PROFILE";
+
+/// The exit status when the build had more warnings than allowed, apart from
+/// the debugger's own.
+const WARNINGS_EXIT_CODE: i32 = 2;
+
+/// The profile settings `--opt-debug` builds with: the crate lightly
+/// optimized so it still steps sensibly, its dependencies more so, and full
+/// debug info.
+const OPT_DEBUG_CONFIG: [(&str, &str); 3] = [
+    ("opt-level", "1"),
+    ("package.\"*\".opt-level", "2"),
+    ("debug", "true"),
+];
+
+#[derive(clap::Args)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<DebugCommand>,
+    /// The debugger: gdb, gdbserver, lldb, devenv, windbg, perf, frida, or the name or path of another
+    #[clap(value_parser = parse_debugger)]
+    debugger: Option<Debugger>,
+    /// Run the selected binary without a debugger, timing it, with the same selection and environment
+    #[clap(long = "no-debugger", conflicts_with = "debugger")]
+    no_debugger: bool,
+    /// Run this debugger executable instead of the one found for the debugger
+    #[clap(
+        long = "debugger-path",
+        value_name = "PATH",
+        conflicts_with = "no_debugger"
+    )]
+    debugger_path: Option<PathBuf>,
+    /// On macOS, keep gdb even when it can't debug, rather than switching to lldb
+    #[clap(long = "no-lldb-fallback")]
+    no_lldb_fallback: bool,
+    /// Launch the selected binary with this shell command line instead of a debugger, with {bin}, {args}, {cwd} and {name} replaced
+    #[clap(
+        long = "exec-template",
+        value_name = "TEMPLATE",
+        conflicts_with_all = ["debugger", "no_debugger"]
+    )]
+    exec_template: Option<String>,
+    /// Debug with this command line, with {bin}, {args}, {cwd} and {name} replaced, rather than a debugger by name
+    #[clap(
+        long = "debugger-cmd",
+        value_name = "TEMPLATE",
+        conflicts_with_all = ["debugger", "no_debugger", "exec_template", "debugger_path"]
+    )]
+    debugger_cmd: Option<String>,
+    #[command(flatten)]
+    build: BuildArgs,
+    /// Save the build flags and environment to a file, to reproduce the build elsewhere
+    #[clap(long = "save-env")]
+    save_env: Option<PathBuf>,
+    /// Restore the build flags and environment saved with `--save-env`
+    #[clap(long = "load-env")]
+    load_env: Option<PathBuf>,
+    /// Source path substitution (FROM=TO) applied when debugging a musl target
+    #[clap(long = "musl-source-map", value_parser = parse_path_map)]
+    musl_source_map: Vec<(String, String)>,
+    /// Don't map the standard library's sources to the toolchain's `rust-src` component
+    #[clap(long = "no-std-source-map")]
+    no_std_source_map: bool,
+    /// Load the toolchain's Natvis visualizers: done by default for WinDbg, and installs them for devenv
+    #[clap(long, conflicts_with = "no_natvis")]
+    natvis: bool,
+    /// Don't load the toolchain's Natvis visualizers into WinDbg
+    #[clap(long = "no-natvis")]
+    no_natvis: bool,
+    /// Map a source path prefix in the debug info (FROM=TO), instead of undoing `--remap-path-prefix` automatically
+    #[clap(long = "path-map", value_parser = parse_path_map)]
+    path_map: Vec<(String, String)>,
+    /// Don't run `dsymutil` on macOS executables with a missing or stale `.dSYM`
+    #[clap(long = "no-dsymutil")]
+    no_dsymutil: bool,
+    /// On macOS, sign the executable ad hoc with the get-task-allow entitlement so lldb may debug it
+    #[clap(long)]
+    codesign: bool,
+    /// Add a `.gdb_index` section to the selected binary so gdb loads it faster
+    #[clap(long = "gdb-index")]
+    gdb_index: bool,
+    /// Check the source files named in the selected binary's debug info exist, before debugging
+    #[clap(long = "verify-debuginfo")]
+    verify_debuginfo: bool,
+    /// Don't add the directories of the workspace's dynamic libraries to the library search path
+    #[clap(long = "no-lib-path-injection")]
+    no_lib_path_injection: bool,
+    /// Don't warn about a profile setting that hurts debugging [default: $CARGO_DEBUG_ALLOW_PROFILE, comma-separated]
+    #[clap(
+        long = "allow-profile",
+        value_name = "SETTING",
+        value_parser = clap::builder::PossibleValuesParser::new(profile::SETTINGS)
+    )]
+    allow_profile: Vec<String>,
+    /// Copy the debugger command line to the clipboard
+    #[clap(long)]
+    copy: bool,
+    /// Build and select the binary, then print the debugger command line instead of running it
+    #[clap(long = "no-run", alias = "print-cmd")]
+    no_run: bool,
+    /// Start gdb in its text user interface
+    #[clap(long)]
+    tui: bool,
+    /// Select a gdb TUI layout on startup (implies `--tui`)
+    #[clap(long = "gdb-tui-layout", value_enum)]
+    gdb_tui_layout: Option<TuiLayout>,
+    /// Terminal size (ROWSxCOLS) for gdb's TUI, for reproducible rendering
+    #[clap(long = "gdb-tui-size", value_parser = parse_tui_size)]
+    gdb_tui_size: Option<(u16, u16)>,
+    /// Start gdb with its machine interface, of the given version (2 if not given)
+    #[clap(
+        long = "gdb-mi-version",
+        value_name = "1|2|3",
+        num_args = 0..=1,
+        default_missing_value = "2",
+        value_parser = clap::value_parser!(u8).range(1..=3)
+    )]
+    gdb_mi_version: Option<u8>,
+    /// Start gdb with the named interpreter (`--interpreter=NAME`), e.g. for MI versions newer than 3
+    #[clap(
+        long = "gdb-interpreter",
+        value_name = "NAME",
+        conflicts_with = "gdb_mi_version"
+    )]
+    gdb_interpreter: Option<String>,
+    /// The address gdbserver listens on, `HOST:PORT` or a bare port on all interfaces [default: $CARGO_DEBUG_GDBSERVER_ADDR]
+    #[clap(long, value_name = "[HOST:]PORT", conflicts_with = "remote")]
+    address: Option<String>,
+    /// With gdbserver, also start a local gdb connected to it, for a session in one command
+    #[clap(
+        long,
+        conflicts_with_all = ["remote", "no_debugger", "no_run", "copy", "emit_script", "pack_repro", "tmux", "new_terminal", "terminal"]
+    )]
+    attach: bool,
+    /// The gdb `--attach` connects with [default: gdb, or gdb-multiarch for another architecture]
+    #[clap(long = "client-gdb", value_name = "PATH", requires = "attach")]
+    client_gdb: Option<PathBuf>,
+    /// Use the toolchain's rust-gdb/rust-lldb wrappers, which pretty-print Rust types
+    #[clap(long, alias = "rust")]
+    pretty: bool,
+    /// Don't load the toolchain's Rust pretty-printers into plain gdb
+    #[clap(long = "no-pretty-printers")]
+    no_pretty_printers: bool,
+    /// Search this directory for sources instead of the selected package's root
+    #[clap(long = "source-root")]
+    source_root: Option<PathBuf>,
+    /// Don't add the selected package's root to the debugger's source search path
+    #[clap(long = "no-source-root", conflicts_with = "source_root")]
+    no_source_root: bool,
+    /// Evaluate a gdb expression on startup, setting `$var = expr` and printing anything else
+    #[clap(long = "gdb-iex-eval", alias = "gdb-init-eval-command")]
+    gdb_iex_eval: Vec<String>,
+    /// Load the pwndbg GDB extension, if it isn't already loaded by ~/.gdbinit
+    #[clap(long)]
+    pwndbg: bool,
+    /// The color theme for pwndbg
+    #[clap(long = "pwndbg-theme", value_enum, requires = "pwndbg")]
+    pwndbg_theme: Option<gdbext::Theme>,
+    /// Load the GEF GDB extension, if it isn't already loaded by ~/.gdbinit
+    #[clap(long, conflicts_with = "pwndbg")]
+    gef: bool,
+    /// With `--gef`, don't load the project's local .gdbinit
+    #[clap(long = "gef-no-local-init", requires = "gef")]
+    gef_no_local_init: bool,
+    /// With `--gef`, override the architecture GEF debugs (e.g. ARM, X86)
+    #[clap(long = "gef-arch", requires = "gef")]
+    gef_arch: Option<String>,
+    /// Run the session without address space layout randomization, for addresses that repeat across runs
+    #[clap(long = "aslr-disable")]
+    aslr_disable: bool,
+    /// Stop gdb or lldb at the first AddressSanitizer report (the default when RUSTFLAGS enable ASan)
+    #[clap(long, conflicts_with = "no_asan")]
+    asan: bool,
+    /// Don't set up the session for AddressSanitizer, even when RUSTFLAGS enable it
+    #[clap(long = "no-asan")]
+    no_asan: bool,
+    /// Watch writes to a `START-END` range of hex addresses in gdb, with stricter ASan checks
+    #[clap(
+        long = "asan-focus",
+        alias = "sanitize-addresses-of-interest",
+        value_name = "START-END",
+        value_parser = parse_address_range
+    )]
+    asan_focus: Option<(u64, u64)>,
+    /// Profile the binary with `perf record` into `target/perf.data` instead of debugging it (Linux)
+    #[clap(
+        long,
+        conflicts_with_all = ["debugger", "no_debugger", "perf_stat_flags"]
+    )]
+    perf: bool,
+    /// With `--perf`, open `perf report` on the profile once it's recorded
+    #[clap(long = "open-report", requires = "perf")]
+    open_report: bool,
+    /// Extra flags for `perf record` with `--debugger perf` or `--perf`, e.g. "-e cache-misses -F 99"
+    #[clap(long = "perf-record-flags", allow_hyphen_values = true)]
+    perf_record_flags: Option<String>,
+    /// Run `perf stat` with these flags instead of `perf record` with `--debugger perf`
+    #[clap(
+        long = "perf-stat-flags",
+        allow_hyphen_values = true,
+        conflicts_with = "perf_record_flags"
+    )]
+    perf_stat_flags: Option<String>,
+    /// A JavaScript file for Frida to load into the program with `--debugger frida`
+    #[clap(long = "frida-script", value_name = "PATH")]
+    frida_script: Option<PathBuf>,
+    /// Source a gdb or lldb script of commands (e.g. `bt`) when the program crashes
+    #[clap(
+        long = "post-crash-script",
+        value_name = "PATH",
+        conflicts_with = "no_debugger"
+    )]
+    post_crash_script: Option<PathBuf>,
+    /// Restore the breakpoints of the last session of the target, and save them for the next one (gdb and lldb)
+    #[clap(long = "save-breakpoints")]
+    save_breakpoints: bool,
+    /// Keep the other threads stopped while stepping one (`step`, the default) or always (`on`)
+    #[clap(
+        long = "lock-scheduler",
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "step"
+    )]
+    lock_scheduler: Option<SchedulerLocking>,
+    /// Follow the program into the processes it spawns (gdb, lldb 14 or later, windbg and cdb)
+    #[clap(long = "debug-children", conflicts_with = "no_debugger")]
+    debug_children: bool,
+    /// Print the version the debugger reports before launching it
+    #[clap(
+        long = "print-debugger-version",
+        conflicts_with_all = ["no_debugger", "exec_template"]
+    )]
+    print_debugger_version: bool,
+    /// Stop when the program panics, at a breakpoint on `rust_panic`
+    #[clap(long = "break-on-panic")]
+    break_on_panic: bool,
+    /// Run the program up to N times in one gdb or lldb session, stopping at the first crash
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    repeat: Option<u32>,
+    /// Run the program without a debugger until it fails (at most N times), then run it once more in the debugger
+    #[clap(
+        long = "run-until-crash",
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "0",
+        conflicts_with_all = ["no_debugger", "repeat", "remote", "tmux", "new_terminal", "terminal", "minidump"]
+    )]
+    run_until_crash: Option<u32>,
+    /// Run the program without a debugger and, only if it fails, run it again in the debugger, stopping on panics in gdb and lldb
+    #[clap(
+        long = "on-failure",
+        conflicts_with_all = ["no_debugger", "repeat", "run_until_crash", "collect_core", "wait_attach", "remote", "tmux", "new_terminal", "terminal", "minidump"]
+    )]
+    on_failure: bool,
+    /// Run the program without a debugger and, if a signal kills it, open its core dump in gdb or lldb
+    #[clap(
+        long = "collect-core",
+        conflicts_with_all = ["no_debugger", "repeat", "run_until_crash", "remote", "tmux", "new_terminal", "terminal", "minidump"]
+    )]
+    collect_core: bool,
+    /// Launch the binary on its own, then attach to the newest process running it once that settles, for programs that fork or re-exec themselves
+    #[clap(
+        long = "wait-attach",
+        conflicts_with_all = ["no_debugger", "exec_template", "collect_core", "backtrace_on_crash", "remote", "tmux", "new_terminal", "terminal", "minidump"]
+    )]
+    wait_attach: bool,
+    /// With `--wait-attach`, start the program stopped with this signal and attach to it right away
+    #[clap(long = "wait-signal", value_enum, requires = "wait_attach")]
+    wait_signal: Option<waitattach::WaitSignal>,
+    /// With `--wait-attach`, how many seconds to wait for the process to settle
+    #[clap(
+        long = "wait-timeout",
+        value_name = "SECS",
+        default_value_t = 30,
+        requires = "wait_attach"
+    )]
+    wait_timeout: u64,
+    /// Run the program under gdb, lldb or cdb in batch mode and, if it crashes, print every thread's backtrace and exit with 134
+    #[clap(
+        long = "backtrace-on-crash",
+        conflicts_with_all = [
+            "no_debugger",
+            "repeat",
+            "collect_core",
+            "remote",
+            "tmux",
+            "new_terminal",
+            "terminal",
+            "minidump",
+            "tui",
+            "gdb_tui_layout",
+            "gdb_mi_version",
+            "gdb_interpreter",
+            "debugger_stdin",
+            "monitor",
+        ]
+    )]
+    backtrace_on_crash: bool,
+    /// Also run another binary of the workspace, with its arguments, while the program runs (repeatable)
+    #[clap(
+        long,
+        value_name = "BIN[:ARGS]",
+        value_parser = companion::parse,
+        conflicts_with_all = ["remote", "tmux", "new_terminal", "terminal", "minidump", "collect_core", "backtrace_on_crash"]
+    )]
+    with: Vec<companion::Companion>,
+    /// Where the output of `--with` binaries goes
+    #[clap(long = "with-output", value_enum, default_value = "log")]
+    with_output: companion::Output,
+    /// Where it can't be told when the program is running, start `--with` binaries this long after the debugger, in milliseconds
+    #[clap(long = "with-delay", value_name = "MS", default_value_t = 1000)]
+    with_delay: u64,
+    /// Exit with status 2 after the session if the build had any warnings
+    #[clap(long = "report-build-warnings", visible_alias = "deny-warnings")]
+    report_build_warnings: bool,
+    /// Exit with status 2 after the session if the build had more than N warnings
+    #[clap(long = "max-warnings", value_name = "N")]
+    max_warnings: Option<usize>,
+    /// Redirect the program's stdin from a file (gdb and lldb), leaving the terminal to the debugger
+    #[clap(long = "stdin-file", value_name = "PATH")]
+    stdin_file: Option<PathBuf>,
+    /// Set a variable in the program's environment (repeatable), overriding `--env-file`
+    #[clap(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    env: Vec<(String, String)>,
+    /// Set the variables of a `.env`-style file of KEY=VALUE lines in the program's environment
+    #[clap(long = "env-file", value_name = "PATH")]
+    env_file: Option<PathBuf>,
+    /// Start the program in this directory
+    #[clap(long, value_name = "DIR")]
+    cwd: Option<PathBuf>,
+    /// Source this gdb (`--command`) or lldb (`--source`) script on startup, e.g. to set breakpoints
+    #[clap(
+        long = "command-file",
+        value_name = "PATH",
+        conflicts_with_all = ["no_debugger", "exec_template", "debugger_cmd"]
+    )]
+    command_file: Option<PathBuf>,
+    /// Type a line of commands at the debugger's prompt on launch (repeatable); the rest of the session reads this terminal
+    #[clap(
+        long = "debugger-stdin",
+        value_name = "TEXT",
+        conflicts_with_all = ["no_debugger", "tmux", "new_terminal", "terminal"]
+    )]
+    debugger_stdin: Vec<String>,
+    /// Source a gdb or lldb command script again whenever it changes, through the debugger's stdin
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["no_debugger", "tmux", "new_terminal", "terminal"]
+    )]
+    monitor: Option<PathBuf>,
+    /// Rebuild and relaunch the session whenever the workspace's sources change
+    #[clap(
+        long,
+        conflicts_with_all = ["build_only", "dry_build", "minidump", "list", "dump_config"]
+    )]
+    watch: bool,
+    /// Ask the program for colored output even without a terminal (`CLICOLOR_FORCE=1`, `FORCE_COLOR=1`)
+    #[clap(long = "force-color-in-debugger")]
+    force_color_in_debugger: bool,
+    /// Show a GUI debugger on the X11 display forwarded over SSH
+    #[clap(long = "x11-forward")]
+    x11_forward: bool,
+    /// The X11 display for `--x11-forward`, instead of `$DISPLAY` (implies it)
+    #[clap(long)]
+    display: Option<String>,
+    /// Import a Python script into lldb on startup, before any other startup commands
+    #[clap(long = "lldb-python")]
+    lldb_python: Option<PathBuf>,
+    /// Build with LLVM coverage instrumentation and report coverage once the debugger exits
+    #[clap(long = "coverage-instrument", alias = "instrument-for-coverage")]
+    coverage_instrument: bool,
+    /// With `--coverage-instrument`, write an HTML report instead of printing a summary
+    #[clap(long = "coverage-html", requires = "coverage_instrument")]
+    coverage_html: bool,
+    /// Write a script reproducing the debug session (PowerShell on Windows or for `.ps1`)
+    #[clap(long = "emit-script")]
+    emit_script: Option<PathBuf>,
+    /// Pack the binary, its debuginfo and the invocation into an archive (`.tar` or `.tar.gz`) instead of debugging
+    #[clap(long = "pack-repro")]
+    pack_repro: Option<PathBuf>,
+    /// With `--pack-repro`, include a core file or minidump in the archive
+    #[clap(long = "include-core", requires = "pack_repro")]
+    include_core: Option<PathBuf>,
+    /// Record a transcript of the debugger session to a file
+    #[clap(long)]
+    transcript: Option<PathBuf>,
+    /// Strip colors and other ANSI escape sequences from the transcript once the session ends
+    #[clap(long = "strip-ansi", requires = "transcript")]
+    strip_ansi: bool,
+    /// Run the session from a traced (`set -x`) shell script that reports its exit code
+    #[clap(long = "shell-debug", alias = "zsh-debug-integration")]
+    shell_debug: bool,
+    /// Write WinDbg launch settings (a `.debugTarget` file) for the selected binary
+    #[cfg(windows)]
+    #[clap(long = "emit-windbg")]
+    emit_windbg: Option<PathBuf>,
+    /// With `--emit-windbg`, open the written settings in WinDbg
+    #[cfg(windows)]
+    #[clap(long, requires = "emit_windbg")]
+    open: bool,
+    /// Fail, rather than warn, when the selected binary's PDB doesn't match it
+    #[cfg(windows)]
+    #[clap(long = "require-matching-pdb")]
+    require_matching_pdb: bool,
+    /// Symbol path for WinDbg, as `_NT_SYMBOL_PATH`
+    #[cfg(windows)]
+    #[clap(long = "symbol-path", conflicts_with = "no_default_sympath")]
+    symbol_path: Option<String>,
+    /// Don't default WinDbg's symbol path to the binary's directory and Microsoft's symbol server
+    #[cfg(windows)]
+    #[clap(long = "no-default-sympath")]
+    no_default_sympath: bool,
+    /// Debug the selected binary as the Windows service NAME, which the Service Control Manager starts; `--service cleanup` removes a debugger `--service-mode ifeo` left registered
+    #[cfg(windows)]
+    #[clap(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["no_debugger", "wait_attach", "remote", "tmux", "new_terminal", "terminal", "minidump"]
+    )]
+    service: Option<String>,
+    /// With `--service`, attach to the service's process, or register the debugger to start it in under Image File Execution Options
+    #[cfg(windows)]
+    #[clap(
+        long = "service-mode",
+        value_enum,
+        default_value = "attach",
+        requires = "service"
+    )]
+    service_mode: service::Mode,
+    /// With `--service`, start the service with `sc start` rather than wait for it to be started
+    #[cfg(windows)]
+    #[clap(long = "service-start", requires = "service")]
+    service_start: bool,
+    /// Launch the debugger, and so the program, as this user with `sudo -u` (Unix)
+    #[cfg(unix)]
+    #[clap(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["tmux", "new_terminal", "terminal", "collect_core", "backtrace_on_crash"]
+    )]
+    user: Option<String>,
+    /// Summarize what's about to be launched and ask before going ahead (always asked with `--user`)
+    #[clap(long)]
+    confirm: bool,
+    /// Don't ask before launching, even with `--confirm` or `--user`
+    #[clap(long)]
+    yes: bool,
+    /// Launch the debugger in a new terminal emulator window
+    #[clap(long = "new-terminal")]
+    new_terminal: bool,
+    /// The terminal emulator to use for `--new-terminal` (implies it)
+    #[clap(long)]
+    terminal: Option<PathBuf>,
+    /// Keep the session's terminal open after the debugger exits, until Enter is pressed
+    #[clap(long)]
+    hold: bool,
+    /// Launch the debugger in a new tmux window or pane instead of the current terminal
+    #[clap(long, value_enum)]
+    tmux: Option<Tmux>,
+    /// With `--tmux`, wait for the debugger's pane to close before exiting
+    #[clap(long, requires = "tmux")]
+    wait: bool,
+    /// Set the Linux OOM killer's score adjustment for the session, from -1000 (never) to 1000
+    #[clap(
+        long = "oom-score-adj",
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i16).range(-1000..=1000)
+    )]
+    oom_score_adj: Option<i16>,
+    /// Set a resource limit for the debugger and the program (repeatable), e.g. `nofile=4096` or `core=unlimited`
+    #[clap(long, value_name = "RESOURCE=SOFT[:HARD]", value_parser = ulimit::parse_limit)]
+    ulimit: Vec<ulimit::Limit>,
+    /// Set the kernel's core dump pattern for the session (`%p` pid, `%e` executable, `%t` time)
+    #[clap(long = "core-pattern")]
+    core_pattern: Option<String>,
+    /// Set up core dumps for the session: no size limit, cores in the target directory, full backtraces
+    #[clap(long = "enable-core-dumps")]
+    enable_core_dumps: bool,
+    /// Lift the program's core size limit, without changing where cores go, and print where that is
+    #[clap(long = "unlimit-core")]
+    unlimit_core: bool,
+    /// Check that the debugger has the Linux capabilities it needs before building
+    #[clap(long = "check-capabilities")]
+    check_capabilities: bool,
+    /// List the workspace's binaries and examples, as the flags selecting them
+    #[clap(long)]
+    list: bool,
+    /// Print the names of the workspace's targets of a kind, for shell completion
+    #[clap(long, value_enum, hide = true)]
+    complete: Option<TargetKind>,
+    /// Print the resolved configuration as TOML, noting where each value came from
+    #[clap(long = "dump-config")]
+    dump_config: bool,
+    /// Log what's being done, and how long each phase of startup took
+    #[clap(long, short = 'v')]
+    verbose: bool,
+    /// Probe the sysroot, Visual Studio and debugger wrappers again rather than using the cached results
+    #[clap(long = "no-cache")]
+    no_cache: bool,
+    /// Validate flags and print the planned commands without building anything
+    #[clap(long = "dry-build")]
+    dry_build: bool,
+    /// Select the binary from a saved `cargo build --message-format json` log instead of building
+    #[clap(
+        long = "from-build-log",
+        value_name = "PATH",
+        conflicts_with = "coverage_instrument"
+    )]
+    from_build_log: Option<PathBuf>,
+    /// Debug a test executable built beforehand, e.g. by `cargo test --no-run`, instead of building
+    #[clap(
+        long = "test-binary",
+        value_name = "PATH",
+        conflicts_with_all = ["bin", "example", "test", "tests", "lib", "bench", "from_build_log", "coverage_instrument", "last_failed"]
+    )]
+    test_binary: Option<PathBuf>,
+    /// Run only the test called NAME (`NAME --exact --nocapture`), ahead of the harness flags after `--`
+    #[clap(long = "test-filter", value_name = "NAME", requires = "test_binary")]
+    test_filter: Option<String>,
+    /// Build the tests with cargo-nextest and debug the one this filterset matches, asking which if several do
+    #[clap(
+        long,
+        value_name = "FILTERSET",
+        conflicts_with_all = ["bin", "example", "test", "tests", "lib", "bench", "last_failed", "all_targets", "test_binary", "from_build_log", "coverage_instrument"]
+    )]
+    nextest: Option<String>,
+    /// Build this cargo-fuzz target the way cargo-fuzz does and debug it on the `--input` it crashed with
+    #[clap(
+        long,
+        value_name = "TARGET",
+        requires = "fuzz_input",
+        conflicts_with_all = ["bin", "example", "test", "tests", "lib", "bench", "last_failed", "all_targets", "test_binary", "from_build_log", "nextest"]
+    )]
+    fuzz: Option<String>,
+    /// With `--fuzz`, the input to replay, usually a crash artifact under `fuzz/artifacts`
+    #[clap(long = "input", value_name = "FILE", requires = "fuzz")]
+    fuzz_input: Option<PathBuf>,
+    /// With `--fuzz`, the sanitizer to build with, instead of the fuzz crate's or address
+    #[clap(long = "fuzz-sanitizer", value_enum, requires = "fuzz")]
+    fuzz_sanitizer: Option<fuzz::Sanitizer>,
+    /// Debug this proc-macro package by running rustc under the debugger as it compiles `--consumer`
+    #[clap(
+        long = "proc-macro",
+        value_name = "PACKAGE",
+        requires = "consumer",
+        conflicts_with_all = ["bin", "example", "test", "tests", "lib", "bench", "last_failed", "all_targets", "test_binary", "from_build_log", "nextest", "fuzz"]
+    )]
+    proc_macro: Option<String>,
+    /// With `--proc-macro`, the file or workspace package using the macro that rustc compiles
+    #[clap(long, value_name = "PATH|PACKAGE", requires = "proc_macro")]
+    consumer: Option<String>,
+    /// With `--last-failed`, take the failed tests from the JSON of a previous run (`-` for stdin) rather than running them again
+    #[clap(long = "from-json", value_name = "PATH", requires = "last_failed")]
+    from_json: Option<PathBuf>,
+    /// Build and print the path of the selected binary, without launching a debugger
+    #[clap(long = "build-only")]
+    build_only: bool,
+    /// Build and describe the selected artifact as JSON, without launching a debugger
+    #[clap(long = "artifact-json")]
+    artifact_json: bool,
+    /// Build and print every artifact cargo reported, as a JSON array, without launching a debugger
+    #[clap(long = "list-artifacts-json", conflicts_with = "artifact_json")]
+    list_artifacts_json: bool,
+    /// Build, then open a Windows minidump (`.dmp`) with windbg, cdb or lldb instead of launching the binary
+    #[clap(
+        long,
+        visible_alias = "dump",
+        value_name = "PATH",
+        conflicts_with_all = [
+            "no_debugger",
+            "stdin_file",
+            "repeat",
+            "break_on_panic",
+            "shared_libs",
+            "remote",
+            "dap",
+            "address",
+        ]
+    )]
+    minidump: Option<PathBuf>,
+    /// Build and compare the selected binary with another build of it, without launching a debugger
+    #[clap(long = "compare-with", value_name = "PATH")]
+    compare_with: Option<PathBuf>,
+    /// Build and print a VS Code launch.json for the selected binary, without launching a debugger
+    #[clap(long = "emit-vscode", value_enum)]
+    emit_vscode: Option<vscode::Flavor>,
+    /// Run the program up to `main` in gdb or lldb, list the shared libraries it loaded and exit
+    #[clap(long = "shared-libs")]
+    shared_libs: bool,
+    /// Build and serve the selected binary over HTTP for a device to fetch, without a debugger
+    #[clap(
+        long = "serve-artifact",
+        value_name = "ADDR:PORT",
+        num_args = 0..=1,
+        default_missing_value = "0.0.0.0:8000"
+    )]
+    serve_artifact: Option<SocketAddr>,
+    /// With `--serve-artifact`, stop once the binary (and its debuginfo) has been downloaded
+    #[clap(long, requires = "serve_artifact")]
+    once: bool,
+    /// Copy the selected binary to `user@host` over SSH and debug it there
+    #[clap(long, value_name = "USER@HOST")]
+    remote: Option<String>,
+    /// With `--remote`, run gdbserver there and connect a local gdb or lldb to it
+    #[clap(long = "remote-client", requires = "remote")]
+    remote_client: bool,
+    /// The port gdbserver listens on with `--remote`
+    #[clap(long = "remote-port", requires = "remote", default_value_t = 2345)]
+    remote_port: u16,
+    /// With `--remote-client`, keep gdbserver running in extended-remote mode between `--watch` sessions, uploading only a changed binary
+    #[clap(long = "persistent-server", requires = "remote_client")]
+    persistent_server: bool,
+    /// Show a desktop notification when the build finishes
+    #[clap(long)]
+    notify: bool,
+    /// With `--notify`, also show a notification when the debugger exits
+    #[clap(long = "notify-exit", requires = "notify")]
+    notify_exit: bool,
+    /// Report progress as one JSON event per line on stdout, like cargo's --message-format
+    #[clap(long = "message-format", value_enum, default_value_t)]
+    message_format: MessageFormat,
+    /// Start a debug adapter (DAP) server for an editor to attach to instead of a debugger
+    #[clap(long)]
+    dap: bool,
+    /// The debug adapter to start with `--dap`
+    #[clap(long = "dap-adapter", value_enum)]
+    dap_adapter: Option<DapAdapter>,
+    #[clap(last = true)]
+    options: Vec<String>,
+}
+
+#[derive(clap::Subcommand)]
+enum DebugCommand {
+    /// Build and run the selected binary directly, without a debugger
+    Run(RunArgs),
+}
+
+#[derive(clap::Args)]
+struct RunArgs {
+    #[command(flatten)]
+    build: BuildArgs,
     #[clap(last = true)]
     options: Vec<String>,
 }
@@ -54,29 +962,83 @@ enum CargoCli {
     Debug(Args),
 }
 
-fn main() -> Result<()> {
-    // TermLogger::init(log::LevelFilter::Debug, simplelog::Config::default()).unwrap();
+/// What cargo reported while building.
+#[derive(Default)]
+struct BuildOutput {
+    artifacts: Vec<Artifact>,
+    /// The number of compiler errors
+    errors: usize,
+    /// The number of compiler warnings
+    warnings: usize,
+    /// Whether an error looked like a corrupted build cache
+    stale_cache: bool,
+}
+
+/// How to launch the debugger, beyond the binary and its options.
+#[derive(Default)]
+struct DebugSetup {
+    /// Use this executable instead of the debugger's usual one
+    program: Option<PathBuf>,
+    /// Arguments passed to the debugger itself, ahead of everything else
+    flags: Vec<String>,
+    /// Debugger commands to run once the binary is loaded
+    commands: Vec<String>,
+    /// A script of the user's, sourced ahead of `commands`
+    command_file: Option<String>,
+}
 
-    let CargoCli::Debug(args) = CargoCli::parse();
+impl DebugSetup {
+    /// The arguments sourcing the command file, then running the startup
+    /// commands, for gdb or lldb, which `debugger` is; none for the others.
+    fn startup_args(&self, debugger: &Debugger) -> Vec<String> {
+        let (source, run) = match debugger {
+            Debugger::Gdb => ("--command", "-ex"),
+            Debugger::Lldb => ("--source", "--one-line"),
+            _ => return vec![],
+        };
+        let mut args = vec![];
+        if let Some(command_file) = &self.command_file {
+            args.extend([source.to_string(), command_file.clone()]);
+        }
+        for command in &self.commands {
+            args.extend([run.to_string(), command.clone()]);
+        }
 
-    let options = args.options;
+        args
+    }
+}
 
+/// Synthesize the `cargo build` invocation for the requested target.
+fn cargo_command(args: &BuildArgs) -> Command {
     trace!("building cargo command");
 
     // Build and execute cargo command
     let cargo_bin = env::var("CARGO").unwrap_or(String::from("cargo"));
     let mut cargo_cmd = Command::new(cargo_bin);
 
+    // Tests are built, not run, to find the one that failed or to debug one
+    let subcommand: &[&str] = if args.bench.is_some() {
+        &["bench", "--no-run"]
+    } else if args.last_failed || builds_tests(args) {
+        &["test", "--no-run"]
+    } else {
+        &["build"]
+    };
     cargo_cmd
-        .args(["build", "--message-format=json"])
+        .args(subcommand)
+        .arg(message_format(diagnostic_color()))
         .stdout(Stdio::piped());
 
     if args.release {
         cargo_cmd.arg("--release");
     }
 
-    if let Some(manifest) = args.manifest {
-        cargo_cmd.args(["--manifest-path", &manifest]);
+    if let Some(profile) = &args.profile {
+        cargo_cmd.args(["--profile", profile]);
+    }
+
+    if let Some(manifest) = args.manifests.first() {
+        cargo_cmd.args(["--manifest-path", manifest]);
     }
 
     if let Some(bin) = &args.bin {
@@ -87,238 +1049,4071 @@ fn main() -> Result<()> {
         cargo_cmd.args(["--example", example]);
     }
 
-    trace!("synthesized cargo command: {:?}", cargo_cmd);
+    if let Some(test) = &args.test {
+        cargo_cmd.args(["--test", test]);
+    }
 
-    trace!("launching cargo command");
-    let mut handle = cargo_cmd.spawn().expect("error starting cargo command");
+    if let Some(bench) = &args.bench {
+        cargo_cmd.args(["--bench", bench]);
+    }
 
-    // Log all output artifacts
-    let mut artifacts = vec![];
-    let reader = std::io::BufReader::new(handle.stdout.take().unwrap());
-    for message in Message::parse_stream(reader) {
-        match message.expect("Invalid cargo JSON message") {
-            Message::CompilerArtifact(artifact) => {
-                artifacts.push(artifact);
-            }
-            _ => (),
-        }
+    if args.tests {
+        cargo_cmd.arg("--tests");
     }
 
-    // Await command completion
-    let status = handle
-        .wait()
-        .expect("cargo command failed, try running the command directly");
+    if args.lib {
+        cargo_cmd.arg("--lib");
+    }
 
-    if let Some(code) = status.code() {
-        if code != 0 {
-            std::process::exit(code);
-        }
+    if let Some(package) = &args.package {
+        cargo_cmd.args(["--package", package]);
     }
 
-    trace!("command executed");
+    for features in &args.features {
+        cargo_cmd.args(["--features", features]);
+    }
 
-    // Find the output(s) we care about
-    trace!("found {} artifacts: {:?}", artifacts.len(), artifacts);
+    if args.no_default_features {
+        cargo_cmd.arg("--no-default-features");
+    }
 
-    let binaries = artifacts
-        .into_iter()
-        .filter_map(|a| {
-            if let Some(executable) = a.executable {
-                Some((a.target, executable))
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<_>>();
+    if args.all_features {
+        cargo_cmd.arg("--all-features");
+    }
 
-    let bin = if let Some(binary) = &args.bin {
-        if let Some(bin) = binaries.iter().find_map(|(target, exe)| {
-            if target.name == **binary {
-                Some(exe.clone())
-            } else {
-                None
-            }
-        }) {
-            bin.to_string()
-        } else {
-            println!("Could not find binary artifact {binary}");
-            std::process::exit(1);
+    if args.all_targets {
+        cargo_cmd.arg("--all-targets");
+    }
+
+    if let Some(target) = &args.target {
+        cargo_cmd.args(["--target", target]);
+    }
+
+    if let Some(dir) = &args.target_dir {
+        cargo_cmd.arg("--target-dir").arg(dir);
+    }
+
+    if args.opt_debug || !args.opt_debug_config.is_empty() {
+        let configs = opt_debug_configs(profile_name(args), &args.opt_debug_config);
+        info!(
+            "--opt-debug: building with --config {}",
+            configs.join(" --config ")
+        );
+        for config in configs {
+            cargo_cmd.args(["--config", &config]);
         }
-    } else {
-        // Try and find the first binary. If more than one, return an error.
-        if binaries.len() == 1 {
-            binaries[0].1.clone().to_string()
-        } else {
-            println!(
-                "More than one binary artifact produced, please explicitly specify the binary."
-            );
-            std::process::exit(1);
+    }
+
+    if args.enable_required_features {
+        match required_features(args) {
+            Ok(Some((target, features))) if !features.is_empty() => {
+                info!(
+                    "enabling the features {target} requires: {}",
+                    features.join(",")
+                );
+                cargo_cmd.args(["--features", &features.join(",")]);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("could not look up the required features: {e}"),
         }
-    };
+    }
 
-    info!("selected binary: {:?}", bin);
+    if let Some(features) = targetfeatures::requested(args) {
+        info!("building with -C target-feature={features}");
+        let (var, flags) = targetfeatures::rustflags(
+            &features,
+            env::var("CARGO_ENCODED_RUSTFLAGS").ok(),
+            env::var("RUSTFLAGS").ok(),
+        );
+        cargo_cmd.env(var, flags);
+    }
 
-    let debugger = args.debugger.unwrap_or_default();
+    // Wrappers (e.g. sccache) are inherited as-is unless explicitly overridden
+    if let Some(wrapper) = &args.rustc_wrapper {
+        cargo_cmd.env("RUSTC_WRAPPER", wrapper);
+    }
 
-    let debug_path: PathBuf;
-    let mut debug_args: Vec<String> = vec![];
+    for var in ["RUSTC_WRAPPER", "RUSTC_WORKSPACE_WRAPPER"] {
+        if let Ok(wrapper) = env::var(var) {
+            trace!("inheriting {var}={wrapper}");
+        }
+    }
 
-    match debugger {
-        Debugger::Gdb => {
-            debug_path = PathBuf::from("gdb");
+    trace!("synthesized cargo command: {:?}", cargo_cmd);
 
-            // Prepare GDB to accept child options
-            if !options.is_empty() {
-                debug_args.push("--args".to_string());
-            }
+    cargo_cmd
+}
 
-            // Append command file if provided
-            /*
-            if let Some(command_file) = o.command_file {
-                debug_args.push("--command".to_string());
-                debug_args.push(command_file);
-            }
-            */
+/// Whether a diagnostic is rustc's closing "N warnings emitted" line, rather
+/// than a warning of its own.
+fn is_summary(diagnostic: &Diagnostic) -> bool {
+    diagnostic.spans.is_empty()
+        && diagnostic.code.is_none()
+        && diagnostic.message.starts_with(|c: char| c.is_ascii_digit())
+        && diagnostic.message.ends_with(" emitted")
+}
 
-            // Specify file to be debugged
-            debug_args.push(bin.clone());
+/// The error codes of crates in `target` that can't be loaded: invalid
+/// metadata, built by another rustc, or by an earlier build alongside a
+/// newer one.
+const STALE_CACHE_CODES: [&str; 4] = ["E0460", "E0464", "E0514", "E0786"];
 
-            // Append child options
-            debug_args.extend(options.iter().cloned());
-        }
-        Debugger::Lldb => {
-            debug_path = PathBuf::from("lldb");
+/// Whether a compiler error is one a corrupted or stale build cache causes,
+/// rather than the code, which `cargo clean` would fix.
+fn is_stale_cache(diagnostic: &Diagnostic) -> bool {
+    if diagnostic
+        .code
+        .as_ref()
+        .is_some_and(|c| STALE_CACHE_CODES.contains(&c.code.as_str()))
+    {
+        return true;
+    }
 
-            // Specify file to be debugged
-            debug_args.push("--file".to_string());
-            debug_args.push(bin.clone());
+    let message = diagnostic.message.to_lowercase();
+    [
+        "found invalid metadata files",
+        "incremental compilation",
+        "could not create session directory",
+        "failed to load dep-graph",
+    ]
+    .iter()
+    .any(|signature| message.contains(signature))
+}
 
-            // Append command file if provided
-            /*
-            if let Some(command_file) = o.command_file {
-                debug_args.push("--source".to_string());
-                debug_args.push(command_file);
-            }
-            */
+/// Whether cargo renders diagnostics in color: as `CARGO_TERM_COLOR` says,
+/// or when they're shown on a terminal.
+fn diagnostic_color() -> bool {
+    match env::var("CARGO_TERM_COLOR").as_deref() {
+        Ok("always") => true,
+        Ok("never") => false,
+        _ => std::io::stderr().is_terminal(),
+    }
+}
 
-            // Append child options
-            if !options.is_empty() {
-                debug_args.push("--".to_string());
-                debug_args.extend(options.iter().cloned());
+/// The JSON cargo is asked for, with diagnostics rendered for us to show.
+fn message_format(color: bool) -> &'static str {
+    if color {
+        "--message-format=json-diagnostic-rendered-ansi"
+    } else {
+        "--message-format=json"
+    }
+}
+
+/// Collect the artifacts, and count the compiler errors, on cargo's JSON
+/// message stream.
+///
+/// Lines that aren't messages, like the banners of rustup shims or wrapper
+/// scripts, are skipped rather than treated as errors.
+fn parse_artifacts(reader: impl std::io::BufRead, events: &mut Events) -> BuildOutput {
+    let mut output = BuildOutput::default();
+    for message in Message::parse_stream(reader) {
+        match message {
+            Ok(Message::CompilerArtifact(artifact)) => output.artifacts.push(artifact),
+            Ok(Message::CompilerMessage(msg)) => {
+                match msg.message.level {
+                    DiagnosticLevel::Error => {
+                        output.errors += 1;
+                        output.stale_cache |= is_stale_cache(&msg.message);
+                    }
+                    // Incremental compilation's own panics are ICEs
+                    DiagnosticLevel::Ice => output.stale_cache |= is_stale_cache(&msg.message),
+                    DiagnosticLevel::Warning if !is_summary(&msg.message) => output.warnings += 1,
+                    _ => {}
+                }
+                // Shown as they arrive, like cargo does without JSON
+                if let Some(rendered) = &msg.message.rendered {
+                    eprint!("{rendered}");
+                }
+                events.compiler_message(&msg.message);
+            }
+            Ok(Message::BuildFinished(finished)) => {
+                debug!(
+                    "cargo reported the build finished, success: {}",
+                    finished.success
+                )
+            }
+            // Wrappers and build tools print to stdout too
+            Ok(Message::TextLine(line)) => eprintln!("{line}"),
+            Ok(_) => {}
+            // The offending line has been consumed, so carry on with the next
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                trace!("ignoring non-UTF-8 output: {e}")
+            }
+            Err(e) => {
+                warn!("could not read cargo's output: {e}");
+                break;
             }
         }
-        Debugger::Gdbserver => {
-            debug_path = PathBuf::from("gdbserver");
+    }
 
-            /*
-            if let Some(address) = o.address {
-                debug_args.push(address);
-            } else {
-                error!("--address is required when gdbserver is used");
-                std::process::exit(1);
-            }
-            */
-            // Specify file to be debugged
-            debug_args.push(bin.clone());
+    output
+}
 
-            // Append child options
-            if !options.is_empty() {
-                debug_args.extend(options.iter().cloned());
+/// What cargo says, lowercased, when it fails for a reason that may be gone
+/// on the next try: the network, or a file system or lock that's busy.
+const TRANSIENT_ERRORS: [&str; 12] = [
+    "spurious network error",
+    "failed to download",
+    "could not resolve host",
+    "couldn't resolve host",
+    "timed out",
+    "timeout was reached",
+    "connection reset",
+    "connection refused",
+    "network failure",
+    "failed to lock file",
+    "resource temporarily unavailable",
+    "stale file handle",
+];
+
+/// Whether a failed build might succeed when run again: cargo failing
+/// without rustc reporting an error, and its `stderr` naming a cause that
+/// passes, like fetching crates, rather than one that doesn't, like an
+/// unknown `--bin` or a broken manifest.
+fn is_transient(output: &BuildOutput, stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    output.errors == 0 && TRANSIENT_ERRORS.iter().any(|e| stderr.contains(e))
+}
+
+/// Copy `pipe`, cargo's stderr, to ours as it comes, returning what it said.
+fn tee_stderr(mut pipe: impl std::io::Read) -> String {
+    let mut said = vec![];
+    let mut buf = [0; 4096];
+    while let Ok(n @ 1..) = pipe.read(&mut buf) {
+        let _ = std::io::stderr().write_all(&buf[..n]);
+        said.extend_from_slice(&buf[..n]);
+    }
+
+    String::from_utf8_lossy(&said).into_owned()
+}
+
+/// Build the requested target with cargo, returning every artifact produced
+/// and how many warnings there were.
+///
+/// Builds that fail transiently are retried `--retry` times. Exits the
+/// process with cargo's status code if the build fails, after a desktop
+/// notification if `notify` is set.
+fn build(args: &BuildArgs, notify: bool, events: &mut Events) -> BuildOutput {
+    if args.manifests.len() > 1 {
+        let mut merged = BuildOutput::default();
+        for one in per_manifest(args) {
+            info!("building {}", one.manifests[0]);
+            let output = build(&one, notify, events);
+            merged.artifacts.extend(output.artifacts);
+            merged.errors += output.errors;
+            merged.warnings += output.warnings;
+            merged.stale_cache |= output.stale_cache;
+        }
+        return merged;
+    }
+
+    let mut attempt = 0;
+    loop {
+        let mut cargo_cmd = cargo_command(args);
+        // Read to tell why it failed, colored as it would be on the terminal
+        if args.retry > 0 {
+            cargo_cmd.stderr(Stdio::piped());
+            if diagnostic_color() {
+                cargo_cmd.env("CARGO_TERM_COLOR", "always");
             }
         }
-        Debugger::Devenv => {
-            #[cfg(target_os = "windows")]
-            {
-                // Find the path to devenv
-                let install_info = vswhere::Config::new()
-                    .only_latest_versions(true)
-                    .run_default_path()
-                    .unwrap();
 
-                let info = install_info.iter().find(|m| {
-                    m.product_id()
-                        .starts_with("Microsoft.VisualStudio.Product.")
-                });
+        trace!("launching cargo command");
+        events.build_started(&cargo_cmd);
+        let mut handle = cargo_cmd.spawn().expect("error starting cargo command");
+        let stderr = handle
+            .stderr
+            .take()
+            .map(|pipe| std::thread::spawn(|| tee_stderr(pipe)));
 
-                if let Some(info) = info {
-                    debug_path = info.product_path().to_owned();
-                    debug_args.push("/DebugExe".to_string());
+        // Read until cargo (and anything it re-execs) closes stdout
+        let reader = std::io::BufReader::new(handle.stdout.take().unwrap());
+        let output = parse_artifacts(reader, events);
 
-                    // Specify file to be debugged
-                    debug_args.push(bin.clone());
+        // Await command completion
+        let status = handle
+            .wait()
+            .expect("cargo command failed, try running the command directly");
+        let stderr = stderr.and_then(|tee| tee.join().ok()).unwrap_or_default();
 
-                    // Append child options
-                    if !options.is_empty() {
-                        debug_args.extend(options.iter().cloned());
-                    }
-                } else {
-                    error!("Could not find a compatible version of Visual Studio :(");
-                    std::process::exit(1);
-                }
+        events.build_finished(status.success(), output.artifacts.len(), output.errors);
+
+        if !status.success() {
+            if attempt < args.retry && is_transient(&output, &stderr) {
+                attempt += 1;
+                warn!(
+                    "cargo failed on the network or a lock, retrying in {}ms ({attempt}/{})",
+                    args.retry_delay, args.retry
+                );
+                std::thread::sleep(Duration::from_millis(args.retry_delay));
+                continue;
             }
-            #[cfg(not(target_os = "windows"))]
-            {
-                panic!("devenv is only available on Windows");
+
+            if output.stale_cache {
+                eprintln!(
+                    "cargo-debug: hint: the build cache in the target directory looks \
+                     corrupted or stale, `cargo clean` and building again may fix it"
+                );
             }
+            if notify {
+                notify::send(&notify::build_failed(output.errors));
+            }
+            // Without an exit code, cargo was killed by a signal
+            std::process::exit(status.code().unwrap_or(1));
         }
-        Debugger::Windbg => {
-            debug_path = PathBuf::from("windbgx");
 
-            debug_args.push("-o".to_string());
+        trace!("command executed");
 
-            // Specify file to be debugged
-            debug_args.push(bin.clone());
+        return output;
+    }
+}
 
-            // Append child options
-            if !options.is_empty() {
-                debug_args.extend(options.iter().cloned());
-            }
-        }
+/// What cargo reported in a saved `--message-format json` log, read like
+/// the live stream of a build.
+fn read_build_log(path: &Path, events: &mut Events) -> Result<BuildOutput> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Could not open build log {}", path.display()))?;
+    let output = parse_artifacts(std::io::BufReader::new(file), events);
+    if output.artifacts.is_empty() {
+        bail!(
+            "{} has no artifacts in it, is it cargo's JSON output?",
+            path.display()
+        );
     }
 
-    trace!("synthesized debug arguments: {:?}", debug_args);
+    Ok(output)
+}
 
-    /*
-    if o.no_run {
-        trace!("no-run selected, exiting");
-        println!("Debug command: ");
-        println!("{} {}", debug_path.display(), debug_args.join(" "));
-        std::process::exit(0);
+/// The kind, description and name of the target `--bin` or `--example` picks.
+fn wanted_target(args: &BuildArgs) -> Option<(&'static str, &'static str, &str)> {
+    if let Some(bin) = &args.bin {
+        Some(("bin", "binary", bin))
+    } else if let Some(example) = &args.example {
+        Some(("example", "example", example))
+    } else if let Some(test) = &args.test {
+        Some(("test", "test", test))
+    } else {
+        args.bench
+            .as_deref()
+            .map(|bench| ("bench", "benchmark", bench))
     }
-    */
+}
 
-    let b = Arc::new(Mutex::new(SystemTime::now()));
+/// Whether the build is of test executables, rather than binaries.
+fn builds_tests(args: &BuildArgs) -> bool {
+    args.test.is_some() || args.bench.is_some() || args.tests || args.lib
+}
 
-    // Override ctrl+c handler to avoid premature exit
-    // TODO: this... doesn't stop the rust process exiting..?
-    ctrlc::set_handler(move || {
-        warn!("CTRL+C");
-        let mut then = b.lock().unwrap();
-        let now = SystemTime::now();
-        if now.duration_since(*then).unwrap() > Duration::from_secs(1) {
-            std::process::exit(0);
-        } else {
-            *then = now;
-        }
-    })
-    .expect("Error setting Ctrl-C handler");
+/// Whether `artifact` is the `kind` target called `name`. cargo also builds
+/// the package's binaries for an example or a test, or a bin can share its
+/// name with an example, so the kind is matched too, and a bin with its unit
+/// tests, so test builds are only the tests and benches themselves.
+fn is_target(artifact: &Artifact, kind: &str, name: &str) -> bool {
+    artifact.target.name == name
+        && artifact.target.kind.iter().any(|k| k == kind)
+        && artifact.profile.test == matches!(kind, "test" | "bench")
+}
 
-    let mut debug_cmd = Command::new(&debug_path);
-    debug_cmd.args(debug_args);
+/// Whether `artifact` is built from the package's library, of any crate
+/// type.
+fn is_lib(artifact: &Artifact) -> bool {
+    artifact
+        .target
+        .kind
+        .iter()
+        .any(|k| k.ends_with("lib") || k == "proc-macro")
+}
 
-    trace!("synthesized debug command: {:?}", debug_cmd);
+/// The artifacts of the build, narrowed to the target `--bin` or `--example`
+/// picks, if any.
+fn filter_artifacts(args: &BuildArgs, artifacts: Vec<Artifact>) -> Vec<Artifact> {
+    match wanted_target(args) {
+        Some((kind, _, name)) => artifacts
+            .into_iter()
+            .filter(|a| is_target(a, kind, name))
+            .collect(),
+        None => artifacts,
+    }
+}
 
-    debug_cmd.status().expect("error running debug command");
+/// The `required-features` of the target `--bin` or `--example` picks, if
+/// it's in the workspace, with a description of the target.
+fn required_features(args: &BuildArgs) -> Result<Option<(String, Vec<String>)>> {
+    let Some((kind, _, name)) = wanted_target(args) else {
+        return Ok(None);
+    };
 
-    trace!("debug command done");
+    let metadata = metadata(args)?;
+    Ok(metadata
+        .workspace_packages()
+        .into_iter()
+        .filter(|package| selected_package(args, package))
+        .find_map(|package| {
+            let target = package
+                .targets
+                .iter()
+                .find(|t| t.name == name && t.kind.iter().any(|k| k == kind))?;
+            Some((
+                format!("{kind} `{name}`"),
+                missing_features(&target.required_features, &package.features),
+            ))
+        }))
+}
 
-    Ok(())
+/// The features in `required` that a build with the default features
+/// doesn't enable, following the features each feature enables.
+fn missing_features(required: &[String], features: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    let mut enabled = BTreeSet::new();
+    let mut pending = vec!["default"];
+    while let Some(feature) = pending.pop() {
+        if !enabled.insert(feature) {
+            continue;
+        }
+        // `dep:foo` and `foo/bar` enable dependencies rather than features
+        let enables = features.get(feature).into_iter().flatten();
+        pending.extend(
+            enables
+                .map(String::as_str)
+                .filter(|f| !f.contains([':', '/'])),
+        );
+    }
+
+    required
+        .iter()
+        .filter(|f| !enabled.contains(f.as_str()))
+        .cloned()
+        .collect()
 }
 
-#[cfg(test)]
-mod test {
-    #[test]
-    fn fake_test() {
-        assert!(true);
+/// Explain a target that wasn't built because its `required-features`
+/// aren't enabled, which cargo skips without saying so.
+fn required_features_hint(args: &BuildArgs) -> Option<String> {
+    let (target, missing) = required_features(args).ok()??;
+    if missing.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "{target} requires the features [{}], which are not enabled; \
+         pass --enable-required-features to enable them",
+        missing.join(", ")
+    ))
+}
+
+/// Pick the artifact to launch out of the artifacts produced by [`build`].
+///
+/// The returned artifact always has an executable.
+fn select_binary(args: &BuildArgs, artifacts: Vec<Artifact>) -> Result<Artifact> {
+    // Find the output(s) we care about
+    trace!("found {} artifacts: {:?}", artifacts.len(), artifacts);
+
+    let mut binaries = artifacts
+        .into_iter()
+        .filter(|a| a.executable.is_some())
+        .collect::<Vec<_>>();
+
+    if let Some(id) = &args.bin_id {
+        return buildid::select(binaries, id);
+    }
+
+    // Test builds also build the package's binaries, for the tests to run
+    if builds_tests(args) && wanted_target(args).is_none() {
+        binaries.retain(|a| a.profile.test && (!args.lib || is_lib(a)));
+    }
+
+    if let Some((kind, description, name)) = wanted_target(args) {
+        binaries.retain(|a| is_target(a, kind, name));
+        match binaries.len() {
+            1 => Ok(binaries.remove(0)),
+            0 => bail!("Could not find {description} artifact {name}"),
+            // Each package of a workspace can have a test of that name
+            _ => {
+                let candidates = binaries
+                    .iter()
+                    .map(|a| format!("{} of {}", a.executable.as_ref().unwrap(), a.manifest_path))
+                    .collect::<Vec<_>>();
+                let packages = binaries
+                    .iter()
+                    .map(|a| package_name(&a.package_id.repr))
+                    .collect::<BTreeSet<_>>();
+                bail!(
+                    "More than one {description} artifact {name} produced ({}), \
+                     pick its package with --package <name>, one of: {}",
+                    candidates.join(", "),
+                    packages.into_iter().collect::<Vec<_>>().join(", ")
+                );
+            }
+        }
+    } else {
+        // Lots of binaries usually means the manifest is a virtual workspace root
+        let max_artifacts = args.max_artifacts.unwrap_or(DEFAULT_MAX_ARTIFACTS);
+        if max_artifacts != 0 && binaries.len() > max_artifacts {
+            bail!(
+                "The build produced {} binary artifacts (more than --max-artifacts {}). \
+                 Is --manifest-path pointing at a workspace root? \
+                 Scope the build with --bin or --example.",
+                binaries.len(),
+                max_artifacts
+            );
+        }
+
+        // Try and find the first binary. If more than one, return an error.
+        match binaries.len() {
+            1 => Ok(binaries.remove(0)),
+            0 => bail!(
+                "The build produced no binaries. Is this a library crate? \
+                 `cargo debug --list` shows the binaries and examples there are to debug."
+            ),
+            _ => {
+                let mut names = binaries
+                    .iter()
+                    .map(|a| a.target.name.as_str())
+                    .collect::<Vec<_>>();
+                names.sort();
+                let pick = if builds_tests(args) {
+                    "--test NAME or --lib"
+                } else {
+                    "--bin NAME"
+                };
+                bail!(
+                    "More than one binary artifact produced ({}), please pick one with {pick}. \
+                     `cargo debug --list` shows all of them.",
+                    names.join(", ")
+                );
+            }
+        }
+    }
+}
+
+/// The selected binary's path, which must still exist.
+///
+/// cargo reports where it linked the executable, but `--out-dir` (or a build
+/// script) can move it afterwards, which debuggers report confusingly.
+fn executable(artifact: &Artifact) -> Result<String> {
+    let Some(path) = &artifact.executable else {
+        bail!("{} is not an executable", artifact.target.name);
+    };
+    if !path.is_file() {
+        bail!(
+            "The selected binary does not exist at {path}. \
+             Was it moved by --out-dir or a build script?"
+        );
+    }
+
+    Ok(path.to_string())
+}
+
+/// `path`, given by `what`, as text for the debugger's commands or cargo's
+/// JSON. Unlike a program's arguments, those can't hold a path that isn't
+/// UTF-8, and a lossy one would name another file.
+fn utf8_path<'a>(path: &'a Path, what: &str) -> Result<&'a str> {
+    path.to_str()
+        .with_context(|| format!("{what} {} is not valid UTF-8", path.display()))
+}
+
+/// Whether cargo will find a manifest to build, the way it looks for one.
+fn has_manifest(args: &BuildArgs) -> bool {
+    if args.manifests.is_empty() {
+        return env::current_dir()
+            .is_ok_and(|cwd| cwd.ancestors().any(|d| d.join("Cargo.toml").is_file()));
+    }
+
+    args.manifests.iter().all(|m| Path::new(m).is_file())
+}
+
+/// The cargo profile the build uses.
+fn profile_name(args: &BuildArgs) -> &str {
+    if let Some(profile) = &args.profile {
+        profile
+    } else if args.release {
+        "release"
+    } else {
+        "dev"
+    }
+}
+
+/// Whether `package` is one `--package` selects, if it's given. A spec can
+/// pin a version, `name@1.2.3`, which only one package of a workspace has.
+fn selected_package(args: &BuildArgs, package: &Package) -> bool {
+    match &args.package {
+        Some(spec) => spec.split('@').next() == Some(package.name.as_str()),
+        None => true,
+    }
+}
+
+/// Whether a host of the target triple `host` can't run binaries for
+/// `target` natively. x86 binaries run on x86_64, the rest need their own
+/// architecture.
+fn foreign_arch(target: &str, host: &str) -> bool {
+    fn arch(triple: &str) -> &str {
+        match triple.split('-').next().unwrap_or_default() {
+            "i386" | "i586" | "i686" => "x86",
+            cpu => cpu,
+        }
+    }
+
+    match (arch(target), arch(host)) {
+        ("x86", "x86_64") => false,
+        (target, host) => target != host,
+    }
+}
+
+/// The target triple being built for, if not the host.
+fn target_triple(args: &BuildArgs) -> Option<String> {
+    args.target
+        .clone()
+        .or_else(|| env::var("CARGO_BUILD_TARGET").ok())
+}
+
+/// The host target triple, as reported by rustc.
+fn host_triple() -> Result<String> {
+    let rustc = env::var("RUSTC").unwrap_or(String::from("rustc"));
+    let output = Command::new(rustc).arg("-vV").output()?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|l| l.strip_prefix("host: "))
+        .map(String::from)
+        .context("Could not determine the host target triple")
+}
+
+fn metadata(args: &BuildArgs) -> Result<Metadata> {
+    let mut metadata_cmd = MetadataCommand::new();
+    // Of the first workspace, with more than one
+    if let Some(manifest) = args.manifests.first() {
+        metadata_cmd.manifest_path(manifest);
+    }
+    // `cargo metadata` has no --target-dir, but reports the variable's
+    if let Some(dir) = &args.target_dir {
+        metadata_cmd.env("CARGO_TARGET_DIR", dir);
+    }
+
+    Ok(metadata_cmd.no_deps().exec()?)
+}
+
+/// The directory cargo builds into.
+fn target_directory(args: &BuildArgs) -> Result<Utf8PathBuf> {
+    Ok(metadata(args)?.target_directory)
+}
+
+/// The sysroot of the toolchain doing the build.
+fn sysroot() -> Result<PathBuf> {
+    // rustup picks the toolchain by directory
+    let key = format!("sysroot {}", env::current_dir()?.display());
+    cache::probe(&key, &cache::toolchain_stamp(), || {
+        let rustc = env::var("RUSTC").unwrap_or(String::from("rustc"));
+        let output = Command::new(rustc)
+            .args(["--print", "sysroot"])
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+    })
+    .context("Could not determine the toolchain sysroot")
+}
+
+/// The commit the toolchain was built from, in `rustc -vV` output.
+fn commit_hash(verbose_version: &str) -> Option<&str> {
+    verbose_version
+        .lines()
+        .find_map(|l| l.strip_prefix("commit-hash: "))
+        .map(str::trim)
+        .filter(|h| *h != "unknown")
+}
+
+/// Map the `/rustc/<hash>` prefix the standard library's debug info was
+/// built under to its sources in the sysroot's `rust-src` component.
+fn std_source_map() -> Result<(String, String)> {
+    let rustc = env::var("RUSTC").unwrap_or(String::from("rustc"));
+    let output = Command::new(rustc).arg("-vV").output()?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    let hash = commit_hash(&version).context("Could not determine the toolchain's commit hash")?;
+
+    let src = sysroot()?.join("lib/rustlib/src/rust");
+    if !src.is_dir() {
+        bail!(
+            "the standard library's sources are not installed, \
+             add them with `rustup component add rust-src`"
+        );
+    }
+
+    Ok((format!("/rustc/{hash}"), src.to_string_lossy().into_owned()))
+}
+
+/// Find the toolchain's `rust-gdb`/`rust-lldb` wrapper for the debugger, which
+/// loads pretty-printers for standard library types.
+fn pretty_wrapper(debugger: &Debugger) -> Option<PathBuf> {
+    let name = match debugger {
+        Debugger::Gdb => "rust-gdb",
+        Debugger::Lldb => "rust-lldb",
+        _ => return None,
+    };
+
+    let key = format!("{name} {}", env::current_dir().ok()?.display());
+    let stamp = format!(
+        "{};{}",
+        cache::toolchain_stamp(),
+        cache::stamp(&[], &["PATH"])
+    );
+    cache::probe(&key, &stamp, || {
+        sysroot()
+            .ok()
+            .and_then(|sysroot| find_executable(&sysroot.join("bin").join(name)))
+            .or_else(|| find_executable(Path::new(name)))
+    })
+}
+
+/// Predict where cargo will place the requested binary, using `cargo metadata`.
+fn predict_binary(args: &BuildArgs) -> Result<Utf8PathBuf> {
+    let metadata = metadata(args)?;
+
+    let (kind, name) = match &args.example {
+        Some(example) => ("example", Some(example)),
+        None => ("bin", args.bin.as_ref()),
+    };
+
+    let targets = metadata
+        .workspace_packages()
+        .into_iter()
+        .filter(|p| selected_package(args, p))
+        .flat_map(|p| p.targets.iter())
+        .filter(|t| t.kind.iter().any(|k| k == kind))
+        .filter(|t| name.is_none_or(|n| t.name == *n))
+        .collect::<Vec<_>>();
+
+    let target = match targets.as_slice() {
+        [target] => target,
+        [] => match name {
+            Some(name) => bail!("Could not find {kind} target {name}"),
+            None => bail!("Could not find any {kind} targets"),
+        },
+        _ => bail!("More than one {kind} target found, please explicitly specify the binary."),
+    };
+
+    Ok(cargo_debug_lib::binary_path(
+        &metadata.target_directory,
+        target_triple(args).as_deref(),
+        profile_name(args),
+        &target.name,
+        kind == "example",
+    ))
+}
+
+/// The package name in a cargo package ID, `foo 0.1.0 (path+file:///foo)`
+/// or, since cargo 1.77, `path+file:///foo#0.1.0` or `...#foo@0.1.0`.
+fn package_name(id: &str) -> &str {
+    if let Some((name, _)) = id.split_once(' ') {
+        return name;
+    }
+    let (url, fragment) = id.rsplit_once('#').unwrap_or((id, ""));
+    match fragment.split_once('@') {
+        Some((name, _)) => name,
+        None => url.rsplit('/').next().unwrap_or(id),
+    }
+}
+
+/// Locate an executable, either by its path or by searching `PATH`.
+fn find_executable(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.to_path_buf());
+    }
+
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths).find_map(|dir| {
+        let candidate = dir.join(program);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+
+        // Bare names resolve with the executable suffix on Windows
+        let candidate = candidate.with_extension(env::consts::EXE_EXTENSION);
+        candidate.is_file().then_some(candidate)
+    })
+}
+
+/// The builds of `args`, one per `--manifest-path`, as each workspace is a
+/// cargo invocation of its own.
+fn per_manifest(args: &BuildArgs) -> Vec<BuildArgs> {
+    if args.manifests.len() <= 1 {
+        return vec![args.clone()];
+    }
+
+    args.manifests
+        .iter()
+        .map(|manifest| BuildArgs {
+            manifests: vec![manifest.clone()],
+            ..args.clone()
+        })
+        .collect()
+}
+
+/// Report what a debug session would do without running cargo at all.
+fn dry_build(
+    args: &BuildArgs,
+    debugger: &Debugger,
+    options: &[String],
+    setup: &DebugSetup,
+) -> Result<()> {
+    for one in per_manifest(args) {
+        let cargo_cmd = cargo_command(&one);
+        let cargo_args = cargo_cmd
+            .get_args()
+            .map(|a| a.to_string_lossy())
+            .collect::<Vec<_>>();
+        println!(
+            "Build command: {} {}",
+            cargo_cmd.get_program().to_string_lossy(),
+            cargo_args.join(" ")
+        );
+    }
+
+    let bin = match predict_binary(args) {
+        Ok(bin) => {
+            println!("Binary: {bin}");
+            bin.to_string()
+        }
+        Err(e) => {
+            warn!("could not resolve the binary from cargo metadata: {e}");
+            println!("Binary: <unresolved>");
+            "<bin>".to_string()
+        }
+    };
+
+    let (debug_path, debug_args) = debug_command(debugger, &bin, options, setup)?;
+    println!(
+        "Debug command: {} {}",
+        debug_path.display(),
+        debug_args.join(" ")
+    );
+
+    let cargo = cargo_command(args);
+    let mut missing = false;
+    for program in [Path::new(cargo.get_program()), &debug_path] {
+        if find_executable(program).is_none() {
+            error!("{} could not be found", program.display());
+            missing = true;
+        }
+    }
+
+    if missing {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Copy text to the system clipboard, printing it instead if there isn't one.
+fn copy_to_clipboard(text: &str) {
+    match arboard::Clipboard::new().and_then(|mut c| c.set_text(text)) {
+        Ok(()) => info!("copied to clipboard: {text}"),
+        Err(e) => {
+            warn!("could not access the clipboard: {e}");
+            println!("{text}");
+        }
+    }
+}
+
+/// Override the ctrl+c handler so the child process gets to handle it first.
+fn set_ctrlc_handler() {
+    let b = Arc::new(Mutex::new(SystemTime::now()));
+
+    // Override ctrl+c handler to avoid premature exit
+    // TODO: this... doesn't stop the rust process exiting..?
+    // Sessions of `--watch` end when it says so
+    let watched = env::var_os(watch::WATCHED_VAR).is_some();
+    ctrlc::set_handler(move || {
+        warn!("CTRL+C");
+        if watched {
+            return;
+        }
+        let mut then = b.lock().unwrap();
+        let now = SystemTime::now();
+        if now.duration_since(*then).unwrap() > Duration::from_secs(1) {
+            cleanup::exit(0);
+        } else {
+            *then = now;
+        }
+    })
+    .expect("Error setting Ctrl-C handler");
+}
+
+/// Run the debugger to completion, reporting its launch and exit as events.
+///
+/// With `input` or a `monitor`, the debugger's stdin is a pipe that gets the
+/// input lines first, as if they were typed at its prompt, and then this
+/// process's stdin, a line at a time so the monitor's commands can go in
+/// between. The debugger no longer reads a terminal, so there's no line
+/// editing after that.
+fn launch(
+    cmd: &mut Command,
+    events: &mut Events,
+    input: &[String],
+    monitor: Option<monitor::Monitor>,
+) -> Result<std::process::ExitStatus> {
+    if !input.is_empty() || monitor.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Could not run {}", cmd.get_program().to_string_lossy()))?;
+    events.debugger_launched(child.id());
+
+    if let Some(stdin) = child.stdin.take() {
+        let stdin = Arc::new(Mutex::new(stdin));
+        if let Some(monitor) = monitor {
+            monitor.spawn(Arc::clone(&stdin));
+        }
+
+        let input = input
+            .iter()
+            .map(|line| format!("{line}\n"))
+            .collect::<String>();
+        // Left blocked on this terminal if the debugger exits first
+        std::thread::spawn(move || {
+            use std::io::{BufRead, Write};
+            if stdin.lock().unwrap().write_all(input.as_bytes()).is_err() {
+                return;
+            }
+            let mut line = String::new();
+            while std::io::stdin().lock().read_line(&mut line).unwrap_or(0) > 0 {
+                if stdin.lock().unwrap().write_all(line.as_bytes()).is_err() {
+                    break;
+                }
+                line.clear();
+            }
+        });
+    }
+
+    let status = child.wait()?;
+    events.debugger_exited(&status);
+
+    Ok(status)
+}
+
+/// Why the build had too many warnings, if it did.
+fn too_many_warnings(warnings: usize, max: usize) -> Option<String> {
+    if warnings <= max {
+        return None;
+    }
+
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    Some(match max {
+        0 => format!("the build had {warnings} warning{}", plural(warnings)),
+        max => format!(
+            "the build had {warnings} warning{}, more than --max-warnings {max}",
+            plural(warnings)
+        ),
+    })
+}
+
+/// The flag that prints its own output to stdout, which would mix with JSON events.
+fn human_output_flag(args: &Args) -> Option<&'static str> {
+    [
+        (args.list, "--list"),
+        (args.complete.is_some(), "--complete"),
+        (args.dump_config, "--dump-config"),
+        (args.dry_build, "--dry-build"),
+        (args.build_only, "--build-only"),
+        (args.artifact_json, "--artifact-json"),
+        (args.list_artifacts_json, "--list-artifacts-json"),
+        (args.compare_with.is_some(), "--compare-with"),
+        (args.emit_vscode.is_some(), "--emit-vscode"),
+        (args.shared_libs, "--shared-libs"),
+        (args.serve_artifact.is_some(), "--serve-artifact"),
+        (args.remote.is_some(), "--remote"),
+        (args.copy, "--copy"),
+    ]
+    .into_iter()
+    .find_map(|(set, flag)| set.then_some(flag))
+}
+
+/// Run the selected binary directly, exiting with its status code.
+fn run(bin: &str, options: &[String]) -> Result<()> {
+    set_ctrlc_handler();
+
+    let mut run_cmd = Command::new(bin);
+    run_cmd.args(options);
+
+    trace!("synthesized run command: {:?}", run_cmd);
+
+    let status = run_cmd.status().expect("error running binary");
+
+    trace!("run command done");
+
+    if let Some(report) = signal::report(bin, &status) {
+        eprintln!("{report}");
+    }
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+/// Parse a `FROM=TO` path mapping.
+fn parse_path_map(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .ok_or_else(|| format!("expected FROM=TO, got `{s}`"))
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{s}`"))
+}
+
+/// The `--config` values for `--opt-debug` building `profile`, with
+/// `overrides` replacing or adding to the defaults.
+fn opt_debug_configs(profile: &str, overrides: &[(String, String)]) -> Vec<String> {
+    let mut settings = OPT_DEBUG_CONFIG
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .to_vec();
+    for (key, value) in overrides {
+        match settings.iter_mut().find(|(k, _)| k == key) {
+            Some(setting) => setting.1 = value.clone(),
+            None => settings.push((key.clone(), value.clone())),
+        }
+    }
+
+    settings
+        .iter()
+        .map(|(key, value)| format!("profile.{profile}.{key}={value}"))
+        .collect()
+}
+
+/// Parse a `ROWSxCOLS` terminal size.
+fn parse_tui_size(s: &str) -> Result<(u16, u16), String> {
+    let parse = |n: &str| n.parse::<u16>().ok().filter(|n| *n != 0);
+    s.split_once('x')
+        .and_then(|(rows, cols)| Some((parse(rows)?, parse(cols)?)))
+        .ok_or_else(|| format!("expected ROWSxCOLS, got `{s}`"))
+}
+
+/// Parse a `START-END` range of hex addresses, with or without `0x`.
+fn parse_address_range(s: &str) -> Result<(u64, u64), String> {
+    let parse = |n: &str| {
+        let n = n.trim();
+        let n = n
+            .strip_prefix("0x")
+            .or_else(|| n.strip_prefix("0X"))
+            .unwrap_or(n);
+        u64::from_str_radix(n, 16).ok()
+    };
+    s.split_once('-')
+        .and_then(|(start, end)| Some((parse(start)?, parse(end)?)))
+        .filter(|(start, end)| start < end)
+        .ok_or_else(|| format!("expected a hex START-END range with START < END, got `{s}`"))
+}
+
+/// The gdb command watching writes to `[start, end)`.
+fn watch_range_command((start, end): (u64, u64)) -> String {
+    match end - start {
+        1 => format!("watch *(char*){start:#x}"),
+        len => format!("watch *(char(*)[{len}]){start:#x}"),
+    }
+}
+
+/// Variables asking for colored output whether or not stdout is a terminal,
+/// understood by most color libraries (`termcolor`, `anstream`, `colored`,
+/// chalk and others).
+const FORCE_COLOR_ENV: [(&str, &str); 2] = [("CLICOLOR_FORCE", "1"), ("FORCE_COLOR", "1")];
+
+/// Startup commands redirecting the program's stdin from `file`.
+///
+/// gdb runs the program through a shell, so the redirection goes in its
+/// arguments, which have to be repeated.
+fn stdin_commands(debugger: &Debugger, file: &Path, options: &[String]) -> Result<Vec<String>> {
+    let file = utf8_path(file, "--stdin-file")?;
+    Ok(match debugger {
+        Debugger::Gdb => {
+            let mut command = "set args".to_string();
+            for option in options {
+                command.push(' ');
+                command.push_str(&quote::sh(option));
+            }
+            vec![format!("{command} < {}", quote::sh(file))]
+        }
+        Debugger::Lldb => vec![format!(
+            "settings set target.input-path {}",
+            quote::lldb(file)
+        )],
+        _ => bail!("--stdin-file is only supported by gdb and lldb"),
+    })
+}
+
+/// The environment variable gdbserver's listen address is taken from when
+/// `--address` isn't given, for scripts and containers.
+const GDBSERVER_ADDR_ENV: &str = "CARGO_DEBUG_GDBSERVER_ADDR";
+
+/// gdbserver's listen address: `--address`, then `CARGO_DEBUG_GDBSERVER_ADDR`.
+/// A bare port listens on all interfaces, so it's reachable from outside a
+/// container. A serial device, such as `/dev/ttyUSB0`, is passed on as is.
+fn gdbserver_address(cli: Option<&str>, env: Option<&str>) -> Result<String> {
+    let address = match (cli, env.map(str::trim)) {
+        (Some(address), _) => address,
+        (None, Some(address)) if !address.is_empty() => address,
+        _ => bail!(
+            "gdbserver needs an address to listen on; \
+             pass --address [HOST:]PORT or set {GDBSERVER_ADDR_ENV}"
+        ),
+    };
+
+    if address.parse::<u16>().is_ok() {
+        return Ok(format!("0.0.0.0:{address}"));
+    }
+    let has_port = match address.rsplit_once(':') {
+        Some((_, port)) => port.parse::<u16>().is_ok(),
+        None => address.starts_with('/'),
+    };
+    if !has_port {
+        bail!("gdbserver can't listen on {address}, which has no port; pass --address [HOST:]PORT");
+    }
+
+    Ok(address.to_string())
+}
+
+/// gdb's `--interpreter` flag, for an MI version or an interpreter by name.
+fn gdb_interpreter_flag(mi_version: Option<u8>, name: Option<&str>) -> Option<String> {
+    let name = match (mi_version, name) {
+        (Some(version), _) => format!("mi{version}"),
+        (None, Some(name)) => name.to_string(),
+        (None, None) => return None,
+    };
+
+    Some(format!("--interpreter={name}"))
+}
+
+/// The perf subcommand and its flags: `record -g` unless `stat` flags are given.
+fn perf_args(record_flags: Option<&str>, stat_flags: Option<&str>) -> Vec<String> {
+    let (mut args, flags) = match stat_flags {
+        Some(flags) => (vec!["stat".to_string()], flags),
+        None => (
+            vec!["record".to_string(), "-g".to_string()],
+            record_flags.unwrap_or_default(),
+        ),
+    };
+    args.extend(flags.split_whitespace().map(String::from));
+
+    args
+}
+
+/// Commands remapping source paths baked into the debug info.
+fn source_map_commands(debugger: &Debugger, maps: &[(String, String)]) -> Vec<String> {
+    maps.iter()
+        .filter_map(|(from, to)| match debugger {
+            Debugger::Gdb => Some(format!(
+                "set substitute-path {} {}",
+                quote::gdb(from),
+                quote::gdb(to)
+            )),
+            Debugger::Lldb => Some(format!(
+                "settings set target.source-map {} {}",
+                quote::lldb(from),
+                quote::lldb(to)
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Commands keeping the other threads stopped while one steps, or runs.
+///
+/// lldb has no such setting, but its stepping commands take a run mode, so
+/// they're aliased to ones stepping the current thread alone.
+fn lock_scheduler_commands(debugger: &Debugger, mode: &SchedulerLocking) -> Result<Vec<String>> {
+    match (debugger, mode) {
+        (Debugger::Gdb, SchedulerLocking::Step) => {
+            Ok(vec!["set scheduler-locking step".to_string()])
+        }
+        (Debugger::Gdb, SchedulerLocking::On) => Ok(vec!["set scheduler-locking on".to_string()]),
+        (Debugger::Lldb, SchedulerLocking::Step) => Ok([
+            ("n", "thread step-over"),
+            ("next", "thread step-over"),
+            ("s", "thread step-in"),
+            ("step", "thread step-in"),
+        ]
+        .into_iter()
+        .flat_map(|(alias, command)| {
+            [
+                format!("command unalias {alias}"),
+                format!("command alias {alias} {command} --run-mode this-thread"),
+            ]
+        })
+        .collect()),
+        (Debugger::Lldb, SchedulerLocking::On) => {
+            bail!("lldb can only keep other threads stopped while stepping, use --lock-scheduler step")
+        }
+        _ => bail!("--lock-scheduler is only supported by gdb and lldb"),
+    }
+}
+
+/// Whether the lldb `lldb --version` describes can follow forks, which
+/// LLDB 14 added on Linux and FreeBSD. Apple's lldb, versioned
+/// `lldb-1500...`, can't.
+fn lldb_follows_forks(version: &str) -> bool {
+    version
+        .split_whitespace()
+        .skip_while(|word| *word != "version")
+        .nth(1)
+        .and_then(|v| v.split('.').next()?.parse::<u32>().ok())
+        .is_some_and(|major| major >= 14)
+}
+
+/// The flags and commands following the program into its children, and a
+/// summary of what that means for `debugger`, as each does it differently.
+/// `lldb_version` is what `lldb --version` printed.
+fn debug_children_setup(
+    debugger: &Debugger,
+    lldb_version: Option<&str>,
+) -> Result<(Vec<String>, Vec<String>, &'static str)> {
+    let is_cdb = |path: &Path| {
+        path.file_stem()
+            .is_some_and(|stem| stem.eq_ignore_ascii_case("cdb"))
+    };
+    match debugger {
+        Debugger::Gdb => Ok((
+            vec![],
+            [
+                "set detach-on-fork off",
+                "set follow-fork-mode child",
+                "set follow-exec-mode new",
+            ]
+            .map(String::from)
+            .to_vec(),
+            "gdb follows forks into the child, keeping the parent, and each exec into a new inferior",
+        )),
+        Debugger::Lldb => {
+            let version = lldb_version.unwrap_or_default().trim();
+            if !lldb_follows_forks(version) {
+                bail!(
+                    "{} can't follow forks, --debug-children needs LLDB 14 or later on Linux or FreeBSD",
+                    if version.is_empty() { "lldb" } else { version }
+                );
+            }
+            Ok((
+                vec![],
+                vec!["settings set target.process.follow-fork-mode child".to_string()],
+                "lldb follows forks into the child, detaching from the parent",
+            ))
+        }
+        // WinDbg is always launched with -o
+        Debugger::Windbg => Ok((
+            vec![],
+            vec![".childdbg 1".to_string()],
+            "windbg debugs every child process too (-o, .childdbg 1)",
+        )),
+        Debugger::Custom(path) if is_cdb(path) => Ok((
+            vec!["-o".to_string()],
+            vec![".childdbg 1".to_string()],
+            "cdb debugs every child process too (-o, .childdbg 1)",
+        )),
+        _ => bail!("--debug-children is only supported by gdb, lldb, windbg and cdb"),
+    }
+}
+
+/// The gdb command evaluating `expr` on startup: assignments to convenience
+/// variables (`$foo = 4`) are `set`, anything else is printed.
+fn gdb_eval_command(expr: &str) -> String {
+    let assignment = expr
+        .strip_prefix('$')
+        .and_then(|rest| rest.split_once('='))
+        .is_some_and(|(name, value)| {
+            let name = name.trim();
+            !name.is_empty()
+                && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+                && !value.starts_with('=')
+        });
+
+    if assignment {
+        format!("set {expr}")
+    } else {
+        format!("print {expr}")
+    }
+}
+
+/// Commands adding `root` to the directories searched for sources.
+fn source_root_commands(debugger: &Debugger, root: &str) -> Vec<String> {
+    match debugger {
+        // gdb takes the rest of the line as the directory, quotes and all
+        Debugger::Gdb => vec![format!("directory {root}")],
+        // Relative paths in the debug info are matched by a `.` prefix
+        Debugger::Lldb => vec![format!(
+            "settings append target.source-map . {}",
+            quote::lldb(root)
+        )],
+        _ => vec![],
+    }
+}
+
+/// The arguments naming `bin` and its `options` to the debugger `program`,
+/// as cargo-debug-lib puts them.
+fn target_args(program: &str, bin: &str, options: &[String]) -> Vec<String> {
+    cargo_debug_lib::debugger_command(program, Path::new(bin), options)
+        .get_args()
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Whether gdb can be given its startup commands in a temporary file. A
+/// command line run elsewhere, or after we're gone, can't rely on one, and
+/// another user (`--user`) can't read this user's.
+fn command_file_usable(args: &Args) -> bool {
+    let detached = args.copy
+        || args.no_run
+        || args.emit_script.is_some()
+        || args.pack_repro.is_some()
+        || args.tmux.is_some()
+        || args.new_terminal
+        || args.terminal.is_some();
+
+    !detached && args.user.is_none()
+}
+
+/// Synthesize the debugger invocation for the selected binary.
+fn debug_command(
+    debugger: &Debugger,
+    bin: &str,
+    options: &[String],
+    setup: &DebugSetup,
+) -> Result<(PathBuf, Vec<String>)> {
+    let debug_path: PathBuf;
+    let mut debug_args: Vec<String> = setup.flags.clone();
+    let commands = &setup.commands;
+
+    match debugger {
+        Debugger::Gdb => {
+            debug_path = PathBuf::from("gdb");
+
+            // Source the command file first, so that its breakpoints are in
+            // place before a startup command runs the program
+            debug_args.extend(setup.startup_args(debugger));
+
+            // Specify file to be debugged, and its options after `--args`
+            debug_args.extend(target_args("gdb", bin, options));
+        }
+        Debugger::Lldb => {
+            debug_path = PathBuf::from("lldb");
+
+            // Specify file to be debugged, with its options set on the
+            // target ahead of the startup commands that may launch it
+            debug_args.extend(target_args("lldb", bin, options));
+
+            // Source the command file ahead of the startup commands, run
+            // once the file is loaded
+            debug_args.extend(setup.startup_args(debugger));
+        }
+        Debugger::Gdbserver => {
+            debug_path = PathBuf::from("gdbserver");
+
+            if !commands.is_empty() {
+                warn!("gdbserver does not take startup commands, ignoring them");
+            }
+
+            // The listen address comes first, in `setup.flags`
+            // Specify file to be debugged, then its options
+            debug_args.extend(target_args("gdbserver", bin, options));
+        }
+        Debugger::Devenv => {
+            #[cfg(target_os = "windows")]
+            {
+                // Find the path to devenv
+                let devenv = cache::probe("devenv", &cache::visual_studio_stamp(), || {
+                    let install_info = vswhere::Config::new()
+                        .only_latest_versions(true)
+                        .run_default_path()
+                        .ok()?;
+
+                    install_info
+                        .iter()
+                        .find(|m| {
+                            m.product_id()
+                                .starts_with("Microsoft.VisualStudio.Product.")
+                        })
+                        .map(|info| info.product_path().to_owned())
+                });
+
+                if !commands.is_empty() {
+                    warn!("devenv does not take startup commands, ignoring them");
+                }
+
+                if let Some(devenv) = devenv {
+                    debug_path = devenv;
+                    debug_args.push("/DebugExe".to_string());
+
+                    // Specify file to be debugged
+                    debug_args.push(bin.to_string());
+
+                    // Append child options
+                    if !options.is_empty() {
+                        debug_args.extend(options.iter().cloned());
+                    }
+                } else {
+                    bail!("Could not find a compatible version of Visual Studio :(");
+                }
+            }
+            #[cfg(not(target_os = "windows"))]
+            {
+                bail!("devenv is only available on Windows");
+            }
+        }
+        Debugger::Perf => {
+            // The subcommand and its flags come in through `setup.flags`
+            debug_path = PathBuf::from("perf");
+
+            if !commands.is_empty() {
+                warn!("perf does not take startup commands, ignoring them");
+            }
+
+            // Specify the program to profile
+            debug_args.push("--".to_string());
+            debug_args.push(bin.to_string());
+
+            // Append child options
+            debug_args.extend(options.iter().cloned());
+        }
+        Debugger::Frida => {
+            // The script to load comes in through `setup.flags`
+            debug_path = PathBuf::from("frida");
+
+            if !commands.is_empty() {
+                warn!("frida does not take startup commands, ignoring them");
+            }
+
+            // Spawn the program, rather than attach to a running one
+            debug_args.push("-f".to_string());
+            debug_args.push(bin.to_string());
+
+            // Append child options, which frida shouldn't parse
+            if !options.is_empty() {
+                debug_args.push("--".to_string());
+                debug_args.extend(options.iter().cloned());
+            }
+        }
+        Debugger::Custom(path) => {
+            debug_path = path.clone();
+
+            if !commands.is_empty() {
+                warn!(
+                    "{} is not a known debugger, ignoring startup commands",
+                    path.display()
+                );
+            }
+
+            // Specify file to be debugged, then its options
+            debug_args.extend(target_args(&path.to_string_lossy(), bin, options));
+        }
+        Debugger::Windbg => {
+            debug_path = PathBuf::from("windbgx");
+
+            debug_args.push("-o".to_string());
+
+            // Run startup commands once the target starts
+            if !commands.is_empty() {
+                debug_args.push("-c".to_string());
+                debug_args.push(commands.join("; "));
+            }
+
+            // Specify file to be debugged
+            debug_args.push(bin.to_string());
+
+            // Append child options
+            if !options.is_empty() {
+                debug_args.extend(options.iter().cloned());
+            }
+        }
+    }
+
+    let debug_path = setup.program.clone().unwrap_or(debug_path);
+
+    Ok((debug_path, debug_args))
+}
+
+fn main() -> Result<()> {
+    let mut phases = timings::Phases::start();
+    let CargoCli::Debug(mut args) = CargoCli::parse();
+
+    if args.verbose {
+        let _ = simplelog::TermLogger::init(
+            log::LevelFilter::Info,
+            simplelog::Config::default(),
+            simplelog::TerminalMode::Stderr,
+            simplelog::ColorChoice::Auto,
+        );
+    }
+    if args.no_cache {
+        cache::disable();
+    }
+
+    if args.message_format == MessageFormat::Json {
+        if let Some(flag) = human_output_flag(&args) {
+            bail!(
+                "--message-format json can't be combined with {flag}, which prints its own output"
+            );
+        }
+    }
+    let mut events = Events::new(args.message_format);
+
+    if let Some(DebugCommand::Run(run_args)) = args.command {
+        let artifacts = build(&run_args.build, false, &mut Events::default()).artifacts;
+        let artifact = select_binary(&run_args.build, artifacts)?;
+        let bin = executable(&artifact)?;
+
+        info!("selected binary: {:?}", bin);
+
+        return run(&bin, &run_args.options);
+    }
+
+    if args.build.doc {
+        bail!(
+            "Doctests are not supported: rustdoc compiles and runs each one on its own, \
+             leaving no binary to debug. Move the example into a #[test] and debug it with --lib."
+        );
+    }
+
+    if !has_manifest(&args.build) {
+        bail!(
+            "There is no Cargo.toml here, or in any parent directory. \
+             Run cargo debug inside a cargo project, or point it at one with --manifest-path; \
+             `cargo debug --list` then shows the binaries and examples it can debug."
+        );
+    }
+
+    // An index of everything built, rather than of the manifests
+    if args.list && !args.build.all_targets {
+        let metadata = metadata(&args.build)?;
+        for kind in [TargetKind::Bin, TargetKind::Example] {
+            for name in complete::target_names(&metadata, &kind) {
+                println!("--{} {name}", kind.name());
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(kind) = &args.complete {
+        for name in complete::target_names(&metadata(&args.build)?, kind) {
+            println!("{name}");
+        }
+        return Ok(());
+    }
+
+    if args.dump_config {
+        print!("{}", config::dump(&args));
+        return Ok(());
+    }
+
+    if args.watch {
+        let metadata = metadata(&args.build)?;
+        return watch::run(
+            metadata.workspace_root.as_std_path(),
+            metadata.target_directory.as_std_path(),
+        );
+    }
+
+    let mut options = testbin::harness_args(args.test_filter.as_deref(), &args.options);
+    if let (Some(target), Some(input)) = (&args.fuzz, &args.fuzz_input) {
+        options.extend(fuzz::prepare(
+            &mut args.build,
+            target,
+            input,
+            args.fuzz_sanitizer,
+        )?);
+    }
+    let nextest_test = match &args.nextest {
+        Some(filterset) => {
+            let test = nextest::select(&args.build, filterset)?;
+            options = testbin::harness_args(Some(&test.name), &args.options);
+            Some(test)
+        }
+        None => None,
+    };
+    let options = options;
+
+    if let Some(path) = &args.load_env {
+        BuildEnv::load(path)?.restore(&mut args.build);
+    }
+
+    if let Some(path) = &args.save_env {
+        BuildEnv::capture(&args.build).save(path)?;
+    }
+
+    let command_file_usable = command_file_usable(&args);
+    let command_line = args
+        .debugger_cmd
+        .as_deref()
+        .map(debuggers::from_command_line)
+        .transpose()?;
+    let debugger = if args.perf {
+        if !cfg!(target_os = "linux") {
+            bail!("--perf is only supported on Linux");
+        }
+        Debugger::Perf
+    } else if let Some(defined) = &command_line {
+        Debugger::Custom(defined.program.clone())
+    } else {
+        args.debugger.unwrap_or_default()
+    };
+    let debugger =
+        if cfg!(target_os = "macos") && !args.no_lldb_fallback && args.debugger_path.is_none() {
+            fallback::macos(debugger)
+        } else {
+            debugger
+        };
+    // A name that isn't built in may be one the manifest or a config file
+    // defines
+    let defined = match &debugger {
+        _ if command_line.is_some() => command_line,
+        Debugger::Custom(path) if path.components().count() == 1 => {
+            debuggers::find(&metadata(&args.build)?, &path.to_string_lossy())?
+        }
+        _ => None,
+    };
+
+    // Before the build, which a bad address would only waste
+    let gdbserver_listen =
+        if debugger == Debugger::Gdbserver && !args.no_debugger && args.remote.is_none() {
+            Some(gdbserver_address(
+                args.address.as_deref(),
+                env::var(GDBSERVER_ADDR_ENV).ok().as_deref(),
+            )?)
+        } else if args.attach {
+            bail!("--attach connects gdb to gdbserver, debug with gdbserver to use it");
+        } else {
+            None
+        };
+    let gdbserver_connect = match &gdbserver_listen {
+        Some(address) if args.attach => Some(gdbclient::client_address(address)?),
+        _ => None,
+    };
+
+    if args.check_capabilities {
+        #[cfg(target_os = "linux")]
+        capabilities::check(&debugger)?;
+        #[cfg(not(target_os = "linux"))]
+        warn!("--check-capabilities is only supported on Linux");
+    }
+
+    #[cfg(unix)]
+    if let Some(user) = &args.user {
+        runas::check(user)?;
+    }
+
+    let mut setup = DebugSetup::default();
+
+    if args.pretty {
+        if !matches!(debugger, Debugger::Gdb | Debugger::Lldb) {
+            bail!(
+                "--pretty wraps gdb or lldb, the toolchain has no wrapper for {}",
+                debugger.name()
+            );
+        }
+
+        setup.program = pretty_wrapper(&debugger);
+        if setup.program.is_none() {
+            warn!(
+                "rust-{0} not found, debugging with plain {0}, without Rust pretty-printers",
+                debugger.name()
+            );
+        }
+    }
+
+    if let Some(path) = &args.debugger_path {
+        setup.program = Some(path.clone());
+    }
+    // Launched here, a binary for another architecture doesn't run at all
+    let remote_launch = args.remote.is_some() || debugger == Debugger::Gdbserver;
+    if matches!(debugger, Debugger::Gdb | Debugger::Lldb) && !remote_launch {
+        if let (Some(triple), Ok(host)) = (target_triple(&args.build), host_triple()) {
+            if foreign_arch(&triple, &host) {
+                warn!(
+                    "the binary is built for {triple}, which this {host} machine can't run; \
+                     debug it on one that can with --remote, or --debugger gdbserver there"
+                );
+            }
+        }
+    }
+
+    // The SDK's cdb and windbg come in one build per architecture
+    if cfg!(windows) && (args.debugger_path.is_some() || matches!(debugger, Debugger::Custom(_))) {
+        let triple = target_triple(&args.build).or_else(|| host_triple().ok());
+        if let Some(triple) = triple {
+            let explicit = args.debugger_path.as_deref();
+            if let Some(path) = winarch::debugger(&debugger, &triple, explicit) {
+                setup.program = Some(path);
+            }
+        }
+    }
+
+    // Debuggers that can't log natively are wrapped once the command is known
+    let mut tee_transcript = None;
+    if let Some(path) = &args.transcript {
+        match transcript::flags(&debugger, path) {
+            Some(flags) => setup.flags.extend(flags),
+            None => tee_transcript = Some(path),
+        }
+    }
+
+    // rust-gdb loads them itself
+    if debugger == Debugger::Gdb && setup.program.is_none() && !args.no_pretty_printers {
+        let sysroot = sysroot().ok();
+        setup.flags.extend(prettyprinters::load(sysroot.as_deref()));
+    }
+
+    if args.pwndbg {
+        if debugger != Debugger::Gdb {
+            bail!("--pwndbg is only supported by gdb");
+        }
+
+        let extension = gdbext::Extension::Pwndbg;
+        setup.flags.extend(gdbext::load(extension)?);
+        if let Some(theme) = &args.pwndbg_theme {
+            setup.commands.extend(extension.theme_commands(theme));
+        }
+    }
+
+    if args.gef {
+        if debugger != Debugger::Gdb {
+            bail!("--gef is only supported by gdb");
+        }
+
+        // Must be evaluated before gdb gets to the local .gdbinit
+        if args.gef_no_local_init {
+            setup.flags.push("-iex".to_string());
+            setup
+                .flags
+                .push("set auto-load local-gdbinit off".to_string());
+        }
+
+        setup.flags.extend(gdbext::load(gdbext::Extension::Gef)?);
+        if let Some(arch) = &args.gef_arch {
+            setup.commands.push(format!("arch set {arch}"));
+        }
+    }
+
+    if !args.gdb_iex_eval.is_empty() {
+        if debugger != Debugger::Gdb {
+            bail!("--gdb-iex-eval is only supported by gdb");
+        }
+
+        for expr in &args.gdb_iex_eval {
+            setup.flags.push("-iex".to_string());
+            setup.flags.push(gdb_eval_command(expr));
+        }
+    }
+
+    let asan_build = sanitizer::detected(
+        env::var("CARGO_ENCODED_RUSTFLAGS").ok().as_deref(),
+        env::var("RUSTFLAGS").ok().as_deref(),
+    );
+    if let Some(range) = args.asan_focus {
+        if debugger != Debugger::Gdb {
+            bail!("--asan-focus is only supported by gdb");
+        }
+
+        if !asan_build {
+            warn!("--asan-focus expects a binary built with -Zsanitizer=address in RUSTFLAGS");
+        }
+
+        setup.flags.push("-iex".to_string());
+        setup.flags.push(watch_range_command(range));
+    }
+
+    // The failing run is only caught in the debugger if it panics there too
+    let catch_panic = (args.run_until_crash.is_some() || args.on_failure || args.fuzz.is_some())
+        && matches!(debugger, Debugger::Gdb | Debugger::Lldb);
+    if let Some(mode) = &args.lock_scheduler {
+        setup
+            .commands
+            .extend(lock_scheduler_commands(&debugger, mode)?);
+    }
+    if args.debug_children {
+        let lldb_version = (debugger == Debugger::Lldb)
+            .then(|| {
+                let lldb = setup.program.clone().unwrap_or(PathBuf::from("lldb"));
+                version::probe(&debugger, &lldb)
+            })
+            .flatten();
+        let (flags, commands, summary) = debug_children_setup(&debugger, lldb_version.as_deref())?;
+        setup.flags.extend(flags);
+        setup.commands.extend(commands);
+        eprintln!("cargo-debug: {summary}");
+    }
+
+    if args.break_on_panic || catch_panic {
+        match debugger {
+            Debugger::Gdb => setup.commands.push("break rust_panic".to_string()),
+            Debugger::Lldb => setup
+                .commands
+                .push("breakpoint set --name rust_panic".to_string()),
+            _ => bail!("--break-on-panic is only supported by gdb and lldb"),
+        }
+    }
+
+    // Detected builds only get what the debugger supports
+    let asan = args.asan || (asan_build && !args.no_asan);
+    if asan && (args.asan || matches!(debugger, Debugger::Gdb | Debugger::Lldb)) {
+        setup.commands.extend(sanitizer::commands(&debugger)?);
+    }
+    if args.aslr_disable {
+        setup.commands.extend(aslr::commands(&debugger));
+    }
+    if let Some(script) = &args.post_crash_script {
+        setup
+            .commands
+            .extend(crashscript::commands(&debugger, script)?);
+    }
+
+    let perf_output = if args.perf {
+        if find_executable(Path::new("perf")).is_none() {
+            bail!("{}", perf::MISSING);
+        }
+        let output = target_directory(&args.build)?.join("perf.data");
+        let frame_pointers = perf::frame_pointers(&pathmap::rustflags());
+        setup.flags = perf::record_args(
+            output.as_str(),
+            frame_pointers,
+            args.perf_record_flags.as_deref(),
+        );
+        Some(output)
+    } else if debugger == Debugger::Perf {
+        setup.flags = perf_args(
+            args.perf_record_flags.as_deref(),
+            args.perf_stat_flags.as_deref(),
+        );
+        None
+    } else if args.perf_record_flags.is_some() || args.perf_stat_flags.is_some() {
+        bail!("--perf-record-flags and --perf-stat-flags need --debugger perf");
+    } else {
+        None
+    };
+
+    if debugger == Debugger::Frida {
+        if find_executable(Path::new("frida")).is_none() {
+            bail!("frida was not found; install it with `pip install frida-tools`");
+        }
+        if let Some(script) = &args.frida_script {
+            if !script.is_file() {
+                bail!("Frida script {} does not exist", script.display());
+            }
+            setup.flags.push("-l".to_string());
+            setup
+                .flags
+                .push(utf8_path(script, "Frida script")?.to_string());
+        }
+    } else if args.frida_script.is_some() {
+        bail!("--frida-script needs --debugger frida");
+    }
+
+    // Import scripts first, so later commands can use what they define
+    if let Some(script) = &args.lldb_python {
+        if debugger != Debugger::Lldb {
+            bail!("--lldb-python is only supported by lldb");
+        }
+        if !script.is_file() {
+            bail!("lldb Python script {} does not exist", script.display());
+        }
+
+        let script = utf8_path(script, "--lldb-python")?;
+        setup
+            .commands
+            .push(format!("command script import {}", quote::lldb(script)));
+    }
+
+    let tui = args.tui || args.gdb_tui_layout.is_some();
+    if (tui || args.gdb_tui_size.is_some()) && debugger != Debugger::Gdb {
+        bail!("--tui, --gdb-tui-layout and --gdb-tui-size are only supported by gdb");
+    }
+
+    if tui {
+        setup.flags.push("--tui".to_string());
+    }
+
+    if let Some(flag) = gdb_interpreter_flag(args.gdb_mi_version, args.gdb_interpreter.as_deref()) {
+        if debugger != Debugger::Gdb {
+            bail!("--gdb-mi-version and --gdb-interpreter are only supported by gdb");
+        }
+        setup.flags.push(flag);
+    }
+
+    if let Some(layout) = &args.gdb_tui_layout {
+        setup.commands.push(format!("layout {}", layout.name()));
+        setup.commands.push("focus cmd".to_string());
+    }
+
+    // Static musl binaries carry musl's debug info, but not its sources
+    if target_triple(&args.build).is_some_and(|t| t.contains("-musl")) {
+        warn!("stepping into libc may be unavailable for musl targets");
+        if args.musl_source_map.is_empty() {
+            info!("use --musl-source-map FROM=TO to point the debugger at musl's sources");
+        }
+
+        setup
+            .commands
+            .extend(source_map_commands(&debugger, &args.musl_source_map));
+    }
+
+    if !args.no_std_source_map && matches!(debugger, Debugger::Gdb | Debugger::Lldb) {
+        match std_source_map() {
+            Ok((from, to)) => setup.commands.push(match debugger {
+                // Appended, as `settings set` would drop the mappings made so far
+                Debugger::Lldb => format!(
+                    "settings append target.source-map {} {}",
+                    quote::lldb(&from),
+                    quote::lldb(&to)
+                ),
+                _ => format!(
+                    "set substitute-path {} {}",
+                    quote::gdb(&from),
+                    quote::gdb(&to)
+                ),
+            }),
+            Err(e) => warn!("not mapping the standard library's sources: {e}"),
+        }
+    }
+
+    if args.natvis || (debugger == Debugger::Windbg && !args.no_natvis) {
+        match sysroot().and_then(|sysroot| natvis::setup(&debugger, &sysroot)) {
+            Ok(commands) => setup.commands.extend(commands),
+            Err(e) => warn!("not loading Natvis visualizers: {e}"),
+        }
+    }
+
+    let path_maps = if args.path_map.is_empty() {
+        let remapped = pathmap::detect();
+        if remapped.is_empty() {
+            vec![]
+        } else {
+            let root = metadata(&args.build)?.workspace_root;
+            pathmap::inverse(&remapped, root.as_std_path(), Path::exists)
+        }
+    } else {
+        args.path_map.clone()
+    };
+    if matches!(debugger, Debugger::Gdb | Debugger::Lldb) {
+        for (from, to) in &path_maps {
+            info!("mapping sources in {from} to {to}");
+        }
+        setup
+            .commands
+            .extend(pathmap::commands(&debugger, &path_maps));
+    }
+
+    if args.dry_build {
+        return dry_build(&args.build, &debugger, &options, &setup);
+    }
+
+    if let (Some(package), Some(consumer)) = (&args.proc_macro, &args.consumer) {
+        let (invocation, commands) = procmacro::prepare(&args.build, &debugger, package, consumer)?;
+        setup.commands.extend(commands);
+        let rustc = invocation.rustc.to_string_lossy();
+        let (debug_path, debug_args) = debug_command(&debugger, &rustc, &invocation.args, &setup)?;
+        trace!("synthesized proc macro arguments: {:?}", debug_args);
+        let status = launch(
+            Command::new(debug_path)
+                .args(debug_args)
+                .envs(invocation.env)
+                .current_dir(&invocation.cwd),
+            &mut events,
+            &args.debugger_stdin,
+            None,
+        )?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+
+    let coverage_dir = if args.coverage_instrument {
+        let dir = target_directory(&args.build)?.join("cargo-debug/coverage");
+        coverage::instrument(dir.as_std_path())?;
+        Some(dir)
+    } else {
+        None
+    };
+
+    phases.end("probe");
+    let output = match (&args.from_build_log, &args.test_binary) {
+        (Some(path), _) => read_build_log(path, &mut events)?,
+        (None, Some(path)) => {
+            let metadata = metadata(&args.build)?;
+            let manifest = match metadata.root_package() {
+                Some(package) => package.manifest_path.clone(),
+                None => metadata.workspace_root.join("Cargo.toml"),
+            };
+            let message = testbin::message(path, manifest.as_std_path())?;
+            parse_artifacts(message.as_bytes(), &mut events)
+        }
+        (None, None) => match &nextest_test {
+            Some(test) => {
+                let message = testbin::message(&test.binary_path, &test.manifest())?;
+                parse_artifacts(message.as_bytes(), &mut events)
+            }
+            None => build(&args.build, args.notify, &mut events),
+        },
+    };
+    phases.end("build");
+    let warnings = output.warnings;
+    if args.list_artifacts_json {
+        let artifacts = filter_artifacts(&args.build, output.artifacts);
+        println!("{}", serde_json::to_string_pretty(&artifacts)?);
+        return Ok(());
+    }
+    let companion_exes = companion::executables(&args.build, &args.with, &output.artifacts)?;
+    // Before selection, which drops all but the selected binary
+    let lib_dirs = if args.no_lib_path_injection {
+        vec![]
+    } else {
+        libpath::dirs(&output.artifacts)
+    };
+    let (artifact, options) = if args.build.last_failed {
+        lastfailed::select(output.artifacts, args.from_json.as_deref(), &options)?
+    } else if args.build.all_targets && wanted_target(&args.build).is_none() {
+        let Some(artifact) = alltargets::select(output.artifacts, args.list)? else {
+            return Ok(());
+        };
+        (artifact, options)
+    } else {
+        let artifact =
+            select_binary(&args.build, output.artifacts).map_err(
+                |e| match required_features_hint(&args.build) {
+                    Some(hint) => anyhow::Error::msg(hint),
+                    None => e,
+                },
+            )?;
+        (artifact, options)
+    };
+    events.artifact_selected(&artifact);
+    phases.end("selection");
+
+    info!("selected binary: {:?}", artifact.executable);
+
+    // The log may be older than the last clean
+    if let Some(log) = &args.from_build_log {
+        if let Some(exe) = artifact.executable.as_ref().filter(|exe| !exe.is_file()) {
+            bail!(
+                "{exe} from {} no longer exists, rebuild it or save a newer log",
+                log.display()
+            );
+        }
+    }
+
+    let mut allowed = args.allow_profile.clone();
+    if allowed.is_empty() {
+        if let Ok(var) = env::var(ALLOW_PROFILE_ENV) {
+            allowed.extend(var.split(',').map(|s| s.trim().to_string()));
+        }
+    }
+    // Optimizing is the point of --opt-debug
+    if args.build.opt_debug || !args.build.opt_debug_config.is_empty() {
+        allowed.push("opt-level".to_string());
+    }
+    let workspace_root = metadata(&args.build).ok().map(|m| m.workspace_root);
+    // A prebuilt binary's profile isn't known
+    if args.test_binary.is_none() && args.nextest.is_none() {
+        profile::check(
+            &artifact,
+            profile_name(&args.build),
+            workspace_root.as_ref().map(|root| root.as_std_path()),
+            &allowed,
+        );
+    }
+
+    if args.artifact_json {
+        let triple = match target_triple(&args.build) {
+            Some(triple) => triple,
+            None => host_triple()?,
+        };
+        let profile = profile_name(&args.build);
+        println!("{}", json::artifact(&artifact, profile, &triple));
+        return Ok(());
+    }
+
+    if !args.no_source_root {
+        let root = match args.source_root {
+            Some(root) => root,
+            None => artifact
+                .manifest_path
+                .parent()
+                .map(|p| p.as_std_path().to_path_buf())
+                .unwrap_or_default(),
+        };
+
+        info!("source root: {}", root.display());
+        setup
+            .commands
+            .extend(source_root_commands(&debugger, &root.to_string_lossy()));
+    }
+
+    if cfg!(target_os = "macos") && !args.no_dsymutil {
+        if let Some(exe) = &artifact.executable {
+            debuginfo::dsymutil(exe.as_std_path())?;
+        }
+    }
+
+    // Attaching is what macOS guards most
+    if args.codesign || (args.wait_attach && cfg!(target_os = "macos")) {
+        match &artifact.executable {
+            Some(exe) if cfg!(target_os = "macos") => codesign::ensure(exe.as_std_path())?,
+            Some(_) => warn!("--codesign is only needed on macOS, ignoring it"),
+            None => {}
+        }
+    }
+
+    if args.save_breakpoints {
+        let dir = target_directory(&args.build)?.join("cargo-debug/breakpoints");
+        let built = artifact
+            .executable
+            .as_ref()
+            .and_then(|exe| std::fs::metadata(exe).and_then(|m| m.modified()).ok());
+        setup.commands.extend(breakpoints::commands(
+            &debugger,
+            dir.as_std_path(),
+            &artifact.target.name,
+            built,
+        )?);
+    }
+
+    for dir in &lib_dirs {
+        info!("adding {} to the library search path", dir.display());
+    }
+    setup
+        .commands
+        .extend(libpath::commands(&debugger, &lib_dirs));
+
+    if args.verify_debuginfo {
+        if let Some(exe) = &artifact.executable {
+            let root = match &workspace_root {
+                Some(root) => root.as_std_path().to_path_buf(),
+                None => env::current_dir()?,
+            };
+            sources::verify(exe.as_std_path(), &root, &path_maps)?;
+        }
+    }
+
+    if args.gdb_index {
+        match &artifact.executable {
+            Some(exe) if debugger == Debugger::Gdb => gdbindex::add(exe.as_std_path())?,
+            Some(_) => warn!("only gdb reads a .gdb_index, ignoring --gdb-index"),
+            None => {}
+        }
+    }
+
+    #[cfg(windows)]
+    pdb::check(&artifact, args.require_matching_pdb)?;
+
+    let (flags, commands) = debuginfo::setup(&debugger, &artifact);
+    setup.flags.extend(flags);
+    setup.commands.extend(commands);
+
+    // gdb and lldb set them on the program, ahead of any command running it;
+    // the others pass them on from their own process
+    let debuggee_env = debuggee::environment(&args.env, args.env_file.as_deref())?;
+    let debuggee_cwd = args
+        .cwd
+        .as_deref()
+        .map(debuggee::working_directory)
+        .transpose()?;
+    let in_debugger = !args.no_debugger && args.exec_template.is_none() && defined.is_none();
+    let debuggee_commands = if in_debugger {
+        debuggee::commands(&debugger, &debuggee_env, debuggee_cwd.as_deref())?
+    } else {
+        None
+    };
+    let (process_env, process_cwd) = match debuggee_commands {
+        Some(commands) => {
+            setup.commands.extend(commands);
+            (vec![], None)
+        }
+        None => (debuggee_env.clone(), debuggee_cwd.clone()),
+    };
+
+    if let Some(file) = &args.command_file {
+        let sourced = matches!(debugger, Debugger::Gdb | Debugger::Lldb)
+            || (debugger == Debugger::Gdbserver && args.attach);
+        if !sourced || defined.is_some() {
+            bail!("--command-file is only supported by gdb (--command) and lldb (--source)");
+        }
+        if !file.is_file() {
+            bail!("--command-file {} does not exist", file.display());
+        }
+        let file = file
+            .canonicalize()
+            .with_context(|| format!("Could not resolve {}", file.display()))?;
+        setup.command_file = Some(utf8_path(&file, "--command-file")?.to_string());
+    }
+
+    if let Some(file) = &args.stdin_file {
+        if !file.is_file() {
+            bail!("--stdin-file {} does not exist", file.display());
+        }
+        // Relative to where we are, not wherever the program runs
+        let file = file
+            .canonicalize()
+            .with_context(|| format!("Could not resolve {}", file.display()))?;
+        setup
+            .commands
+            .extend(stdin_commands(&debugger, &file, &options)?);
+    }
+
+    // Runs the program, so it has to come after every other startup command
+    if let Some(n) = args.repeat {
+        setup.commands.extend(repeat::commands(&debugger, n)?);
+    }
+
+    let bin = executable(&artifact)?;
+
+    if args.build_only {
+        println!("{bin}");
+        return Ok(());
+    }
+
+    if let Some(flavor) = &args.emit_vscode {
+        let launch = vscode::launch_json(flavor, &debugger, &bin, &options, &setup.commands)?;
+        println!("{}", serde_json::to_string_pretty(&launch)?);
+        return Ok(());
+    }
+
+    if args.shared_libs {
+        let (flags, commands) = sharedlibs::setup(&debugger)?;
+        let batch = DebugSetup {
+            program: setup.program.clone(),
+            flags,
+            commands,
+            command_file: None,
+        };
+        let (debug_path, debug_args) = debug_command(&debugger, &bin, &options, &batch)?;
+
+        let status = Command::new(&debug_path)
+            .args(debug_args)
+            .status()
+            .with_context(|| format!("Could not run {}", debug_path.display()))?;
+        if !status.success() {
+            error!("{} could not start the program", bin);
+            std::process::exit(status.code().unwrap_or(1));
+        }
+
+        return Ok(());
+    }
+
+    if let Some(addr) = &args.serve_artifact {
+        return serve::serve(addr, Path::new(&bin), args.once);
+    }
+
+    // Exits like cmp(1), with 1 when the binaries differ
+    if let Some(other) = &args.compare_with {
+        if compare::report(Path::new(&bin), other)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Like a core file, a dump is of a process that's already gone
+    if let Some(dump) = &args.minidump {
+        minidump::check(dump)?;
+        if !options.is_empty() {
+            bail!("--minidump opens a dump rather than running the program, so it takes no program arguments");
+        }
+
+        #[cfg(windows)]
+        let symbol_path = args.symbol_path.as_deref();
+        #[cfg(not(windows))]
+        let symbol_path = None;
+        let (debug_path, debug_args) =
+            minidump::command(&debugger, dump, &bin, symbol_path, &setup)?;
+        trace!("synthesized minidump arguments: {:?}", debug_args);
+        let status = Command::new(&debug_path)
+            .args(debug_args)
+            .status()
+            .with_context(|| format!("Could not run {}", debug_path.display()))?;
+        if !status.success() {
+            std::process::exit(status.code().unwrap_or(1));
+        }
+
+        return Ok(());
+    }
+
+    #[cfg(windows)]
+    if let Some(path) = &args.emit_windbg {
+        windbg::emit(path, &bin, &options)?;
+        if args.open {
+            windbg::open(path)?;
+        }
+
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    let user = args.user.as_deref();
+    #[cfg(not(unix))]
+    let user = None;
+    confirm::ask(
+        &confirm::Launch {
+            debugger: debugger.name(),
+            name: &artifact.target.name,
+            user,
+            remote: args.remote.as_deref(),
+            repeat: args.repeat,
+            run_until_crash: args.run_until_crash,
+        },
+        args.confirm,
+        args.yes,
+    )?;
+
+    if args.notify {
+        notify::send(&format!(
+            "launching {} for {}",
+            debugger.name(),
+            artifact.target.name
+        ));
+    }
+
+    if args.dap {
+        return dap::serve(&args.dap_adapter.unwrap_or_default(), &bin, &options);
+    }
+
+    if let Some(destination) = &args.remote {
+        let remote = remote::Remote {
+            destination,
+            port: args.remote_port,
+            client: args.remote_client,
+        };
+        if args.command_file.is_some() && !args.remote_client {
+            bail!("--command-file needs --remote-client, the script is on this machine");
+        }
+        if args.persistent_server {
+            let target_dir = target_directory(&args.build)?;
+            let state = persistent::state_file(target_dir.as_std_path());
+            return persistent::debug(&state, &remote, &debugger, &bin, &options, &setup);
+        }
+        return remote::debug(&remote, &debugger, &bin, &options, &setup);
+    }
+
+    // curses sizes the TUI from these rather than the terminal when set
+    let mut debug_env = vec![];
+    if let Some((rows, cols)) = args.gdb_tui_size {
+        debug_env.push(("LINES".to_string(), rows.to_string()));
+        debug_env.push(("COLUMNS".to_string(), cols.to_string()));
+    }
+    debug_env.extend(libpath::environment(
+        &lib_dirs,
+        env::var_os(libpath::search_var()),
+    ));
+    if args.enable_core_dumps {
+        debug_env.push(("RUST_BACKTRACE".to_string(), "full".to_string()));
+        debug_env.push(("RUST_LIB_BACKTRACE".to_string(), "1".to_string()));
+    }
+    let mut asan_options = vec![];
+    if asan {
+        asan_options.push(sanitizer::OPTIONS);
+    }
+    if args.fuzz.is_some() {
+        asan_options.push(fuzz::ASAN_OPTIONS);
+    }
+    if args.asan_focus.is_some() {
+        asan_options.push("alloc_dealloc_mismatch=1:strict_init_order=1");
+    }
+    if !asan_options.is_empty() {
+        let existing = env::var("ASAN_OPTIONS").ok();
+        let asan_options = sanitizer::options(existing, &asan_options.join(":"));
+        debug_env.push(("ASAN_OPTIONS".to_string(), asan_options));
+    }
+    if args.x11_forward || args.display.is_some() {
+        debug_env.extend(x11::environment(&debugger, args.display.as_deref())?);
+    }
+    if args.force_color_in_debugger {
+        debug_env.extend(FORCE_COLOR_ENV.map(|(k, v)| (k.to_string(), v.to_string())));
+    }
+    debug_env.extend(process_env);
+
+    if let Some(address) = gdbserver_listen {
+        info!("gdbserver listening on {address}");
+        setup.flags.push(address);
+
+        if let Some(connect) = gdbserver_connect {
+            let foreign = match (target_triple(&args.build), host_triple()) {
+                (Some(triple), Ok(host)) => foreign_arch(&triple, &host),
+                _ => false,
+            };
+            // gdb gets the startup commands, gdbserver only the program
+            let mut client = DebugSetup {
+                program: Some(gdbclient::client_program(
+                    args.client_gdb.as_deref(),
+                    foreign,
+                )),
+                commands: std::mem::take(&mut setup.commands),
+                command_file: setup.command_file.take(),
+                ..Default::default()
+            };
+            client
+                .commands
+                .extend(gdbclient::connect_commands(&connect));
+
+            let (server_path, server_args) =
+                debug_command(&Debugger::Gdbserver, &bin, &options, &setup)?;
+            trace!("synthesized gdbserver arguments: {:?}", server_args);
+            // The program inherits all of it from gdbserver
+            let mut server_cmd = Command::new(&server_path);
+            server_cmd
+                .args(&server_args)
+                .envs(debug_env.iter().map(|(k, v)| (k, v)));
+            debuggee::apply(&mut server_cmd, &[], process_cwd.as_deref());
+            ulimit::apply(&mut server_cmd, &args.ulimit)?;
+            if args.aslr_disable {
+                aslr::disable(&mut server_cmd);
+            }
+            let (client_path, client_args) = debug_command(&Debugger::Gdb, &bin, &[], &client)?;
+            return gdbclient::debug(server_cmd, (&client_path, &client_args));
+        }
+    } else if args.address.is_some() {
+        warn!("--address is only used by gdbserver, ignoring it");
+    }
+
+    // Removed when dropped, once the session is over
+    let _command_file = if debugger == Debugger::Gdb
+        && !args.no_debugger
+        && args.exec_template.is_none()
+        && command_file_usable
+        && !setup.commands.is_empty()
+    {
+        commandfile::write(&setup)?.map(|(sourced, file)| {
+            setup = sourced;
+            file
+        })
+    } else {
+        None
+    };
+
+    let (mut debug_path, mut debug_args) = if args.no_debugger {
+        (PathBuf::from(&bin), options.clone())
+    } else if let Some(template) = &args.exec_template {
+        let cwd = env::current_dir()?;
+        let values = template::Values {
+            bin: &bin,
+            args: &options,
+            cwd: utf8_path(&cwd, "The current directory")?,
+            name: &artifact.target.name,
+        };
+        template::command(template, &values)?
+    } else if let Some(defined) = &defined {
+        if !setup.commands.is_empty() {
+            warn!(
+                "{} is defined by its args, ignoring the startup commands",
+                defined.name
+            );
+        }
+        let cwd = env::current_dir()?;
+        let values = template::Values {
+            bin: &bin,
+            args: &options,
+            cwd: utf8_path(&cwd, "The current directory")?,
+            name: &artifact.target.name,
+        };
+        let (program, args) = defined.command(&values)?;
+        (setup.program.clone().unwrap_or(program), args)
+    } else {
+        debug_command(&debugger, &bin, &options, &setup)?
+    };
+
+    if args.print_debugger_version {
+        match version::probe(&debugger, &debug_path) {
+            Some(version) => eprintln!("cargo-debug: {}: {version}", debug_path.display()),
+            None => eprintln!(
+                "cargo-debug: {} didn't report its version",
+                debug_path.display()
+            ),
+        }
+    }
+
+    if let Some(path) = tee_transcript {
+        match transcript::tee(path, &debug_path, &debug_args) {
+            Some((tee_path, tee_args)) => (debug_path, debug_args) = (tee_path, tee_args),
+            None => warn!(
+                "{} can't record a transcript on this platform, ignoring --transcript",
+                debugger.name()
+            ),
+        }
+    }
+
+    trace!("synthesized debug arguments: {:?}", debug_args);
+    events.debugger_resolved(&debugger, &debug_path, &debug_args);
+
+    #[cfg(windows)]
+    if debugger == Debugger::Windbg {
+        let symbol_path = match &args.symbol_path {
+            Some(path) => Some(path.clone()),
+            // Leave a symbol path that's already set up alone
+            None if args.no_default_sympath || env::var_os("_NT_SYMBOL_PATH").is_some() => None,
+            None => {
+                let bin_dir = Path::new(&bin)
+                    .parent()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let local_app_data = env::var("LOCALAPPDATA").ok();
+                Some(windbg::default_symbol_path(
+                    local_app_data.as_deref(),
+                    &bin_dir,
+                ))
+            }
+        };
+        if let Some(symbol_path) = symbol_path {
+            trace!("_NT_SYMBOL_PATH={symbol_path}");
+            debug_env.push(("_NT_SYMBOL_PATH".to_string(), symbol_path));
+        }
+    }
+
+    if args.shell_debug {
+        match script::traced(&debug_path, &debug_args) {
+            Some((shell_path, shell_args)) => (debug_path, debug_args) = (shell_path, shell_args),
+            None => warn!("--shell-debug needs a POSIX shell, which this platform doesn't have"),
+        }
+    }
+
+    if args.copy {
+        copy_to_clipboard(&quote::command_line(&debug_path, &debug_args));
+    }
+
+    if let Some(path) = &args.emit_script {
+        script::emit(path, &debug_env, &debug_path, &debug_args)?;
+    }
+
+    if let Some(path) = &args.pack_repro {
+        let target = match target_triple(&args.build) {
+            Some(triple) => triple,
+            None => host_triple()?,
+        };
+        let env = script::session_env(&debug_env);
+        return repro::pack(
+            path,
+            &repro::Contents {
+                artifact: &artifact,
+                bin: Path::new(&bin),
+                options: &options,
+                env: &env,
+                debugger: &debugger,
+                target: &target,
+                core: args.include_core.as_deref(),
+            },
+        );
+    }
+
+    if args.no_run {
+        trace!("no-run selected, exiting");
+        println!("{}", quote::command_line(&debug_path, &debug_args));
+        std::process::exit(0);
+    }
+
+    if let Some(mode) = &args.tmux {
+        let cwd = match &process_cwd {
+            Some(dir) => dir.clone(),
+            None => env::current_dir()?,
+        };
+        return tmux::launch(
+            mode,
+            &artifact.target.name,
+            &cwd,
+            &debug_path,
+            &debug_args,
+            args.wait,
+        );
+    }
+
+    if args.new_terminal || args.terminal.is_some() {
+        let cwd = match &process_cwd {
+            Some(dir) => dir.clone(),
+            None => env::current_dir()?,
+        };
+        match terminal::command(
+            args.terminal.as_deref(),
+            &cwd,
+            &debug_path,
+            &debug_args,
+            args.hold,
+        ) {
+            Some((terminal_path, terminal_args)) => {
+                trace!("synthesized terminal arguments: {:?}", terminal_args);
+
+                match Command::new(&terminal_path).args(&terminal_args).spawn() {
+                    Ok(_) => return Ok(()),
+                    Err(e) => warn!(
+                        "could not start {}: {e}, debugging in this terminal",
+                        terminal_path.display()
+                    ),
+                }
+            }
+            None => warn!("no terminal emulator found, debugging in this terminal"),
+        }
+    }
+
+    if let Some(value) = args.oom_score_adj {
+        #[cfg(target_os = "linux")]
+        oom::set(value)?;
+        #[cfg(not(target_os = "linux"))]
+        warn!("--oom-score-adj {value} is only supported on Linux, ignoring it");
+    }
+
+    let core_dir = if args.enable_core_dumps {
+        let dir = target_directory(&args.build)?.join("cargo-debug/cores");
+        std::fs::create_dir_all(&dir).with_context(|| format!("Could not create {dir}"))?;
+        coredump::enable()?;
+        Some(dir)
+    } else {
+        None
+    };
+    let core_pattern = match (&args.core_pattern, &core_dir) {
+        (Some(pattern), _) => Some(pattern.clone()),
+        (None, Some(dir)) => Some(coredump::default_pattern(dir.as_std_path())),
+        (None, None) => None,
+    };
+    // Put back however the session ends
+    let restore_core_pattern = core_pattern
+        .as_deref()
+        .and_then(coredump::set_pattern)
+        .map(|previous| cleanup::defer(move || coredump::restore_pattern(&previous)));
+    let mut limits = args.ulimit.clone();
+    if args.unlimit_core {
+        if let Some(limit) = coredump::unlimited()? {
+            limits.push(limit);
+            coredump::destination();
+        }
+    }
+
+    set_ctrlc_handler();
+
+    if args.collect_core {
+        let mut run_cmd = Command::new(&bin);
+        run_cmd
+            .args(&options)
+            .envs(debug_env.iter().map(|(k, v)| (k, v)));
+        debuggee::apply(&mut run_cmd, &debuggee_env, debuggee_cwd.as_deref());
+        let mut limits = limits.clone();
+        limits.extend(coredump::unlimited()?);
+        ulimit::apply(&mut run_cmd, &limits)?;
+        if args.aslr_disable {
+            aslr::disable(&mut run_cmd);
+        }
+
+        let started = SystemTime::now();
+        let mut child = run_cmd
+            .spawn()
+            .with_context(|| format!("Could not run {bin}"))?;
+        let pid = child.id();
+        let status = child.wait()?;
+        drop(restore_core_pattern);
+
+        let name = &artifact.target.name;
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal: Option<i32> = None;
+        let Some(signal) = signal else {
+            eprintln!("{name} didn't crash ({status})");
+            cleanup::exit(status.code().unwrap_or(1));
+        };
+        if let Some(report) = signal::report(name, &status) {
+            eprintln!("{report}");
+        }
+
+        let exe = Path::new(&bin)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let dir = target_directory(&args.build)?.join("cargo-debug/cores");
+        let cwd = match &debuggee_cwd {
+            Some(dir) => dir.clone(),
+            None => env::current_dir()?,
+        };
+        let core = coredump::locate(pid, &exe, signal, &cwd, started, dir.as_std_path())?
+            .with_context(|| format!("Could not find the core dump of {name}"))?;
+        eprintln!("opening {}", core.display());
+
+        let (debug_path, debug_args) = coredump::open_command(&debugger, &core, &bin, &setup)?;
+        trace!("synthesized core arguments: {:?}", debug_args);
+        let status = launch(
+            Command::new(debug_path).args(debug_args),
+            &mut events,
+            &[],
+            None,
+        )?;
+        cleanup::exit(status.code().unwrap_or(1));
+    }
+
+    if let Some(max) = args.run_until_crash {
+        let mut run_cmd = Command::new(&bin);
+        run_cmd
+            .args(&options)
+            .envs(debug_env.iter().map(|(k, v)| (k, v)));
+        debuggee::apply(&mut run_cmd, &debuggee_env, debuggee_cwd.as_deref());
+        ulimit::apply(&mut run_cmd, &limits)?;
+        if args.aslr_disable {
+            aslr::disable(&mut run_cmd);
+        }
+        let dir = target_directory(&args.build)?.join("cargo-debug/run-until-crash");
+        let name = &artifact.target.name;
+        let Some(iteration) = crashloop::run(&mut run_cmd, max, dir.as_std_path(), name)? else {
+            return Ok(());
+        };
+        eprintln!(
+            "cargo-debug: running {name} as in run {iteration}, in {}",
+            debugger.name()
+        );
+    }
+
+    if args.on_failure {
+        let mut run_cmd = Command::new(&bin);
+        run_cmd
+            .args(&options)
+            .envs(debug_env.iter().map(|(k, v)| (k, v)));
+        debuggee::apply(&mut run_cmd, &debuggee_env, debuggee_cwd.as_deref());
+        ulimit::apply(&mut run_cmd, &limits)?;
+        if args.aslr_disable {
+            aslr::disable(&mut run_cmd);
+        }
+        let dir = target_directory(&args.build)?.join("cargo-debug/on-failure");
+        let name = &artifact.target.name;
+        let status = crashloop::once(&mut run_cmd, dir.as_std_path(), name)?;
+        if status.success() {
+            eprintln!("cargo-debug: {name} succeeded ({status}), not debugging it");
+            return Ok(());
+        }
+        eprintln!("cargo-debug: running {name} again in {}", debugger.name());
+    }
+
+    #[cfg(windows)]
+    if let Some(name) = &args.service {
+        let exe = Path::new(&bin);
+        if name == "cleanup" {
+            return service::cleanup(exe);
+        }
+        service::check_binary(name, exe)?;
+        if args.service_mode == service::Mode::Ifeo {
+            return service::register(name, exe, &debugger, &setup, args.service_start);
+        }
+
+        let pid = service::wait(name, args.service_start)?;
+        eprintln!("cargo-debug: attaching to the service {name} (pid {pid})");
+        let (attach_path, attach_args) = waitattach::command(&debugger, &bin, pid, &setup)?;
+        trace!("synthesized attach arguments: {:?}", attach_args);
+        let status = launch(
+            Command::new(attach_path).args(attach_args),
+            &mut events,
+            &[],
+            None,
+        )?;
+        cleanup::exit(status.code().unwrap_or(1));
+    }
+
+    if args.wait_attach {
+        let mut run_cmd = Command::new(&bin);
+        run_cmd
+            .args(&options)
+            .envs(debug_env.iter().map(|(k, v)| (k, v)));
+        debuggee::apply(&mut run_cmd, &debuggee_env, debuggee_cwd.as_deref());
+        ulimit::apply(&mut run_cmd, &limits)?;
+        if args.aslr_disable {
+            aslr::disable(&mut run_cmd);
+        }
+        let pid = waitattach::launch(
+            &mut run_cmd,
+            Path::new(&bin),
+            args.wait_signal.as_ref(),
+            Duration::from_secs(args.wait_timeout),
+        )?;
+        eprintln!(
+            "cargo-debug: attaching to {} (pid {pid})",
+            artifact.target.name
+        );
+
+        let (attach_path, attach_args) = waitattach::command(&debugger, &bin, pid, &setup)?;
+        trace!("synthesized attach arguments: {:?}", attach_args);
+        let status = launch(
+            Command::new(attach_path).args(attach_args),
+            &mut events,
+            &[],
+            None,
+        )?;
+        cleanup::exit(status.code().unwrap_or(1));
+    }
+
+    // Exits like the program, or with CRASH_EXIT_CODE if it crashed
+    if args.backtrace_on_crash {
+        let (trace_path, trace_args) = crashtrace::command(&debugger, &bin, &options, &setup)?;
+        let mut trace_cmd = Command::new(&trace_path);
+        trace_cmd
+            .args(trace_args)
+            .envs(debug_env.iter().map(|(k, v)| (k, v)));
+        debuggee::apply(&mut trace_cmd, &[], process_cwd.as_deref());
+        ulimit::apply(&mut trace_cmd, &limits)?;
+        if args.aslr_disable {
+            aslr::disable(&mut trace_cmd);
+        }
+        trace!("synthesized backtrace command: {:?}", trace_cmd);
+
+        let ended = crashtrace::run(&mut trace_cmd, &mut events);
+        drop(restore_core_pattern);
+        let name = &artifact.target.name;
+        match ended? {
+            (Some(crashtrace::Outcome::Exited(code)), _) => cleanup::exit(code),
+            (Some(crashtrace::Outcome::Crashed), _) => {
+                eprintln!("cargo-debug: {name} crashed");
+                cleanup::exit(crashtrace::CRASH_EXIT_CODE);
+            }
+            (None, status) => bail!(
+                "{} exited ({status}) without {name} running to the end",
+                trace_path.display()
+            ),
+        }
+    }
+
+    #[cfg(unix)]
+    let (debug_path, debug_args) = match &args.user {
+        Some(user) => {
+            let names = debug_env.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>();
+            runas::command(user, &debug_path, debug_args, &names)
+        }
+        None => (debug_path, debug_args.into_iter().map(Into::into).collect()),
+    };
+
+    let mut debug_cmd = Command::new(&debug_path);
+    debug_cmd.args(debug_args).envs(debug_env);
+    debuggee::apply(&mut debug_cmd, &[], process_cwd.as_deref());
+    ulimit::apply(&mut debug_cmd, &limits)?;
+    if args.aslr_disable {
+        aslr::disable(&mut debug_cmd);
+    }
+    // Windows relocates images unless their header says otherwise
+    // Put back however the session ends
+    let fixed_image = if args.aslr_disable && cfg!(windows) {
+        aslr::FixedImage::new(Path::new(&bin))?.map(|image| cleanup::defer(move || image.restore()))
+    } else {
+        None
+    };
+
+    // Keep stdout for events, the session can still be seen on stderr
+    if events.enabled() {
+        debug_cmd.stdout(std::io::stderr());
+    }
+
+    trace!("synthesized debug command: {:?}", debug_cmd);
+
+    let start = Instant::now();
+    let started = SystemTime::now();
+    let monitor = match &args.monitor {
+        Some(path) => Some(monitor::Monitor::new(&debugger, path)?),
+        None => None,
+    };
+    let companions = (!args.with.is_empty()).then(|| {
+        companion::Companions::start(
+            args.with.iter().cloned().zip(companion_exes).collect(),
+            Path::new(&bin),
+            Duration::from_millis(args.with_delay),
+            args.with_output.clone(),
+            target_directory(&args.build)
+                .map(|dir| dir.join("cargo-debug/with").into_std_path_buf())
+                .unwrap_or_else(|_| env::temp_dir().join("cargo-debug-with")),
+        )
+    });
+    phases.end("launch");
+    phases.report();
+    let status = launch(&mut debug_cmd, &mut events, &args.debugger_stdin, monitor);
+    if let Some(companions) = companions {
+        companions.stop();
+    }
+    drop(restore_core_pattern);
+    drop(fixed_image);
+    if args.hold {
+        terminal::wait_for_enter();
+    }
+    let status = status?;
+
+    if let Some(output) = &perf_output {
+        perf::check_profile(output.as_std_path(), started)?;
+        let report = perf::report_args(output.as_str());
+        if args.open_report {
+            launch(Command::new("perf").args(&report), &mut events, &[], None)?;
+        } else {
+            eprintln!(
+                "cargo-debug: profile written, view it with `{}`",
+                quote::sh_command_line(Path::new("perf"), &report)
+            );
+        }
+    }
+
+    if let Some(dir) = &core_dir {
+        #[cfg(unix)]
+        let crashed = std::os::unix::process::ExitStatusExt::signal(&status).is_some();
+        #[cfg(not(unix))]
+        let crashed = false;
+        coredump::report(dir.as_std_path(), started, crashed);
+    }
+
+    if let Some(path) = args.transcript.as_ref().filter(|_| args.strip_ansi) {
+        transcript::strip_file(path)?;
+    }
+
+    // These exit along with the program rather than stopping at a crash
+    let raw_launch = args.no_debugger
+        || matches!(
+            debugger,
+            Debugger::Gdbserver | Debugger::Devenv | Debugger::Windbg
+        );
+    if raw_launch {
+        if let Some(report) = signal::report(&artifact.target.name, &status) {
+            eprintln!("{report}");
+        }
+    }
+
+    // Without a debugger, we stand in for the program
+    if args.no_debugger {
+        eprintln!(
+            "{} finished in {:.3?} ({status})",
+            artifact.target.name,
+            start.elapsed()
+        );
+        if !status.success() {
+            cleanup::exit(status.code().unwrap_or(1));
+        }
+    }
+
+    trace!("debug command done");
+
+    if args.notify_exit {
+        notify::send(&format!(
+            "{} exited for {}",
+            debugger.name(),
+            artifact.target.name
+        ));
+    }
+
+    if let Some(dir) = coverage_dir {
+        coverage::report(dir.as_std_path(), &bin, args.coverage_html)?;
+    }
+
+    let max_warnings = args
+        .max_warnings
+        .or(args.report_build_warnings.then_some(0));
+    if let Some(problem) = max_warnings.and_then(|max| too_many_warnings(warnings, max)) {
+        error!("{problem}");
+        cleanup::exit(WARNINGS_EXIT_CODE);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn artifact_json(name: &str, kind: &str, executable: Option<&str>) -> String {
+        let executable = match executable {
+            Some(exe) => format!("\"{exe}\""),
+            None => "null".to_string(),
+        };
+        format!(
+            r#"{{"reason":"compiler-artifact","package_id":"foo 0.1.0 (path+file:///foo)","manifest_path":"/foo/Cargo.toml","target":{{"kind":["{kind}"],"crate_types":["{kind}"],"name":"{name}","src_path":"/foo/src/main.rs","edition":"2021","doctest":false,"test":true,"doc":true}},"profile":{{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false}},"features":[],"filenames":[],"executable":{executable},"fresh":false}}"#
+        )
+    }
+
+    /// Build an artifact the way cargo would report it on its JSON message stream.
+    pub(crate) fn artifact(name: &str, kind: &str, executable: Option<&str>) -> Artifact {
+        let json = artifact_json(name, kind, executable);
+        match Message::parse_stream(json.as_bytes()).next() {
+            Some(Ok(Message::CompilerArtifact(artifact))) => artifact,
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_artifacts_after_banner() {
+        // What a wrapper printing a banner before re-executing cargo looks like
+        let artifact = |name| artifact_json(name, "bin", Some("/foo/target/debug/foo"));
+
+        let mut stream = b"info: syncing channel updates for 'stable'\n".to_vec();
+        stream.extend(b"{not json\n\xff\xfe\n");
+        stream.extend(artifact("foo").as_bytes());
+        stream.extend(b"\ninfo: re-executing\n");
+        stream.extend(artifact("bar").as_bytes());
+        stream.push(b'\n');
+
+        let output = parse_artifacts(stream.as_slice(), &mut Events::default());
+        let names = output
+            .artifacts
+            .iter()
+            .map(|a| a.target.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["foo", "bar"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launch_with_input() {
+        let input = ["break main".to_string(), "run".to_string()];
+        let script = r#"read a; read b; test "$a; $b" = "break main; run""#;
+        let status = launch(
+            Command::new("sh").args(["-c", script]),
+            &mut Events::default(),
+            &input,
+            None,
+        )
+        .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn build_log() {
+        let dir = env::temp_dir().join(format!("cargo-debug-build-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("build.json");
+        let stream = [
+            artifact_json("dep", "lib", None),
+            artifact_json("foo", "bin", Some("/foo/target/custom/foo")),
+        ]
+        .join("\n");
+        std::fs::write(&log, stream).unwrap();
+
+        let output = read_build_log(&log, &mut Events::default()).unwrap();
+        let names = output
+            .artifacts
+            .iter()
+            .map(|a| a.target.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["dep", "foo"]);
+
+        std::fs::write(&log, "   Compiling foo v0.1.0\n").unwrap();
+        assert!(read_build_log(&log, &mut Events::default()).is_err());
+        assert!(read_build_log(&dir.join("missing.json"), &mut Events::default()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn count_warnings() {
+        let message = |level: &str, message: &str| {
+            format!(
+                r#"{{"reason":"compiler-message","package_id":"foo 0.1.0 (path+file:///foo)","manifest_path":"/foo/Cargo.toml","target":{{"kind":["bin"],"crate_types":["bin"],"name":"foo","src_path":"/foo/src/main.rs","edition":"2021","doctest":false,"test":true,"doc":true}},"message":{{"message":"{message}","code":null,"level":"{level}","spans":[],"children":[],"rendered":null}}}}"#
+            )
+        };
+        let stream = [
+            message("warning", "unused variable: `x`"),
+            message("warning", "function `f` is never used"),
+            message("warning", "2 warnings emitted"),
+            message("error", "mismatched types"),
+        ]
+        .join("\n");
+
+        let output = parse_artifacts(stream.as_bytes(), &mut Events::default());
+        assert_eq!((output.warnings, output.errors), (2, 1));
+        // A compiler error fails the build again, but the network may not
+        let network =
+            "error: failed to download from `https://static.crates.io/crates/foo/1.0.0/download`\n\
+                       Caused by:\n  [28] Timeout was reached";
+        assert!(!is_transient(&output, network));
+        assert!(is_transient(&BuildOutput::default(), network));
+        assert!(is_transient(
+            &BuildOutput::default(),
+            "error: failed to lock file: Resource temporarily unavailable"
+        ));
+        // Nor does cargo failing alone, for a reason that stays
+        assert!(!is_transient(
+            &BuildOutput::default(),
+            "error: no bin target named `fo`.\n\nhelp: a target with a similar name exists: `foo`"
+        ));
+        assert!(!is_transient(
+            &BuildOutput::default(),
+            "error: failed to parse manifest at `/foo/Cargo.toml`"
+        ));
+        assert!(!output.stale_cache);
+
+        let stream = [
+            message("error", "found invalid metadata files for crate `serde`"),
+            message("error", "mismatched types"),
+        ]
+        .join("\n");
+        let output = parse_artifacts(stream.as_bytes(), &mut Events::default());
+        assert!(output.stale_cache);
+
+        assert_eq!(too_many_warnings(2, 2), None);
+        assert_eq!(
+            too_many_warnings(2, 0).as_deref(),
+            Some("the build had 2 warnings")
+        );
+        assert_eq!(
+            too_many_warnings(3, 2).as_deref(),
+            Some("the build had 3 warnings, more than --max-warnings 2")
+        );
+    }
+
+    pub(crate) fn build_args(bin: Option<&str>) -> BuildArgs {
+        BuildArgs {
+            release: false,
+            manifests: vec![],
+            example: None,
+            bin: bin.map(String::from),
+            test: None,
+            tests: false,
+            lib: false,
+            bench: None,
+            package: None,
+            features: vec![],
+            no_default_features: false,
+            all_features: false,
+            profile: None,
+            doc: false,
+            bin_id: None,
+            last_failed: false,
+            all_targets: false,
+            target: None,
+            target_dir: None,
+            rustc_wrapper: None,
+            max_artifacts: None,
+            opt_debug: false,
+            opt_debug_config: vec![],
+            enable_required_features: false,
+            retry: 0,
+            retry_delay: 1000,
+            target_features: None,
+            avx2: false,
+            no_avx: false,
+            sse4_2: false,
+            native_features: false,
+        }
+    }
+
+    #[test]
+    fn last_failed_builds_tests() {
+        let args_of = |args: &BuildArgs| {
+            cargo_command(args)
+                .get_args()
+                .map(|a| a.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let format = message_format(diagnostic_color());
+        let mut args = build_args(None);
+        assert_eq!(args_of(&args)[..2], ["build", format]);
+        args.last_failed = true;
+        assert_eq!(args_of(&args)[..3], ["test", "--no-run", format]);
+
+        // Diagnostics come rendered for a terminal when they're shown on one
+        assert_eq!(message_format(false), "--message-format=json");
+        assert_eq!(
+            message_format(true),
+            "--message-format=json-diagnostic-rendered-ansi"
+        );
+    }
+
+    #[test]
+    fn several_manifests() {
+        let mut args = build_args(Some("foo"));
+        assert_eq!(per_manifest(&args).len(), 1);
+        args.manifests = vec!["a/Cargo.toml".to_string(), "b/Cargo.toml".to_string()];
+        let builds = per_manifest(&args);
+        let manifests = builds
+            .iter()
+            .map(|b| {
+                let cargo_args = cargo_command(b)
+                    .get_args()
+                    .map(|a| a.to_str().unwrap().to_string())
+                    .collect::<Vec<_>>();
+                let i = cargo_args
+                    .iter()
+                    .position(|a| a == "--manifest-path")
+                    .unwrap();
+                cargo_args[i + 1].clone()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(manifests, ["a/Cargo.toml", "b/Cargo.toml"]);
+        assert!(builds.iter().all(|b| b.bin.as_deref() == Some("foo")));
+        assert!(!has_manifest(&args));
+    }
+
+    #[test]
+    fn all_targets_bin() {
+        let mut args = build_args(Some("foo"));
+        args.all_targets = true;
+        assert!(cargo_command(&args)
+            .get_args()
+            .any(|a| a == "--all-targets"));
+
+        // The binary, not its unit tests
+        let mut unit = artifact(
+            "foo",
+            "bin",
+            Some("/foo/target/debug/deps/foo-0123456789abcdef"),
+        );
+        unit.profile.test = true;
+        let artifacts = vec![unit, artifact("foo", "bin", Some("/foo/target/debug/foo"))];
+        let bin = select_binary(&args, artifacts).unwrap();
+        assert_eq!(bin.executable.unwrap().as_str(), "/foo/target/debug/foo");
+    }
+
+    #[test]
+    fn test_targets() {
+        let args_of = |args: &BuildArgs| {
+            cargo_command(args)
+                .get_args()
+                .map(|a| a.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let test = |name: &str, kind: &str, path: &str| {
+            let mut test = artifact(name, kind, Some(path));
+            test.profile.test = true;
+            test
+        };
+        let artifacts = || {
+            vec![
+                test("foo", "lib", "/foo/target/debug/deps/foo-0123456789abcdef"),
+                test("foo", "bin", "/foo/target/debug/deps/foo-fedcba9876543210"),
+                test("api", "test", "/foo/target/debug/deps/api-00112233aabbccdd"),
+                test("cli", "test", "/foo/target/debug/deps/cli-44556677eeff0011"),
+                artifact("foo", "bin", Some("/foo/target/debug/foo")),
+            ]
+        };
+
+        // A named test, among several
+        let mut args = build_args(None);
+        args.test = Some("api".to_string());
+        assert_eq!(args_of(&args)[..2], ["test", "--no-run"]);
+        assert!(args_of(&args).ends_with(&["--test".to_string(), "api".to_string()]));
+        let bin = select_binary(&args, artifacts()).unwrap();
+        assert_eq!(bin.target.name, "api");
+
+        // The same test in two packages of the workspace
+        let mut other = test("api", "test", "/foo/target/debug/deps/api-8899aabbccddeeff");
+        other.manifest_path = "/foo/bar/Cargo.toml".into();
+        other.package_id.repr = "path+file:///foo/bar#0.1.0".to_string();
+        let mut both = artifacts();
+        both.push(other);
+        let err = select_binary(&args, both).unwrap_err().to_string();
+        assert!(
+            err.contains("api-00112233aabbccdd of /foo/Cargo.toml"),
+            "{err}"
+        );
+        assert!(
+            err.contains("api-8899aabbccddeeff of /foo/bar/Cargo.toml"),
+            "{err}"
+        );
+        assert!(err.ends_with("--package <name>, one of: bar, foo"), "{err}");
+        assert_eq!(package_name("foo 0.1.0 (path+file:///foo)"), "foo");
+        assert_eq!(package_name("path+file:///foo/my-crate#0.1.0"), "my-crate");
+        assert_eq!(
+            package_name("registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0"),
+            "serde"
+        );
+
+        // The library's unit tests
+        let mut args = build_args(None);
+        args.lib = true;
+        args.tests = true;
+        let bin = select_binary(&args, artifacts()).unwrap();
+        assert_eq!(
+            bin.executable.unwrap().as_str(),
+            "/foo/target/debug/deps/foo-0123456789abcdef"
+        );
+
+        // Only the test executables are picked from
+        let mut args = build_args(None);
+        args.tests = true;
+        let err = select_binary(&args, artifacts()).unwrap_err().to_string();
+        assert!(err.contains("(api, cli, foo, foo)"), "{err}");
+        assert!(err.contains("--test NAME"), "{err}");
+
+        let mut args = build_args(None);
+        args.bench = Some("throughput".to_string());
+        assert_eq!(args_of(&args)[..2], ["bench", "--no-run"]);
+        let mut bench = artifacts();
+        bench.push(test(
+            "throughput",
+            "bench",
+            "/foo/target/release/deps/throughput-8899aabbccddeeff",
+        ));
+        let bin = select_binary(&args, bench).unwrap();
+        assert_eq!(bin.target.name, "throughput");
+    }
+
+    #[test]
+    fn cargo_build_flags() {
+        let mut args = build_args(Some("foo"));
+        args.package = Some("mycrate".to_string());
+        args.features = vec!["foo,bar".to_string(), "baz".to_string()];
+        args.no_default_features = true;
+        args.profile = Some("dev-opt".to_string());
+        let cargo_args = cargo_command(&args)
+            .get_args()
+            .map(|a| a.to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        let flags = [
+            "--profile",
+            "dev-opt",
+            "--bin",
+            "foo",
+            "--package",
+            "mycrate",
+            "--features",
+            "foo,bar",
+            "--features",
+            "baz",
+            "--no-default-features",
+        ];
+        assert_eq!(cargo_args[2..2 + flags.len()], flags);
+        assert_eq!(profile_name(&args), "dev-opt");
+
+        // Cross builds link under target/<triple>, as cargo reports
+        args.target = Some("aarch64-unknown-linux-gnu".to_string());
+        let cargo_args = cargo_command(&args)
+            .get_args()
+            .map(|a| a.to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert!(cargo_args
+            .windows(2)
+            .any(|w| w == ["--target", "aarch64-unknown-linux-gnu"]));
+        let cross = "/foo/target/aarch64-unknown-linux-gnu/dev-opt/foo";
+        let artifacts = vec![artifact("foo", "bin", Some(cross))];
+        let bin = select_binary(&args, artifacts).unwrap();
+        assert_eq!(bin.executable.unwrap().as_str(), cross);
+
+        assert!(foreign_arch(
+            "aarch64-unknown-linux-gnu",
+            "x86_64-unknown-linux-gnu"
+        ));
+        assert!(foreign_arch(
+            "x86_64-pc-windows-msvc",
+            "aarch64-pc-windows-msvc"
+        ));
+        assert!(!foreign_arch(
+            "i686-unknown-linux-gnu",
+            "x86_64-unknown-linux-gnu"
+        ));
+        assert!(!foreign_arch(
+            "x86_64-unknown-linux-musl",
+            "x86_64-unknown-linux-gnu"
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let core = Path::new(std::ffi::OsStr::from_bytes(b"/tmp/core.\xff"));
+        assert!(utf8_path(core, "--stdin-file").is_err());
+        assert!(stdin_commands(&Debugger::Gdb, core, &[]).is_err());
+
+        // Passed to the debugger as it's named
+        let setup = DebugSetup::default();
+        let (_, args) = coredump::open_command(&Debugger::Gdb, core, "foo", &setup).unwrap();
+        assert_eq!(args.last().unwrap(), core.as_os_str());
+    }
+
+    #[test]
+    fn custom_target_dir() {
+        let mut args = build_args(Some("foo"));
+        args.target_dir = Some(PathBuf::from("/tmp/custom-target"));
+        let cargo_cmd = cargo_command(&args);
+        let cargo_args = cargo_cmd
+            .get_args()
+            .map(|a| a.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(cargo_args
+            .windows(2)
+            .any(|w| w == ["--target-dir", "/tmp/custom-target"]));
+
+        // cargo reports the binary where it linked it, in that directory
+        let artifacts = vec![artifact("foo", "bin", Some("/tmp/custom-target/debug/foo"))];
+        let bin = select_binary(&args, artifacts).unwrap();
+        assert_eq!(
+            bin.executable.unwrap().as_str(),
+            "/tmp/custom-target/debug/foo"
+        );
+    }
+
+    #[test]
+    fn scheduler_locking() {
+        assert_eq!(
+            lock_scheduler_commands(&Debugger::Gdb, &SchedulerLocking::Step).unwrap(),
+            ["set scheduler-locking step"]
+        );
+        assert_eq!(
+            lock_scheduler_commands(&Debugger::Gdb, &SchedulerLocking::On).unwrap(),
+            ["set scheduler-locking on"]
+        );
+
+        let lldb = lock_scheduler_commands(&Debugger::Lldb, &SchedulerLocking::Step).unwrap();
+        assert_eq!(lldb.len(), 8);
+        assert_eq!(
+            lldb[..2],
+            [
+                "command unalias n",
+                "command alias n thread step-over --run-mode this-thread"
+            ]
+        );
+        assert!(lock_scheduler_commands(&Debugger::Lldb, &SchedulerLocking::On).is_err());
+        assert!(lock_scheduler_commands(&Debugger::Windbg, &SchedulerLocking::Step).is_err());
+    }
+
+    #[test]
+    fn child_processes() {
+        let (flags, commands, _) = debug_children_setup(&Debugger::Gdb, None).unwrap();
+        assert!(flags.is_empty());
+        assert_eq!(
+            commands,
+            [
+                "set detach-on-fork off",
+                "set follow-fork-mode child",
+                "set follow-exec-mode new"
+            ]
+        );
+
+        assert!(lldb_follows_forks("lldb version 17.0.6"));
+        assert!(lldb_follows_forks("Ubuntu LLDB version 14.0.0"));
+        assert!(!lldb_follows_forks("lldb version 13.0.1"));
+        assert!(!lldb_follows_forks(
+            "lldb-1500.0.200.58
+Apple Swift version 5.9.2"
+        ));
+        let (_, commands, _) = debug_children_setup(
+            &Debugger::Lldb,
+            Some(
+                "lldb version 17.0.6
+",
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            commands,
+            ["settings set target.process.follow-fork-mode child"]
+        );
+        assert!(debug_children_setup(&Debugger::Lldb, Some("lldb-1500.0.200.58")).is_err());
+        assert!(debug_children_setup(&Debugger::Lldb, None).is_err());
+
+        let (flags, commands, _) = debug_children_setup(&Debugger::Windbg, None).unwrap();
+        assert_eq!(
+            (flags.len(), commands),
+            (0, vec![".childdbg 1".to_string()])
+        );
+        let cdb = Debugger::Custom(PathBuf::from("cdb.exe"));
+        let (flags, commands, _) = debug_children_setup(&cdb, None).unwrap();
+        assert_eq!(
+            (flags, commands),
+            (vec!["-o".to_string()], vec![".childdbg 1".to_string()])
+        );
+        assert!(debug_children_setup(&Debugger::Perf, None).is_err());
+    }
+
+    #[test]
+    fn opt_debug() {
+        let mut args = build_args(None);
+        args.opt_debug_config = vec![
+            ("opt-level".to_string(), "2".to_string()),
+            ("lto".to_string(), "false".to_string()),
+        ];
+
+        let cargo_cmd = cargo_command(&args);
+        let cargo_args = cargo_cmd
+            .get_args()
+            .map(|a| a.to_str().unwrap())
+            .collect::<Vec<_>>();
+        let configs = cargo_args
+            .windows(2)
+            .filter(|w| w[0] == "--config")
+            .map(|w| w[1])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            configs,
+            [
+                "profile.dev.opt-level=2",
+                "profile.dev.package.\"*\".opt-level=2",
+                "profile.dev.debug=true",
+                "profile.dev.lto=false",
+            ]
+        );
+
+        assert!(!cargo_command(&build_args(None))
+            .get_args()
+            .any(|a| a == "--config"));
+    }
+
+    #[test]
+    fn override_rustc_wrapper() {
+        let mut args = build_args(None);
+        args.rustc_wrapper = Some("sccache".to_string());
+
+        let cargo_cmd = cargo_command(&args);
+        let wrapper = cargo_cmd
+            .get_envs()
+            .find_map(|(k, v)| (k == "RUSTC_WRAPPER").then_some(v));
+        assert_eq!(wrapper, Some(Some("sccache".as_ref())));
+    }
+
+    #[test]
+    fn select_sole_binary() {
+        let artifacts = vec![
+            artifact("foo", "lib", None),
+            artifact("foo", "bin", Some("/foo/target/debug/foo")),
+        ];
+
+        let bin = select_binary(&build_args(None), artifacts).unwrap();
+        assert_eq!(bin.executable.unwrap(), "/foo/target/debug/foo");
+    }
+
+    #[test]
+    fn select_named_binary() {
+        let artifacts = vec![
+            artifact("foo", "bin", Some("/foo/target/debug/foo")),
+            artifact("bar", "bin", Some("/foo/target/debug/bar")),
+        ];
+
+        let bin = select_binary(&build_args(Some("bar")), artifacts).unwrap();
+        assert_eq!(bin.executable.unwrap(), "/foo/target/debug/bar");
+    }
+
+    #[test]
+    fn select_example() {
+        let artifacts = vec![
+            artifact("foo", "bin", Some("/foo/target/debug/foo")),
+            artifact("foo", "example", Some("/foo/target/debug/examples/foo")),
+        ];
+
+        let mut args = build_args(None);
+        args.example = Some("foo".to_string());
+        let example = select_binary(&args, artifacts.clone()).unwrap();
+        assert_eq!(
+            example.executable.unwrap(),
+            "/foo/target/debug/examples/foo"
+        );
+
+        // The same name with --bin is the binary
+        let bin = select_binary(&build_args(Some("foo")), artifacts.clone()).unwrap();
+        assert_eq!(bin.executable.unwrap(), "/foo/target/debug/foo");
+
+        // The sole binary built alongside isn't a stand-in for a missing example
+        let err = select_binary(&args, artifacts[..1].to_vec()).unwrap_err();
+        assert!(err.to_string().contains("example artifact foo"));
+    }
+
+    #[test]
+    fn filter_artifact_json() {
+        let artifacts = vec![
+            artifact("foo", "lib", None),
+            artifact("foo", "bin", Some("/foo/target/debug/foo")),
+            artifact("foo", "example", Some("/foo/target/debug/examples/foo")),
+        ];
+
+        assert_eq!(
+            filter_artifacts(&build_args(None), artifacts.clone()).len(),
+            3
+        );
+        let bins = filter_artifacts(&build_args(Some("foo")), artifacts);
+        assert_eq!(bins.len(), 1);
+        assert_eq!(bins[0].target.kind, ["bin"]);
+    }
+
+    #[test]
+    fn missing_required_features() {
+        let metadata: serde_json::Value =
+            serde_json::from_str(include_str!("../testdata/required-features.json")).unwrap();
+        let package = &metadata["packages"][0];
+        let features = package["features"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(name, enables)| {
+                let enables = enables.as_array().unwrap().iter();
+                let enables = enables.map(|f| f.as_str().unwrap().to_string());
+                (name.clone(), enables.collect())
+            })
+            .collect::<BTreeMap<String, Vec<String>>>();
+        let required = |target: usize| {
+            package["targets"][target]["required-features"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|f| f.as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // `cli` is enabled by default, through `full`
+        assert!(missing_features(&required(0), &features).is_empty());
+        assert_eq!(missing_features(&required(1), &features), ["gui"]);
+    }
+
+    #[test]
+    fn select_ambiguous_binary() {
+        let artifacts = vec![
+            artifact("foo", "bin", Some("/foo/target/debug/foo")),
+            artifact("bar", "bin", Some("/foo/target/debug/bar")),
+        ];
+
+        assert!(select_binary(&build_args(None), artifacts.clone()).is_err());
+        assert!(select_binary(&build_args(Some("baz")), artifacts).is_err());
+    }
+
+    #[test]
+    fn select_hints() {
+        let artifacts = vec![artifact("foo", "lib", None)];
+        let err = select_binary(&build_args(None), artifacts).unwrap_err();
+        assert!(err.to_string().contains("no binaries"));
+
+        let artifacts = vec![
+            artifact("foo", "bin", Some("/foo/target/debug/foo")),
+            artifact("bar", "bin", Some("/foo/target/debug/bar")),
+        ];
+        let err = select_binary(&build_args(None), artifacts).unwrap_err();
+        assert!(err.to_string().contains("(bar, foo)"));
+        assert!(err.to_string().contains("--list"));
+    }
+
+    #[test]
+    fn missing_executable() {
+        // With `-Z unstable-options --out-dir out`, cargo copies the binary
+        // but still reports where it linked it
+        let moved = artifact("foo", "bin", Some("/nonexistent/target/debug/foo"));
+        let err = executable(&moved).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("does not exist at /nonexistent/target/debug/foo"));
+
+        let exe = env::current_exe().unwrap();
+        let linked = artifact("foo", "bin", exe.to_str());
+        assert_eq!(executable(&linked).unwrap(), exe.to_str().unwrap());
+    }
+
+    #[test]
+    fn too_many_binaries() {
+        let artifacts = (0..4)
+            .map(|i| artifact(&format!("foo{i}"), "bin", Some("/foo/target/debug/foo")))
+            .collect::<Vec<_>>();
+
+        let mut args = build_args(None);
+        args.max_artifacts = Some(3);
+        let err = select_binary(&args, artifacts).unwrap_err();
+        assert!(err.to_string().contains("--max-artifacts 3"));
+    }
+
+    #[test]
+    fn gdbserver_addresses() {
+        assert_eq!(
+            gdbserver_address(Some("localhost:2345"), Some("9999")).unwrap(),
+            "localhost:2345"
+        );
+        assert_eq!(
+            gdbserver_address(None, Some("2345")).unwrap(),
+            "0.0.0.0:2345"
+        );
+        assert_eq!(gdbserver_address(Some(":2345"), None).unwrap(), ":2345");
+        assert!(gdbserver_address(None, Some(" ")).is_err());
+        assert!(gdbserver_address(None, None).is_err());
+
+        // A host without a port is a mistake gdbserver only reports later
+        assert!(gdbserver_address(Some("pi.local"), None).is_err());
+        assert!(gdbserver_address(Some("pi.local:gdb"), None).is_err());
+        assert_eq!(
+            gdbserver_address(Some("/dev/ttyUSB0"), None).unwrap(),
+            "/dev/ttyUSB0"
+        );
+    }
+
+    #[test]
+    fn gdb_interpreter() {
+        assert_eq!(gdb_interpreter_flag(None, None), None);
+        assert_eq!(
+            gdb_interpreter_flag(Some(3), None).as_deref(),
+            Some("--interpreter=mi3")
+        );
+        assert_eq!(
+            gdb_interpreter_flag(None, Some("mi4")).as_deref(),
+            Some("--interpreter=mi4")
+        );
+    }
+
+    #[test]
+    fn tui_size() {
+        assert_eq!(parse_tui_size("40x120"), Ok((40, 120)));
+        assert!(parse_tui_size("40").is_err());
+        assert!(parse_tui_size("0x120").is_err());
+        assert!(parse_tui_size("40xwide").is_err());
+    }
+
+    /// A `Write` whose contents can be read back after handing it to [`Events`].
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn json_event_stream() {
+        let buffer = SharedBuffer::default();
+        let mut events = Events::to(Box::new(buffer.clone()));
+
+        // A build with a warning, then the session, with `sh` standing in for gdb
+        events.build_started(&cargo_command(&build_args(None)));
+        let mut stream = br#"{"reason":"compiler-message","package_id":"foo 0.1.0 (path+file:///foo)","manifest_path":"/foo/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"foo","src_path":"/foo/src/main.rs","edition":"2021","doctest":false,"test":true,"doc":true},"message":{"message":"unused variable: `x`","code":null,"level":"warning","spans":[],"children":[],"rendered":"warning: unused variable: `x`\n"}}"#.to_vec();
+        stream.push(b'\n');
+        stream.extend(artifact_json("foo", "bin", Some("/foo/target/debug/foo")).as_bytes());
+        stream.push(b'\n');
+        let output = parse_artifacts(stream.as_slice(), &mut events);
+        events.build_finished(true, output.artifacts.len(), output.errors);
+
+        let artifact = select_binary(&build_args(None), output.artifacts).unwrap();
+        events.artifact_selected(&artifact);
+
+        let debug_args = vec!["-c".to_string(), "exit 3".to_string()];
+        events.debugger_resolved(&Debugger::Gdb, Path::new("sh"), &debug_args);
+        let status = launch(Command::new("sh").args(&debug_args), &mut events, &[], None).unwrap();
+        assert_eq!(status.code(), Some(3));
+
+        let stdout = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let events = stdout
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        let reasons = events
+            .iter()
+            .map(|e| e["reason"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            [
+                "build-started",
+                "compiler-message",
+                "build-finished",
+                "artifact-selected",
+                "debugger-resolved",
+                "debugger-launched",
+                "debugger-exited"
+            ]
+        );
+
+        assert_eq!(events[0]["command"][1], "build");
+        assert_eq!(events[1]["level"], "warning");
+        assert_eq!(events[2]["artifacts"], 1);
+        assert_eq!(events[2]["errors"], 0);
+        assert_eq!(events[3]["executable"], "/foo/target/debug/foo");
+        assert_eq!(events[4]["debugger"], "gdb");
+        assert!(events[5]["pid"].as_u64().is_some());
+        assert_eq!(events[6]["code"], 3);
+        assert_eq!(events[6]["success"], false);
+    }
+
+    #[test]
+    fn custom_debugger() {
+        assert_eq!(parse_debugger("lldb"), Ok(Debugger::Lldb));
+        assert_eq!(
+            parse_debugger("/opt/rr/bin/rr"),
+            Ok(Debugger::Custom(PathBuf::from("/opt/rr/bin/rr")))
+        );
+        assert!(parse_debugger("").is_err());
+
+        let debugger = parse_debugger("ugdb").unwrap();
+        let (path, args) = debug_command(
+            &debugger,
+            "/foo/target/debug/foo",
+            &["--bar".to_string()],
+            &DebugSetup::default(),
+        )
+        .unwrap();
+        assert_eq!(path, PathBuf::from("ugdb"));
+        assert_eq!(args, ["/foo/target/debug/foo", "--bar"]);
+    }
+
+    #[test]
+    fn perf_flags() {
+        assert_eq!(perf_args(None, None), ["record", "-g"]);
+        assert_eq!(
+            perf_args(Some("-e cache-misses  -F 99"), None),
+            ["record", "-g", "-e", "cache-misses", "-F", "99"]
+        );
+        assert_eq!(perf_args(None, Some("-d")), ["stat", "-d"]);
+
+        let setup = DebugSetup {
+            flags: perf_args(None, None),
+            ..Default::default()
+        };
+        let (path, args) = debug_command(
+            &Debugger::Perf,
+            "/foo/target/debug/foo",
+            &["-v".to_string()],
+            &setup,
+        )
+        .unwrap();
+        assert_eq!(path, PathBuf::from("perf"));
+        assert_eq!(args, ["record", "-g", "--", "/foo/target/debug/foo", "-v"]);
+    }
+
+    #[test]
+    fn frida_command() {
+        let setup = DebugSetup {
+            flags: vec!["-l".to_string(), "trace.js".to_string()],
+            ..Default::default()
+        };
+        let (path, args) = debug_command(
+            &Debugger::Frida,
+            "/foo/target/debug/foo",
+            &["-v".to_string()],
+            &setup,
+        )
+        .unwrap();
+        assert_eq!(path, PathBuf::from("frida"));
+        assert_eq!(
+            args,
+            ["-l", "trace.js", "-f", "/foo/target/debug/foo", "--", "-v"]
+        );
+    }
+
+    #[test]
+    fn asan_focus_range() {
+        assert_eq!(parse_address_range("0x1000-0x1008"), Ok((0x1000, 0x1008)));
+        assert_eq!(
+            parse_address_range("7ffe10-7ffe11"),
+            Ok((0x7ffe10, 0x7ffe11))
+        );
+        assert!(parse_address_range("0x1008-0x1000").is_err());
+        assert!(parse_address_range("0x1000").is_err());
+
+        assert_eq!(
+            watch_range_command((0x7ffe10, 0x7ffe11)),
+            "watch *(char*)0x7ffe10"
+        );
+        assert_eq!(
+            watch_range_command((0x1000, 0x1008)),
+            "watch *(char(*)[8])0x1000"
+        );
+    }
+
+    #[test]
+    fn stdin_redirect() {
+        let file = Path::new("/tmp/in put.txt");
+        assert_eq!(
+            stdin_commands(&Debugger::Gdb, file, &["-v".to_string(), "a b".to_string()]).unwrap(),
+            ["set args -v 'a b' < '/tmp/in put.txt'"]
+        );
+        assert_eq!(
+            stdin_commands(&Debugger::Gdb, file, &[]).unwrap(),
+            ["set args < '/tmp/in put.txt'"]
+        );
+        assert_eq!(
+            stdin_commands(&Debugger::Lldb, file, &[]).unwrap(),
+            ["settings set target.input-path \"/tmp/in put.txt\""]
+        );
+        assert_eq!(
+            stdin_commands(&Debugger::Lldb, Path::new(r#"/tmp/"in".txt"#), &[]).unwrap(),
+            [r#"settings set target.input-path "/tmp/\"in\".txt""#]
+        );
+        assert!(stdin_commands(&Debugger::Windbg, file, &[]).is_err());
+    }
+
+    #[test]
+    fn debug_command_program_override() {
+        let setup = DebugSetup {
+            program: Some(PathBuf::from("rust-gdb")),
+            flags: vec!["--tui".to_string()],
+            commands: vec!["layout src".to_string()],
+            command_file: None,
+        };
+
+        let (path, args) = debug_command(
+            &Debugger::Gdb,
+            "/foo/target/debug/foo",
+            &["--bar".to_string()],
+            &setup,
+        )
+        .unwrap();
+        assert_eq!(path, PathBuf::from("rust-gdb"));
+        assert_eq!(
+            args,
+            [
+                "--tui",
+                "-ex",
+                "layout src",
+                "--args",
+                "/foo/target/debug/foo",
+                "--bar"
+            ]
+        );
+    }
+
+    #[test]
+    fn lldb_run_args_setting() {
+        let setup = DebugSetup {
+            commands: vec!["process launch".to_string()],
+            ..Default::default()
+        };
+
+        // Arguments are set before the startup commands that may launch
+        let (path, args) = debug_command(
+            &Debugger::Lldb,
+            "/foo/target/debug/foo",
+            &["--bar".to_string(), "a b".to_string()],
+            &setup,
+        )
+        .unwrap();
+        assert_eq!(path, PathBuf::from("lldb"));
+        assert_eq!(
+            args,
+            [
+                "--file",
+                "/foo/target/debug/foo",
+                "--one-line",
+                "settings set target.run-args --bar \"a b\"",
+                "--one-line",
+                "process launch"
+            ]
+        );
+
+        let (_, args) = debug_command(
+            &Debugger::Lldb,
+            "/foo/target/debug/foo",
+            &[],
+            &DebugSetup::default(),
+        )
+        .unwrap();
+        assert_eq!(args, ["--file", "/foo/target/debug/foo"]);
+    }
+
+    #[test]
+    fn command_file_and_environment() {
+        // --command-file breakpoints.gdb --env "GREETING=hello world" --cwd /data
+        let vars = [("GREETING".to_string(), "hello world".to_string())];
+        let cwd = Path::new("/data");
+        let setup = |debugger| DebugSetup {
+            commands: debuggee::commands(debugger, &vars, Some(cwd))
+                .unwrap()
+                .unwrap(),
+            command_file: Some("/foo/breakpoints.gdb".to_string()),
+            ..Default::default()
+        };
+
+        // Sourced ahead of the startup commands, before --args and the binary
+        let (_, args) = debug_command(
+            &Debugger::Gdb,
+            "/foo/target/debug/foo",
+            &["--bar".to_string()],
+            &setup(&Debugger::Gdb),
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "--command",
+                "/foo/breakpoints.gdb",
+                "-ex",
+                "set environment GREETING = hello world",
+                "-ex",
+                "set cwd /data",
+                "--args",
+                "/foo/target/debug/foo",
+                "--bar"
+            ]
+        );
+
+        let (_, args) = debug_command(
+            &Debugger::Lldb,
+            "/foo/target/debug/foo",
+            &[],
+            &setup(&Debugger::Lldb),
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "--file",
+                "/foo/target/debug/foo",
+                "--source",
+                "/foo/breakpoints.gdb",
+                "--one-line",
+                "settings set target.env-vars \"GREETING=hello world\"",
+                "--one-line",
+                "platform settings -w /data"
+            ]
+        );
+    }
+
+    #[test]
+    fn command_file_users() {
+        let usable = |argv: &[&str]| {
+            let argv = ["cargo", "debug", "gdb"].iter().chain(argv);
+            let CargoCli::Debug(args) = CargoCli::try_parse_from(argv).unwrap();
+            command_file_usable(&args)
+        };
+
+        assert!(usable(&[]));
+        assert!(!usable(&["--no-run"]));
+        assert!(!usable(&["--new-terminal"]));
+        // gdb runs as them, and can't read a file only this user can
+        assert!(!usable(&["--user", "nobody"]));
+    }
+
+    #[test]
+    fn gdb_eval() {
+        assert_eq!(
+            gdb_eval_command("$thread_count = 4"),
+            "set $thread_count = 4"
+        );
+        assert_eq!(gdb_eval_command("$foo=bar(1)"), "set $foo=bar(1)");
+        assert_eq!(gdb_eval_command("$foo == 4"), "print $foo == 4");
+        assert_eq!(gdb_eval_command("sizeof(int)"), "print sizeof(int)");
+        assert_eq!(gdb_eval_command("$_siginfo"), "print $_siginfo");
+    }
+
+    #[test]
+    fn source_root() {
+        assert_eq!(
+            source_root_commands(&Debugger::Gdb, "/my dir/foo"),
+            ["directory /my dir/foo"]
+        );
+        assert_eq!(
+            source_root_commands(&Debugger::Lldb, "/my dir/foo"),
+            ["settings append target.source-map . \"/my dir/foo\""]
+        );
+        assert!(source_root_commands(&Debugger::Windbg, "/foo").is_empty());
+    }
+
+    #[test]
+    fn std_commit_hash() {
+        let version = "rustc 1.75.0 (82e1608df 2023-12-21)\n\
+                       binary: rustc\n\
+                       commit-hash: 82e1608dfa6e0b5569232559e3d385fea5a93112\n\
+                       host: x86_64-unknown-linux-gnu\n";
+        assert_eq!(
+            commit_hash(version),
+            Some("82e1608dfa6e0b5569232559e3d385fea5a93112")
+        );
+        assert_eq!(commit_hash("commit-hash: unknown\n"), None);
+    }
+
+    #[test]
+    fn musl_source_map() {
+        let maps = vec![("/build/musl".to_string(), "/src/musl".to_string())];
+
+        assert_eq!(
+            source_map_commands(&Debugger::Gdb, &maps),
+            ["set substitute-path /build/musl /src/musl"]
+        );
+        assert_eq!(
+            source_map_commands(&Debugger::Lldb, &maps),
+            ["settings set target.source-map /build/musl /src/musl"]
+        );
+
+        // A quote or a backslash doesn't end the path early
+        let maps = vec![(
+            r"C:\build\musl".to_string(),
+            r#"/src/"my" musl"#.to_string(),
+        )];
+        assert_eq!(
+            source_map_commands(&Debugger::Gdb, &maps),
+            [r#"set substitute-path "C:\\build\\musl" "/src/\"my\" musl""#]
+        );
+        assert_eq!(
+            source_map_commands(&Debugger::Lldb, &maps),
+            [r#"settings set target.source-map "C:\\build\\musl" "/src/\"my\" musl""#]
+        );
+        assert!(source_map_commands(&Debugger::Windbg, &maps).is_empty());
+        assert!(parse_path_map("/build/musl").is_err());
     }
 }
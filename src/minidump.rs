@@ -0,0 +1,179 @@
+//! Opening Windows minidumps (`.dmp` files written by `MiniDumpWriteDump`)
+//! against the freshly built binary.
+//!
+//! WinDbg and cdb open them with `-z`, lldb as a core file. The binary's
+//! directory is added to the symbol path so its PDB is found even when the
+//! dump was written on another machine.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{DebugSetup, Debugger};
+
+/// What every minidump, full memory dumps included, starts with.
+const SIGNATURE: &[u8; 4] = b"MDMP";
+
+fn read_signature(dump: &Path) -> std::io::Result<bool> {
+    let mut signature = [0; 4];
+    File::open(dump)?.read_exact(&mut signature)?;
+    Ok(&signature == SIGNATURE)
+}
+
+/// Whether `dump` is a minidump, as opposed to a core file.
+pub fn is_minidump(dump: &Path) -> bool {
+    read_signature(dump).unwrap_or(false)
+}
+
+/// Check that `dump` is a minidump, rather than a core file, say.
+pub fn check(dump: &Path) -> Result<()> {
+    if !dump.is_file() {
+        bail!("--minidump {} does not exist", dump.display());
+    }
+    if !read_signature(dump).with_context(|| format!("Could not read {}", dump.display()))? {
+        bail!(
+            "{} is not a Windows minidump, open core files with --collect-core or the debugger",
+            dump.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// The WinDbg-like program of `debugger`: WinDbg itself, or cdb, its
+/// console version, which is run as a custom debugger.
+fn windbg_program(debugger: &Debugger) -> Option<PathBuf> {
+    match debugger {
+        Debugger::Windbg => Some(PathBuf::from("windbgx")),
+        Debugger::Custom(path)
+            if path
+                .file_stem()
+                .is_some_and(|stem| stem.eq_ignore_ascii_case("cdb")) =>
+        {
+            Some(path.clone())
+        }
+        _ => None,
+    }
+}
+
+/// The debugger invocation opening `dump` with symbols for `bin`, and
+/// `symbol_path` for WinDbg and cdb, given. The dump is passed as it's
+/// named, whether or not that's UTF-8.
+pub fn command(
+    debugger: &Debugger,
+    dump: &Path,
+    bin: &str,
+    symbol_path: Option<&str>,
+    setup: &DebugSetup,
+) -> Result<(PathBuf, Vec<OsString>)> {
+    let bin_dir = Path::new(bin)
+        .parent()
+        .and_then(Path::to_str)
+        .unwrap_or_default();
+    let mut args = setup.flags.iter().map(OsString::from).collect::<Vec<_>>();
+
+    if let Some(program) = windbg_program(debugger) {
+        // Appended to whatever _NT_SYMBOL_PATH says, then reloaded so the
+        // frames already on screen resolve
+        let mut commands = vec![format!(".sympath+ {bin_dir}"), ".reload".to_string()];
+        commands.extend(setup.commands.iter().cloned());
+
+        if let Some(symbol_path) = symbol_path {
+            args.extend(["-y".into(), symbol_path.into()]);
+        }
+        args.extend(["-z".into(), dump.into(), "-i".into(), bin_dir.into()]);
+        args.extend(["-c".into(), commands.join("; ").into()]);
+
+        return Ok((setup.program.clone().unwrap_or(program), args));
+    }
+
+    match debugger {
+        Debugger::Lldb => {
+            args.extend(["--file".into(), bin.into(), "--core".into(), dump.into()]);
+            for command in &setup.commands {
+                args.extend(["--one-line".into(), command.into()]);
+            }
+        }
+        Debugger::Gdb => {
+            bail!("gdb can't read Windows minidumps, open them with windbg, cdb or lldb")
+        }
+        other => bail!(
+            "--minidump is only supported by windbg, cdb and lldb, not {}",
+            other.name()
+        ),
+    }
+
+    let program = setup.program.clone().unwrap_or(PathBuf::from("lldb"));
+    Ok((program, args))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn minidump_commands() {
+        let setup = DebugSetup {
+            commands: vec!["k".to_string()],
+            ..Default::default()
+        };
+        let dump = Path::new("crash.dmp");
+        let bin = "target/debug/foo.exe";
+
+        let (program, args) = command(&Debugger::Windbg, dump, bin, None, &setup).unwrap();
+        assert_eq!(program, PathBuf::from("windbgx"));
+        assert_eq!(
+            args,
+            [
+                "-z",
+                "crash.dmp",
+                "-i",
+                "target/debug",
+                "-c",
+                ".sympath+ target/debug; .reload; k"
+            ]
+        );
+
+        let cdb = Debugger::Custom(PathBuf::from("cdb"));
+        let (program, args) = command(&cdb, dump, bin, None, &setup).unwrap();
+        assert_eq!(program, PathBuf::from("cdb"));
+        assert_eq!(args[..2], ["-z", "crash.dmp"]);
+        let (_, args) = command(&cdb, dump, bin, Some("srv*C:\\symbols"), &setup).unwrap();
+        assert_eq!(args[..4], ["-y", "srv*C:\\symbols", "-z", "crash.dmp"]);
+
+        let (program, args) = command(&Debugger::Lldb, dump, bin, None, &setup).unwrap();
+        assert_eq!(program, PathBuf::from("lldb"));
+        assert_eq!(
+            args,
+            [
+                "--file",
+                "target/debug/foo.exe",
+                "--core",
+                "crash.dmp",
+                "--one-line",
+                "k"
+            ]
+        );
+
+        assert!(command(&Debugger::Gdb, dump, bin, None, &setup).is_err());
+        let rr = Debugger::Custom(PathBuf::from("rr"));
+        assert!(command(&rr, dump, bin, None, &setup).is_err());
+    }
+
+    #[test]
+    fn dump_signature() {
+        let dir = std::env::temp_dir().join(format!("cargo-debug-minidump-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dump = dir.join("crash.dmp");
+        std::fs::write(&dump, b"MDMP\x93\xa7\0\0").unwrap();
+        assert!(check(&dump).is_ok());
+        let core = dir.join("core");
+        std::fs::write(&core, b"\x7fELF\x02\x01\x01\0").unwrap();
+        assert!(check(&core).is_err());
+        assert!(check(&dir.join("missing.dmp")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
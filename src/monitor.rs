@@ -0,0 +1,116 @@
+//! Reloading a command script into the running debugger when it changes.
+//!
+//! The debugger's stdin is a pipe from cargo-debug (see `--debugger-stdin`),
+//! so a `source` command can be typed into it whenever the script is saved.
+//! The script's modification time is polled, which needs no file watching
+//! support from the platform.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+
+use crate::Debugger;
+
+/// How often the script is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A script sourced into the debugger whenever it changes.
+pub struct Monitor {
+    path: PathBuf,
+    command: String,
+    modified: Option<SystemTime>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Monitor {
+    pub fn new(debugger: &Debugger, path: &Path) -> Result<Monitor> {
+        // The debugger may run somewhere else than we do
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Could not find {}", path.display()))?;
+        let command = match debugger {
+            Debugger::Gdb => format!("source {}", path.display()),
+            Debugger::Lldb => format!("command source \"{}\"", path.display()),
+            _ => bail!("--monitor is only supported by gdb and lldb"),
+        };
+
+        Ok(Monitor {
+            modified: modified(&path),
+            path,
+            command,
+        })
+    }
+
+    /// Whether the script changed since it was last checked.
+    fn changed(&mut self) -> bool {
+        let now = modified(&self.path);
+        if now.is_none() || now == self.modified {
+            return false;
+        }
+
+        self.modified = now;
+        true
+    }
+
+    /// Type the `source` command into `stdin` each time the script changes,
+    /// until the debugger stops reading.
+    pub fn spawn<W: Write + Send + 'static>(mut self, stdin: Arc<Mutex<W>>) {
+        thread::spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+            if !self.changed() {
+                continue;
+            }
+
+            info!("{} changed, reloading it", self.path.display());
+            let mut stdin = stdin.lock().unwrap();
+            if let Err(e) = writeln!(stdin, "{}", self.command).and_then(|()| stdin.flush()) {
+                warn!("could not reload {}: {e}", self.path.display());
+                break;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn monitor_script() {
+        let path = std::env::temp_dir().join(format!("cargo-debug-{}.gdb", std::process::id()));
+        fs::write(&path, "break main\n").unwrap();
+
+        let mut monitor = Monitor::new(&Debugger::Gdb, &path).unwrap();
+        assert_eq!(
+            monitor.command,
+            format!("source {}", path.canonicalize().unwrap().display())
+        );
+        assert!(!monitor.changed());
+
+        let later = SystemTime::now() + Duration::from_secs(10);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(monitor.changed());
+        assert!(!monitor.changed());
+
+        let lldb = Monitor::new(&Debugger::Lldb, &path).unwrap();
+        assert!(lldb.command.starts_with("command source \""));
+        assert!(Monitor::new(&Debugger::Windbg, &path).is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert!(Monitor::new(&Debugger::Gdb, &path).is_err());
+    }
+}
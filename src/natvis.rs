@@ -0,0 +1,167 @@
+//! Loading the toolchain's Natvis visualizers into WinDbg and Visual Studio.
+//!
+//! rustc ships `.natvis` files for the standard library's types (`Vec`,
+//! `String`, `HashMap`, ...) in `<sysroot>/lib/rustlib/etc`. WinDbg loads
+//! them with `.nvload`; Visual Studio only reads visualizers from its
+//! per-user `Visualizers` directories, so they are copied there.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+
+use crate::Debugger;
+
+/// The `.natvis` files in the toolchain's `sysroot`, by name.
+fn find(sysroot: &Path) -> Result<Vec<PathBuf>> {
+    let dir = sysroot.join("lib/rustlib/etc");
+    let mut files = fs::read_dir(&dir)
+        .with_context(|| format!("Could not read {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "natvis"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    Ok(files)
+}
+
+/// WinDbg commands loading `files`.
+fn windbg_commands(files: &[PathBuf]) -> Vec<String> {
+    files
+        .iter()
+        .map(|file| format!(".nvload \"{}\"", file.display()))
+        .collect()
+}
+
+/// The `Visualizers` directory of each Visual Studio version with a
+/// directory in `documents`, e.g. `Visual Studio 2022\Visualizers`.
+fn visualizer_dirs(documents: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(documents) else {
+        return vec![];
+    };
+
+    let mut dirs = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("Visual Studio ")
+                && entry.path().is_dir()
+        })
+        .map(|entry| entry.path().join("Visualizers"))
+        .collect::<Vec<_>>();
+    dirs.sort();
+
+    dirs
+}
+
+/// Copy `files` into `dir`, unless an identical copy is already there.
+fn install(files: &[PathBuf], dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+
+    for file in files {
+        let Some(name) = file.file_name() else {
+            continue;
+        };
+        let contents =
+            fs::read(file).with_context(|| format!("Could not read {}", file.display()))?;
+        let installed = dir.join(name);
+        if fs::read(&installed).is_ok_and(|existing| existing == contents) {
+            continue;
+        }
+
+        fs::write(&installed, contents)
+            .with_context(|| format!("Could not write {}", installed.display()))?;
+        info!("installed {}", installed.display());
+    }
+
+    Ok(())
+}
+
+/// Startup commands loading the toolchain's visualizers into `debugger`, or
+/// installing them where it looks for them.
+pub fn setup(debugger: &Debugger, sysroot: &Path) -> Result<Vec<String>> {
+    let files = find(sysroot)?;
+    if files.is_empty() {
+        warn!("the toolchain has no .natvis files to load");
+        return Ok(vec![]);
+    }
+
+    match debugger {
+        Debugger::Windbg => Ok(windbg_commands(&files)),
+        Debugger::Devenv => {
+            let documents = env::var_os("USERPROFILE")
+                .map(|home| PathBuf::from(home).join("Documents"))
+                .context("USERPROFILE is not set, so Visual Studio's visualizers can't be found")?;
+            let dirs = visualizer_dirs(&documents);
+            if dirs.is_empty() {
+                warn!(
+                    "no Visual Studio directories in {}, not installing visualizers",
+                    documents.display()
+                );
+            }
+            for dir in dirs {
+                install(&files, &dir)?;
+            }
+
+            Ok(vec![])
+        }
+        _ => {
+            warn!(
+                "{} doesn't use Natvis visualizers, ignoring --natvis",
+                debugger.name()
+            );
+            Ok(vec![])
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn natvis_files() {
+        let root = env::temp_dir().join(format!("cargo-debug-natvis-{}", std::process::id()));
+        let etc = root.join("sysroot/lib/rustlib/etc");
+        fs::create_dir_all(&etc).unwrap();
+        for name in [
+            "liballoc.natvis",
+            "libcore.natvis",
+            "gdb_load_rust_pretty_printers.py",
+        ] {
+            fs::write(etc.join(name), name).unwrap();
+        }
+
+        let files = find(&root.join("sysroot")).unwrap();
+        assert_eq!(
+            files,
+            [etc.join("liballoc.natvis"), etc.join("libcore.natvis")]
+        );
+        assert_eq!(
+            windbg_commands(&files[..1]),
+            [format!(
+                ".nvload \"{}\"",
+                etc.join("liballoc.natvis").display()
+            )]
+        );
+
+        // Only Visual Studio's own directories get visualizers
+        let documents = root.join("Documents");
+        fs::create_dir_all(documents.join("Visual Studio 2022")).unwrap();
+        fs::create_dir_all(documents.join("My Games")).unwrap();
+        let dirs = visualizer_dirs(&documents);
+        assert_eq!(dirs, [documents.join("Visual Studio 2022/Visualizers")]);
+
+        install(&files, &dirs[0]).unwrap();
+        assert_eq!(
+            fs::read_to_string(dirs[0].join("libcore.natvis")).unwrap(),
+            "libcore.natvis"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! Picking a test to debug with a cargo-nextest filterset (`--nextest`).
+//!
+//! `cargo nextest list` builds the test binaries and reports, as JSON, each
+//! binary's path and which of its tests the filterset matches. The test
+//! picked is then debugged in its binary like a `--test-binary` one, run
+//! with libtest's `NAME --exact --nocapture`.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{find_executable, picker, BuildArgs};
+
+/// A test the filterset matched.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Test {
+    /// nextest's name for the binary, e.g. `foo::integration`
+    binary_id: String,
+    pub binary_path: PathBuf,
+    /// The package's directory, where nextest runs the binary
+    cwd: PathBuf,
+    /// libtest's name for the test, e.g. `parser::tests::empty_input`
+    pub name: String,
+}
+
+impl Test {
+    /// The package's manifest, which the binary was built from.
+    pub fn manifest(&self) -> PathBuf {
+        self.cwd.join("Cargo.toml")
+    }
+}
+
+/// The tests matched in the output of `cargo nextest list --message-format
+/// json`.
+fn matched_tests(json: &str) -> Result<Vec<Test>> {
+    let list: serde_json::Value =
+        serde_json::from_str(json).context("Could not parse the output of cargo nextest list")?;
+    let Some(suites) = list.get("rust-suites").and_then(|s| s.as_object()) else {
+        bail!("cargo nextest list did not report any test binaries");
+    };
+
+    let mut tests = vec![];
+    for suite in suites.values() {
+        let field = |name: &str| suite.get(name).and_then(|v| v.as_str());
+        let (Some(binary_id), Some(binary_path), Some(cwd)) =
+            (field("binary-id"), field("binary-path"), field("cwd"))
+        else {
+            continue;
+        };
+        let Some(cases) = suite.get("testcases").and_then(|c| c.as_object()) else {
+            continue;
+        };
+        for (name, case) in cases {
+            let status = case
+                .get("filter-match")
+                .and_then(|m| m.get("status"))
+                .and_then(|s| s.as_str());
+            if status == Some("matches") {
+                tests.push(Test {
+                    binary_id: binary_id.to_string(),
+                    binary_path: PathBuf::from(binary_path),
+                    cwd: PathBuf::from(cwd),
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(tests)
+}
+
+/// `cargo nextest list` for the tests `filterset` matches, building them
+/// as `args` says.
+fn list(args: &BuildArgs, filterset: &str) -> Result<String> {
+    if find_executable(Path::new("cargo-nextest")).is_none() {
+        bail!(
+            "--nextest needs cargo-nextest, which was not found; \
+             install it with `cargo install cargo-nextest --locked`"
+        );
+    }
+
+    let cargo = std::env::var("CARGO").unwrap_or(String::from("cargo"));
+    let mut cmd = Command::new(cargo);
+    cmd.args([
+        "nextest",
+        "list",
+        "--message-format",
+        "json",
+        "-E",
+        filterset,
+    ]);
+    if args.release {
+        cmd.arg("--release");
+    }
+    if let Some(manifest) = args.manifests.first() {
+        cmd.args(["--manifest-path", manifest]);
+    }
+    if let Some(target) = &args.target {
+        cmd.args(["--target", target]);
+    }
+    if let Some(dir) = &args.target_dir {
+        cmd.arg("--target-dir").arg(dir);
+    }
+
+    // The build's progress goes to stderr, as with cargo
+    let output = cmd
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Could not run cargo nextest list")?;
+    if !output.status.success() {
+        bail!("cargo nextest list failed ({})", output.status);
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The test `filterset` picks, built by `cargo nextest list`; when it
+/// matches several, which one is asked.
+pub fn select(args: &BuildArgs, filterset: &str) -> Result<Test> {
+    let mut tests = matched_tests(&list(args, filterset)?)?;
+    let index = match tests.len() {
+        0 => bail!("No test matches the nextest filterset {filterset}"),
+        1 => 0,
+        n => {
+            let choices = tests
+                .iter()
+                .map(|t| format!("{} {}", t.binary_id, t.name))
+                .collect::<Vec<_>>();
+            picker::pick(&format!("{n} tests match {filterset}"), &choices)?
+        }
+    };
+
+    Ok(tests.swap_remove(index))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nextest_list() {
+        let tests = matched_tests(include_str!("../testdata/nextest-list.json")).unwrap();
+        assert_eq!(
+            tests,
+            [
+                Test {
+                    binary_id: "foo".to_string(),
+                    binary_path: PathBuf::from("/work/foo/target/debug/deps/foo-0123456789abcdef"),
+                    cwd: PathBuf::from("/work/foo"),
+                    name: "parser::tests::empty_input".to_string(),
+                },
+                Test {
+                    binary_id: "foo::integration".to_string(),
+                    binary_path: PathBuf::from(
+                        "/work/foo/target/debug/deps/integration-fedcba9876543210"
+                    ),
+                    cwd: PathBuf::from("/work/foo"),
+                    name: "empty_input_roundtrip".to_string(),
+                },
+            ]
+        );
+        assert_eq!(tests[1].manifest(), Path::new("/work/foo/Cargo.toml"));
+
+        assert!(matched_tests("{}").is_err());
+        assert!(matched_tests("not json").is_err());
+    }
+}
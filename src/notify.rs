@@ -0,0 +1,101 @@
+//! Desktop notifications, for builds long enough to switch away from.
+//!
+//! These go through each platform's own tooling, and are best effort:
+//! without a desktop to notify (or the tooling), nothing happens.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use log::trace;
+
+use crate::quote;
+
+const TITLE: &str = "cargo debug";
+
+/// The command showing a notification, if there's a desktop to show it on.
+fn notify_command(body: &str) -> Option<(PathBuf, Vec<String>)> {
+    if cfg!(windows) {
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms; \
+             $n = New-Object System.Windows.Forms.NotifyIcon; \
+             $n.Icon = [System.Drawing.SystemIcons]::Information; \
+             $n.Visible = $true; \
+             $n.ShowBalloonTip(5000, {}, {}, 'Info'); \
+             Start-Sleep -Seconds 5; \
+             $n.Dispose()",
+            quote::powershell(TITLE),
+            quote::powershell(body)
+        );
+        Some((
+            PathBuf::from("powershell"),
+            vec!["-NoProfile".to_string(), "-Command".to_string(), script],
+        ))
+    } else if cfg!(target_os = "macos") {
+        // Logged in over SSH, the notification would pop up on someone else's screen
+        if env::var_os("SSH_CONNECTION").is_some() {
+            return None;
+        }
+
+        let escape = |s: &str| s.replace('\\', r"\\").replace('"', r#"\""#);
+        Some((
+            PathBuf::from("osascript"),
+            vec![
+                "-e".to_string(),
+                format!(
+                    "display notification \"{}\" with title \"{}\"",
+                    escape(body),
+                    escape(TITLE)
+                ),
+            ],
+        ))
+    } else {
+        if env::var_os("DISPLAY").is_none() && env::var_os("WAYLAND_DISPLAY").is_none() {
+            return None;
+        }
+
+        Some((
+            PathBuf::from("notify-send"),
+            vec![TITLE.to_string(), body.to_string()],
+        ))
+    }
+}
+
+/// Show a desktop notification, silently doing nothing if we can't.
+pub fn send(body: &str) {
+    let Some((program, args)) = notify_command(body) else {
+        trace!("no desktop to notify: {body}");
+        return;
+    };
+
+    let spawned = Command::new(&program)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(e) = spawned {
+        trace!("could not run {}: {e}", program.display());
+    }
+}
+
+/// The notification for a failed build.
+pub fn build_failed(errors: usize) -> String {
+    match errors {
+        0 => "build failed".to_string(),
+        1 => "build failed: 1 error".to_string(),
+        n => format!("build failed: {n} errors"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_failure_message() {
+        assert_eq!(build_failed(0), "build failed");
+        assert_eq!(build_failed(1), "build failed: 1 error");
+        assert_eq!(build_failed(3), "build failed: 3 errors");
+    }
+}
@@ -0,0 +1,49 @@
+//! Adjusting how likely the Linux OOM killer is to pick the debug session.
+
+use anyhow::{bail, Context, Result};
+use log::info;
+
+const OOM_SCORE_ADJ: &str = "/proc/self/oom_score_adj";
+
+fn current() -> Result<i16> {
+    let value = std::fs::read_to_string(OOM_SCORE_ADJ)
+        .with_context(|| format!("Could not read {OOM_SCORE_ADJ}"))?;
+    value
+        .trim()
+        .parse::<i16>()
+        .with_context(|| format!("Unexpected {OOM_SCORE_ADJ}: {value}"))
+}
+
+/// Set our OOM score adjustment, which the debugger and the program inherit.
+///
+/// Anyone can make themselves a likelier target, but lowering the score
+/// below where it started needs CAP_SYS_RESOURCE.
+pub fn set(value: i16) -> Result<()> {
+    let before = current()?;
+    if let Err(e) = std::fs::write(OOM_SCORE_ADJ, value.to_string()) {
+        if value < before {
+            bail!(
+                "Could not lower the OOM score adjustment from {before} to {value} ({e}). \
+                 This needs CAP_SYS_RESOURCE, try running with sudo."
+            );
+        }
+        bail!("Could not write {OOM_SCORE_ADJ}: {e}");
+    }
+
+    info!("OOM score adjustment: {before} -> {value}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keep_current_score() {
+        // Writing the current value back is always allowed
+        let score = current().unwrap();
+        set(score).unwrap();
+        assert_eq!(current().unwrap(), score);
+    }
+}
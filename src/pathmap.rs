@@ -0,0 +1,172 @@
+//! Undoing `--remap-path-prefix` in the debugger.
+//!
+//! Builds made reproducible with `--remap-path-prefix $PWD=/build` have
+//! `/build/src/main.rs` in their debug info, which doesn't exist here. The
+//! remappings are found in the rustflags cargo would use, from the
+//! environment or cargo's config files, and the debugger is pointed back
+//! from each remapped prefix to the local directory.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use log::trace;
+
+use crate::{cargoconfig, Debugger};
+
+const FLAG: &str = "--remap-path-prefix";
+
+/// The `FROM=TO` remappings among rustc flags.
+fn remappings<'a>(flags: impl IntoIterator<Item = &'a str>) -> Vec<(String, String)> {
+    let mut flags = flags.into_iter();
+    let mut maps = vec![];
+    while let Some(flag) = flags.next() {
+        let value = match flag.strip_prefix(FLAG) {
+            Some("") => flags.next(),
+            Some(value) => value.strip_prefix('='),
+            None => None,
+        };
+        // rustc splits at the last `=`, so FROM may contain one
+        if let Some((from, to)) = value.and_then(|v| v.rsplit_once('=')) {
+            maps.push((from.to_string(), to.to_string()));
+        }
+    }
+
+    maps
+}
+
+/// The double-quoted strings in a cargo config file, which is all of
+/// `rustflags` that matters here, whether it's a string or an array.
+fn quoted_strings(config: &str) -> Vec<String> {
+    config
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split('"').skip(1).step_by(2))
+        .map(String::from)
+        .collect()
+}
+
+/// The rustflags cargo would pass to rustc: `CARGO_ENCODED_RUSTFLAGS` or
+/// `RUSTFLAGS` if set, as those override the config files, or else the
+/// strings of every config file.
+pub fn rustflags() -> Vec<String> {
+    if let Ok(flags) = env::var("CARGO_ENCODED_RUSTFLAGS") {
+        return flags.split('\x1f').map(String::from).collect();
+    }
+    if let Ok(flags) = env::var("RUSTFLAGS") {
+        return flags.split_whitespace().map(String::from).collect();
+    }
+
+    let mut flags = vec![];
+    for file in cargoconfig::current_files() {
+        if let Ok(config) = fs::read_to_string(&file) {
+            flags.extend(quoted_strings(&config));
+        }
+    }
+
+    flags
+}
+
+/// The remappings cargo would pass to rustc.
+pub fn detect() -> Vec<(String, String)> {
+    let maps = remappings(rustflags().iter().map(String::as_str));
+    if !maps.is_empty() {
+        trace!("the rustflags remap path prefixes: {:?}", maps);
+    }
+
+    maps
+}
+
+/// The debugger's mappings undoing `remappings`: each remapped prefix back to
+/// where it came from, or to `root` if that doesn't exist here (a build made
+/// elsewhere, in a container for instance).
+pub fn inverse(
+    remappings: &[(String, String)],
+    root: &Path,
+    exists: impl Fn(&Path) -> bool,
+) -> Vec<(String, String)> {
+    remappings
+        .iter()
+        .map(|(from, to)| {
+            let local = if exists(Path::new(from)) {
+                from.clone()
+            } else {
+                root.to_string_lossy().into_owned()
+            };
+            (to.clone(), local)
+        })
+        .collect()
+}
+
+/// Commands mapping each `FROM` path in the debug info to `TO`.
+pub fn commands(debugger: &Debugger, maps: &[(String, String)]) -> Vec<String> {
+    maps.iter()
+        .filter_map(|(from, to)| match debugger {
+            Debugger::Gdb => Some(format!("set substitute-path \"{from}\" \"{to}\"")),
+            // Appended, as `settings set` would drop the mappings made so far
+            Debugger::Lldb => Some(format!(
+                "settings append target.source-map \"{from}\" \"{to}\""
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_remappings() {
+        assert_eq!(
+            remappings("-C debuginfo=2 --remap-path-prefix /home/me/foo=/build".split(' ')),
+            [("/home/me/foo".to_string(), "/build".to_string())]
+        );
+        assert_eq!(
+            remappings(["--remap-path-prefix=/a=b=/build", "--remap-path-prefix"]),
+            [("/a=b".to_string(), "/build".to_string())]
+        );
+        assert!(remappings(["-Cdebuginfo=2"]).is_empty());
+
+        let config = r#"
+[build]
+# rustflags = ["--remap-path-prefix", "/old=/nope"]
+rustflags = [
+    "--remap-path-prefix", "/home/me/foo=/build",
+    "-Cforce-frame-pointers=yes",
+]
+"#;
+        let strings = quoted_strings(config);
+        assert_eq!(
+            remappings(strings.iter().map(String::as_str)),
+            [("/home/me/foo".to_string(), "/build".to_string())]
+        );
+    }
+
+    #[test]
+    fn inverse_remappings() {
+        let remapped = [
+            ("/home/me/foo".to_string(), "/build".to_string()),
+            ("/ci/checkout".to_string(), "/src".to_string()),
+        ];
+        let exists = |p: &Path| p == Path::new("/home/me/foo");
+
+        let maps = inverse(&remapped, Path::new("/work/foo"), exists);
+        assert_eq!(
+            maps,
+            [
+                ("/build".to_string(), "/home/me/foo".to_string()),
+                ("/src".to_string(), "/work/foo".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            commands(&Debugger::Gdb, &maps[..1]),
+            ["set substitute-path \"/build\" \"/home/me/foo\""]
+        );
+        assert_eq!(
+            commands(&Debugger::Lldb, &maps[..1]),
+            ["settings append target.source-map \"/build\" \"/home/me/foo\""]
+        );
+    }
+}
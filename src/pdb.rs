@@ -0,0 +1,262 @@
+//! Checking an executable's PDB is the one it was linked with.
+//!
+//! The linker records the PDB's GUID and age in the executable's debug
+//! directory. When the PDB next to it has another GUID or age (left over
+//! from an earlier build, say), debuggers load no symbols from it and
+//! breakpoints silently fail to bind, so that's caught before launching.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use cargo_metadata::Artifact;
+use log::{info, warn};
+use object::Object;
+
+/// The magic at the start of a PDB 7.0 file, a multi-stream file (MSF).
+const MSF_MAGIC: &[u8] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+
+/// The stream holding the PDB's GUID.
+const PDB_INFO_STREAM: usize = 1;
+/// The stream holding the age the executable is matched against.
+const DBI_STREAM: usize = 3;
+
+/// What ties an executable to its PDB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Signature {
+    guid: [u8; 16],
+    age: u32,
+}
+
+impl fmt::Display for Signature {
+    /// Formatted as debuggers and symbol servers show it: the GUID's first
+    /// three fields are little-endian.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let g = &self.guid;
+        write!(
+            f,
+            "{{{:08X}-{:04X}-{:04X}-",
+            u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+            u16::from_le_bytes([g[4], g[5]]),
+            u16::from_le_bytes([g[6], g[7]])
+        )?;
+        for (i, b) in g[8..].iter().enumerate() {
+            if i == 2 {
+                f.write_str("-")?;
+            }
+            write!(f, "{b:02X}")?;
+        }
+        write!(f, "}} age {}", self.age)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// The contents of stream `index` of a PDB.
+fn msf_stream(data: &[u8], index: usize) -> Option<Vec<u8>> {
+    if !data.starts_with(MSF_MAGIC) {
+        return None;
+    }
+
+    let block_size = read_u32(data, 32)? as usize;
+    let directory_size = read_u32(data, 44)? as usize;
+    let block_map = read_u32(data, 52)? as usize;
+    if block_size == 0 {
+        return None;
+    }
+    let block = |i: usize| data.get(i.checked_mul(block_size)?..)?.get(..block_size);
+    let blocks = |size: usize| size.div_ceil(block_size);
+
+    // The stream directory is spread over the blocks listed in the block map
+    let map = block(block_map)?;
+    let mut directory = vec![];
+    for i in 0..blocks(directory_size) {
+        directory.extend_from_slice(block(read_u32(map, i * 4)? as usize)?);
+    }
+
+    // The stream count, each stream's size, then each stream's blocks
+    let streams = read_u32(&directory, 0)? as usize;
+    if index >= streams {
+        return None;
+    }
+    let size = |i: usize| match read_u32(&directory, 4 + i * 4) {
+        Some(u32::MAX) => Some(0),
+        size => size.map(|s| s as usize),
+    };
+    let mut offset = 4 + streams * 4;
+    for i in 0..index {
+        offset += blocks(size(i)?) * 4;
+    }
+
+    let size = size(index)?;
+    let mut stream = vec![];
+    for i in 0..blocks(size) {
+        stream.extend_from_slice(block(read_u32(&directory, offset + i * 4)? as usize)?);
+    }
+    stream.truncate(size);
+
+    Some(stream)
+}
+
+/// The signature of a PDB: the GUID from its info stream, after the version,
+/// timestamp and an age of its own, and the age from its DBI stream, after
+/// two version fields.
+fn pdb_signature(data: &[u8]) -> Option<Signature> {
+    let info = msf_stream(data, PDB_INFO_STREAM)?;
+    let dbi = msf_stream(data, DBI_STREAM)?;
+
+    Some(Signature {
+        guid: info.get(12..28)?.try_into().ok()?,
+        age: read_u32(&dbi, 8)?,
+    })
+}
+
+/// The signature of the PDB the executable was linked with, and its path
+/// then.
+fn exe_signature(data: &[u8]) -> Result<Option<(Signature, PathBuf)>> {
+    let file = object::File::parse(data)?;
+    let Some(codeview) = file.pdb_info()? else {
+        return Ok(None);
+    };
+    let path = PathBuf::from(String::from_utf8_lossy(codeview.path()).into_owned());
+
+    Ok(Some((
+        Signature {
+            guid: codeview.guid(),
+            age: codeview.age(),
+        },
+        path,
+    )))
+}
+
+/// The PDB debuggers will load for `exe`: the one cargo reported, or the one
+/// rustc writes next to it.
+pub fn pdb_path(artifact: &Artifact, exe: &Path) -> PathBuf {
+    artifact
+        .filenames
+        .iter()
+        .find(|f| f.extension() == Some("pdb"))
+        .map(|f| f.as_std_path().to_path_buf())
+        .unwrap_or_else(|| {
+            let stem = exe.file_stem().unwrap_or_default().to_string_lossy();
+            exe.with_file_name(format!("{}.pdb", stem.replace('-', "_")))
+        })
+}
+
+/// Check the artifact's PDB matches its executable, warning when it doesn't,
+/// or failing if `require` is set.
+pub fn check(artifact: &Artifact, require: bool) -> Result<()> {
+    let Some(exe) = &artifact.executable else {
+        return Ok(());
+    };
+    let exe = exe.as_std_path();
+    let data = fs::read(exe).with_context(|| format!("Could not read {}", exe.display()))?;
+    let Some((expected, linked)) =
+        exe_signature(&data).with_context(|| format!("Could not parse {}", exe.display()))?
+    else {
+        info!("{} has no PDB to check", exe.display());
+        return Ok(());
+    };
+
+    let pdb = pdb_path(artifact, exe);
+    let found = fs::read(&pdb).ok().and_then(|data| pdb_signature(&data));
+    let problem = match found {
+        Some(found) if found == expected => {
+            info!("{} matches {} ({expected})", pdb.display(), exe.display());
+            return Ok(());
+        }
+        Some(found) => format!(
+            "{} doesn't match {}: the executable expects {expected} (linked with {}), \
+             but the PDB is {found}; symbols won't load and breakpoints won't bind",
+            pdb.display(),
+            exe.display(),
+            linked.display()
+        ),
+        None => format!(
+            "{} expects the PDB {expected}, but {} is missing or unreadable",
+            exe.display(),
+            pdb.display()
+        ),
+    };
+
+    if require {
+        bail!("{problem}");
+    }
+    warn!("{problem}; rebuild to regenerate it, or pass --require-matching-pdb to stop here");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BLOCK_SIZE: usize = 512;
+
+    /// A PDB with the given streams, one block each, the way the linker
+    /// lays one out: the superblock, the block map, then the directory.
+    fn msf(streams: &[&[u8]]) -> Vec<u8> {
+        // Empty streams have no blocks
+        let stored = streams.iter().filter(|s| !s.is_empty()).collect::<Vec<_>>();
+        let mut directory = vec![streams.len() as u32];
+        directory.extend(streams.iter().map(|s| s.len() as u32));
+        directory.extend((0..stored.len()).map(|i| 3 + i as u32));
+
+        let mut superblock = MSF_MAGIC.to_vec();
+        for value in [
+            BLOCK_SIZE as u32,
+            1,
+            3 + stored.len() as u32,
+            directory.len() as u32 * 4,
+            0,
+            1,
+        ] {
+            superblock.extend(value.to_le_bytes());
+        }
+
+        let words = |words: &[u32]| words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let mut blocks: Vec<Vec<u8>> = vec![superblock, words(&[2]), words(&directory)];
+        blocks.extend(stored.iter().map(|s| s.to_vec()));
+
+        blocks
+            .into_iter()
+            .flat_map(|mut block| {
+                block.resize(BLOCK_SIZE, 0);
+                block
+            })
+            .collect()
+    }
+
+    const GUID: [u8; 16] = [
+        0x78, 0x56, 0x34, 0x12, 0xbc, 0x9a, 0xf0, 0xde, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd,
+        0xef,
+    ];
+
+    #[test]
+    fn pdb_signatures() {
+        // Version, timestamp and age, then the GUID
+        let mut info = vec![0; 12];
+        info.extend(GUID);
+        // Two version fields, then the age
+        let mut dbi = vec![0; 8];
+        dbi.extend(3u32.to_le_bytes());
+
+        let pdb = msf(&[b"", &info, b"tpi", &dbi]);
+        assert_eq!(msf_stream(&pdb, 2), Some(b"tpi".to_vec()));
+        assert_eq!(msf_stream(&pdb, 4), None);
+
+        let signature = pdb_signature(&pdb).unwrap();
+        assert_eq!(signature, Signature { guid: GUID, age: 3 });
+        assert_eq!(
+            signature.to_string(),
+            "{12345678-9ABC-DEF0-0123-456789ABCDEF} age 3"
+        );
+
+        assert_eq!(pdb_signature(b"\x7fELF"), None);
+        assert_eq!(pdb_signature(&pdb[..BLOCK_SIZE * 2]), None);
+    }
+}
@@ -0,0 +1,132 @@
+//! Profiling the binary that was just built with `perf record` (`--perf`),
+//! rather than debugging it.
+//!
+//! Call graphs are unwound with the frame pointers when the build keeps
+//! them (`-C force-frame-pointers`), which is cheap; otherwise from copies
+//! of the stack with the DWARF unwind info, which works on any build but
+//! makes for much bigger profiles.
+
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{bail, Result};
+
+/// Where to get perf when it isn't installed.
+pub const MISSING: &str = "perf was not found; install it with your distribution's package \
+     (linux-perf on Debian, linux-tools-generic on Ubuntu, perf on Fedora and Arch)";
+
+/// Whether `flags`, the rustflags of the build, keep frame pointers.
+pub fn frame_pointers<S: AsRef<str>>(flags: &[S]) -> bool {
+    let mut kept = false;
+    let mut flags = flags.iter().map(AsRef::as_ref);
+    while let Some(flag) = flags.next() {
+        let codegen = match flag {
+            "-C" | "--codegen" => flags.next(),
+            flag => flag
+                .strip_prefix("-C")
+                .or_else(|| flag.strip_prefix("--codegen="))
+                .map(str::trim_start),
+        };
+        // The last one wins, as with rustc
+        if let Some(value) = codegen.and_then(|c| c.strip_prefix("force-frame-pointers")) {
+            kept = matches!(value, "" | "=yes" | "=y" | "=on" | "=true");
+        }
+    }
+
+    kept
+}
+
+/// The `perf record` subcommand and its flags, writing the profile to
+/// `output`, then `flags` of the user's.
+pub fn record_args(output: &str, frame_pointers: bool, flags: Option<&str>) -> Vec<String> {
+    let call_graph = if frame_pointers { "fp" } else { "dwarf" };
+    let mut args = ["record", "-g", "--call-graph", call_graph, "-o", output]
+        .map(String::from)
+        .to_vec();
+    args.extend(
+        flags
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(String::from),
+    );
+
+    args
+}
+
+/// The command showing the profile in `output`.
+pub fn report_args(output: &str) -> Vec<String> {
+    ["report", "-i", output].map(String::from).to_vec()
+}
+
+/// Why perf may not have been allowed to record, from the kernel's
+/// `perf_event_paranoid` level.
+fn paranoid_hint(level: Option<i32>) -> &'static str {
+    match level {
+        // Debian's and Android's level, which only root goes past
+        Some(level) if level > 2 => {
+            "kernel.perf_event_paranoid is above 2, which keeps unprivileged users from \
+             profiling; allow it with `sudo sysctl kernel.perf_event_paranoid=2`"
+        }
+        _ => {
+            "if perf wasn't allowed to record, lower kernel.perf_event_paranoid with \
+             `sudo sysctl kernel.perf_event_paranoid=2`, or give perf CAP_PERFMON"
+        }
+    }
+}
+
+/// Check that perf wrote a profile to `output` since `started`, with a hint
+/// when it didn't, as perf's own messages are easily missed among the
+/// program's output.
+pub fn check_profile(output: &Path, started: SystemTime) -> Result<()> {
+    let written = fs::metadata(output)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| modified >= started);
+    if !written {
+        let level = fs::read_to_string("/proc/sys/kernel/perf_event_paranoid")
+            .ok()
+            .and_then(|s| s.trim().parse().ok());
+        bail!(
+            "perf didn't write a profile to {}; {}",
+            output.display(),
+            paranoid_hint(level)
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn call_graph() {
+        assert!(frame_pointers(&["-Cforce-frame-pointers=yes"]));
+        assert!(frame_pointers(&["-C", "force-frame-pointers"]));
+        assert!(frame_pointers(&["--codegen=force-frame-pointers=on"]));
+        assert!(!frame_pointers(&[
+            "-C",
+            "force-frame-pointers=yes",
+            "-Cforce-frame-pointers=no"
+        ]));
+        assert!(!frame_pointers(&["-Copt-level=1"]));
+        assert!(!frame_pointers::<&str>(&[]));
+
+        assert_eq!(
+            record_args("target/perf.data", false, Some("-F 99")),
+            [
+                "record",
+                "-g",
+                "--call-graph",
+                "dwarf",
+                "-o",
+                "target/perf.data",
+                "-F",
+                "99"
+            ]
+        );
+        assert_eq!(record_args("perf.data", true, None)[3], "fp");
+        assert!(paranoid_hint(Some(3)).contains("above 2"));
+    }
+}
@@ -0,0 +1,320 @@
+//! Keeping gdbserver running on the remote between sessions
+//! (`--persistent-server`), for `--watch` loops over slow links.
+//!
+//! The first session starts `gdbserver --multi` over SSH, with its port
+//! forwarded back here, in a process group of its own so that the end of a
+//! session doesn't take it down. What it started is kept in
+//! `target/cargo-debug/persistent-server.json`; later sessions find it there,
+//! upload the binary only when it changed, and have gdb connect in
+//! extended-remote mode, `set remote exec-file` and `run` it again.
+//!
+//! A server whose SSH connection is gone is replaced by the next session.
+//! The server is stopped when `--watch` ends, or straight after the session
+//! outside of `--watch`.
+
+use std::env;
+use std::fs;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use log::{info, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::remote::{copy, ssh_args, ssh_output, Remote};
+use crate::{debug_command, quote, watch, DebugSetup, Debugger};
+
+/// How long gdbserver's forwarded port gets to start listening.
+const STARTUP: Duration = Duration::from_secs(10);
+
+/// The binary uploaded last.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct Upload {
+    name: String,
+    /// Its size and modification time
+    stamp: String,
+}
+
+/// The server a session left running.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct State {
+    destination: String,
+    port: u16,
+    /// The temporary directory on the remote the binary is uploaded to
+    dir: String,
+    /// The local ssh running gdbserver and forwarding its port
+    ssh_pid: u32,
+    uploaded: Option<Upload>,
+}
+
+/// Where the state of the server is kept for the workspace building into
+/// `target_dir`.
+pub fn state_file(target_dir: &Path) -> PathBuf {
+    target_dir.join("cargo-debug/persistent-server.json")
+}
+
+fn load(file: &Path) -> Option<State> {
+    serde_json::from_str(&fs::read_to_string(file).ok()?).ok()
+}
+
+fn save(file: &Path, state: &State) -> Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, serde_json::to_string_pretty(state)?)
+        .with_context(|| format!("Could not write {}", file.display()))
+}
+
+/// Whether the process `pid` is still running.
+fn alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    // SAFETY: signal 0 only checks that the process exists
+    return unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    #[cfg(not(unix))]
+    return false;
+}
+
+/// Whether `state` is a server `remote` can use: the same one, still
+/// connected, as ssh exits with gdbserver.
+fn reusable(state: &State, remote: &Remote, alive: impl Fn(u32) -> bool) -> bool {
+    state.destination == remote.destination && state.port == remote.port && alive(state.ssh_pid)
+}
+
+/// The size and modification time of `bin`, which change when it's rebuilt.
+fn stamp(bin: &str) -> Result<String> {
+    let metadata = fs::metadata(bin).with_context(|| format!("Could not read {bin}"))?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(format!("{}@{}", metadata.len(), modified.as_nanos()))
+}
+
+/// Wait for ssh to forward `port`, failing if it exits first.
+fn wait_listening(ssh: &mut Child, port: u16) -> Result<()> {
+    let deadline = Instant::now() + STARTUP;
+    while Instant::now() < deadline {
+        if let Some(status) = ssh.try_wait()? {
+            bail!("ssh running gdbserver exited ({status})");
+        }
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    bail!("gdbserver's port {port} was not forwarded within {STARTUP:?}")
+}
+
+/// Start `gdbserver --multi` on the remote, forwarding its port here.
+fn start(remote: &Remote) -> Result<State> {
+    let dir = ssh_output(remote.destination, "mktemp -d /tmp/cargo-debug.XXXXXX")?;
+    let server_args = ["--multi".to_string(), format!("localhost:{}", remote.port)];
+    let command = quote::sh_command_line(Path::new("gdbserver"), &server_args);
+    let mut args = vec!["-L".to_string(), format!("{0}:localhost:{0}", remote.port)];
+    args.extend(ssh_args(remote.destination, false, &command));
+    trace!("synthesized ssh arguments: {:?}", args);
+
+    let mut cmd = Command::new("ssh");
+    cmd.args(&args).stdin(Stdio::null());
+    // Out of the session's process group, which --watch signals to end it
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+    let mut ssh = cmd.spawn().context("Could not run ssh")?;
+    if let Err(e) = wait_listening(&mut ssh, remote.port) {
+        let _ = ssh.kill();
+        let _ = ssh.wait();
+        return Err(e);
+    }
+    info!(
+        "started gdbserver --multi on {}, port {}",
+        remote.destination, remote.port
+    );
+
+    Ok(State {
+        destination: remote.destination.to_string(),
+        port: remote.port,
+        dir,
+        ssh_pid: ssh.id(),
+        uploaded: None,
+    })
+}
+
+/// Stop the server `state` describes and remove its directory.
+fn shut_down(state: &State) {
+    #[cfg(unix)]
+    if alive(state.ssh_pid) {
+        // SAFETY: signals the ssh this tool started
+        unsafe { libc::kill(state.ssh_pid as libc::pid_t, libc::SIGTERM) };
+    }
+    let remove = format!("rm -rf {}", quote::sh(&state.dir));
+    if let Err(e) = ssh_output(&state.destination, &remove) {
+        warn!(
+            "could not clean up {} on {}: {e}",
+            state.dir, state.destination
+        );
+    }
+}
+
+/// Stop the server kept in `file`, if there is one.
+pub fn stop(file: &Path) {
+    if let Some(state) = load(file) {
+        info!("stopping gdbserver on {}", state.destination);
+        shut_down(&state);
+    }
+    let _ = fs::remove_file(file);
+}
+
+/// gdb's commands running `remote_bin` on the server at `port`.
+fn client_commands(remote_bin: &str, port: u16) -> Vec<String> {
+    vec![
+        format!("target extended-remote localhost:{port}"),
+        format!("set remote exec-file {remote_bin}"),
+        "run".to_string(),
+    ]
+}
+
+/// Debug `bin` with gdb on the server kept in `file`, starting one if
+/// there's none to reuse.
+pub fn debug(
+    file: &Path,
+    remote: &Remote,
+    debugger: &Debugger,
+    bin: &str,
+    options: &[String],
+    setup: &DebugSetup,
+) -> Result<()> {
+    if cfg!(not(unix)) {
+        bail!("--persistent-server is only supported on Unix");
+    }
+    if *debugger != Debugger::Gdb {
+        bail!("--persistent-server needs gdb, for its extended-remote mode");
+    }
+    let name = Path::new(bin)
+        .file_name()
+        .context("The selected binary has no file name")?
+        .to_string_lossy()
+        .into_owned();
+
+    let mut state = match load(file) {
+        Some(state) if reusable(&state, remote, alive) => {
+            info!("reusing gdbserver on {}", state.destination);
+            state
+        }
+        stale => {
+            if let Some(stale) = stale {
+                warn!(
+                    "the persistent gdbserver on {} is gone, starting another",
+                    stale.destination
+                );
+                shut_down(&stale);
+            }
+            start(remote)?
+        }
+    };
+    save(file, &state)?;
+
+    let upload = Upload {
+        name: name.clone(),
+        stamp: stamp(bin)?,
+    };
+    if state.uploaded.as_ref() == Some(&upload) {
+        info!("{name} is unchanged, not uploading it again");
+    } else {
+        info!("copying {name} to {}:{}", remote.destination, state.dir);
+        copy(remote.destination, bin, &state.dir)?;
+        state.uploaded = Some(upload);
+        save(file, &state)?;
+    }
+
+    // Debug the local copy, which has the same symbols
+    let mut commands = setup.commands.clone();
+    commands.extend(client_commands(
+        &format!("{}/{name}", state.dir),
+        remote.port,
+    ));
+    let local = DebugSetup {
+        program: setup.program.clone(),
+        flags: setup.flags.clone(),
+        commands,
+        command_file: setup.command_file.clone(),
+    };
+    let (debug_path, debug_args) = debug_command(debugger, bin, options, &local)?;
+
+    // The debugger handles ctrl+c, and we still need to tidy up after it
+    ctrlc::set_handler(|| {}).expect("Error setting Ctrl-C handler");
+    let status = Command::new(&debug_path).args(debug_args).status();
+
+    // Outside --watch, there's no next session to keep it for
+    if env::var_os(watch::WATCHED_VAR).is_none() {
+        stop(file);
+    } else if !alive(state.ssh_pid) {
+        warn!(
+            "gdbserver on {} exited, the next session starts another",
+            remote.destination
+        );
+        stop(file);
+    }
+    status.with_context(|| format!("Could not run {}", debug_path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn server_state() {
+        let dir = env::temp_dir().join(format!("cargo-debug-persistent-{}", std::process::id()));
+        let file = state_file(&dir);
+        assert_eq!(load(&file), None);
+
+        let bin = dir.join("foo");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&bin, "v1").unwrap();
+        let first = stamp(bin.to_str().unwrap()).unwrap();
+        fs::write(&bin, "v2, rebuilt").unwrap();
+        assert_ne!(stamp(bin.to_str().unwrap()).unwrap(), first);
+
+        let state = State {
+            destination: "me@pi.local".to_string(),
+            port: 2345,
+            dir: "/tmp/cargo-debug.abc123".to_string(),
+            ssh_pid: 4242,
+            uploaded: Some(Upload {
+                name: "foo".to_string(),
+                stamp: first,
+            }),
+        };
+        save(&file, &state).unwrap();
+        assert_eq!(load(&file).as_ref(), Some(&state));
+
+        let remote = Remote {
+            destination: "me@pi.local",
+            port: 2345,
+            client: true,
+        };
+        assert!(reusable(&state, &remote, |_| true));
+        assert!(!reusable(&state, &remote, |_| false));
+        let elsewhere = Remote {
+            destination: "me@other.local",
+            ..remote
+        };
+        assert!(!reusable(&state, &elsewhere, |_| true));
+
+        assert_eq!(
+            client_commands("/tmp/cargo-debug.abc123/foo", 2345),
+            [
+                "target extended-remote localhost:2345",
+                "set remote exec-file /tmp/cargo-debug.abc123/foo",
+                "run"
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
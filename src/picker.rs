@@ -0,0 +1,33 @@
+//! Asking which of several candidates to debug, at the terminal.
+
+use std::io::{BufRead, IsTerminal, Write};
+
+use anyhow::{bail, Result};
+
+/// The index of the one of `choices` picked, after `heading`, e.g. "3 tests
+/// failed". Without a terminal to ask on, the choices are an error.
+pub fn pick(heading: &str, choices: &[String]) -> Result<usize> {
+    if !std::io::stdin().is_terminal() {
+        bail!(
+            "{heading}, run cargo-debug in a terminal to pick one: {}",
+            choices.join(", ")
+        );
+    }
+
+    eprintln!("cargo-debug: {heading}:");
+    for (i, choice) in choices.iter().enumerate() {
+        eprintln!("  {}) {choice}", i + 1);
+    }
+    loop {
+        eprint!("cargo-debug: debug which one? [1-{}] ", choices.len());
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        if std::io::stdin().lock().read_line(&mut answer)? == 0 {
+            bail!("nothing picked");
+        }
+        match answer.trim().parse::<usize>() {
+            Ok(n) if (1..=choices.len()).contains(&n) => return Ok(n - 1),
+            _ => eprintln!("cargo-debug: enter a number from 1 to {}", choices.len()),
+        }
+    }
+}
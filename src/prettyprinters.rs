@@ -0,0 +1,75 @@
+//! Loading the toolchain's Rust pretty-printers into plain gdb.
+//!
+//! `rust-gdb` is a shell wrapper that points gdb at the Python printers in
+//! `<sysroot>/lib/rustlib/etc`. When gdb is run directly, the same commands
+//! are passed with `-iex` so they're evaluated before the binary is loaded
+//! and its objfile picks up the printers.
+
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+/// The script registering the printers, which imports its siblings.
+const SCRIPT: &str = "gdb_load_rust_pretty_printers.py";
+
+/// The toolchain directory holding the printers, if they're installed.
+pub fn find(sysroot: &Path) -> Option<PathBuf> {
+    let etc = sysroot.join("lib/rustlib/etc");
+    etc.join(SCRIPT).is_file().then_some(etc)
+}
+
+/// gdb arguments loading the printers in `etc`.
+///
+/// The directory is trusted and searched before the script is sourced, and
+/// all of it happens before the binary is loaded.
+pub fn flags(etc: &Path) -> Vec<String> {
+    let etc_path = etc.display();
+    [
+        format!("add-auto-load-safe-path {etc_path}"),
+        format!("directory {etc_path}"),
+        format!("source {}", etc.join(SCRIPT).display()),
+    ]
+    .into_iter()
+    .flat_map(|c| ["-iex".to_string(), c])
+    .collect()
+}
+
+/// gdb arguments loading the printers of the toolchain at `sysroot`, or
+/// none with a warning when they aren't installed.
+pub fn load(sysroot: Option<&Path>) -> Vec<String> {
+    match sysroot.and_then(find) {
+        Some(etc) => flags(&etc),
+        None => {
+            warn!(
+                "the toolchain's gdb pretty-printers were not found, \
+                 Rust types won't be pretty-printed (disable with --no-pretty-printers)"
+            );
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gdb_printer_flags() {
+        let flags = flags(Path::new("/rust/lib/rustlib/etc"));
+        assert_eq!(
+            flags,
+            [
+                "-iex",
+                "add-auto-load-safe-path /rust/lib/rustlib/etc",
+                "-iex",
+                "directory /rust/lib/rustlib/etc",
+                "-iex",
+                "source /rust/lib/rustlib/etc/gdb_load_rust_pretty_printers.py"
+            ]
+        );
+
+        let missing = std::env::temp_dir().join("cargo-debug-no-sysroot");
+        assert_eq!(find(&missing), None);
+        assert!(load(None).is_empty());
+    }
+}
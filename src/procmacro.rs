@@ -0,0 +1,438 @@
+//! Debugging a proc macro (`--proc-macro`) by running rustc, which loads
+//! and runs it, under the debugger while it compiles a consumer of the
+//! macro.
+//!
+//! The consumer is either a package using the macro, whose rustc command
+//! line (environment, `--extern` flags and all) is taken from `cargo build
+//! -vv` once its crate root is touched so it's compiled again, or a single
+//! file, compiled as a library against the macro cargo builds. Either way
+//! the debugger starts the toolchain's rustc rather than rustup's proxy,
+//! with pending breakpoints on the macro's entry points: the functions
+//! marked `#[proc_macro]`, `#[proc_macro_derive]` or
+//! `#[proc_macro_attribute]` in its crate root.
+
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+use cargo_metadata::{Metadata, Package, Target};
+use log::{info, warn};
+
+use crate::{
+    cargo_command, metadata, parse_artifacts, quote, sysroot, BuildArgs, Debugger, Events,
+};
+
+/// The entry points' attributes.
+const ATTRIBUTES: [&str; 3] = [
+    "#[proc_macro]",
+    "#[proc_macro_derive(",
+    "#[proc_macro_attribute]",
+];
+
+/// A rustc run compiling the consumer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Invocation {
+    pub rustc: PathBuf,
+    pub args: Vec<String>,
+    /// Set on top of the inherited environment
+    pub env: Vec<(String, String)>,
+    pub cwd: PathBuf,
+}
+
+/// Whether `word` is a rustc executable.
+fn is_rustc(word: &str) -> bool {
+    Path::new(word)
+        .file_stem()
+        .is_some_and(|stem| stem == "rustc")
+}
+
+/// The command compiling `crate_name` in the output of `cargo build -vv`,
+/// which prints it as
+///
+/// ```text
+///      Running `CARGO=/usr/bin/cargo ... /path/to/rustc --crate-name foo ...`
+/// ```
+///
+/// A `RUSTC_WRAPPER` in front of rustc is left out. Where it ran isn't
+/// shown, so `cwd` is left empty.
+fn rustc_invocation(output: &str, crate_name: &str, windows: bool) -> Option<Invocation> {
+    output.lines().find_map(|line| {
+        let command = line.trim().strip_prefix("Running `")?.strip_suffix('`')?;
+        let words = quote::words(command, windows)?;
+        let rustc = words.iter().position(|w| is_rustc(w))?;
+        let args = &words[rustc + 1..];
+        let compiles = args
+            .windows(2)
+            .any(|pair| pair[0] == "--crate-name" && pair[1] == crate_name);
+        if !compiles {
+            return None;
+        }
+
+        let env = words[..rustc]
+            .iter()
+            .filter_map(|w| w.split_once('='))
+            .filter(|(name, _)| !name.is_empty() && !name.contains(['/', '\\']))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Some(Invocation {
+            rustc: PathBuf::from(&words[rustc]),
+            args: args.to_vec(),
+            env,
+            cwd: PathBuf::new(),
+        })
+    })
+}
+
+/// `args` with cargo's JSON diagnostics taken out, for a person to read.
+fn interactive_args(args: Vec<String>) -> Vec<String> {
+    let mut kept = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--error-format" || arg == "--json" {
+            args.next();
+            continue;
+        }
+        if arg.starts_with("--error-format=") || arg.starts_with("--json=") {
+            continue;
+        }
+        kept.push(arg);
+    }
+
+    kept
+}
+
+/// The names of the entry points in a proc macro crate root's `source`.
+fn entry_points(source: &str) -> Vec<String> {
+    let mut names = vec![];
+    let mut marked = false;
+    for line in source.lines() {
+        let line = line.trim();
+        if ATTRIBUTES.iter().any(|a| line.starts_with(a)) {
+            marked = true;
+            continue;
+        }
+        if !marked || line.starts_with("#[") || line.starts_with("//") {
+            continue;
+        }
+        marked = false;
+        let Some((_, rest)) = line.split_once("fn ") else {
+            continue;
+        };
+        let name = rest
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .next()
+            .unwrap_or_default();
+        if !name.is_empty() {
+            names.push(name.to_string());
+        }
+    }
+
+    names
+}
+
+/// Pending breakpoints on the entry points `names` of the macro crate
+/// `crate_name`, which rustc loads only once it gets to the macro.
+fn breakpoints(debugger: &Debugger, crate_name: &str, names: &[String]) -> Result<Vec<String>> {
+    let commands = match debugger {
+        Debugger::Gdb => std::iter::once("set breakpoint pending on".to_string())
+            .chain(names.iter().map(|n| format!("break {crate_name}::{n}")))
+            .collect(),
+        Debugger::Lldb => names
+            .iter()
+            .map(|n| format!("breakpoint set --name {crate_name}::{n}"))
+            .collect(),
+        other => bail!(
+            "--proc-macro is only supported by gdb and lldb, not {}",
+            other.name()
+        ),
+    };
+
+    Ok(commands)
+}
+
+/// The workspace's package `name`.
+fn package<'a>(metadata: &'a Metadata, name: &str) -> Result<&'a Package> {
+    metadata
+        .packages
+        .iter()
+        .find(|p| p.name == name)
+        .with_context(|| format!("There is no package {name} in this workspace"))
+}
+
+fn has_kind(target: &Target, kinds: &[&str]) -> bool {
+    target.kind.iter().any(|k| kinds.contains(&k.as_str()))
+}
+
+/// The crate `package` builds first: its library, or else its first binary.
+fn main_target(package: &Package) -> Result<&Target> {
+    let targets = &package.targets;
+    targets
+        .iter()
+        .find(|t| has_kind(t, &["lib", "rlib", "dylib", "proc-macro"]))
+        .or_else(|| targets.iter().find(|t| has_kind(t, &["bin"])))
+        .with_context(|| format!("{} has no library or binary to compile", package.name))
+}
+
+fn crate_name(target: &Target) -> String {
+    target.name.replace('-', "_")
+}
+
+/// `args` building a package's own target, rather than the one selected.
+fn package_build(args: &BuildArgs) -> BuildArgs {
+    BuildArgs {
+        bin: None,
+        bin_id: None,
+        example: None,
+        test: None,
+        tests: false,
+        lib: false,
+        bench: None,
+        package: None,
+        all_targets: false,
+        last_failed: false,
+        ..args.clone()
+    }
+}
+
+/// The rustc compiling the consumer package `consumer`, from `cargo build
+/// -vv`.
+fn package_invocation(
+    args: &BuildArgs,
+    metadata: &Metadata,
+    consumer: &Package,
+) -> Result<Invocation> {
+    let target = main_target(consumer)?;
+    let name = crate_name(target);
+    // cargo only shows the commands it runs, so the crate has to be stale
+    File::options()
+        .write(true)
+        .open(&target.src_path)
+        .and_then(|file| file.set_modified(SystemTime::now()))
+        .with_context(|| format!("Could not touch {}", target.src_path))?;
+
+    let mut cmd = cargo_command(&package_build(args));
+    cmd.args(["-vv", "--package", &consumer.name]);
+    if has_kind(target, &["bin"]) {
+        cmd.args(["--bin", &target.name]);
+    } else {
+        cmd.arg("--lib");
+    }
+    info!("capturing how {name} is compiled: {cmd:?}");
+    let output = cmd.output().context("Could not run cargo build")?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        eprint!("{stderr}");
+        bail!(
+            "cargo build -p {} failed ({})",
+            consumer.name,
+            output.status
+        );
+    }
+
+    let invocation = rustc_invocation(&stderr, &name, cfg!(windows))
+        .with_context(|| format!("cargo build -vv did not show how {name} is compiled"))?;
+
+    // The jobserver cargo handed rustc is gone
+    Ok(Invocation {
+        args: interactive_args(invocation.args),
+        env: invocation
+            .env
+            .into_iter()
+            .filter(|(name, _)| name != "CARGO_MAKEFLAGS")
+            .collect(),
+        cwd: metadata.workspace_root.clone().into_std_path_buf(),
+        ..invocation
+    })
+}
+
+/// The rustc compiling the file `consumer` as a library using the macro
+/// `macro_target` of `macro_package`, which cargo builds.
+fn file_invocation(
+    args: &BuildArgs,
+    metadata: &Metadata,
+    macro_package: &Package,
+    macro_target: &Target,
+    consumer: &Path,
+) -> Result<Invocation> {
+    let mut cmd = cargo_command(&package_build(args));
+    cmd.args(["--package", &macro_package.name, "--lib"]);
+    let output = cmd.output().context("Could not run cargo build")?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        bail!(
+            "cargo build -p {} failed ({})",
+            macro_package.name,
+            output.status
+        );
+    }
+    let artifacts = parse_artifacts(output.stdout.as_slice(), &mut Events::default()).artifacts;
+    let library = artifacts
+        .iter()
+        .filter(|a| has_kind(&a.target, &["proc-macro"]))
+        .find_map(|a| a.filenames.first())
+        .with_context(|| format!("cargo did not build {} as a proc macro", macro_package.name))?;
+
+    let out_dir = metadata.target_directory.join("cargo-debug/proc-macro");
+    fs::create_dir_all(&out_dir)?;
+    let stem = consumer
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = stem.replace(|c: char| !(c.is_alphanumeric() || c == '_'), "_");
+    let deps = library.parent().map(|d| d.to_string()).unwrap_or_default();
+
+    Ok(Invocation {
+        rustc: PathBuf::new(),
+        args: vec![
+            "--crate-name".to_string(),
+            name,
+            format!("--edition={}", macro_target.edition),
+            "--crate-type=lib".to_string(),
+            consumer.to_string_lossy().into_owned(),
+            "--emit=metadata".to_string(),
+            "--out-dir".to_string(),
+            out_dir.to_string(),
+            "--extern".to_string(),
+            format!("{}={library}", crate_name(macro_target)),
+            "-L".to_string(),
+            format!("dependency={deps}"),
+        ],
+        env: vec![],
+        cwd: env::current_dir()?,
+    })
+}
+
+/// The rustc run compiling `consumer`, a file or a package, with the macro
+/// `macro_package`, and `debugger`'s commands breaking in the macro.
+pub fn prepare(
+    args: &BuildArgs,
+    debugger: &Debugger,
+    macro_package: &str,
+    consumer: &str,
+) -> Result<(Invocation, Vec<String>)> {
+    let metadata = metadata(args)?;
+    let macro_package = package(&metadata, macro_package)?;
+    let macro_target = macro_package
+        .targets
+        .iter()
+        .find(|t| has_kind(t, &["proc-macro"]))
+        .with_context(|| format!("{} is not a proc-macro crate", macro_package.name))?;
+    let macro_crate = crate_name(macro_target);
+
+    let source = fs::read_to_string(&macro_target.src_path)
+        .with_context(|| format!("Could not read {}", macro_target.src_path))?;
+    let names = entry_points(&source);
+    if names.is_empty() {
+        warn!(
+            "found no #[proc_macro] functions in {}, set breakpoints yourself",
+            macro_target.src_path
+        );
+    }
+    let commands = breakpoints(debugger, &macro_crate, &names)?;
+
+    let path = Path::new(consumer);
+    let mut invocation = if path.is_file() {
+        file_invocation(args, &metadata, macro_package, macro_target, path)?
+    } else {
+        package_invocation(args, &metadata, package(&metadata, consumer)?)?
+    };
+    let extern_flag = format!("{macro_crate}=");
+    if !invocation.args.iter().any(|a| a.starts_with(&extern_flag)) {
+        warn!("{consumer} does not depend on {macro_crate}, its macros may never run");
+    }
+
+    // rustup's proxy would run rustc as a child the debugger doesn't follow
+    invocation.rustc = sysroot()?
+        .join("bin")
+        .join(format!("rustc{}", env::consts::EXE_SUFFIX));
+    info!(
+        "debugging {} compiling {consumer}, breaking in {macro_crate}::{{{}}}",
+        invocation.rustc.display(),
+        names.join(", ")
+    );
+
+    Ok((invocation, commands))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn captured_invocation() {
+        let output = include_str!("../testdata/cargo-build-vv.txt");
+        let invocation = rustc_invocation(output, "consumer", false).unwrap();
+        assert_eq!(
+            invocation.rustc,
+            Path::new("/home/me/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/bin/rustc")
+        );
+        assert_eq!(
+            invocation.env,
+            [
+                ("CARGO".to_string(), "/usr/bin/cargo".to_string()),
+                ("CARGO_CRATE_NAME".to_string(), "consumer".to_string()),
+                (
+                    "CARGO_MANIFEST_DIR".to_string(),
+                    "/work/my dir/consumer".to_string()
+                ),
+                ("CARGO_PKG_NAME".to_string(), "consumer".to_string()),
+            ]
+        );
+        let args = invocation.args;
+        assert_eq!(args[..3], ["--crate-name", "consumer", "--edition=2021"]);
+        assert!(
+            args.contains(&"my_macros=/work/target/debug/deps/libmy_macros-5f1c.so".to_string())
+        );
+
+        let args = interactive_args(args);
+        assert!(!args
+            .iter()
+            .any(|a| a.starts_with("--error-format") || a.starts_with("--json")));
+        assert!(args.contains(&"--crate-type".to_string()));
+
+        // The macro crate's own build isn't the one
+        assert!(rustc_invocation(output, "unicode_ident", false).is_none());
+    }
+
+    #[test]
+    fn macro_entry_points() {
+        let source = r#"
+use proc_macro::TokenStream;
+
+#[proc_macro_derive(Builder, attributes(builder))]
+pub fn derive_builder(input: TokenStream) -> TokenStream {
+    input
+}
+
+#[proc_macro_attribute]
+#[allow(unused)]
+pub fn traced(attr: TokenStream, item: TokenStream) -> TokenStream {
+    item
+}
+
+#[proc_macro]
+pub fn sql(input: TokenStream) -> TokenStream {
+    input
+}
+
+fn helper() {}
+"#;
+        let names = entry_points(source);
+        assert_eq!(names, ["derive_builder", "traced", "sql"]);
+
+        assert_eq!(
+            breakpoints(&Debugger::Gdb, "my_macros", &names[..1]).unwrap(),
+            [
+                "set breakpoint pending on",
+                "break my_macros::derive_builder"
+            ]
+        );
+        assert_eq!(
+            breakpoints(&Debugger::Lldb, "my_macros", &names[2..]).unwrap(),
+            ["breakpoint set --name my_macros::sql"]
+        );
+        assert!(breakpoints(&Debugger::Windbg, "my_macros", &names).is_err());
+    }
+}
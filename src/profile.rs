@@ -0,0 +1,231 @@
+//! Spotting profile settings that get in the way of debugging.
+//!
+//! The optimization level and debug info are what cargo reports for the
+//! artifact. `lto`, `strip` and `panic` aren't reported, so they're resolved
+//! from the manifest, cargo's config files and the environment, which also
+//! tells where each offending value was set.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use cargo_metadata::{Artifact, ArtifactDebuginfo};
+use log::{trace, warn};
+
+use crate::cargoconfig::{self, Setting};
+
+/// The settings that can be allowed with `--allow-profile`.
+pub const SETTINGS: [&str; 5] = ["opt-level", "lto", "debug", "strip", "panic"];
+
+/// What the advisories are decided on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Settings {
+    opt_level: String,
+    full_debuginfo: bool,
+    debug: String,
+    configured: BTreeMap<String, Setting>,
+}
+
+/// The `key = value` pairs of `[profile.<name>]` in a manifest, with quotes
+/// stripped from string values.
+pub fn manifest_settings(manifest: &str, name: &str) -> BTreeMap<String, String> {
+    let header = format!("[profile.{name}]");
+    let mut settings = BTreeMap::new();
+    let mut in_profile = false;
+    for line in manifest.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.starts_with('[') {
+            in_profile = line == header;
+            continue;
+        }
+        if !in_profile {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+            settings.insert(key.trim().to_string(), value.to_string());
+        }
+    }
+
+    settings
+}
+
+/// `CARGO_PROFILE_<NAME>_<KEY>`, which takes precedence over the manifest.
+fn env_var(profile: &str, key: &str) -> String {
+    format!(
+        "CARGO_PROFILE_{}_{}",
+        profile.to_uppercase().replace('-', "_"),
+        key.to_uppercase().replace('-', "_")
+    )
+}
+
+/// One line per setting of `profile` that hurts debugging and isn't
+/// `allowed`, with the override that fixes it.
+fn advisories(profile: &str, settings: &Settings, allowed: &[String]) -> Vec<String> {
+    let manifest = |key: &str| settings.configured.get(key).map(|s| s.value.as_str());
+    let is_off = |value: &str| matches!(value, "false" | "off" | "none" | "0");
+
+    let mut found = vec![];
+    if settings.opt_level != "0" {
+        found.push((
+            "opt-level",
+            format!(
+                "opt-level = {}: variables may be optimized out and stepping jumps around",
+                settings.opt_level
+            ),
+            "0".to_string(),
+        ));
+    }
+    if let Some(lto) = manifest("lto").filter(|lto| !is_off(lto)) {
+        found.push((
+            "lto",
+            format!("lto = {lto}: code is inlined across crates, hiding their frames"),
+            "false".to_string(),
+        ));
+    }
+    if !settings.full_debuginfo {
+        found.push((
+            "debug",
+            format!(
+                "debug = {}: without full debug info, variables and types can't be shown",
+                settings.debug
+            ),
+            "true".to_string(),
+        ));
+    }
+    if let Some(strip) = manifest("strip").filter(|strip| !is_off(strip)) {
+        found.push((
+            "strip",
+            format!("strip = {strip}: symbols are stripped from the binary"),
+            "false".to_string(),
+        ));
+    }
+    if manifest("panic") == Some("abort") {
+        found.push((
+            "panic",
+            "panic = \"abort\": panics abort without unwinding, so `catch_unwind` never catches"
+                .to_string(),
+            "unwind".to_string(),
+        ));
+    }
+
+    found
+        .into_iter()
+        .filter(|(key, _, _)| !allowed.iter().any(|a| a == key))
+        .map(|(key, problem, fix)| {
+            let source = match settings.configured.get(key) {
+                Some(setting) => format!(" (set in {})", setting.source),
+                None => String::new(),
+            };
+            format!("{problem}{source}; try {}={fix}", env_var(profile, key))
+        })
+        .collect()
+}
+
+/// Warn about the settings of `profile` that degrade debugging the
+/// artifact, apart from those `allowed`.
+pub fn check(
+    artifact: &Artifact,
+    profile: &str,
+    workspace_root: Option<&Path>,
+    allowed: &[String],
+) {
+    let (full_debuginfo, debug) = match &artifact.profile.debuginfo {
+        ArtifactDebuginfo::Full => (true, "2".to_string()),
+        ArtifactDebuginfo::None => (false, "0".to_string()),
+        ArtifactDebuginfo::Limited => (false, "1".to_string()),
+        ArtifactDebuginfo::LineTablesOnly => (false, "\"line-tables-only\"".to_string()),
+        ArtifactDebuginfo::LineDirectivesOnly => (false, "\"line-directives-only\"".to_string()),
+        other => (false, format!("{other:?}")),
+    };
+
+    let manifest = workspace_root.map(|root| root.join("Cargo.toml"));
+    let configured = cargoconfig::profile(profile, manifest.as_deref());
+    trace!("profile {profile} settings: {:?}", configured);
+
+    let settings = Settings {
+        opt_level: artifact.profile.opt_level.clone(),
+        full_debuginfo,
+        debug,
+        configured,
+    };
+    let advisories = advisories(profile, &settings, allowed);
+    if advisories.is_empty() {
+        return;
+    }
+
+    warn!(
+        "the {profile} profile has settings that make debugging harder \
+         (silence them with --allow-profile SETTING):\n  {}",
+        advisories.join("\n  ")
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[package]
+name = "foo"
+
+[profile.release]
+lto = "fat"   # smaller binaries
+strip = true
+panic = 'abort'
+
+[profile.dev]
+lto = false
+"#;
+
+    fn configured(manifest: &str, profile: &str) -> BTreeMap<String, Setting> {
+        manifest_settings(manifest, profile)
+            .into_iter()
+            .map(|(key, value)| {
+                let source = "Cargo.toml".to_string();
+                (key, Setting { value, source })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn profile_advisories() {
+        let release = manifest_settings(MANIFEST, "release");
+        assert_eq!(release["lto"], "fat");
+        assert_eq!(release["panic"], "abort");
+        assert_eq!(manifest_settings(MANIFEST, "dev")["lto"], "false");
+        assert!(manifest_settings(MANIFEST, "bench").is_empty());
+
+        let dev = Settings {
+            opt_level: "0".to_string(),
+            full_debuginfo: true,
+            debug: "2".to_string(),
+            configured: configured(MANIFEST, "dev"),
+        };
+        assert!(advisories("dev", &dev, &[]).is_empty());
+
+        let release = Settings {
+            opt_level: "3".to_string(),
+            full_debuginfo: false,
+            debug: "0".to_string(),
+            configured: configured(MANIFEST, "release"),
+        };
+        let found = advisories("release", &release, &[]);
+        assert_eq!(found.len(), 5);
+        assert!(found[0].starts_with("opt-level = 3"));
+        assert!(found[1].ends_with("(set in Cargo.toml); try CARGO_PROFILE_RELEASE_LTO=false"));
+        assert!(found[4].ends_with("try CARGO_PROFILE_RELEASE_PANIC=unwind"));
+
+        // Allowed settings aren't mentioned
+        let allowed = ["opt-level".to_string(), "strip".to_string()];
+        let found = advisories("release", &release, &allowed);
+        assert_eq!(found.len(), 3);
+        assert!(found
+            .iter()
+            .all(|a| !a.starts_with("opt-level") && !a.starts_with("strip")));
+
+        assert_eq!(
+            env_var("release-lto", "opt-level"),
+            "CARGO_PROFILE_RELEASE_LTO_OPT_LEVEL"
+        );
+    }
+}
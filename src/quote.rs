@@ -0,0 +1,209 @@
+//! Quoting command lines so they survive being pasted into a shell.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Quote an argument for a POSIX shell.
+pub fn sh(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return Cow::Borrowed(arg);
+    }
+
+    Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+}
+
+/// Quote an argument the way the MSVC runtime splits command lines.
+pub fn windows(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return Cow::Borrowed(arg);
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Escape the backslashes and the quote itself
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+
+    // Backslashes before the closing quote would escape it
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+
+    Cow::Owned(quoted)
+}
+
+/// Quote an argument as a PowerShell string literal.
+pub fn powershell(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:\\".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return Cow::Borrowed(arg);
+    }
+
+    Cow::Owned(format!("'{}'", arg.replace('\'', "''")))
+}
+
+/// Quote an argument for gdb commands that split their arguments like a
+/// command line, such as `set substitute-path`: in double quotes, where a
+/// backslash escapes the next character.
+pub fn gdb(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return Cow::Borrowed(arg);
+    }
+
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+
+    Cow::Owned(quoted)
+}
+
+/// Quote an argument for lldb's command interpreter, as the debugger command
+/// lines cargo-debug-lib builds do.
+pub use cargo_debug_lib::quote_lldb as lldb;
+
+/// Join a program and its arguments into a command line for the host platform.
+pub fn command_line(program: &Path, args: &[String]) -> String {
+    let quote = if cfg!(windows) { windows } else { sh };
+    join(quote, program, args)
+}
+
+/// Join a program and its arguments into a POSIX shell command line.
+pub fn sh_command_line(program: &Path, args: &[String]) -> String {
+    join(sh, program, args)
+}
+
+fn join(quote: fn(&str) -> Cow<'_, str>, program: &Path, args: &[String]) -> String {
+    let program = program.to_string_lossy();
+    std::iter::once(quote(&program))
+        .chain(args.iter().map(|a| quote(a)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The words of a command line, the reverse of quoting it: quoted for `sh`,
+/// or with double quotes on Windows, where backslashes are path separators.
+/// An unterminated quote or escape makes it `None`.
+pub fn words(line: &str, windows: bool) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word = None::<String>;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {
+                words.extend(word.take());
+                continue;
+            }
+            '\'' if !windows => {
+                let quoted = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => quoted.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let quoted = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' if !windows => quoted.push(chars.next()?),
+                        c => quoted.push(c),
+                    }
+                }
+            }
+            '\\' if !windows => word.get_or_insert_with(String::new).push(chars.next()?),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Some(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quote_sh() {
+        assert_eq!(sh("--args"), "--args");
+        assert_eq!(sh("/foo/target/debug/foo"), "/foo/target/debug/foo");
+        assert_eq!(sh(""), "''");
+        assert_eq!(sh("/my dir/foo"), "'/my dir/foo'");
+        assert_eq!(sh("it's"), r"'it'\''s'");
+        assert_eq!(sh("$HOME"), "'$HOME'");
+    }
+
+    #[test]
+    fn quote_gdb() {
+        assert_eq!(gdb("/rustc/abc"), "/rustc/abc");
+        assert_eq!(gdb(""), r#""""#);
+        assert_eq!(gdb("/my dir/foo"), r#""/my dir/foo""#);
+        assert_eq!(gdb(r#"C:\my "dir""#), r#""C:\\my \"dir\"""#);
+        // Only lldb expands these
+        assert_eq!(gdb("$HOME"), r#""$HOME""#);
+    }
+
+    #[test]
+    fn quote_lldb() {
+        assert_eq!(lldb("--verbose"), "--verbose");
+        assert_eq!(lldb(""), r#""""#);
+        assert_eq!(lldb("/my dir/foo"), r#""/my dir/foo""#);
+        assert_eq!(lldb(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(lldb(r"C:\foo `x` $y"), r#""C:\\foo \`x\` \$y""#);
+    }
+
+    #[test]
+    fn quote_powershell() {
+        assert_eq!(powershell(r"C:\foo\foo.exe"), r"C:\foo\foo.exe");
+        assert_eq!(powershell(""), "''");
+        assert_eq!(powershell("--args"), "--args");
+        assert_eq!(powershell("$env:HOME"), "'$env:HOME'");
+        assert_eq!(powershell("it's"), "'it''s'");
+    }
+
+    #[test]
+    fn quote_windows() {
+        assert_eq!(windows(r"C:\foo\foo.exe"), r"C:\foo\foo.exe");
+        assert_eq!(windows(""), r#""""#);
+        assert_eq!(
+            windows(r"C:\Program Files\foo.exe"),
+            r#""C:\Program Files\foo.exe""#
+        );
+        assert_eq!(windows(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(windows(r"C:\my dir\"), r#""C:\my dir\\""#);
+    }
+
+    #[test]
+    fn command_words() {
+        assert_eq!(
+            words(r#"a 'b c' "d \"e\"" f\ g 'it'\''s'"#, false).unwrap(),
+            ["a", "b c", r#"d "e""#, "f g", "it's"]
+        );
+        assert_eq!(
+            words(r#"C:\rust\bin\rustc.exe "C:\my dir\lib.rs""#, true).unwrap(),
+            [r"C:\rust\bin\rustc.exe", r"C:\my dir\lib.rs"]
+        );
+        assert_eq!(words("'unterminated", false), None);
+    }
+}
@@ -0,0 +1,234 @@
+//! Debugging on another machine over SSH.
+//!
+//! The binary is copied into a temporary directory on the remote, which is
+//! removed once the session ends. The remote needs the debugger (or
+//! gdbserver) installed, and SSH must be able to log in without prompting
+//! for anything but a password or passphrase.
+
+use std::path::Path;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use log::{info, trace, warn};
+
+use crate::{debug_command, find_executable, quote, DebugSetup, Debugger};
+
+/// Where to debug, and how.
+pub struct Remote<'a> {
+    /// The SSH destination, e.g. `user@host`
+    pub destination: &'a str,
+    /// The port gdbserver listens on
+    pub port: u16,
+    /// Run gdbserver on the remote and connect a local debugger to it
+    pub client: bool,
+}
+
+/// The host part of an SSH destination.
+fn host(destination: &str) -> &str {
+    destination
+        .rsplit_once('@')
+        .map_or(destination, |(_, host)| host)
+}
+
+/// Arguments to ssh that run `command` on `destination`, with a terminal if
+/// it's interactive.
+pub fn ssh_args(destination: &str, tty: bool, command: &str) -> Vec<String> {
+    let mut args = vec![];
+    if tty {
+        args.push("-t".to_string());
+    }
+    args.extend([
+        "--".to_string(),
+        destination.to_string(),
+        command.to_string(),
+    ]);
+
+    args
+}
+
+/// Run a command on the remote, returning what it printed.
+pub fn ssh_output(destination: &str, command: &str) -> Result<String> {
+    let output = Command::new("ssh")
+        .args(ssh_args(destination, false, command))
+        .output()
+        .context("Could not run ssh")?;
+    if !output.status.success() {
+        bail!(
+            "`{command}` failed on {destination}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Copy `bin` into `dir` on the remote, with rsync if it's around.
+pub fn copy(destination: &str, bin: &str, dir: &str) -> Result<()> {
+    let target = format!("{destination}:{dir}/");
+    let mut cmd = if find_executable(Path::new("rsync")).is_some() {
+        let mut cmd = Command::new("rsync");
+        cmd.args(["--compress", "--times", "--", bin, &target]);
+        cmd
+    } else {
+        let mut cmd = Command::new("scp");
+        cmd.args(["-q", "--", bin, &target]);
+        cmd
+    };
+
+    trace!("synthesized copy command: {:?}", cmd);
+
+    let status = cmd.status().context("Could not copy the binary")?;
+    if !status.success() {
+        bail!("Could not copy {bin} to {target}");
+    }
+
+    Ok(())
+}
+
+/// Commands connecting a local debugger to gdbserver on `port`.
+fn client_commands(debugger: &Debugger, port: u16) -> Result<Vec<String>> {
+    Ok(match debugger {
+        Debugger::Gdb => vec![format!("target remote localhost:{port}")],
+        Debugger::Lldb => vec![format!("gdb-remote localhost:{port}")],
+        _ => bail!("--remote-client needs gdb or lldb to connect to gdbserver"),
+    })
+}
+
+/// Start gdbserver on the remote, forwarding `port` back here.
+fn start_gdbserver(remote: &Remote, bin: &str, options: &[String]) -> Result<Child> {
+    let mut gdbserver_args = vec![format!("localhost:{}", remote.port), bin.to_string()];
+    gdbserver_args.extend(options.iter().cloned());
+    let command = quote::sh_command_line(Path::new("gdbserver"), &gdbserver_args);
+
+    let mut args = vec!["-L".to_string(), format!("{0}:localhost:{0}", remote.port)];
+    args.extend(ssh_args(remote.destination, false, &command));
+
+    trace!("synthesized ssh arguments: {:?}", args);
+
+    let child = Command::new("ssh")
+        .args(&args)
+        .spawn()
+        .context("Could not run ssh")?;
+
+    // Give gdbserver a moment to start listening
+    thread::sleep(Duration::from_secs(1));
+
+    Ok(child)
+}
+
+fn session(
+    remote: &Remote,
+    debugger: &Debugger,
+    bin: &str,
+    remote_bin: &str,
+    options: &[String],
+    setup: &DebugSetup,
+) -> Result<()> {
+    if remote.client {
+        let mut gdbserver = start_gdbserver(remote, remote_bin, options)?;
+
+        // Debug the local copy, which has the same symbols
+        let mut commands = setup.commands.clone();
+        commands.extend(client_commands(debugger, remote.port)?);
+        let local = DebugSetup {
+            program: setup.program.clone(),
+            flags: setup.flags.clone(),
+            commands,
+            command_file: setup.command_file.clone(),
+        };
+        let (debug_path, debug_args) = debug_command(debugger, bin, &[], &local)?;
+
+        // The debugger handles ctrl+c, and we still need to clean up after it
+        ctrlc::set_handler(|| {}).expect("Error setting Ctrl-C handler");
+
+        let status = Command::new(&debug_path).args(debug_args).status();
+        let _ = gdbserver.kill();
+        let _ = gdbserver.wait();
+        status.with_context(|| format!("Could not run {}", debug_path.display()))?;
+
+        return Ok(());
+    }
+
+    let command = if *debugger == Debugger::Gdbserver {
+        println!(
+            "connect with `target remote {}:{}`",
+            host(remote.destination),
+            remote.port
+        );
+
+        let mut gdbserver_args = vec![format!(":{}", remote.port), remote_bin.to_string()];
+        gdbserver_args.extend(options.iter().cloned());
+        quote::sh_command_line(Path::new("gdbserver"), &gdbserver_args)
+    } else {
+        // The local rust-gdb/rust-lldb wrapper isn't there, use the remote's debugger
+        let remote_setup = DebugSetup {
+            program: None,
+            flags: setup.flags.clone(),
+            commands: setup.commands.clone(),
+            command_file: None,
+        };
+        let (debug_path, debug_args) = debug_command(debugger, remote_bin, options, &remote_setup)?;
+        quote::sh_command_line(&debug_path, &debug_args)
+    };
+
+    let args = ssh_args(remote.destination, true, &command);
+    trace!("synthesized ssh arguments: {:?}", args);
+
+    Command::new("ssh")
+        .args(args)
+        .status()
+        .context("Could not run ssh")?;
+
+    Ok(())
+}
+
+/// Copy `bin` to the remote and debug it there, cleaning up afterwards.
+pub fn debug(
+    remote: &Remote,
+    debugger: &Debugger,
+    bin: &str,
+    options: &[String],
+    setup: &DebugSetup,
+) -> Result<()> {
+    let name = Path::new(bin)
+        .file_name()
+        .context("The selected binary has no file name")?
+        .to_string_lossy()
+        .into_owned();
+
+    let dir = ssh_output(remote.destination, "mktemp -d /tmp/cargo-debug.XXXXXX")?;
+    info!("copying {name} to {}:{dir}", remote.destination);
+
+    let result = copy(remote.destination, bin, &dir).and_then(|()| {
+        let remote_bin = format!("{dir}/{name}");
+        session(remote, debugger, bin, &remote_bin, options, setup)
+    });
+
+    if let Err(e) = ssh_output(remote.destination, &format!("rm -rf {}", quote::sh(&dir))) {
+        warn!("could not clean up {dir} on {}: {e}", remote.destination);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ssh_destination() {
+        assert_eq!(host("me@pi.local"), "pi.local");
+        assert_eq!(host("pi.local"), "pi.local");
+
+        assert_eq!(
+            ssh_args("me@pi.local", true, "gdb --args /tmp/x/foo 'a b'"),
+            ["-t", "--", "me@pi.local", "gdb --args /tmp/x/foo 'a b'"]
+        );
+        assert_eq!(
+            client_commands(&Debugger::Lldb, 2345).unwrap(),
+            ["gdb-remote localhost:2345"]
+        );
+    }
+}
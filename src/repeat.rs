@@ -0,0 +1,87 @@
+//! Running the program over and over in one debugger session, for flaky bugs.
+//!
+//! The loop is a generated script the debugger sources once the binary is
+//! loaded. It stops at the first iteration that doesn't exit, which is a
+//! crash or a breakpoint, and says which iteration that was.
+
+use std::env;
+
+use anyhow::{bail, Context, Result};
+
+use crate::Debugger;
+
+/// A gdb script running the program `n` times.
+///
+/// `$_thread` is 0 once the program has exited, and still set when it has
+/// stopped instead.
+fn gdb_script(n: u32) -> String {
+    format!(
+        "set $cargo_debug_i = 0\n\
+         while $cargo_debug_i < {n}\n\
+         \x20 set $cargo_debug_i = $cargo_debug_i + 1\n\
+         \x20 printf \"cargo-debug: iteration %d of {n}\\n\", $cargo_debug_i\n\
+         \x20 run\n\
+         \x20 if $_thread != 0\n\
+         \x20   printf \"cargo-debug: iteration %d of {n} stopped\\n\", $cargo_debug_i\n\
+         \x20   loop_break\n\
+         \x20 end\n\
+         end\n"
+    )
+}
+
+/// An lldb Python module running the program `n` times when imported.
+fn lldb_script(n: u32) -> String {
+    format!(
+        "import lldb\n\
+         \n\
+         \n\
+         def __lldb_init_module(debugger, internal_dict):\n\
+         \x20   debugger.SetAsync(False)\n\
+         \x20   for i in range(1, {n} + 1):\n\
+         \x20       print(f\"cargo-debug: iteration {{i}} of {n}\")\n\
+         \x20       debugger.HandleCommand(\"process launch\")\n\
+         \x20       process = debugger.GetSelectedTarget().GetProcess()\n\
+         \x20       if process.GetState() != lldb.eStateExited:\n\
+         \x20           print(f\"cargo-debug: iteration {{i}} of {n} stopped\")\n\
+         \x20           break\n"
+    )
+}
+
+/// Write the loop running the program `n` times, returning the debugger
+/// commands that start it.
+pub fn commands(debugger: &Debugger, n: u32) -> Result<Vec<String>> {
+    let (extension, script) = match debugger {
+        Debugger::Gdb => ("gdb", gdb_script(n)),
+        Debugger::Lldb => ("py", lldb_script(n)),
+        _ => bail!("--repeat is only supported by gdb and lldb"),
+    };
+
+    // lldb imports the script as a module, so its name must be an identifier
+    let path = env::temp_dir().join(format!(
+        "cargo_debug_repeat_{}.{extension}",
+        std::process::id()
+    ));
+    std::fs::write(&path, script).with_context(|| format!("Could not write {}", path.display()))?;
+
+    Ok(match debugger {
+        Debugger::Lldb => vec![format!("command script import \"{}\"", path.display())],
+        _ => vec![format!("source {}", path.display())],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gdb_loop() {
+        let script = gdb_script(3);
+        assert!(script.starts_with("set $cargo_debug_i = 0\nwhile $cargo_debug_i < 3\n"));
+        assert!(script.contains("\n  run\n  if $_thread != 0\n"));
+        assert!(script.ends_with("    loop_break\n  end\nend\n"));
+
+        let script = lldb_script(3);
+        assert!(script.contains("    for i in range(1, 3 + 1):\n"));
+        assert!(script.contains("print(f\"cargo-debug: iteration {i} of 3 stopped\")"));
+    }
+}
@@ -0,0 +1,530 @@
+//! Packing what it takes to reproduce a crash elsewhere into one archive
+//! (`--pack-repro`): the selected binary with its split debuginfo, a core
+//! or minidump if given, `manifest.json` describing the invocation, and
+//! `open.sh` and `open.ps1` opening the contents in the debugger.
+//!
+//! The archive is a tar written here, gzipped by `gzip -n` for `.tar.gz`
+//! and `.tgz` paths. Its entries are sorted, with no owners or times, so
+//! packing the same files twice gives the same archive.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use cargo_metadata::Artifact;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    coredump, debug_command, find_executable, minidump, quote, serve, DebugSetup, Debugger,
+};
+
+/// How much debuginfo is worth a warning, as the archive may be unwieldy.
+const LARGE_DEBUGINFO: u64 = 512 << 20;
+
+/// The invocation the archive reproduces, as `manifest.json`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Manifest {
+    cargo_debug: String,
+    /// The command line cargo-debug was started with
+    invocation: Vec<String>,
+    cwd: String,
+    debugger: String,
+    target: String,
+    rustc: Option<String>,
+    cargo: Option<String>,
+    /// The binary, in the archive
+    executable: String,
+    /// The binary's arguments
+    args: Vec<String>,
+    env: BTreeMap<String, String>,
+    /// The core or minidump, in the archive
+    core: Option<String>,
+    files: Vec<String>,
+}
+
+/// What goes in the archive, apart from what's found next to the binary.
+pub struct Contents<'a> {
+    pub artifact: &'a Artifact,
+    pub bin: &'a Path,
+    pub options: &'a [String],
+    pub env: &'a [(String, String)],
+    pub debugger: &'a Debugger,
+    pub target: &'a str,
+    pub core: Option<&'a Path>,
+}
+
+/// `program -V`, e.g. `rustc 1.80.0 (051478957 2024-07-21)`.
+fn version(program: &str) -> Option<String> {
+    let output = Command::new(program).arg("-V").output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The files under `dir`, for `.dSYM` bundles.
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .flat_map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                files_under(&path)
+            } else {
+                vec![path]
+            }
+        })
+        .collect()
+}
+
+/// The binary's split debuginfo: `.dwp`, `.debug` and PDB files next to it,
+/// and its `.dSYM` bundle, as archive names under `bin/` and paths.
+#[cfg_attr(not(windows), allow(unused_variables))]
+fn debuginfo(artifact: &Artifact, bin: &Path) -> Vec<(String, PathBuf)> {
+    let name = |path: &Path| {
+        format!(
+            "bin/{}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        )
+    };
+    let mut files = serve::served_files(bin)
+        .into_iter()
+        .skip(1)
+        .map(|path| (name(&path), path))
+        .collect::<Vec<_>>();
+    // rustc names the PDB after the crate, with underscores
+    #[cfg(windows)]
+    {
+        let pdb = crate::pdb::pdb_path(artifact, bin);
+        if pdb.is_file() && !files.iter().any(|(_, path)| *path == pdb) {
+            files.push((name(&pdb), pdb));
+        }
+    }
+
+    let dsym = bin.with_file_name(format!(
+        "{}.dSYM",
+        bin.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    for path in files_under(&dsym) {
+        let Ok(relative) = path.strip_prefix(bin.parent().unwrap_or(Path::new(""))) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        files.push((format!("bin/{relative}"), path));
+    }
+
+    files
+}
+
+/// The debugger invocation opening the contents, from the archive's
+/// directory: the binary with its arguments, or its core or minidump.
+fn open_command(
+    debugger: &Debugger,
+    executable: &str,
+    options: &[String],
+    core: Option<(&str, bool)>,
+) -> Result<(PathBuf, Vec<String>)> {
+    let setup = DebugSetup::default();
+    let strings = |args: Vec<OsString>| {
+        args.into_iter()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    };
+    Ok(match core {
+        Some((core, true)) => {
+            let (program, args) =
+                minidump::command(debugger, Path::new(core), executable, None, &setup)?;
+            (program, strings(args))
+        }
+        Some((core, false)) => {
+            let (program, args) =
+                coredump::open_command(debugger, Path::new(core), executable, &setup)?;
+            (program, strings(args))
+        }
+        None => debug_command(debugger, executable, options, &setup)?,
+    })
+}
+
+/// `open.sh`, running `program` from the archive's directory.
+fn open_sh(env: &BTreeMap<String, String>, program: &Path, args: &[String]) -> String {
+    let mut script =
+        "#!/bin/sh\n# Generated by cargo-debug --pack-repro\nset -e\n\ncd \"$(dirname \"$0\")\"\n"
+            .to_string();
+    for (name, value) in env {
+        script.push_str(&format!("export {name}={}\n", quote::sh(value)));
+    }
+    script.push_str(&format!("exec {}\n", quote::sh_command_line(program, args)));
+
+    script
+}
+
+/// `open.ps1`, running `program` from the archive's directory.
+fn open_ps1(env: &BTreeMap<String, String>, program: &Path, args: &[String]) -> String {
+    let mut script = "# Generated by cargo-debug --pack-repro\n$ErrorActionPreference = 'Stop'\n\nSet-Location -LiteralPath $PSScriptRoot\n".to_string();
+    for (name, value) in env {
+        let value = value.replace('\'', "''");
+        script.push_str(&format!("$env:{name} = '{value}'\n"));
+    }
+    script.push_str(&format!(
+        "& {}",
+        quote::powershell(&program.to_string_lossy())
+    ));
+    for arg in args {
+        script.push(' ');
+        script.push_str(&quote::powershell(arg));
+    }
+    script.push_str("\nexit $LASTEXITCODE\n");
+
+    script
+}
+
+/// An archive entry's contents.
+enum Source {
+    File(PathBuf),
+    Text(String),
+}
+
+struct Entry {
+    name: String,
+    source: Source,
+    executable: bool,
+}
+
+impl Entry {
+    fn size(&self) -> Result<u64> {
+        Ok(match &self.source {
+            Source::File(path) => fs::metadata(path)
+                .with_context(|| format!("Could not read {}", path.display()))?
+                .len(),
+            Source::Text(text) => text.len() as u64,
+        })
+    }
+}
+
+/// A tar header field holding `value` in octal, NUL-terminated.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+}
+
+/// The ustar header of the file `name`, `size` bytes long. Names longer
+/// than the header's 100 bytes are split into its prefix field at a `/`.
+fn tar_header(name: &str, size: u64, executable: bool) -> Result<[u8; 512]> {
+    let (prefix, name) = if name.len() <= 100 {
+        ("", name)
+    } else {
+        name.char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && name.len() - i - 1 <= 100)
+            .map(|(i, _)| (&name[..i], &name[i + 1..]))
+            .next()
+            .with_context(|| format!("{name} is too long a path for a tar archive"))?
+    };
+
+    let mut header = [0; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(
+        &mut header[100..108],
+        if executable { 0o755 } else { 0o644 },
+    );
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size);
+    octal(&mut header[136..148], 0);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // Summed with the checksum field as spaces
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|&b| u64::from(b)).sum::<u64>();
+    octal(&mut header[148..155], checksum);
+
+    Ok(header)
+}
+
+/// Write `entries` as a tar archive, in the order given.
+fn write_tar(out: &mut impl Write, entries: &[Entry]) -> Result<()> {
+    for entry in entries {
+        let size = entry.size()?;
+        out.write_all(&tar_header(&entry.name, size, entry.executable)?)?;
+        match &entry.source {
+            Source::File(path) => {
+                let mut file = File::open(path)
+                    .with_context(|| format!("Could not read {}", path.display()))?;
+                io::copy(&mut file, out)?;
+            }
+            Source::Text(text) => out.write_all(text.as_bytes())?,
+        }
+        let padding = (512 - size % 512) % 512;
+        out.write_all(&vec![0; padding as usize])?;
+    }
+    // The end of the archive
+    out.write_all(&[0; 1024])?;
+
+    Ok(())
+}
+
+/// Whether `path` is to be gzipped, and its name without the extensions.
+fn archive_name(path: &Path) -> (bool, String) {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    for (extension, gzipped) in [(".tar.gz", true), (".tgz", true), (".tar", false)] {
+        if let Some(stem) = name.strip_suffix(extension) {
+            return (gzipped, stem.to_string());
+        }
+    }
+
+    (false, name)
+}
+
+/// Write `entries` to the archive at `path`.
+fn write_archive(path: &Path, gzipped: bool, entries: &[Entry]) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Could not create {}", path.display()))?;
+    if !gzipped {
+        let mut out = io::BufWriter::new(file);
+        write_tar(&mut out, entries)?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    if find_executable(Path::new("gzip")).is_none() {
+        bail!(
+            "gzip was not found to compress {}, pack to a .tar instead",
+            path.display()
+        );
+    }
+    // No name or time in the gzip header either
+    let mut gzip = Command::new("gzip")
+        .args(["-n", "-c"])
+        .stdin(Stdio::piped())
+        .stdout(file)
+        .spawn()
+        .context("Could not run gzip")?;
+    if let Some(stdin) = gzip.stdin.take() {
+        let mut out = io::BufWriter::new(stdin);
+        write_tar(&mut out, entries)?;
+        out.flush()?;
+    }
+    let status = gzip.wait()?;
+    if !status.success() {
+        bail!("gzip failed ({status})");
+    }
+
+    Ok(())
+}
+
+/// Pack `contents` into the archive at `path`.
+pub fn pack(path: &Path, contents: &Contents) -> Result<()> {
+    let file_name = |path: &Path| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let executable = format!("bin/{}", file_name(contents.bin));
+    let mut entries = vec![Entry {
+        name: executable.clone(),
+        source: Source::File(contents.bin.to_path_buf()),
+        executable: true,
+    }];
+
+    let mut debuginfo_size = 0;
+    for (name, path) in debuginfo(contents.artifact, contents.bin) {
+        let entry = Entry {
+            name,
+            source: Source::File(path),
+            executable: false,
+        };
+        debuginfo_size += entry.size()?;
+        entries.push(entry);
+    }
+    if debuginfo_size > LARGE_DEBUGINFO {
+        warn!(
+            "the repro bundle holds {} MiB of debuginfo, consider `split-debuginfo` or a lower `debug` level",
+            debuginfo_size >> 20
+        );
+    }
+
+    let core = match contents.core {
+        Some(core) => {
+            if !core.is_file() {
+                bail!("--include-core {} does not exist", core.display());
+            }
+            let name = format!("core/{}", file_name(core));
+            entries.push(Entry {
+                name: name.clone(),
+                source: Source::File(core.to_path_buf()),
+                executable: false,
+            });
+            Some((name, minidump::is_minidump(core)))
+        }
+        None => None,
+    };
+
+    let env = contents.env.iter().cloned().collect::<BTreeMap<_, _>>();
+    let (program, args) = open_command(
+        contents.debugger,
+        &executable,
+        contents.options,
+        core.as_ref()
+            .map(|(name, minidump)| (name.as_str(), *minidump)),
+    )?;
+    entries.push(Entry {
+        name: "open.sh".to_string(),
+        source: Source::Text(open_sh(&env, &program, &args)),
+        executable: true,
+    });
+    entries.push(Entry {
+        name: "open.ps1".to_string(),
+        source: Source::Text(open_ps1(&env, &program, &args)),
+        executable: false,
+    });
+
+    let mut files = entries.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+    files.sort();
+    let rustc = env::var("RUSTC").unwrap_or(String::from("rustc"));
+    let cargo = env::var("CARGO").unwrap_or(String::from("cargo"));
+    let manifest = Manifest {
+        cargo_debug: env!("CARGO_PKG_VERSION").to_string(),
+        invocation: env::args().skip(1).collect(),
+        cwd: env::current_dir()?.to_string_lossy().into_owned(),
+        debugger: contents.debugger.name().to_string(),
+        target: contents.target.to_string(),
+        rustc: version(&rustc),
+        cargo: version(&cargo),
+        executable,
+        args: contents.options.to_vec(),
+        env,
+        core: core.map(|(name, _)| name),
+        files,
+    };
+    entries.push(Entry {
+        name: "manifest.json".to_string(),
+        source: Source::Text(serde_json::to_string_pretty(&manifest)?),
+        executable: false,
+    });
+
+    let (gzipped, top) = archive_name(path);
+    for entry in &mut entries {
+        entry.name = format!("{top}/{}", entry.name);
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    write_archive(path, gzipped, &entries)?;
+
+    info!("packed {} files into {}", entries.len(), path.display());
+    eprintln!(
+        "cargo-debug: wrote {}, unpack it and run {top}/open.sh (or open.ps1) to debug",
+        path.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::artifact;
+
+    /// The files in a tar archive, by name.
+    fn unpack(tar: &[u8]) -> BTreeMap<String, Vec<u8>> {
+        let mut files = BTreeMap::new();
+        let mut offset = 0;
+        while offset + 512 <= tar.len() && tar[offset] != 0 {
+            let header = &tar[offset..offset + 512];
+            let field = |range: std::ops::Range<usize>| {
+                let bytes = &header[range];
+                let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                String::from_utf8(bytes[..end].to_vec()).unwrap()
+            };
+            let size = u64::from_str_radix(&field(124..136), 8).unwrap() as usize;
+            let prefix = field(345..500);
+            let name = match prefix.as_str() {
+                "" => field(0..100),
+                prefix => format!("{prefix}/{}", field(0..100)),
+            };
+            let start = offset + 512;
+            files.insert(name, tar[start..start + size].to_vec());
+            offset = start + size.div_ceil(512) * 512;
+        }
+
+        files
+    }
+
+    #[test]
+    fn repro_bundle() {
+        let dir = env::temp_dir().join(format!("cargo-debug-repro-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let bin = dir.join("foo");
+        fs::write(&bin, b"\x7fELF").unwrap();
+        fs::write(dir.join("foo.dwp"), b"dwarf").unwrap();
+        let core = dir.join("core.1234");
+        fs::write(&core, b"\x7fELF core").unwrap();
+
+        let artifact = artifact("foo", "bin", bin.to_str());
+        let options = ["--seed".to_string(), "42".to_string()];
+        let env = [("RUST_BACKTRACE".to_string(), "1".to_string())];
+        let contents = Contents {
+            artifact: &artifact,
+            bin: &bin,
+            options: &options,
+            env: &env,
+            debugger: &Debugger::Gdb,
+            target: "x86_64-unknown-linux-gnu",
+            core: Some(&core),
+        };
+        let archive = dir.join("foo-repro.tar");
+        pack(&archive, &contents).unwrap();
+        let tar = fs::read(&archive).unwrap();
+
+        let files = unpack(&tar);
+        let names = files.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "foo-repro/bin/foo",
+                "foo-repro/bin/foo.dwp",
+                "foo-repro/core/core.1234",
+                "foo-repro/manifest.json",
+                "foo-repro/open.ps1",
+                "foo-repro/open.sh"
+            ]
+        );
+        assert_eq!(files["foo-repro/bin/foo.dwp"], b"dwarf");
+
+        let manifest: Manifest = serde_json::from_slice(&files["foo-repro/manifest.json"]).unwrap();
+        assert_eq!(manifest.debugger, "gdb");
+        assert_eq!(manifest.executable, "bin/foo");
+        assert_eq!(manifest.args, options);
+        assert_eq!(manifest.core.as_deref(), Some("core/core.1234"));
+        assert_eq!(manifest.env["RUST_BACKTRACE"], "1");
+        assert_eq!(manifest.files.len(), 5);
+
+        let open = String::from_utf8(files["foo-repro/open.sh"].clone()).unwrap();
+        assert!(open.contains("export RUST_BACKTRACE=1\nexec gdb bin/foo core/core.1234\n"));
+
+        // Packing the same files again gives the same archive
+        pack(&archive, &contents).unwrap();
+        assert_eq!(fs::read(&archive).unwrap(), tar);
+
+        assert_eq!(
+            archive_name(Path::new("a/x.tar.gz")),
+            (true, "x".to_string())
+        );
+        assert_eq!(archive_name(Path::new("x.tgz")), (true, "x".to_string()));
+        assert_eq!(archive_name(Path::new("x.tar")), (false, "x".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+//! Running the debugger, and so the program, as another user (`--user`),
+//! to reproduce bugs that depend on permissions.
+//!
+//! Only the launch goes through `sudo -u`, after the build ran as the
+//! invoking user, who usually needs sudo rights for it. sudo clears the
+//! environment, so the variables set for the program are asked to be kept.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use log::warn;
+
+use crate::find_executable;
+
+/// Check that `user` exists, as `id` knows it, and warn about what running
+/// as them means.
+pub fn check(user: &str) -> Result<()> {
+    if find_executable(Path::new("sudo")).is_none() {
+        bail!("--user needs sudo, which was not found");
+    }
+    let known = Command::new("id")
+        .args(["-u", user])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("Could not run id")?
+        .success();
+    if !known {
+        bail!("There is no user {user}");
+    }
+
+    warn!(
+        "--user runs the debugger as {user} through sudo: it can read and change \
+         anything {user} can, and the program runs with {user}'s rights"
+    );
+
+    Ok(())
+}
+
+/// `program` and `args`, run as `user` by sudo, keeping the variables `env`
+/// names.
+pub fn command(
+    user: &str,
+    program: &Path,
+    args: Vec<String>,
+    env: &[String],
+) -> (PathBuf, Vec<OsString>) {
+    let mut sudo_args = vec![OsString::from("-u"), user.into()];
+    if !env.is_empty() {
+        sudo_args.push(format!("--preserve-env={}", env.join(",")).into());
+    }
+    sudo_args.push("--".into());
+    sudo_args.push(program.into());
+    sudo_args.extend(args.into_iter().map(OsString::from));
+
+    (PathBuf::from("sudo"), sudo_args)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sudo_command() {
+        let (program, args) = command(
+            "nobody",
+            Path::new("gdb"),
+            vec!["--args".to_string(), "foo".to_string()],
+            &["RUST_BACKTRACE".to_string(), "FOO".to_string()],
+        );
+        assert_eq!(program, PathBuf::from("sudo"));
+        assert_eq!(
+            args,
+            [
+                "-u",
+                "nobody",
+                "--preserve-env=RUST_BACKTRACE,FOO",
+                "--",
+                "gdb",
+                "--args",
+                "foo"
+            ]
+        );
+        assert_eq!(
+            command("nobody", Path::new("gdb"), vec![], &[]).1,
+            ["-u", "nobody", "--", "gdb"]
+        );
+    }
+}
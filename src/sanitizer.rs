@@ -0,0 +1,96 @@
+//! Debugging AddressSanitizer builds.
+//!
+//! ASan reports an error from its own SIGSEGV handler or from the check that
+//! failed, prints the report and exits. To stop at the first report with the
+//! bad access still on the stack, the debugger passes SIGSEGV through to
+//! ASan and breaks where reports are made, and ASan aborts rather than
+//! exits so the session doesn't end with the process.
+
+use anyhow::{bail, Result};
+
+use crate::Debugger;
+
+/// What the program's `ASAN_OPTIONS` get for a debugging session.
+pub const OPTIONS: &str = "abort_on_error=1:disable_coredump=0";
+
+/// The startup commands of each debugger: SIGSEGV is left to ASan, and
+/// there's a breakpoint where the report starts and where ASan gives up.
+const RECIPES: [(Debugger, &[&str]); 2] = [
+    (
+        Debugger::Gdb,
+        &[
+            "handle SIGSEGV nostop noprint pass",
+            "break __asan::ReportGenericError",
+            "break __sanitizer::Die",
+        ],
+    ),
+    (
+        Debugger::Lldb,
+        &[
+            "process handle SIGSEGV --stop false --notify false --pass true",
+            "breakpoint set --name __asan::ReportGenericError",
+            "breakpoint set --name __sanitizer::Die",
+        ],
+    ),
+];
+
+/// Whether the rustflags cargo builds with enable AddressSanitizer:
+/// `CARGO_ENCODED_RUSTFLAGS` if set, as cargo then ignores `RUSTFLAGS`.
+pub fn detected(encoded: Option<&str>, plain: Option<&str>) -> bool {
+    let flags = match encoded {
+        Some(flags) => flags.split('\x1f').collect::<Vec<_>>(),
+        None => plain.unwrap_or_default().split_whitespace().collect(),
+    };
+
+    flags
+        .iter()
+        .any(|flag| flag.ends_with("sanitizer=address") || flag.contains("sanitizer=address,"))
+}
+
+/// The startup commands stopping `debugger` at the first ASan report.
+pub fn commands(debugger: &Debugger) -> Result<Vec<String>> {
+    match RECIPES.iter().find(|(d, _)| d == debugger) {
+        Some((_, commands)) => Ok(commands.iter().map(|c| c.to_string()).collect()),
+        None => bail!("--asan is only supported by gdb and lldb"),
+    }
+}
+
+/// `ASAN_OPTIONS` with `options` after what's `existing`, so they win.
+pub fn options(existing: Option<String>, options: &str) -> String {
+    match existing {
+        Some(existing) if !existing.is_empty() => format!("{existing}:{options}"),
+        _ => options.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn asan_recipes() {
+        assert!(detected(None, Some("-C debuginfo=2 -Zsanitizer=address")));
+        assert!(detected(Some("-Zsanitizer=address,memory\x1f-Cfoo"), None));
+        // The encoded form hides RUSTFLAGS from cargo
+        assert!(!detected(Some("-Cfoo"), Some("-Zsanitizer=address")));
+        assert!(!detected(None, Some("-Zsanitizer=thread")));
+        assert!(!detected(None, None));
+
+        let gdb = commands(&Debugger::Gdb).unwrap();
+        assert_eq!(gdb[0], "handle SIGSEGV nostop noprint pass");
+        assert_eq!(
+            gdb[1..],
+            ["break __asan::ReportGenericError", "break __sanitizer::Die"]
+        );
+        let lldb = commands(&Debugger::Lldb).unwrap();
+        assert!(lldb[0].starts_with("process handle SIGSEGV"));
+        assert_eq!(lldb[2], "breakpoint set --name __sanitizer::Die");
+        assert!(commands(&Debugger::Windbg).is_err());
+
+        assert_eq!(options(None, OPTIONS), OPTIONS);
+        assert_eq!(
+            options(Some("detect_leaks=0".to_string()), OPTIONS),
+            "detect_leaks=0:abort_on_error=1:disable_coredump=0"
+        );
+    }
+}
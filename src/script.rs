@@ -0,0 +1,218 @@
+//! Writing a script that reproduces a debug session without cargo-debug.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::info;
+
+use crate::quote;
+
+/// Variables from our environment that change how the debugged program runs.
+const VARS: &[&str] = &[
+    "RUST_BACKTRACE",
+    "RUST_LIB_BACKTRACE",
+    "RUST_LOG",
+    "RUST_MIN_STACK",
+    "LLVM_PROFILE_FILE",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dialect {
+    Sh,
+    PowerShell,
+}
+
+impl Dialect {
+    /// PowerShell on Windows and `sh` elsewhere, unless the extension says otherwise.
+    fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ps1") => Dialect::PowerShell,
+            Some("sh") => Dialect::Sh,
+            _ if cfg!(windows) => Dialect::PowerShell,
+            _ => Dialect::Sh,
+        }
+    }
+}
+
+/// Everything a script needs to reproduce the session.
+struct Session<'a> {
+    version: &'a str,
+    invocation: &'a str,
+    cwd: &'a Path,
+    env: &'a [(String, String)],
+    program: &'a Path,
+    args: &'a [String],
+}
+
+fn render(dialect: Dialect, session: &Session) -> String {
+    let mut script = String::new();
+    let cwd = session.cwd.to_string_lossy();
+    let program = session.program.to_string_lossy();
+
+    match dialect {
+        Dialect::Sh => {
+            script.push_str("#!/bin/sh\n");
+            script.push_str(&format!("# Generated by cargo-debug {}\n", session.version));
+            script.push_str(&format!("# {}\n", session.invocation));
+            script.push_str("set -e\n\n");
+            script.push_str(&format!("cd {}\n", quote::sh(&cwd)));
+            for (name, value) in session.env {
+                script.push_str(&format!("export {name}={}\n", quote::sh(value)));
+            }
+            script.push_str(&format!(
+                "exec {}\n",
+                quote::sh_command_line(session.program, session.args)
+            ));
+        }
+        Dialect::PowerShell => {
+            script.push_str(&format!("# Generated by cargo-debug {}\n", session.version));
+            script.push_str(&format!("# {}\n", session.invocation));
+            script.push_str("$ErrorActionPreference = 'Stop'\n\n");
+            script.push_str(&format!(
+                "Set-Location -LiteralPath {}\n",
+                quote::powershell(&cwd)
+            ));
+            for (name, value) in session.env {
+                // Always a string literal, a bare word would run as a command
+                let value = value.replace('\'', "''");
+                script.push_str(&format!("$env:{name} = '{value}'\n"));
+            }
+            script.push_str(&format!("& {}", quote::powershell(&program)));
+            for arg in session.args {
+                script.push(' ');
+                script.push_str(&quote::powershell(arg));
+            }
+            script.push_str("\nexit $LASTEXITCODE\n");
+        }
+    }
+
+    script
+}
+
+/// Wrap `program` in a shell script that traces each command (`set -x`) and
+/// reports the exit code, for programs driven by shell scripts.
+///
+/// Returns `None` where there is no `sh`.
+pub fn traced(program: &Path, args: &[String]) -> Option<(PathBuf, Vec<String>)> {
+    if !cfg!(unix) {
+        return None;
+    }
+
+    let script = format!(
+        "set -x\n{}\nstatus=$?\nset +x\necho \"cargo-debug: exit code $status\" >&2\nexit $status\n",
+        quote::sh_command_line(program, args)
+    );
+
+    Some((PathBuf::from("sh"), vec!["-c".to_string(), script]))
+}
+
+/// The command line this process was started with, as the user would have typed it.
+fn invocation() -> String {
+    // cargo passes the subcommand name as the first argument
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    quote::sh_command_line(Path::new("cargo"), &args).replace(['\r', '\n'], " ")
+}
+
+/// `env` on top of the inherited variables relevant to the session.
+pub fn session_env(env: &[(String, String)]) -> Vec<(String, String)> {
+    VARS.iter()
+        .filter_map(|name| Some((name.to_string(), env::var(name).ok()?)))
+        .chain(env.iter().cloned())
+        .collect()
+}
+
+/// Write a script to `path` that runs `program` with `args` the way this
+/// session would, with `env` set on top of the relevant inherited variables.
+pub fn emit(path: &Path, env: &[(String, String)], program: &Path, args: &[String]) -> Result<()> {
+    let env = session_env(env);
+
+    let cwd = env::current_dir()?;
+    let invocation = invocation();
+    let session = Session {
+        version: env!("CARGO_PKG_VERSION"),
+        invocation: &invocation,
+        cwd: &cwd,
+        env: &env,
+        program,
+        args,
+    };
+
+    std::fs::write(path, render(Dialect::for_path(path), &session))
+        .with_context(|| format!("Could not write script to {}", path.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    info!("wrote debug session script to {}", path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn session(dialect: Dialect) -> String {
+        let env = [("RUST_BACKTRACE".to_string(), "1".to_string())];
+        let args = [
+            "--args".to_string(),
+            "/my dir/foo".to_string(),
+            "--seed".to_string(),
+            "42".to_string(),
+        ];
+
+        render(
+            dialect,
+            &Session {
+                version: "0.0.0",
+                invocation: "cargo debug gdb --emit-script repro -- --seed 42",
+                cwd: Path::new("/my dir"),
+                env: &env,
+                program: Path::new("gdb"),
+                args: &args,
+            },
+        )
+    }
+
+    #[test]
+    fn sh_script() {
+        assert_eq!(
+            session(Dialect::Sh),
+            include_str!("../testdata/emit-script.sh")
+        );
+    }
+
+    #[test]
+    fn powershell_script() {
+        assert_eq!(
+            session(Dialect::PowerShell),
+            include_str!("../testdata/emit-script.ps1")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn traced_script() {
+        let (program, args) = traced(Path::new("/my dir/foo"), &["--seed".to_string()]).unwrap();
+        assert_eq!(program, PathBuf::from("sh"));
+        assert_eq!(args[0], "-c");
+        assert_eq!(
+            args[1],
+            "set -x\n'/my dir/foo' --seed\nstatus=$?\nset +x\n\
+             echo \"cargo-debug: exit code $status\" >&2\nexit $status\n"
+        );
+    }
+
+    #[test]
+    fn dialect_for_path() {
+        assert_eq!(
+            Dialect::for_path(Path::new("repro.ps1")),
+            Dialect::PowerShell
+        );
+        assert_eq!(Dialect::for_path(Path::new("repro.sh")), Dialect::Sh);
+    }
+}
@@ -0,0 +1,190 @@
+//! Serving the selected binary over HTTP, for devices that can reach us but
+//! can't be reached over SSH.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+
+/// The binary, followed by any split debuginfo sitting next to it.
+pub fn served_files(bin: &Path) -> Vec<PathBuf> {
+    let mut files = vec![bin.to_path_buf()];
+    for extension in ["dwp", "debug", "pdb"] {
+        let debuginfo = bin.with_extension(extension);
+        if debuginfo.is_file() {
+            files.push(debuginfo);
+        }
+    }
+
+    files
+}
+
+/// An address the device can use to reach `addr`.
+///
+/// When listening on all interfaces, this is the address of the interface
+/// with the default route. Connecting a UDP socket sends nothing.
+fn reachable_ip(addr: &SocketAddr) -> IpAddr {
+    if !addr.ip().is_unspecified() {
+        return addr.ip();
+    }
+
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|s| s.connect((Ipv4Addr::new(192, 0, 2, 1), 80)).map(|_| s))
+        .and_then(|s| s.local_addr())
+        .map(|a| a.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+/// The file name requested by an HTTP request line, e.g. `GET /foo HTTP/1.1`.
+fn requested_name(request_line: &str) -> Option<&str> {
+    let mut parts = request_line.split_whitespace();
+    if parts.next()? != "GET" {
+        return None;
+    }
+
+    let path = parts.next()?.strip_prefix('/')?;
+    Some(path.split(['?', '#']).next().unwrap_or(path))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Answer a single request, returning the index of the file sent in full.
+fn handle(stream: TcpStream, files: &[PathBuf]) -> Result<Option<usize>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain the headers, we don't need any of them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut stream = stream;
+    let found = requested_name(&request_line)
+        .and_then(|name| files.iter().position(|f| file_name(f) == name));
+    let Some(index) = found else {
+        write!(
+            stream,
+            "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )?;
+        return Ok(None);
+    };
+
+    let mut file = File::open(&files[index])
+        .with_context(|| format!("Could not open {}", files[index].display()))?;
+    let length = file.metadata()?.len();
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\n\
+         Content-Type: application/octet-stream\r\n\
+         Content-Length: {length}\r\n\
+         Connection: close\r\n\r\n"
+    )?;
+    std::io::copy(&mut file, &mut stream)?;
+    stream.flush()?;
+
+    Ok(Some(index))
+}
+
+/// Serve `bin` and its split debuginfo on `addr` until interrupted, or with
+/// `once`, until each file has been downloaded.
+pub fn serve(addr: &SocketAddr, bin: &Path, once: bool) -> Result<()> {
+    let listener =
+        TcpListener::bind(addr).with_context(|| format!("Could not listen on {addr}"))?;
+    let local = listener.local_addr()?;
+    let base = format!(
+        "http://{}",
+        SocketAddr::new(reachable_ip(&local), local.port())
+    );
+
+    let files = served_files(bin);
+    let urls = files
+        .iter()
+        .map(|f| format!("{base}/{}", file_name(f)))
+        .collect::<Vec<_>>();
+
+    info!("serving {} on {local}, fetch it with:", bin.display());
+    let curl = urls.iter().map(|u| format!("-O {u}")).collect::<Vec<_>>();
+    println!("curl {}", curl.join(" "));
+    println!("wget {}", urls.join(" "));
+
+    let mut downloaded = vec![false; files.len()];
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("failed to accept a connection: {e}");
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map(|p| p.to_string())
+            .unwrap_or_default();
+
+        match handle(stream, &files) {
+            Ok(Some(index)) => {
+                info!("{peer} downloaded {}", file_name(&files[index]));
+                downloaded[index] = true;
+                if once && downloaded.iter().all(|d| *d) {
+                    break;
+                }
+            }
+            Ok(None) => warn!("{peer} asked for something we don't serve"),
+            Err(e) => warn!("failed to serve {peer}: {e}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn request_line() {
+        assert_eq!(requested_name("GET /foo HTTP/1.1\r\n"), Some("foo"));
+        assert_eq!(requested_name("GET /foo.dwp?x=1 HTTP/1.0"), Some("foo.dwp"));
+        assert_eq!(requested_name("POST /foo HTTP/1.1"), None);
+        assert_eq!(requested_name(""), None);
+    }
+
+    #[test]
+    fn serve_file() {
+        let dir = std::env::temp_dir().join(format!("cargo-debug-serve-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bin = dir.join("foo");
+        std::fs::write(&bin, b"\x7fELF").unwrap();
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(b"GET /foo HTTP/1.1\r\nHost: x\r\n\r\n")
+                .unwrap();
+            let mut response = vec![];
+            stream.read_to_end(&mut response).unwrap();
+            response
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        assert_eq!(handle(stream, &served_files(&bin)).unwrap(), Some(0));
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with(b"HTTP/1.0 200 OK\r\n"));
+        assert!(response.ends_with(b"\r\n\r\n\x7fELF"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
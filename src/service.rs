@@ -0,0 +1,401 @@
+//! Debugging a Windows service (`--service NAME`), which misbehaves only when
+//! the Service Control Manager starts it and so can't be launched under a
+//! debugger.
+//!
+//! In `attach` mode, the service's process is waited for, after `sc start`
+//! when `--service-start` asks for it, and the debugger attached to it like
+//! `--wait-attach` does. In `ifeo` mode, the debugger is registered for the
+//! binary under Image File Execution Options, so that Windows starts the
+//! service in it, until Enter or Ctrl+C removes it again;
+//! `--service cleanup` removes one left behind. Services run in session 0,
+//! where a debugger's window can't be seen, so `ifeo` mode suits
+//! vsjitdebugger (`--debugger devenv`) or a debugger serving a remote
+//! session best.
+//!
+//! Both need an elevated prompt, and both go through `reg`, `sc` and
+//! `whoami` rather than the APIs underneath.
+
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+
+use crate::{find_executable, quote, DebugSetup, Debugger};
+
+/// Where Windows looks up a debugger to start an image in.
+const IFEO: &str =
+    r"HKLM\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Image File Execution Options";
+
+/// The integrity levels of an elevated prompt: high, and system's.
+const ELEVATED_LEVELS: [&str; 2] = ["S-1-16-12288", "S-1-16-16384"];
+
+/// How long the service gets to start.
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// sc's codes for the states of a service.
+const STOPPED: u32 = 1;
+const START_PENDING: u32 = 2;
+const RUNNING: u32 = 4;
+
+/// The error `sc start` fails with when the service runs already.
+const ALREADY_RUNNING: &str = "1056";
+
+/// How the debugger gets hold of the service.
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Attach to the service's process once it's running
+    Attach,
+    /// Register the debugger under Image File Execution Options, to start the service in it
+    Ifeo,
+}
+
+/// Whether `whoami /groups` lists an elevated integrity level.
+fn elevated(groups: &str) -> bool {
+    ELEVATED_LEVELS.iter().any(|level| groups.contains(level))
+}
+
+/// Fail unless running elevated, which the registry and services need.
+fn check_elevated() -> Result<()> {
+    let output = Command::new("whoami")
+        .arg("/groups")
+        .output()
+        .context("Could not run whoami")?;
+    if !elevated(&String::from_utf8_lossy(&output.stdout)) {
+        bail!("--service needs administrator rights, run it from an elevated prompt");
+    }
+
+    Ok(())
+}
+
+/// The value of `name` in a listing like `sc` prints, `NAME : value`.
+fn field<'a>(output: &'a str, name: &str) -> Option<&'a str> {
+    output.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == name).then(|| value.trim())
+    })
+}
+
+/// The state and pid of a service, from `sc queryex`.
+fn parse_query(output: &str) -> Option<(u32, u32)> {
+    let state = field(output, "STATE")?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    let pid = field(output, "PID")?.parse().ok()?;
+
+    Some((state, pid))
+}
+
+/// The executable a service runs, from `sc qc`'s command line.
+fn binary_path(output: &str) -> Option<&str> {
+    let command = field(output, "BINARY_PATH_NAME")?;
+    match command.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next(),
+        None => command.split_whitespace().next(),
+    }
+}
+
+/// The `Debugger` value in `reg query`'s listing of a key.
+fn parse_debugger_value(output: &str) -> Option<&str> {
+    output.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix("Debugger")?;
+        Some(rest.trim_start().strip_prefix("REG_SZ")?.trim())
+    })
+}
+
+fn run_sc(args: &[&str]) -> Result<Output> {
+    Command::new("sc")
+        .args(args)
+        .output()
+        .context("Could not run sc")
+}
+
+/// Warn when the service `name` runs some other binary than `exe`.
+pub fn check_binary(name: &str, exe: &Path) -> Result<()> {
+    let output = run_sc(&["qc", name])?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        bail!("Could not query the service {name}: {}", stdout.trim());
+    }
+    match binary_path(&stdout) {
+        // Paths are case-insensitive
+        Some(path) if !path.eq_ignore_ascii_case(&exe.to_string_lossy()) => warn!(
+            "the service {name} runs {path}, not the binary just built, {}",
+            exe.display()
+        ),
+        Some(_) => {}
+        None => warn!("could not tell which binary the service {name} runs"),
+    }
+
+    Ok(())
+}
+
+/// Ask the Service Control Manager to start `name`, which may run already.
+fn start(name: &str) -> Result<()> {
+    info!("starting the service {name}");
+    let output = run_sc(&["start", name])?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() && !stdout.contains(ALREADY_RUNNING) {
+        bail!("Could not start the service {name}: {}", stdout.trim());
+    }
+
+    Ok(())
+}
+
+/// The pid of the service `name` once it has a process, starting it first
+/// when `start_it` is set.
+pub fn wait(name: &str, start_it: bool) -> Result<u32> {
+    check_elevated()?;
+    if start_it {
+        start(name)?;
+    } else {
+        eprintln!("cargo-debug: waiting for the service {name} to start");
+    }
+
+    let deadline = Instant::now() + START_TIMEOUT;
+    let mut starting = false;
+    while Instant::now() < deadline {
+        let output = run_sc(&["queryex", name])?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() {
+            bail!("Could not query the service {name}: {}", stdout.trim());
+        }
+        match parse_query(&stdout) {
+            Some((START_PENDING | RUNNING, pid)) if pid != 0 => return Ok(pid),
+            // Started, then stopped again before its process was seen
+            Some((STOPPED, _)) if starting => {
+                bail!("The service {name} stopped straight after starting")
+            }
+            Some((state, _)) => starting |= state != STOPPED,
+            None => bail!("Could not read the state of the service {name}"),
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+
+    bail!("The service {name} did not start within {START_TIMEOUT:?}")
+}
+
+/// The key registering a debugger for executables named `exe_name`.
+fn ifeo_key(exe_name: &str) -> String {
+    format!(r"{IFEO}\{exe_name}")
+}
+
+/// The command line Windows starts the service with, ahead of the
+/// executable and its arguments, which it appends.
+fn ifeo_command(debugger: &Debugger, program: &Path, setup: &DebugSetup) -> Result<String> {
+    let mut args = setup.flags.clone();
+    match debugger {
+        Debugger::Gdb => {
+            args.extend(setup.startup_args(debugger));
+            args.push("--args".to_string());
+        }
+        Debugger::Lldb => {
+            args.extend(setup.startup_args(debugger));
+            args.push("--".to_string());
+        }
+        Debugger::Windbg if !setup.commands.is_empty() => {
+            args.extend(["-c".to_string(), setup.commands.join("; ")]);
+        }
+        Debugger::Windbg | Debugger::Devenv | Debugger::Custom(_) => {}
+        other => bail!("--service-mode ifeo is not supported by {}", other.name()),
+    }
+
+    Ok(quote::command_line(program, &args))
+}
+
+/// The debugger's program, with its full path, as services don't share the
+/// user's PATH.
+fn ifeo_program(debugger: &Debugger, setup: &DebugSetup) -> Result<PathBuf> {
+    let program = match (&setup.program, debugger) {
+        (Some(program), _) => program.clone(),
+        (None, Debugger::Windbg) => PathBuf::from("windbgx"),
+        (None, Debugger::Devenv) => PathBuf::from("vsjitdebugger"),
+        (None, Debugger::Custom(program)) => program.clone(),
+        (None, other) => PathBuf::from(other.name()),
+    };
+
+    find_executable(&program).with_context(|| format!("Could not find {}", program.display()))
+}
+
+/// The debugger registered for `exe_name`, if any.
+fn registered(exe_name: &str) -> Result<Option<String>> {
+    let output = Command::new("reg")
+        .args(["query", &ifeo_key(exe_name), "/v", "Debugger"])
+        .output()
+        .context("Could not run reg")?;
+    // reg fails when there's no such key or value
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    Ok(parse_debugger_value(&String::from_utf8_lossy(&output.stdout)).map(String::from))
+}
+
+/// Remove the debugger registered for `exe_name`, leaving the key's other
+/// values, such as global flags, alone.
+fn unregister(exe_name: &str) -> Result<()> {
+    let status = Command::new("reg")
+        .args(["delete", &ifeo_key(exe_name), "/v", "Debugger", "/f"])
+        .status()
+        .context("Could not run reg")?;
+    if !status.success() {
+        bail!("Could not remove the debugger registered for {exe_name}");
+    }
+
+    Ok(())
+}
+
+fn exe_name(exe: &Path) -> Result<String> {
+    Ok(exe
+        .file_name()
+        .context("The selected binary has no file name")?
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// Remove a debugger `--service-mode ifeo` left registered for `exe`.
+pub fn cleanup(exe: &Path) -> Result<()> {
+    check_elevated()?;
+    let exe_name = exe_name(exe)?;
+    match registered(&exe_name)? {
+        Some(command) => {
+            unregister(&exe_name)?;
+            eprintln!("cargo-debug: removed the debugger registered for {exe_name} ({command})");
+        }
+        None => eprintln!("cargo-debug: there's no debugger registered for {exe_name}"),
+    }
+
+    Ok(())
+}
+
+/// Register `debugger` for `exe`, start the service `name` when `start_it`
+/// is set, and remove the registration once Enter or Ctrl+C is pressed.
+pub fn register(
+    name: &str,
+    exe: &Path,
+    debugger: &Debugger,
+    setup: &DebugSetup,
+    start_it: bool,
+) -> Result<()> {
+    check_elevated()?;
+    let exe_name = exe_name(exe)?;
+    let command = ifeo_command(debugger, &ifeo_program(debugger, setup)?, setup)?;
+    if let Some(existing) = registered(&exe_name)? {
+        bail!(
+            "{exe_name} already has a debugger registered ({existing}), \
+             remove it with `cargo debug --service cleanup`"
+        );
+    }
+
+    let status = Command::new("reg")
+        .args([
+            "add",
+            &ifeo_key(&exe_name),
+            "/v",
+            "Debugger",
+            "/t",
+            "REG_SZ",
+        ])
+        .args(["/d", &command, "/f"])
+        .status()
+        .context("Could not run reg")?;
+    if !status.success() {
+        bail!("Could not register the debugger for {exe_name}");
+    }
+    info!("registered `{command}` for {exe_name}");
+
+    // Don't leave the debugger registered if we're interrupted
+    let handler_exe_name = exe_name.clone();
+    ctrlc::set_handler(move || {
+        let _ = unregister(&handler_exe_name);
+        std::process::exit(130);
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    let result = (|| {
+        if start_it {
+            start(name)?;
+        }
+        eprintln!(
+            "cargo-debug: the service {name} starts in {} now, press Enter to unregister it",
+            debugger.name()
+        );
+        io::stdin().lock().read_line(&mut String::new())?;
+        Ok(())
+    })();
+    unregister(&exe_name)?;
+    eprintln!("cargo-debug: unregistered the debugger for {exe_name}");
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn service_control_output() {
+        let query = include_str!("../testdata/sc-queryex.txt");
+        assert_eq!(parse_query(query), Some((RUNNING, 4242)));
+        assert_eq!(parse_query(""), None);
+
+        let config = include_str!("../testdata/sc-qc.txt");
+        assert_eq!(
+            binary_path(config),
+            Some(r"C:\Users\me\foo\target\debug\fooservice.exe")
+        );
+        let unquoted = "        BINARY_PATH_NAME   : C:\\svc\\foo.exe -k netsvcs\n";
+        assert_eq!(binary_path(unquoted), Some(r"C:\svc\foo.exe"));
+
+        let reg = "\r\nHKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Image File Execution Options\\fooservice.exe\r\n    Debugger    REG_SZ    C:\\dbg\\cdb.exe -server tcp:port=5005\r\n\r\n";
+        assert_eq!(
+            parse_debugger_value(reg),
+            Some(r"C:\dbg\cdb.exe -server tcp:port=5005")
+        );
+        assert_eq!(parse_debugger_value("\r\n"), None);
+
+        assert!(elevated(
+            "Mandatory Label\\High Mandatory Level Label S-1-16-12288"
+        ));
+        assert!(!elevated(
+            "Mandatory Label\\Medium Mandatory Level Label S-1-16-8192"
+        ));
+    }
+
+    #[test]
+    fn ifeo_registration() {
+        assert_eq!(
+            ifeo_key("fooservice.exe"),
+            r"HKLM\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Image File Execution Options\fooservice.exe"
+        );
+
+        let setup = DebugSetup {
+            program: None,
+            flags: vec!["-q".to_string()],
+            commands: vec!["break main".to_string()],
+            command_file: None,
+        };
+        let gdb = ifeo_command(&Debugger::Gdb, Path::new("gdb"), &setup).unwrap();
+        assert_eq!(
+            gdb,
+            quote::command_line(
+                Path::new("gdb"),
+                &["-q", "-ex", "break main", "--args"].map(String::from)
+            )
+        );
+        let windbg = ifeo_command(&Debugger::Windbg, Path::new("windbgx"), &setup).unwrap();
+        assert_eq!(
+            windbg,
+            quote::command_line(
+                Path::new("windbgx"),
+                &["-q", "-c", "break main"].map(String::from)
+            )
+        );
+        assert!(ifeo_command(&Debugger::Perf, Path::new("perf"), &setup).is_err());
+    }
+}
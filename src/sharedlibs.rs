@@ -0,0 +1,57 @@
+//! Listing the shared libraries the program loads, for "wrong library" bugs.
+//!
+//! The debugger runs the program in batch mode up to `main`, by which point
+//! the dynamic loader has mapped everything the binary links against, lists
+//! the libraries and exits.
+
+use anyhow::{bail, Result};
+
+use crate::Debugger;
+
+/// The flags and startup commands that list the program's shared libraries.
+///
+/// The debugger exits with a nonzero status if the program never reached
+/// `main`, e.g. because a library is missing.
+pub fn setup(debugger: &Debugger) -> Result<(Vec<String>, Vec<String>)> {
+    let (flags, commands): (&[&str], &[&str]) = match debugger {
+        // $_thread is 0 if the program has exited, or never started
+        Debugger::Gdb => (
+            &["-batch"],
+            &[
+                "tbreak main",
+                "run",
+                "info sharedlibrary",
+                "quit $_thread == 0",
+            ],
+        ),
+        // lldb exits with an error once a command in batch mode fails
+        Debugger::Lldb => (
+            &["--batch"],
+            &[
+                "breakpoint set --name main --one-shot true",
+                "process launch",
+                "image list",
+                "process kill",
+            ],
+        ),
+        _ => bail!("--shared-libs is only supported by gdb and lldb"),
+    };
+
+    let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect();
+    Ok((strings(flags), strings(commands)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gdb_batch() {
+        let (flags, commands) = setup(&Debugger::Gdb).unwrap();
+        assert_eq!(flags, ["-batch"]);
+        assert_eq!(commands[2], "info sharedlibrary");
+        assert_eq!(commands.last().unwrap(), "quit $_thread == 0");
+
+        assert!(setup(&Debugger::Windbg).is_err());
+    }
+}
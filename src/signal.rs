@@ -0,0 +1,87 @@
+//! Describing how a program was terminated by a signal.
+//!
+//! Debuggers that launch the program without taking control of it
+//! (gdbserver, devenv and windbg, or no debugger at all) exit along with
+//! it, so the signal that killed it is the only crash context there is.
+
+use std::process::ExitStatus;
+
+/// The name and description of the common signals.
+#[cfg(unix)]
+const SIGNALS: [(libc::c_int, &str, &str); 19] = [
+    (libc::SIGHUP, "SIGHUP", "hangup"),
+    (libc::SIGINT, "SIGINT", "interrupt"),
+    (libc::SIGQUIT, "SIGQUIT", "quit"),
+    (libc::SIGILL, "SIGILL", "illegal instruction"),
+    (libc::SIGTRAP, "SIGTRAP", "trace/breakpoint trap"),
+    (libc::SIGABRT, "SIGABRT", "aborted"),
+    (libc::SIGBUS, "SIGBUS", "bus error"),
+    (libc::SIGFPE, "SIGFPE", "arithmetic exception"),
+    (libc::SIGKILL, "SIGKILL", "killed"),
+    (libc::SIGUSR1, "SIGUSR1", "user signal 1"),
+    (libc::SIGSEGV, "SIGSEGV", "segmentation fault"),
+    (libc::SIGUSR2, "SIGUSR2", "user signal 2"),
+    (libc::SIGPIPE, "SIGPIPE", "broken pipe"),
+    (libc::SIGALRM, "SIGALRM", "alarm clock"),
+    (libc::SIGTERM, "SIGTERM", "terminated"),
+    (libc::SIGXCPU, "SIGXCPU", "CPU time limit exceeded"),
+    (libc::SIGXFSZ, "SIGXFSZ", "file size limit exceeded"),
+    (libc::SIGSYS, "SIGSYS", "bad system call"),
+    (libc::SIGSTOP, "SIGSTOP", "stopped"),
+];
+
+/// `signal` by name, e.g. "SIGSEGV (segmentation fault)".
+#[cfg(unix)]
+fn describe(signal: i32) -> String {
+    match SIGNALS.iter().find(|(number, _, _)| *number == signal) {
+        Some((_, name, description)) => format!("{name} ({description})"),
+        None => format!("signal {signal}"),
+    }
+}
+
+/// How `name` was terminated, if it was by a signal.
+#[cfg(unix)]
+pub fn report(name: &str, status: &ExitStatus) -> Option<String> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let signal = status.signal()?;
+    let core = if status.core_dumped() {
+        ", core dumped"
+    } else {
+        ""
+    };
+
+    Some(format!(
+        "{name} was terminated by {}{core}",
+        describe(signal)
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn report(_name: &str, _status: &ExitStatus) -> Option<String> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn signal_reports() {
+        assert_eq!(describe(libc::SIGSEGV), "SIGSEGV (segmentation fault)");
+        assert_eq!(describe(libc::SIGABRT), "SIGABRT (aborted)");
+        assert_eq!(describe(64), "signal 64");
+
+        // A wait status: the signal in the low bits, 0x80 if it dumped core
+        assert_eq!(
+            report("foo", &ExitStatus::from_raw(libc::SIGSEGV | 0x80)).as_deref(),
+            Some("foo was terminated by SIGSEGV (segmentation fault), core dumped")
+        );
+        assert_eq!(
+            report("foo", &ExitStatus::from_raw(libc::SIGKILL)).as_deref(),
+            Some("foo was terminated by SIGKILL (killed)")
+        );
+        assert_eq!(report("foo", &ExitStatus::from_raw(1 << 8)), None);
+    }
+}
@@ -0,0 +1,149 @@
+//! Checking the source files named in the debug info exist here.
+//!
+//! The line tables record where each source file was when the binary was
+//! built. After a source tree moves, or for a binary built elsewhere, the
+//! debugger can't show any source until the old location is mapped to the
+//! new one, which is easiest to spot before the session starts.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::coverage::llvm_tool;
+
+/// How many missing files are listed, the rest are counted.
+const MAX_LISTED: usize = 10;
+
+/// Prefixes of sources that are never on disk as recorded: the standard
+/// library's, mapped with the toolchain's `rust-src` instead.
+const IGNORED_PREFIXES: [&str; 2] = ["/rustc/", "\\rustc\\"];
+
+/// The distinct source paths in `llvm-dwarfdump --show-sources` output.
+fn parse_sources(output: &str) -> Vec<String> {
+    let mut sources = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter(|line| !IGNORED_PREFIXES.iter().any(|p| line.starts_with(p)))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    sources.sort();
+    sources.dedup();
+
+    sources
+}
+
+/// The sources that don't exist, once `maps` are applied. Relative paths are
+/// relative to `root`.
+fn missing(
+    sources: &[String],
+    root: &Path,
+    maps: &[(String, String)],
+    exists: impl Fn(&Path) -> bool,
+) -> Vec<String> {
+    sources
+        .iter()
+        .filter(|source| {
+            let mapped = maps
+                .iter()
+                .find_map(|(from, to)| {
+                    let rest = source.strip_prefix(from.as_str())?;
+                    Some(format!("{to}{rest}"))
+                })
+                .unwrap_or_else(|| source.to_string());
+            !exists(&root.join(mapped))
+        })
+        .cloned()
+        .collect()
+}
+
+/// A summary of the sources that are missing, or `None` if all are there.
+fn summary(total: usize, missing: &[String]) -> Option<String> {
+    if missing.is_empty() {
+        return None;
+    }
+
+    let mut lines = vec![format!(
+        "{} of the {total} source files in the debug info are missing:",
+        missing.len()
+    )];
+    lines.extend(missing.iter().take(MAX_LISTED).map(|m| format!("  {m}")));
+    if missing.len() > MAX_LISTED {
+        lines.push(format!("  ... and {} more", missing.len() - MAX_LISTED));
+    }
+    lines.push(
+        "if the sources have moved, map their old location with --path-map FROM=TO".to_string(),
+    );
+
+    Some(lines.join("\n"))
+}
+
+/// Check the sources in `bin`'s debug info exist, printing a summary of
+/// those that don't.
+pub fn verify(bin: &Path, root: &Path, maps: &[(String, String)]) -> Result<()> {
+    let dwarfdump = llvm_tool("llvm-dwarfdump")?;
+    let output = Command::new(&dwarfdump)
+        .arg("--show-sources")
+        .arg(bin)
+        .output()
+        .with_context(|| format!("Could not run {}", dwarfdump.display()))?;
+    if !output.status.success() {
+        bail!(
+            "llvm-dwarfdump could not read {}: {}",
+            bin.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let sources = parse_sources(&String::from_utf8_lossy(&output.stdout));
+    let missing = missing(&sources, root, maps, |p| p.is_file());
+    match summary(sources.len(), &missing) {
+        Some(summary) => eprintln!("{summary}"),
+        None => eprintln!(
+            "all {} source files in the debug info were found",
+            sources.len()
+        ),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn missing_sources() {
+        let output = "/home/me/foo/src/main.rs\n\
+                      /rustc/90b35a6239c3d8bdabc530a6a0816f7ff89a0aaf/library/std/src/rt.rs\n\
+                      /build/foo/src/lib.rs\n\
+                      src/util.rs\n\
+                      /home/me/foo/src/main.rs\n";
+        let sources = parse_sources(output);
+        assert_eq!(
+            sources,
+            [
+                "/build/foo/src/lib.rs",
+                "/home/me/foo/src/main.rs",
+                "src/util.rs"
+            ]
+        );
+
+        let exists = |p: &Path| {
+            p == Path::new("/home/me/foo/src/main.rs") || p == Path::new("/home/me/foo/src/util.rs")
+        };
+        let root = Path::new("/home/me/foo");
+        let gone = missing(&sources, root, &[], exists);
+        assert_eq!(gone, ["/build/foo/src/lib.rs"]);
+
+        let maps = [("/build/foo".to_string(), "/home/me/foo".to_string())];
+        let exists = |p: &Path| p.starts_with("/home/me/foo");
+        assert!(missing(&sources, root, &maps, exists).is_empty());
+
+        let summary = summary(3, &gone).unwrap();
+        assert!(summary.starts_with("1 of the 3 source files in the debug info are missing:"));
+        assert!(summary.contains("--path-map"));
+        assert_eq!(super::summary(3, &[]), None);
+    }
+}
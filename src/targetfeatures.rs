@@ -0,0 +1,137 @@
+//! Choosing the CPU features the debug build is compiled for.
+//!
+//! SIMD code takes different paths depending on the features enabled at
+//! compile time, so `-C target-feature` is added to the rustflags cargo
+//! uses. The presets expand to feature lists, and `--native-features` to
+//! those the host CPU has.
+
+use crate::coverage::append_flag;
+use crate::BuildArgs;
+
+/// What `--avx2` enables; AVX2 implies AVX.
+pub const AVX2: &str = "+avx,+avx2";
+/// What `--no-avx` disables, with the features that imply AVX.
+pub const NO_AVX: &str = "-avx,-avx2,-fma,-avx512f";
+/// What `--sse4.2` enables.
+pub const SSE4_2: &str = "+sse4.2";
+
+/// The x86 features the host CPU has, as `+feature`s.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn host() -> Vec<String> {
+    macro_rules! detected {
+        ($($feature:tt),*) => {
+            [$(($feature, std::arch::is_x86_feature_detected!($feature))),*]
+        };
+    }
+
+    detected!(
+        "sse3",
+        "ssse3",
+        "sse4.1",
+        "sse4.2",
+        "popcnt",
+        "avx",
+        "avx2",
+        "fma",
+        "bmi1",
+        "bmi2",
+        "lzcnt",
+        "f16c",
+        "aes",
+        "pclmulqdq",
+        "avx512f",
+        "avx512bw",
+        "avx512cd",
+        "avx512dq",
+        "avx512vl"
+    )
+    .into_iter()
+    .filter(|(_, detected)| *detected)
+    .map(|(feature, _)| format!("+{feature}"))
+    .collect()
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+pub fn host() -> Vec<String> {
+    log::warn!("--native-features only detects x86 CPU features, building without them");
+    vec![]
+}
+
+/// The `-C target-feature` list the build asks for, if any: the host's
+/// features, then the presets, then `--target-features`, so that later
+/// entries override earlier ones.
+pub fn requested(args: &BuildArgs) -> Option<String> {
+    let mut features = vec![];
+    if args.native_features {
+        features.extend(host());
+    }
+    for (set, preset) in [
+        (args.avx2, AVX2),
+        (args.no_avx, NO_AVX),
+        (args.sse4_2, SSE4_2),
+    ] {
+        if set {
+            features.push(preset.to_string());
+        }
+    }
+    features.extend(args.target_features.clone());
+
+    let features = features
+        .iter()
+        .flat_map(|f| f.split(','))
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .collect::<Vec<_>>();
+    (!features.is_empty()).then(|| features.join(","))
+}
+
+/// The rustflags variable cargo reads, and its value with `features` added:
+/// the encoded form when it's set, as cargo then ignores `RUSTFLAGS`.
+pub fn rustflags(
+    features: &str,
+    encoded: Option<String>,
+    plain: Option<String>,
+) -> (&'static str, String) {
+    let flag = format!("-Ctarget-feature={features}");
+    match encoded {
+        Some(flags) => (
+            "CARGO_ENCODED_RUSTFLAGS",
+            append_flag(Some(flags), '\x1f', &flag),
+        ),
+        None => ("RUSTFLAGS", append_flag(plain, ' ', &flag)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn target_feature_flags() {
+        let mut args = crate::test::build_args(None);
+        assert_eq!(requested(&args), None);
+
+        args.sse4_2 = true;
+        args.no_avx = true;
+        args.target_features = Some("+bmi2, -fma".to_string());
+        assert_eq!(
+            requested(&args).unwrap(),
+            "-avx,-avx2,-fma,-avx512f,+sse4.2,+bmi2,-fma"
+        );
+
+        assert_eq!(
+            rustflags("+avx2", None, Some("-Cdebuginfo=2".to_string())),
+            (
+                "RUSTFLAGS",
+                "-Cdebuginfo=2 -Ctarget-feature=+avx2".to_string()
+            )
+        );
+        assert_eq!(
+            rustflags("+avx2", Some(String::new()), Some("-Cfoo".to_string())),
+            (
+                "CARGO_ENCODED_RUSTFLAGS",
+                "-Ctarget-feature=+avx2".to_string()
+            )
+        );
+    }
+}
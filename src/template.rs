@@ -0,0 +1,216 @@
+//! Launching the selected binary with a command line of the user's own
+//! (`--exec-template`), for debuggers and wrappers cargo-debug doesn't
+//! know.
+//!
+//! The placeholders are replaced with quoted values, and the result runs in
+//! the shell: `sh -c` on Unix, `cmd /C` on Windows.
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+use crate::quote;
+
+/// The placeholders, with what they stand for.
+pub const PLACEHOLDERS: [(&str, &str); 4] = [
+    ("bin", "the path of the selected binary"),
+    ("args", "the options given after `--`, each quoted"),
+    ("cwd", "the current directory"),
+    ("name", "the name of the selected target"),
+];
+
+/// What the placeholders are replaced with.
+pub struct Values<'a> {
+    pub bin: &'a str,
+    pub args: &'a [String],
+    pub cwd: &'a str,
+    pub name: &'a str,
+}
+
+fn quote(arg: &str) -> Cow<'_, str> {
+    if cfg!(windows) {
+        quote::windows(arg)
+    } else {
+        quote::sh(arg)
+    }
+}
+
+/// `template` with each placeholder replaced by `replace` given its name;
+/// `{{` and `}}` stand for braces. `what` names the template in errors.
+fn substitute(
+    template: &str,
+    what: &str,
+    mut replace: impl FnMut(&str) -> Option<String>,
+) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if let Some(after) = tail.strip_prefix("{{").or(tail.strip_prefix("}}")) {
+            expanded.push_str(&tail[..1]);
+            rest = after;
+            continue;
+        }
+
+        let Some((name, after)) = tail[1..].split_once('}').filter(|_| tail.starts_with('{'))
+        else {
+            bail!("Unmatched brace in {what} `{template}`, write `{{{{` or `}}}}` for one");
+        };
+        match replace(name) {
+            Some(value) => expanded.push_str(&value),
+            None => {
+                let known = PLACEHOLDERS
+                    .iter()
+                    .map(|(p, _)| format!("{{{p}}}"))
+                    .collect::<Vec<_>>();
+                bail!(
+                    "Unknown placeholder {{{name}}} in {what}, the placeholders are {}",
+                    known.join(", ")
+                );
+            }
+        }
+        rest = after;
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// `template` with its placeholders replaced; `{{` and `}}` stand for
+/// braces. It must have `{bin}`, or there's nothing to launch.
+pub fn expand(template: &str, values: &Values) -> Result<String> {
+    let mut has_bin = false;
+    let expanded = substitute(template, "--exec-template", |name| match name {
+        "bin" => {
+            has_bin = true;
+            Some(quote(values.bin).into_owned())
+        }
+        "args" => {
+            let args = values.args.iter().map(|a| quote(a)).collect::<Vec<_>>();
+            Some(args.join(" "))
+        }
+        "cwd" => Some(quote(values.cwd).into_owned()),
+        "name" => Some(quote(values.name).into_owned()),
+        _ => None,
+    })?;
+
+    if !has_bin {
+        bail!("--exec-template must have {{bin}}, where the selected binary goes");
+    }
+
+    Ok(expanded)
+}
+
+/// `args` with their placeholders replaced, unquoted as no shell runs them.
+/// `{args}` is an argument of its own, standing for all of the options.
+/// One of them must have `{bin}`. `what` names the template in errors.
+pub fn expand_args(args: &[String], values: &Values, what: &str) -> Result<Vec<String>> {
+    let mut has_bin = false;
+    let mut expanded = vec![];
+    for arg in args {
+        if arg == "{args}" {
+            expanded.extend(values.args.iter().cloned());
+            continue;
+        }
+        let mut spliced = false;
+        expanded.push(substitute(arg, what, |name| match name {
+            "bin" => {
+                has_bin = true;
+                Some(values.bin.to_string())
+            }
+            "args" => {
+                spliced = true;
+                Some(String::new())
+            }
+            "cwd" => Some(values.cwd.to_string()),
+            "name" => Some(values.name.to_string()),
+            _ => None,
+        })?);
+        if spliced {
+            bail!("{{args}} must be an argument of its own in {what}, not part of `{arg}`");
+        }
+    }
+
+    if !has_bin {
+        bail!("{what} must have {{bin}}, where the selected binary goes");
+    }
+
+    Ok(expanded)
+}
+
+/// The shell command running the expanded template.
+pub fn command(template: &str, values: &Values) -> Result<(PathBuf, Vec<String>)> {
+    let expanded = expand(template, values)?;
+    Ok(if cfg!(windows) {
+        (PathBuf::from("cmd"), vec!["/C".to_string(), expanded])
+    } else {
+        (PathBuf::from("sh"), vec!["-c".to_string(), expanded])
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn expand_template() {
+        let args = ["-v".to_string(), "it's".to_string()];
+        let values = Values {
+            bin: "/foo/target/debug/foo",
+            args: &args,
+            cwd: "/my dir",
+            name: "foo",
+        };
+        assert_eq!(
+            expand("rr record {bin} {args} # {name} in {cwd}", &values).unwrap(),
+            r"rr record /foo/target/debug/foo -v 'it'\''s' # foo in '/my dir'"
+        );
+        assert_eq!(
+            expand("awk '{{print}}' < {bin}", &values).unwrap(),
+            "awk '{print}' < /foo/target/debug/foo"
+        );
+        assert!(expand("valgrind {args}", &values).is_err());
+        assert!(expand("valgrind {bin} {pid}", &values).is_err());
+        assert!(expand("valgrind {bin", &values).is_err());
+        assert!(expand("valgrind } {bin}", &values).is_err());
+    }
+
+    #[test]
+    fn expand_argument_template() {
+        let args = ["-v".to_string(), "it's".to_string()];
+        let values = Values {
+            bin: "/foo/target/debug/foo",
+            args: &args,
+            cwd: "/my dir",
+            name: "foo",
+        };
+        let template = ["-q", "--cd={cwd}", "--args", "{bin}", "{args}"].map(String::from);
+        assert_eq!(
+            expand_args(&template, &values, "mygdb").unwrap(),
+            [
+                "-q",
+                "--cd=/my dir",
+                "--args",
+                "/foo/target/debug/foo",
+                "-v",
+                "it's"
+            ]
+        );
+        let err = expand_args(
+            &["{bin}".to_string(), "--{args}".to_string()],
+            &values,
+            "mygdb",
+        );
+        assert!(err.unwrap_err().to_string().contains("of its own"));
+        assert!(expand_args(&["{args}".to_string()], &values, "mygdb").is_err());
+        assert!(expand_args(
+            &["{bin}".to_string(), "{pid}".to_string()],
+            &values,
+            "mygdb"
+        )
+        .is_err());
+    }
+}
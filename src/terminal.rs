@@ -0,0 +1,248 @@
+//! Launching the debugger in a new terminal emulator window.
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{find_executable, quote};
+
+/// Terminal emulators tried on Linux, in order.
+const LINUX_TERMINALS: &[&str] = &[
+    "x-terminal-emulator",
+    "gnome-terminal",
+    "konsole",
+    "alacritty",
+    "xterm",
+];
+
+/// Find a terminal emulator for the host platform.
+fn detect() -> Option<PathBuf> {
+    let candidates: &[&str] = if cfg!(windows) {
+        &["wt"]
+    } else if cfg!(target_os = "macos") {
+        // Terminal.app can only be told to run a command through AppleScript
+        &["osascript"]
+    } else {
+        LINUX_TERMINALS
+    };
+
+    candidates
+        .iter()
+        .find_map(|t| find_executable(Path::new(t)))
+}
+
+/// The terminal emulator's name, e.g. `gnome-terminal` or `wt`.
+fn name(terminal: &Path) -> String {
+    terminal
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// What `program` prints once it exits, to keep the window open, and the
+/// shell that runs it: `$@` is the program and its arguments.
+const HOLD_SCRIPT: &str = r#""$@"; printf '\n[exited with %s, press Enter to close]' "$?"; read _"#;
+
+/// The flag keeping `terminal`'s window open once the command exits, for
+/// the emulators that have one.
+fn hold_flag(terminal: &Path) -> Option<&'static str> {
+    match name(terminal).as_str() {
+        "xterm" => Some("-hold"),
+        "konsole" | "alacritty" => Some("--hold"),
+        _ => None,
+    }
+}
+
+/// `program` and `args` run by a shell that waits for Enter once they exit,
+/// for the terminals that close their window along with the command.
+fn hold_command(program: &Path, args: Vec<OsString>) -> (PathBuf, Vec<OsString>) {
+    let (shell, mut shell_args): (_, Vec<OsString>) = if cfg!(windows) {
+        ("cmd", vec!["/k".into()])
+    } else {
+        ("sh", vec!["-c".into(), HOLD_SCRIPT.into(), "sh".into()])
+    };
+    shell_args.push(program.into());
+    shell_args.extend(args);
+
+    (PathBuf::from(shell), shell_args)
+}
+
+/// Arguments to `terminal` that open a new window running `program` in `cwd`.
+///
+/// Terminals that aren't known are assumed to take the command after `-e`,
+/// as xterm does. The directory and program are passed as they're named,
+/// UTF-8 or not, except into AppleScript, which is text.
+fn terminal_args(terminal: &Path, cwd: &Path, program: &Path, args: &[OsString]) -> Vec<OsString> {
+    match name(terminal).as_str() {
+        "wt" => {
+            // Windows Terminal splits its own command line on `;`
+            let mut wt_args = vec!["new-tab".into(), "-d".into(), cwd.into(), program.into()];
+            wt_args.extend(
+                args.iter()
+                    .map(|a| a.to_string_lossy().replace(';', r"\;").into()),
+            );
+            wt_args
+        }
+        "gnome-terminal" => {
+            // The window is opened by a server process that doesn't share our cwd
+            let mut working_directory = OsString::from("--working-directory=");
+            working_directory.push(cwd);
+            let mut gnome_args = vec![working_directory, "--".into(), program.into()];
+            gnome_args.extend(args.iter().cloned());
+            gnome_args
+        }
+        "osascript" => {
+            let command = format!(
+                "cd {} && {}",
+                quote::sh(&cwd.to_string_lossy()),
+                quote::sh_command_line(
+                    program,
+                    &args
+                        .iter()
+                        .map(|a| a.to_string_lossy().into_owned())
+                        .collect::<Vec<_>>()
+                )
+            );
+            let command = command.replace('\\', r"\\").replace('"', r#"\""#);
+            vec![
+                "-e".into(),
+                format!("tell application \"Terminal\" to do script \"{command}\"").into(),
+                "-e".into(),
+                "tell application \"Terminal\" to activate".into(),
+            ]
+        }
+        _ => {
+            let mut e_args = vec!["-e".into(), program.into()];
+            e_args.extend(args.iter().cloned());
+            e_args
+        }
+    }
+}
+
+/// Synthesize the invocation that runs the debugger command in a new terminal
+/// window, using `terminal` if given or else a detected one.
+///
+/// With `hold`, the window stays open once the debugger exits: the
+/// emulator is asked to keep it, or the command waits for Enter. Terminal.app
+/// keeps its windows anyway.
+///
+/// Returns `None` if no terminal emulator could be found.
+pub fn command(
+    terminal: Option<&Path>,
+    cwd: &Path,
+    program: &Path,
+    args: &[String],
+    hold: bool,
+) -> Option<(PathBuf, Vec<OsString>)> {
+    let terminal = match terminal {
+        Some(terminal) => terminal.to_path_buf(),
+        None => detect()?,
+    };
+
+    let mut program = program.to_path_buf();
+    let mut args = args.iter().map(OsString::from).collect::<Vec<_>>();
+    let mut flags = vec![];
+    if hold {
+        match hold_flag(&terminal) {
+            Some(flag) => flags.push(OsString::from(flag)),
+            None if name(&terminal) == "osascript" => {}
+            None => (program, args) = hold_command(&program, args),
+        }
+    }
+
+    flags.extend(terminal_args(&terminal, cwd, &program, &args));
+    Some((terminal, flags))
+}
+
+/// Wait for Enter, keeping the session's output on screen for `--hold`
+/// outside a new terminal window.
+pub fn wait_for_enter() {
+    eprint!("cargo-debug: press Enter to exit");
+    let _ = std::io::stderr().flush();
+    let _ = std::io::stdin().read_line(&mut String::new());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(terminal: &str) -> Vec<OsString> {
+        terminal_args(
+            Path::new(terminal),
+            Path::new("/foo"),
+            Path::new("gdb"),
+            &["--args", "a;b", "it's"].map(OsString::from),
+        )
+    }
+
+    #[test]
+    fn windows_terminal() {
+        assert_eq!(
+            args("wt.exe"),
+            ["new-tab", "-d", "/foo", "gdb", "--args", r"a\;b", "it's"]
+        );
+    }
+
+    #[test]
+    fn gnome_terminal() {
+        assert_eq!(
+            args("/usr/bin/gnome-terminal"),
+            [
+                "--working-directory=/foo",
+                "--",
+                "gdb",
+                "--args",
+                "a;b",
+                "it's"
+            ]
+        );
+    }
+
+    #[test]
+    fn generic_terminal() {
+        assert_eq!(args("alacritty"), ["-e", "gdb", "--args", "a;b", "it's"]);
+        assert_eq!(args("konsole"), ["-e", "gdb", "--args", "a;b", "it's"]);
+    }
+
+    #[test]
+    fn held_window() {
+        let held = |terminal: &str| {
+            command(
+                Some(Path::new(terminal)),
+                Path::new("/foo"),
+                Path::new("gdb"),
+                &["a".to_string()],
+                true,
+            )
+            .unwrap()
+            .1
+        };
+        assert_eq!(held("xterm"), ["-hold", "-e", "gdb", "a"]);
+        assert_eq!(held("konsole"), ["--hold", "-e", "gdb", "a"]);
+        if cfg!(unix) {
+            assert_eq!(
+                held("gnome-terminal"),
+                [
+                    "--working-directory=/foo",
+                    "--",
+                    "sh",
+                    "-c",
+                    HOLD_SCRIPT,
+                    "sh",
+                    "gdb",
+                    "a"
+                ]
+            );
+        }
+        assert_eq!(held("osascript").len(), 4);
+    }
+
+    #[test]
+    fn macos_terminal() {
+        let args = args("osascript");
+        assert_eq!(
+            args[1],
+            r#"tell application "Terminal" to do script "cd /foo && gdb --args 'a;b' 'it'\\''s'""#
+        );
+    }
+}
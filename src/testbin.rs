@@ -0,0 +1,131 @@
+//! Debugging a test executable that's already built, like the ones
+//! `cargo test --no-run` lists, named after their crate with a hash:
+//! `target/debug/deps/foo-0123456789abcdef`.
+//!
+//! The executable is described as cargo would have reported it, as if it
+//! came from a build log. The test harness takes a filter and flags of its
+//! own as arguments: `NAME --exact` runs only the test called NAME, and
+//! `--nocapture` lets it print, which a debugging session wants.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::utf8_path;
+
+/// The name of the test target `path` was built from, without the hash
+/// cargo appends to the file name.
+fn target_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match stem.rsplit_once('-') {
+        Some((name, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            name.to_string()
+        }
+        _ => stem,
+    }
+}
+
+/// The compiler-artifact message for the test executable at `path`, of the
+/// package whose manifest is `manifest`.
+pub fn message(path: &Path, manifest: &Path) -> Result<String> {
+    if !path.is_file() {
+        bail!("--test-binary {} does not exist", path.display());
+    }
+    let path = path.canonicalize()?;
+    let name = target_name(&path);
+    let dir = manifest.parent().unwrap_or(manifest);
+    // cargo's messages only hold UTF-8 paths
+    let exe = utf8_path(&path, "--test-binary")?;
+    let text = |path: &Path| path.to_string_lossy().into_owned();
+
+    // The profile is cargo's default for tests; it isn't checked, as the
+    // build wasn't ours
+    Ok(serde_json::json!({
+        "reason": "compiler-artifact",
+        "package_id": format!("{name} 0.0.0 (path+file://{})", dir.display()),
+        "manifest_path": text(manifest),
+        "target": {
+            "kind": ["test"],
+            "crate_types": ["bin"],
+            "name": name,
+            "src_path": text(&dir.join("src/lib.rs")),
+            "edition": "2021",
+            "doctest": false,
+            "test": true,
+            "doc": false
+        },
+        "profile": {
+            "opt_level": "0",
+            "debuginfo": 2,
+            "debug_assertions": true,
+            "overflow_checks": true,
+            "test": true
+        },
+        "features": [],
+        "filenames": [exe],
+        "executable": exe,
+        "fresh": true
+    })
+    .to_string())
+}
+
+/// The test executable's arguments: the harness flags running only the
+/// test `filter`, if there is one, then `options`.
+pub fn harness_args(filter: Option<&str>, options: &[String]) -> Vec<String> {
+    let mut args = vec![];
+    if let Some(filter) = filter {
+        args.extend([filter.to_string(), "--exact".to_string()]);
+        if !options.iter().any(|o| o == "--nocapture") {
+            args.push("--nocapture".to_string());
+        }
+    }
+    args.extend(options.iter().cloned());
+
+    args
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prebuilt_test_binary() {
+        assert_eq!(
+            target_name(Path::new("target/debug/deps/my_crate-0123456789abcdef")),
+            "my_crate"
+        );
+        assert_eq!(
+            target_name(Path::new(
+                "target/debug/deps/integration-0123456789abcdef.exe"
+            )),
+            "integration"
+        );
+        assert_eq!(target_name(Path::new("bin/foo-bar")), "foo-bar");
+
+        let exe = std::env::current_exe().unwrap();
+        let line = message(&exe, Path::new("/foo/Cargo.toml")).unwrap();
+        let mut events = crate::events::Events::default();
+        let output = crate::parse_artifacts(line.as_bytes(), &mut events);
+        let artifact = &output.artifacts[0];
+        assert_eq!(artifact.target.kind, ["test"]);
+        assert_eq!(artifact.manifest_path.as_str(), "/foo/Cargo.toml");
+        assert_eq!(
+            artifact.executable.as_ref().unwrap().as_str(),
+            exe.canonicalize().unwrap().to_str().unwrap()
+        );
+        assert!(message(Path::new("/nonexistent/foo-test"), Path::new("Cargo.toml")).is_err());
+
+        assert_eq!(
+            harness_args(Some("parses_empty"), &["--test-threads=1".to_string()]),
+            ["parses_empty", "--exact", "--nocapture", "--test-threads=1"]
+        );
+        assert_eq!(
+            harness_args(Some("a"), &["--nocapture".to_string()]),
+            ["a", "--exact", "--nocapture"]
+        );
+        assert_eq!(harness_args(None, &["x".to_string()]), ["x"]);
+    }
+}
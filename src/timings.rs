@@ -0,0 +1,77 @@
+//! How long each phase of startup takes, reported with `--verbose`: probing
+//! the environment, the build, selecting the binary and getting the
+//! debugger launched.
+
+use std::time::{Duration, Instant};
+
+use log::info;
+
+/// The phases timed so far.
+pub struct Phases {
+    last: Instant,
+    done: Vec<(&'static str, Duration)>,
+}
+
+/// `duration` to a precision that suits it.
+fn human(duration: Duration) -> String {
+    if duration >= Duration::from_secs(1) {
+        format!("{:.2}s", duration.as_secs_f64())
+    } else if duration >= Duration::from_millis(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{}µs", duration.as_micros())
+    }
+}
+
+impl Phases {
+    pub fn start() -> Self {
+        Phases {
+            last: Instant::now(),
+            done: vec![],
+        }
+    }
+
+    /// End `phase`, which began when the one before it ended.
+    pub fn end(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.done.push((phase, now - self.last));
+        self.last = now;
+    }
+
+    fn summary(&self) -> String {
+        let total = self.done.iter().map(|(_, d)| *d).sum::<Duration>();
+        let phases = self
+            .done
+            .iter()
+            .map(|(phase, duration)| format!("{phase} {}", human(*duration)))
+            .collect::<Vec<_>>();
+
+        format!("{} (total {})", phases.join(", "), human(total))
+    }
+
+    pub fn report(&self) {
+        info!("startup: {}", self.summary());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn phase_summary() {
+        let phases = Phases {
+            last: Instant::now(),
+            done: vec![
+                ("probe", Duration::from_micros(850)),
+                ("build", Duration::from_millis(3204)),
+                ("selection", Duration::from_millis(2)),
+                ("launch", Duration::from_millis(41)),
+            ],
+        };
+        assert_eq!(
+            phases.summary(),
+            "probe 850µs, build 3.20s, selection 2ms, launch 41ms (total 3.25s)"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Launching the debugger in a new tmux window or pane.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use log::{info, trace};
+
+use crate::quote;
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum Tmux {
+    /// Open a new window named after the target
+    Window,
+    /// Split the current pane
+    Pane,
+}
+
+/// Arguments to tmux that run `command` in a new window or pane and print its pane id.
+///
+/// tmux hands a single `shell-command` argument to `default-shell -c`, so
+/// `command` must already be quoted for a POSIX shell.
+fn tmux_args(mode: &Tmux, name: &str, cwd: &Path, command: String) -> Vec<OsString> {
+    let mut args = match mode {
+        Tmux::Window => vec!["new-window".into(), "-n".into(), name.into()],
+        Tmux::Pane => vec!["split-window".into()],
+    };
+
+    // The directory goes to tmux as it's named, UTF-8 or not
+    args.extend([
+        "-c".into(),
+        cwd.into(),
+        "-P".into(),
+        "-F".into(),
+        "#{pane_id}".into(),
+        command.into(),
+    ]);
+
+    args
+}
+
+fn pane_alive(pane: &str) -> Result<bool> {
+    let output = Command::new("tmux")
+        .args(["list-panes", "-a", "-F", "#{pane_id}"])
+        .output()
+        .context("Could not run tmux")?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|l| l == pane))
+}
+
+/// Run the debugger command in a new tmux window or pane, optionally waiting
+/// for the pane to close.
+pub fn launch(
+    mode: &Tmux,
+    name: &str,
+    cwd: &Path,
+    program: &Path,
+    args: &[String],
+    wait: bool,
+) -> Result<()> {
+    if env::var_os("TMUX").is_none() {
+        bail!("--tmux requires running inside a tmux session ($TMUX is not set)");
+    }
+
+    let command = quote::sh_command_line(program, args);
+    let tmux_args = tmux_args(mode, name, cwd, command);
+
+    trace!("synthesized tmux arguments: {:?}", tmux_args);
+
+    let output = Command::new("tmux")
+        .args(&tmux_args)
+        .output()
+        .context("Could not run tmux")?;
+    if !output.status.success() {
+        bail!(
+            "tmux failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let pane = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    info!("launched debugger in tmux pane {pane}");
+
+    if wait {
+        while pane_alive(&pane)? {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn window_args() {
+        let args = tmux_args(
+            &Tmux::Window,
+            "foo",
+            Path::new("/foo"),
+            "gdb --args /foo/target/debug/foo".to_string(),
+        );
+
+        assert_eq!(
+            args,
+            [
+                "new-window",
+                "-n",
+                "foo",
+                "-c",
+                "/foo",
+                "-P",
+                "-F",
+                "#{pane_id}",
+                "gdb --args /foo/target/debug/foo"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_command_round_trip() {
+        // tmux runs the command through `sh -c`, so arguments must come back out intact
+        let args = ["%s|", "a b", "it's", "$HOME", "\"x\"", ""].map(String::from);
+        let command = quote::sh_command_line(Path::new("printf"), &args);
+
+        let output = Command::new("sh").arg("-c").arg(&command).output().unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "a b|it's|$HOME|\"x\"||"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Recording a transcript of the debugger session.
+//!
+//! gdb and windbg log natively. Other debuggers are run under `script(1)`,
+//! which tees the terminal through a pty; this is only available on Unix.
+//! Either way, the transcript keeps the terminal's colors unless they're
+//! stripped once the session is over.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{quote, Debugger};
+
+/// Arguments to the debugger that make it log its session to `path`, if it can.
+pub fn flags(debugger: &Debugger, path: &Path) -> Option<Vec<String>> {
+    let path = path.to_string_lossy();
+
+    match debugger {
+        // Evaluated before the binary is loaded, so loading is logged too
+        Debugger::Gdb => Some(
+            [
+                format!("set logging file {path}"),
+                "set logging overwrite on".to_string(),
+                "set logging enabled on".to_string(),
+            ]
+            .into_iter()
+            .flat_map(|c| ["-iex".to_string(), c])
+            .collect(),
+        ),
+        Debugger::Windbg => Some(vec!["-logo".to_string(), path.into_owned()]),
+        Debugger::Lldb
+        | Debugger::Gdbserver
+        | Debugger::Devenv
+        | Debugger::Perf
+        | Debugger::Frida
+        | Debugger::Custom(_) => None,
+    }
+}
+
+/// Wrap the debugger command in `script(1)` so its terminal is teed to `path`.
+///
+/// Returns `None` where there is no `script`.
+pub fn tee(path: &Path, program: &Path, args: &[String]) -> Option<(PathBuf, Vec<String>)> {
+    let path = path.to_string_lossy().into_owned();
+
+    let script_args = if cfg!(target_os = "linux") {
+        // util-linux takes a shell command, and -e passes its exit code through
+        vec![
+            "-q".to_string(),
+            "-f".to_string(),
+            "-e".to_string(),
+            "-c".to_string(),
+            quote::sh_command_line(program, args),
+            path,
+        ]
+    } else if cfg!(unix) {
+        // The BSDs (and macOS) take the command after the file
+        let mut script_args = vec!["-q".to_string(), path];
+        script_args.push(program.to_string_lossy().into_owned());
+        script_args.extend(args.iter().cloned());
+        script_args
+    } else {
+        return None;
+    };
+
+    Some((PathBuf::from("script"), script_args))
+}
+
+/// `text` without its ANSI escape sequences: CSI sequences (colors, cursor
+/// movement), OSC sequences (titles, hyperlinks) and two-byte escapes.
+pub fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // Parameters and intermediates up to a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // Terminated by BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    stripped
+}
+
+/// Strip the ANSI escape sequences from the transcript at `path`, in place.
+pub fn strip_file(path: &Path) -> Result<()> {
+    let contents =
+        fs::read(path).with_context(|| format!("Could not read transcript {}", path.display()))?;
+    let stripped = strip_ansi(&String::from_utf8_lossy(&contents));
+    fs::write(path, stripped)
+        .with_context(|| format!("Could not write transcript {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gdb_logging() {
+        let flags = flags(&Debugger::Gdb, Path::new("/tmp/session.log")).unwrap();
+        assert_eq!(
+            flags,
+            [
+                "-iex",
+                "set logging file /tmp/session.log",
+                "-iex",
+                "set logging overwrite on",
+                "-iex",
+                "set logging enabled on"
+            ]
+        );
+
+        assert!(super::flags(&Debugger::Lldb, Path::new("/tmp/session.log")).is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tee_with_script() {
+        let (program, args) = tee(
+            Path::new("/tmp/session.log"),
+            Path::new("lldb"),
+            &["--file".to_string(), "/my dir/foo".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(program, PathBuf::from("script"));
+        assert_eq!(
+            args,
+            [
+                "-q",
+                "-f",
+                "-e",
+                "-c",
+                "lldb --file '/my dir/foo'",
+                "/tmp/session.log"
+            ]
+        );
+    }
+
+    #[test]
+    fn strip_escapes() {
+        assert_eq!(strip_ansi("plain text\n"), "plain text\n");
+        assert_eq!(
+            strip_ansi("\x1b[1;32mBreakpoint 1\x1b[0m, \x1b[33mfoo::main\x1b[m ()"),
+            "Breakpoint 1, foo::main ()"
+        );
+        assert_eq!(strip_ansi("\x1b]0;gdb\x07(gdb) "), "(gdb) ");
+        assert_eq!(strip_ansi("\x1b]8;;file:///a\x1b\\a\x1b]8;;\x1b\\"), "a");
+        assert_eq!(strip_ansi("\x1b=\x1b[?2004h(lldb) "), "(lldb) ");
+        // A truncated sequence at the end is dropped
+        assert_eq!(strip_ansi("done\x1b[3"), "done");
+    }
+}
@@ -0,0 +1,231 @@
+//! Resource limits for the debugger and the program, like the shell's
+//! `ulimit`.
+//!
+//! Limits are set with `setrlimit` in the debugger's process just before it
+//! starts, and the program inherits them. Values are in `setrlimit`'s units:
+//! bytes for sizes, seconds for `cpu`, and a count for `nofile` and `nproc`.
+
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use anyhow::Result;
+#[cfg(unix)]
+use anyhow::{bail, Context};
+#[cfg(not(unix))]
+use log::warn;
+
+/// A resource, named like `ulimit`'s flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    As,
+    Core,
+    Cpu,
+    Data,
+    Fsize,
+    Memlock,
+    Nofile,
+    Nproc,
+    Rss,
+    Stack,
+}
+
+impl Resource {
+    const ALL: [(&'static str, Resource); 10] = [
+        ("as", Resource::As),
+        ("core", Resource::Core),
+        ("cpu", Resource::Cpu),
+        ("data", Resource::Data),
+        ("fsize", Resource::Fsize),
+        ("memlock", Resource::Memlock),
+        ("nofile", Resource::Nofile),
+        ("nproc", Resource::Nproc),
+        ("rss", Resource::Rss),
+        ("stack", Resource::Stack),
+    ];
+
+    pub fn name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(_, r)| *r == self)
+            .map_or("?", |(name, _)| name)
+    }
+}
+
+/// A limit: the soft limit, and the hard limit if it changes too. `None` is
+/// unlimited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limit {
+    pub resource: Resource,
+    pub soft: Option<u64>,
+    pub hard: Option<Option<u64>>,
+}
+
+/// Parse `RESOURCE=SOFT[:HARD]`, where each value is a number or `unlimited`.
+pub fn parse_limit(s: &str) -> Result<Limit, String> {
+    let (name, values) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected RESOURCE=SOFT[:HARD], got `{s}`"))?;
+    let resource = Resource::ALL
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, r)| *r)
+        .ok_or_else(|| {
+            let names = Resource::ALL.map(|(n, _)| n).join(", ");
+            format!("unknown resource `{name}`, expected one of {names}")
+        })?;
+
+    let value = |v: &str| match v {
+        "unlimited" => Ok(None),
+        v => v
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|_| format!("expected a number or `unlimited`, got `{v}`")),
+    };
+    let (soft, hard) = match values.split_once(':') {
+        Some((soft, hard)) => (value(soft)?, Some(value(hard)?)),
+        None => (value(values)?, None),
+    };
+
+    // Unlimited is the largest limit of all
+    let unlimited = |v: Option<u64>| v.unwrap_or(u64::MAX);
+    if hard.is_some_and(|hard| unlimited(soft) > unlimited(hard)) {
+        return Err(format!("the soft limit of {name} is above its hard limit"));
+    }
+
+    Ok(Limit {
+        resource,
+        soft,
+        hard,
+    })
+}
+
+#[cfg(unix)]
+fn rlim(value: Option<u64>) -> libc::rlim_t {
+    value.map_or(libc::RLIM_INFINITY, |v| v as libc::rlim_t)
+}
+
+/// Get the current limit and set the new one for `resource`.
+///
+/// This runs between fork and exec, so it only makes system calls.
+#[cfg(unix)]
+pub fn set(resource: Resource, f: impl FnOnce(&mut libc::rlimit)) -> io::Result<()> {
+    let id = match resource {
+        Resource::As => libc::RLIMIT_AS,
+        Resource::Core => libc::RLIMIT_CORE,
+        Resource::Cpu => libc::RLIMIT_CPU,
+        Resource::Data => libc::RLIMIT_DATA,
+        Resource::Fsize => libc::RLIMIT_FSIZE,
+        Resource::Memlock => libc::RLIMIT_MEMLOCK,
+        Resource::Nofile => libc::RLIMIT_NOFILE,
+        Resource::Nproc => libc::RLIMIT_NPROC,
+        Resource::Rss => libc::RLIMIT_RSS,
+        Resource::Stack => libc::RLIMIT_STACK,
+    };
+
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid rlimit for both calls to read and write
+    unsafe {
+        if libc::getrlimit(id, &mut limit) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        f(&mut limit);
+        if libc::setrlimit(id, &limit) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Set `limits` for `cmd` when it's run.
+#[cfg(unix)]
+pub fn apply(cmd: &mut Command, limits: &[Limit]) -> Result<()> {
+    // Check what we can up front, a failure in the child only says "invalid argument"
+    for limit in limits {
+        let mut current = (0, 0);
+        set(limit.resource, |l| current = (l.rlim_cur, l.rlim_max))
+            .with_context(|| format!("Could not read the {} limit", limit.resource.name()))?;
+
+        let hard = limit.hard.map_or(current.1, rlim);
+        if rlim(limit.soft) > hard {
+            bail!(
+                "--ulimit {}: the soft limit is above the hard limit ({})",
+                limit.resource.name(),
+                if hard == libc::RLIM_INFINITY {
+                    "unlimited".to_string()
+                } else {
+                    hard.to_string()
+                }
+            );
+        }
+    }
+
+    let limits = limits.to_vec();
+    // SAFETY: the hook only calls getrlimit and setrlimit, which are async-signal-safe
+    unsafe {
+        cmd.pre_exec(move || {
+            for limit in &limits {
+                set(limit.resource, |l| {
+                    l.rlim_cur = rlim(limit.soft);
+                    if let Some(hard) = limit.hard {
+                        l.rlim_max = rlim(hard);
+                    }
+                })?;
+            }
+
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply(_cmd: &mut Command, limits: &[Limit]) -> Result<()> {
+    if !limits.is_empty() {
+        warn!("--ulimit is not supported on this platform, ignoring it");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_limits() {
+        assert_eq!(
+            parse_limit("nofile=1024"),
+            Ok(Limit {
+                resource: Resource::Nofile,
+                soft: Some(1024),
+                hard: None,
+            })
+        );
+        assert_eq!(
+            parse_limit("core=unlimited:unlimited"),
+            Ok(Limit {
+                resource: Resource::Core,
+                soft: None,
+                hard: Some(None),
+            })
+        );
+        assert_eq!(
+            parse_limit("stack=8388608:unlimited").map(|l| l.hard),
+            Ok(Some(None))
+        );
+
+        assert!(parse_limit("nofile").is_err());
+        assert!(parse_limit("files=10").is_err());
+        assert!(parse_limit("nofile=lots").is_err());
+        assert!(parse_limit("nofile=2048:1024").is_err());
+        assert!(parse_limit("nofile=unlimited:1024").is_err());
+    }
+}
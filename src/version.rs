@@ -0,0 +1,100 @@
+//! Asking the debugger for its version (`--print-debugger-version`), so
+//! sessions and bug reports record which build was used.
+//!
+//! Not every debugger has a version flag, and one that doesn't may start
+//! a session instead, so the probe gets no input and is killed if it runs
+//! for too long.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use log::trace;
+
+use crate::Debugger;
+
+/// How long the debugger gets to print its version.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The flag `debugger` prints its version with, if it has one.
+fn version_flag(debugger: &Debugger) -> Option<&'static str> {
+    match debugger {
+        // WinDbg and Visual Studio only show theirs in a window
+        Debugger::Windbg | Debugger::Devenv => None,
+        Debugger::Custom(path)
+            if path
+                .file_stem()
+                .is_some_and(|stem| stem.eq_ignore_ascii_case("cdb")) =>
+        {
+            Some("-version")
+        }
+        _ => Some("--version"),
+    }
+}
+
+/// The version line of `output`: its first line that isn't blank.
+fn version_line(output: &str) -> Option<&str> {
+    output.lines().map(str::trim).find(|line| !line.is_empty())
+}
+
+/// The version `program`, running `debugger`, reports, if it does.
+pub fn probe(debugger: &Debugger, program: &Path) -> Option<String> {
+    let flag = version_flag(debugger)?;
+    let mut child = Command::new(program)
+        .arg(flag)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    let started = Instant::now();
+    while child.try_wait().ok()?.is_none() {
+        if started.elapsed() >= TIMEOUT {
+            trace!("{} {flag} didn't exit, killing it", program.display());
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    let output = child.wait_with_output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    trace!("{} {flag}: {stdout}{stderr}", program.display());
+    if !output.status.success() {
+        return None;
+    }
+
+    version_line(&stdout)
+        .or(version_line(&stderr))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn version_output() {
+        let gdb = "GNU gdb (Ubuntu 12.1-0ubuntu1~22.04) 12.1\nCopyright (C) 2022 Free Software Foundation, Inc.\n";
+        assert_eq!(
+            version_line(gdb),
+            Some("GNU gdb (Ubuntu 12.1-0ubuntu1~22.04) 12.1")
+        );
+        assert_eq!(
+            version_line("\n  lldb version 17.0.6\n"),
+            Some("lldb version 17.0.6")
+        );
+        assert_eq!(version_line(""), None);
+
+        assert_eq!(version_flag(&Debugger::Gdb), Some("--version"));
+        assert_eq!(version_flag(&Debugger::Windbg), None);
+        let cdb = Debugger::Custom("cdb.exe".into());
+        assert_eq!(version_flag(&cdb), Some("-version"));
+
+        let missing = Path::new("cargo-debug-no-such-debugger");
+        assert_eq!(probe(&Debugger::Gdb, missing), None);
+    }
+}
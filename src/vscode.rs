@@ -0,0 +1,214 @@
+//! Generating VS Code `launch.json` configurations for the selected binary.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::{find_executable, sysroot, Debugger};
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum Flavor {
+    /// The CodeLLDB extension's `lldb` type
+    Codelldb,
+    /// Microsoft's C/C++ extension's `cppdbg` type
+    Cppdbg,
+}
+
+/// Commands loading the toolchain's Rust pretty-printers into the debugger.
+fn pretty_printer_commands(debugger: &Debugger, etc: &Path) -> Vec<String> {
+    match debugger {
+        Debugger::Gdb => vec![format!(
+            "source {}",
+            etc.join("gdb_load_rust_pretty_printers.py").display()
+        )],
+        Debugger::Lldb => vec![
+            format!(
+                "command script import \"{}\"",
+                etc.join("lldb_lookup.py").display()
+            ),
+            format!(
+                "command source -s 0 \"{}\"",
+                etc.join("lldb_commands").display()
+            ),
+        ],
+        _ => vec![],
+    }
+}
+
+/// The session being launched.
+struct Launch<'a> {
+    bin: &'a str,
+    options: &'a [String],
+    cwd: &'a Path,
+    commands: &'a [String],
+}
+
+fn configuration(
+    flavor: &Flavor,
+    debugger: &Debugger,
+    mi_debugger: &Path,
+    etc: Option<&Path>,
+    launch: &Launch,
+) -> Value {
+    let pretty = etc
+        .map(|etc| pretty_printer_commands(debugger, etc))
+        .unwrap_or_default();
+
+    match flavor {
+        Flavor::Codelldb => json!({
+            "name": "cargo debug",
+            "type": "lldb",
+            "request": "launch",
+            "program": launch.bin,
+            "args": launch.options,
+            "cwd": launch.cwd,
+            "initCommands": launch.commands,
+        }),
+        Flavor::Cppdbg => {
+            let mode = match debugger {
+                Debugger::Lldb => "lldb",
+                _ => "gdb",
+            };
+
+            let mut setup_commands = vec![json!({
+                "description": "Enable pretty-printing",
+                "text": "-enable-pretty-printing",
+                "ignoreFailures": true,
+            })];
+            setup_commands.extend(pretty.iter().map(|c| {
+                json!({
+                    "description": "Load Rust pretty-printers",
+                    "text": c,
+                    "ignoreFailures": true,
+                })
+            }));
+            setup_commands.extend(launch.commands.iter().map(|c| {
+                json!({
+                    "text": c,
+                    "ignoreFailures": false,
+                })
+            }));
+
+            json!({
+                "name": "cargo debug",
+                "type": "cppdbg",
+                "request": "launch",
+                "program": launch.bin,
+                "args": launch.options,
+                "stopAtEntry": false,
+                "cwd": launch.cwd,
+                "environment": [],
+                "externalConsole": false,
+                "MIMode": mode,
+                "miDebuggerPath": mi_debugger,
+                "setupCommands": setup_commands,
+            })
+        }
+    }
+}
+
+/// A `launch.json` document debugging `bin` with `options`, running the
+/// startup `commands` first.
+pub fn launch_json(
+    flavor: &Flavor,
+    debugger: &Debugger,
+    bin: &str,
+    options: &[String],
+    commands: &[String],
+) -> Result<Value> {
+    let mi_debugger = match debugger {
+        Debugger::Gdb => "gdb",
+        Debugger::Lldb => "lldb-mi",
+        _ => bail!("--emit-vscode only supports gdb and lldb"),
+    };
+    let mi_debugger = match flavor {
+        Flavor::Cppdbg => find_executable(Path::new(mi_debugger))
+            .with_context(|| format!("Could not find {mi_debugger}"))?,
+        Flavor::Codelldb => mi_debugger.into(),
+    };
+
+    let etc = sysroot().ok().map(|s| s.join("lib/rustlib/etc"));
+    let cwd = std::env::current_dir()?;
+
+    Ok(json!({
+        "version": "0.2.0",
+        "configurations": [configuration(
+            flavor,
+            debugger,
+            &mi_debugger,
+            etc.as_deref(),
+            &Launch {
+                bin,
+                options,
+                cwd: &cwd,
+                commands,
+            },
+        )],
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cppdbg_schema() {
+        let config = configuration(
+            &Flavor::Cppdbg,
+            &Debugger::Gdb,
+            Path::new("/usr/bin/gdb"),
+            Some(Path::new("/rust/lib/rustlib/etc")),
+            &Launch {
+                bin: "/foo/target/debug/foo",
+                options: &["--bar".to_string()],
+                cwd: Path::new("/foo"),
+                commands: &["directory /foo".to_string()],
+            },
+        );
+
+        // Required and documented fields of the cppdbg launch configuration
+        assert_eq!(config["type"], "cppdbg");
+        assert_eq!(config["request"], "launch");
+        assert_eq!(config["program"], "/foo/target/debug/foo");
+        assert_eq!(config["args"][0], "--bar");
+        assert_eq!(config["cwd"], "/foo");
+        assert_eq!(config["MIMode"], "gdb");
+        assert_eq!(config["miDebuggerPath"], "/usr/bin/gdb");
+        assert!(config["stopAtEntry"].is_boolean());
+        assert!(config["externalConsole"].is_boolean());
+        assert!(config["environment"].is_array());
+
+        let setup = &config["setupCommands"];
+        assert_eq!(setup[0]["text"], "-enable-pretty-printing");
+        assert_eq!(
+            setup[1]["text"],
+            "source /rust/lib/rustlib/etc/gdb_load_rust_pretty_printers.py"
+        );
+        assert_eq!(setup[2]["text"], "directory /foo");
+        assert_eq!(setup[2]["ignoreFailures"], false);
+    }
+
+    #[test]
+    fn cppdbg_lldb_mode() {
+        let config = configuration(
+            &Flavor::Cppdbg,
+            &Debugger::Lldb,
+            Path::new("/usr/bin/lldb-mi"),
+            None,
+            &Launch {
+                bin: "/foo/target/debug/foo",
+                options: &[],
+                cwd: Path::new("/foo"),
+                commands: &[],
+            },
+        );
+
+        assert_eq!(config["MIMode"], "lldb");
+        assert_eq!(
+            config["setupCommands"][0]["text"],
+            "-enable-pretty-printing"
+        );
+        assert!(config["setupCommands"][1].is_null());
+    }
+}
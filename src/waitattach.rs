@@ -0,0 +1,311 @@
+//! Attaching to the process a program ends up as (`--wait-attach`), for
+//! programs that fork or re-exec themselves at startup, where launching
+//! them under the debugger would debug the parent that's about to exit.
+//!
+//! The binary is launched on its own, with `CARGO_DEBUG_WAIT=1` in its
+//! environment, then the process table is polled for the processes running
+//! it, and the debugger attached to the newest once it has stayed the
+//! newest for a while. A program can wait for the debugger while the
+//! variable is set. With `--wait-signal STOP`, it's stopped before it even
+//! starts instead, and the debugger attaches to it right away.
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use log::{info, trace};
+
+use crate::{DebugSetup, Debugger};
+
+/// Set in the program's environment, for it to wait for the debugger.
+pub const WAIT_VAR: &str = "CARGO_DEBUG_WAIT";
+
+/// How often the process table is read.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the newest process has to stay the newest to be the one.
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// The signals the program can be started stopped with.
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum WaitSignal {
+    /// Stopped with SIGSTOP before it runs, and continued by the debugger
+    Stop,
+}
+
+/// A process running the binary, and when it started, in clock ticks since
+/// boot, or its pid where that isn't known, as pids mostly grow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Process {
+    pid: u32,
+    started: u64,
+}
+
+/// The start time in a `/proc/PID/stat` line: the 22nd field, the 20th
+/// after the parenthesized command name, which may contain anything.
+#[cfg(target_os = "linux")]
+fn start_time(stat: &str) -> Option<u64> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// The processes running `exe`.
+#[cfg(target_os = "linux")]
+fn processes(exe: &Path) -> Vec<Process> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let running = std::fs::read_link(entry.path().join("exe")).ok()?;
+            if running != exe {
+                return None;
+            }
+            let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+            Some(Process {
+                pid,
+                started: start_time(&stat)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn processes(exe: &Path) -> Vec<Process> {
+    let Ok(output) = Command::new("pgrep").arg("-f").arg(exe).output() else {
+        return vec![];
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .map(|pid| Process {
+            pid,
+            started: u64::from(pid),
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn processes(_exe: &Path) -> Vec<Process> {
+    vec![]
+}
+
+/// The newest of `processes` that isn't one of those running `before`.
+fn newest(processes: &[Process], before: &[Process]) -> Option<Process> {
+    processes
+        .iter()
+        .filter(|p| !before.iter().any(|b| b.pid == p.pid))
+        .max_by_key(|p| (p.started, p.pid))
+        .copied()
+}
+
+/// Start `cmd`, the program, stopped before it runs.
+#[cfg(unix)]
+fn stop_at_start(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: the hook only raises a signal, which is async-signal-safe
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::raise(libc::SIGSTOP) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+}
+
+/// Launch `cmd`, the program running `exe`, and return the pid of the
+/// process to attach to: the program itself when it's started stopped, or
+/// else the newest process running `exe` once it has settled.
+pub fn launch(
+    cmd: &mut Command,
+    exe: &Path,
+    signal: Option<&WaitSignal>,
+    timeout: Duration,
+) -> Result<u32> {
+    if cfg!(not(unix)) {
+        bail!("--wait-attach is only supported on Unix");
+    }
+    cmd.env(WAIT_VAR, "1");
+    #[cfg(not(unix))]
+    let _ = signal;
+
+    #[cfg(unix)]
+    if signal == Some(&WaitSignal::Stop) {
+        stop_at_start(cmd);
+        let child = cmd
+            .spawn()
+            .with_context(|| format!("Could not run {}", exe.display()))?;
+        info!("{} is stopped as pid {}", exe.display(), child.id());
+        return Ok(child.id());
+    }
+
+    let exe = exe.canonicalize().unwrap_or(exe.to_path_buf());
+    let before = processes(&exe);
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Could not run {}", exe.display()))?;
+    settle(&mut child, &exe, &before, timeout)
+}
+
+/// Poll for the newest process running `exe` until it's stayed the newest
+/// for [`SETTLE`].
+fn settle(child: &mut Child, exe: &Path, before: &[Process], timeout: Duration) -> Result<u32> {
+    let started = Instant::now();
+    let mut current: Option<(Process, Instant)> = None;
+    loop {
+        // Reap the parent once it's done, so it isn't taken for running
+        let exited = child.try_wait()?.is_some();
+        match (newest(&processes(exe), before), current) {
+            (Some(process), Some((settled, since))) if process == settled => {
+                if since.elapsed() >= SETTLE {
+                    return Ok(process.pid);
+                }
+            }
+            (Some(process), _) => {
+                trace!("newest process running {}: {}", exe.display(), process.pid);
+                current = Some((process, Instant::now()));
+            }
+            (None, _) if exited => bail!(
+                "{} exited without leaving a process running it to attach to",
+                exe.display()
+            ),
+            (None, _) => current = None,
+        }
+
+        if started.elapsed() >= timeout {
+            bail!(
+                "No process running {} settled within {}s, see --wait-timeout",
+                exe.display(),
+                timeout.as_secs()
+            );
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The invocation attaching `debugger` to `pid`, running `bin`, with the
+/// setup's flags and commands.
+pub fn command(
+    debugger: &Debugger,
+    bin: &str,
+    pid: u32,
+    setup: &DebugSetup,
+) -> Result<(PathBuf, Vec<String>)> {
+    let mut args = setup.flags.clone();
+    let pid = pid.to_string();
+    let program = match debugger {
+        Debugger::Gdb => {
+            args.extend(setup.startup_args(debugger));
+            args.extend([bin.to_string(), "-p".to_string(), pid]);
+            "gdb"
+        }
+        Debugger::Lldb => {
+            args.extend(["--file".to_string(), bin.to_string()]);
+            args.extend(["--attach-pid".to_string(), pid]);
+            args.extend(setup.startup_args(debugger));
+            "lldb"
+        }
+        // The address is among the flags already
+        Debugger::Gdbserver => {
+            args.insert(0, "--attach".to_string());
+            args.push(pid);
+            "gdbserver"
+        }
+        Debugger::Windbg => {
+            args.extend(["-p".to_string(), pid]);
+            if !setup.commands.is_empty() {
+                args.extend(["-c".to_string(), setup.commands.join("; ")]);
+            }
+            "windbgx"
+        }
+        other => bail!(
+            "--wait-attach is only supported by gdb, lldb, gdbserver and windbg, not {}",
+            other.name()
+        ),
+    };
+
+    Ok((
+        setup.program.clone().unwrap_or(PathBuf::from(program)),
+        args,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn process_table() {
+        let stat = "42 (a) b) c) S 1 42 42 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 1 0 12345 0";
+        assert_eq!(start_time(stat), Some(12345));
+
+        let exe = std::env::current_exe().unwrap();
+        let me = processes(&exe);
+        assert!(me.iter().any(|p| p.pid == std::process::id()));
+    }
+
+    #[test]
+    fn attach_to_newest() {
+        let parent = Process {
+            pid: 10,
+            started: 5,
+        };
+        let child = Process {
+            pid: 12,
+            started: 7,
+        };
+        let old = Process {
+            pid: 99,
+            started: 1,
+        };
+        assert_eq!(newest(&[parent, child, old], &[old]), Some(child));
+        assert_eq!(newest(&[old], &[old]), None);
+
+        let setup = DebugSetup {
+            commands: vec!["break main".to_string()],
+            ..Default::default()
+        };
+        let (program, args) = command(&Debugger::Gdb, "/foo/foo", 12, &setup).unwrap();
+        assert_eq!(program, PathBuf::from("gdb"));
+        assert_eq!(args, ["-ex", "break main", "/foo/foo", "-p", "12"]);
+        let (_, args) = command(&Debugger::Lldb, "/foo/foo", 12, &setup).unwrap();
+        assert_eq!(
+            args,
+            [
+                "--file",
+                "/foo/foo",
+                "--attach-pid",
+                "12",
+                "--one-line",
+                "break main"
+            ]
+        );
+        let server = DebugSetup {
+            flags: vec![":1234".to_string()],
+            ..Default::default()
+        };
+        let (_, args) = command(&Debugger::Gdbserver, "/foo/foo", 12, &server).unwrap();
+        assert_eq!(args, ["--attach", ":1234", "12"]);
+        assert!(command(&Debugger::Perf, "/foo/foo", 12, &setup).is_err());
+
+        // --command-file breakpoints.gdb, sourced ahead of the startup commands
+        let sourced = DebugSetup {
+            command_file: Some("/foo/breakpoints.gdb".to_string()),
+            ..setup
+        };
+        let (_, args) = command(&Debugger::Gdb, "/foo/foo", 12, &sourced).unwrap();
+        assert_eq!(args[..3], ["--command", "/foo/breakpoints.gdb", "-ex"]);
+        let (_, args) = command(&Debugger::Lldb, "/foo/foo", 12, &sourced).unwrap();
+        assert_eq!(
+            args[4..7],
+            ["--source", "/foo/breakpoints.gdb", "--one-line"]
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! Rebuilding and relaunching the session when the workspace's sources
+//! change.
+//!
+//! Each iteration runs cargo-debug again without `--watch`, so it builds and
+//! launches exactly as a single run would. The sources are polled for
+//! changes, like `--monitor`'s script. On Unix, the session runs in its own
+//! process group in the terminal's foreground: Ctrl+C reaches the debugger
+//! while it runs, and a change sends the group SIGTERM, which gdb and lldb
+//! take as a request to quit. Ctrl+C between sessions ends the loop, and
+//! stops the gdbserver `--persistent-server` kept running.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+#[cfg(unix)]
+use std::time::Instant;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use log::{trace, warn};
+
+use crate::persistent;
+
+/// Set for the sessions the loop runs, so Ctrl+C doesn't end them.
+pub const WATCHED_VAR: &str = "CARGO_DEBUG_WATCHED";
+
+/// How often the sources are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the sources have to stay unchanged before rebuilding.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+/// How long a session gets to quit before it's killed.
+const GRACE: Duration = Duration::from_secs(5);
+
+/// The modification time of each source file.
+type Snapshot = BTreeMap<PathBuf, Option<SystemTime>>;
+
+/// Every file under `dir`, except in `target` and hidden directories.
+fn walk(dir: &Path, target: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path == target || entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        if path.is_dir() {
+            walk(&path, target, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// The workspace's source files: those git tracks or doesn't ignore, or
+/// without git, every file outside `target` and hidden directories.
+fn sources(root: &Path, target: &Path) -> Vec<PathBuf> {
+    let output = Command::new("git")
+        .args([
+            "ls-files",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+            "-z",
+        ])
+        .current_dir(root)
+        .output()
+        .ok()
+        .filter(|o| o.status.success());
+
+    match output {
+        Some(output) => output
+            .stdout
+            .split(|b| *b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| root.join(&*String::from_utf8_lossy(name)))
+            .filter(|file| !file.starts_with(target))
+            .collect(),
+        None => {
+            let mut files = vec![];
+            walk(root, target, &mut files);
+            files
+        }
+    }
+}
+
+fn snapshot(files: Vec<PathBuf>) -> Snapshot {
+    files
+        .into_iter()
+        .map(|file| {
+            let modified = fs::metadata(&file).and_then(|m| m.modified()).ok();
+            (file, modified)
+        })
+        .collect()
+}
+
+/// The arguments of a session: this invocation's, without `--watch`.
+fn session_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    // The program's own options come after `--`
+    let mut options = false;
+    args.into_iter()
+        .filter(|arg| {
+            options |= arg == "--";
+            options || arg != "--watch"
+        })
+        .collect()
+}
+
+/// Run the session in its own process group, in the terminal's foreground.
+#[cfg(unix)]
+fn foreground(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: the hook only makes system calls
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setpgid(0, 0);
+            if libc::isatty(libc::STDIN_FILENO) == 1 {
+                libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpid());
+            }
+            // Ignored by the loop so it can take the terminal back
+            libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn foreground(_cmd: &mut Command) {}
+
+/// Put the loop back in the terminal's foreground after a session.
+fn reclaim_terminal() {
+    #[cfg(unix)]
+    // SAFETY: plain system calls; SIGTTOU is ignored, so this doesn't stop us
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) == 1 {
+            libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+        }
+    }
+}
+
+/// Ask the session to quit, killing it if it doesn't in time.
+#[cfg(unix)]
+fn terminate(session: &mut Child) -> Result<ExitStatus> {
+    let group = session.id() as libc::pid_t;
+    // SAFETY: signals the session's own process group
+    unsafe { libc::killpg(group, libc::SIGTERM) };
+
+    let deadline = Instant::now() + GRACE;
+    while Instant::now() < deadline {
+        if let Some(status) = session.try_wait()? {
+            return Ok(status);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    warn!("the session didn't quit in {GRACE:?}, killing it");
+    // SAFETY: as above
+    unsafe { libc::killpg(group, libc::SIGKILL) };
+    Ok(session.wait()?)
+}
+
+#[cfg(not(unix))]
+fn terminate(session: &mut Child) -> Result<ExitStatus> {
+    session.kill()?;
+    Ok(session.wait()?)
+}
+
+struct Watcher {
+    root: PathBuf,
+    target: PathBuf,
+    files: Snapshot,
+}
+
+impl Watcher {
+    fn changed(&mut self) -> bool {
+        let now = snapshot(sources(&self.root, &self.target));
+        if now == self.files {
+            return false;
+        }
+
+        self.files = now;
+        true
+    }
+
+    /// Wait for the sources to stop changing, e.g. while an editor saves
+    /// several files.
+    fn settle(&mut self) {
+        thread::sleep(DEBOUNCE);
+        while self.changed() {
+            thread::sleep(DEBOUNCE);
+        }
+    }
+}
+
+/// Run the session again each time the sources in `root` change, until
+/// Ctrl+C between sessions.
+pub fn run(root: &Path, target: &Path) -> Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = Arc::clone(&stop);
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst))
+        .expect("Error setting Ctrl-C handler");
+    #[cfg(unix)]
+    // SAFETY: changes how this process reacts to a signal and nothing else
+    unsafe {
+        libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+    }
+
+    let exe = env::current_exe().context("Could not find cargo-debug's executable")?;
+    let args = session_args(env::args_os().skip(1));
+    let mut watcher = Watcher {
+        root: root.to_path_buf(),
+        target: target.to_path_buf(),
+        files: snapshot(sources(root, target)),
+    };
+    trace!(
+        "watching {} files in {}",
+        watcher.files.len(),
+        root.display()
+    );
+
+    for iteration in 1.. {
+        eprintln!("cargo-debug: watch iteration {iteration}");
+        let mut cmd = Command::new(&exe);
+        cmd.args(&args).env(WATCHED_VAR, "1");
+        foreground(&mut cmd);
+        let mut session = cmd
+            .spawn()
+            .with_context(|| format!("Could not run {}", exe.display()))?;
+
+        let mut changed = false;
+        while session.try_wait()?.is_none() {
+            thread::sleep(POLL_INTERVAL);
+            if watcher.changed() {
+                changed = true;
+                eprintln!("cargo-debug: sources changed, restarting the session");
+                terminate(&mut session)?;
+                break;
+            }
+        }
+        reclaim_terminal();
+
+        if !changed {
+            eprintln!("cargo-debug: waiting for changes, Ctrl+C to stop");
+            stop.store(false, Ordering::SeqCst);
+            while !watcher.changed() {
+                if stop.load(Ordering::SeqCst) {
+                    persistent::stop(&persistent::state_file(target));
+                    return Ok(());
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        watcher.settle();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn watched_sources() {
+        let root = env::temp_dir().join(format!("cargo-debug-watch-{}", std::process::id()));
+        let target = root.join("target");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(target.join("debug")).unwrap();
+        fs::write(root.join("Cargo.toml"), "").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join(".git/HEAD"), "").unwrap();
+        fs::write(target.join("debug/foo"), "").unwrap();
+
+        let mut files = vec![];
+        walk(&root, &target, &mut files);
+        files.sort();
+        assert_eq!(files, [root.join("Cargo.toml"), root.join("src/main.rs")]);
+
+        let mut watcher = Watcher {
+            files: snapshot(sources(&root, &target)),
+            root: root.clone(),
+            target: target.clone(),
+        };
+        assert!(!watcher.changed());
+        fs::write(target.join("debug/foo"), "rebuilt").unwrap();
+        assert!(!watcher.changed());
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn session_arguments() {
+        let args = ["debug", "--watch", "--bin", "foo", "--", "--watch"].map(OsString::from);
+        assert_eq!(
+            session_args(args),
+            ["debug", "--bin", "foo", "--", "--watch"].map(OsString::from)
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Picking the classic Windows debugger (cdb, windbg, kd or ntsd) built for
+//! the target's architecture.
+//!
+//! The Windows SDK installs one of each under `Debuggers\x64`, `x86`,
+//! `arm64` and `arm`, and the first on PATH is usually the x64 one, which
+//! can't debug an x86 process as well as the x86 one, nor an ARM64 one at
+//! all. WinDbgX picks for itself, so it's left alone.
+
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+use crate::Debugger;
+
+/// The SDK's architecture directories.
+const ARCHES: [&str; 4] = ["x64", "x86", "arm64", "arm"];
+
+/// The classic debuggers the SDK ships for each architecture.
+const CLASSIC: [&str; 4] = ["cdb", "windbg", "kd", "ntsd"];
+
+/// The SDK's directory for the architecture of `triple`.
+pub fn arch(triple: &str) -> Option<&'static str> {
+    let cpu = triple.split('-').next()?;
+    match cpu {
+        "x86_64" => Some("x64"),
+        "i386" | "i586" | "i686" => Some("x86"),
+        // ARM64EC code runs in an x64-compatible process, which the ARM64
+        // debugger handles
+        "aarch64" | "arm64ec" => Some("arm64"),
+        cpu if cpu.starts_with("thumbv7") || cpu.starts_with("armv7") => Some("arm"),
+        _ => None,
+    }
+}
+
+/// The architecture of the SDK directory `path` is in, if it's in one.
+fn path_arch(path: &Path) -> Option<&'static str> {
+    let dir = path.parent()?;
+    let name = dir.file_name()?.to_str()?;
+    let debuggers = dir.parent()?.file_name()?;
+    if !debuggers.eq_ignore_ascii_case("Debuggers") {
+        return None;
+    }
+
+    ARCHES
+        .iter()
+        .find(|arch| name.eq_ignore_ascii_case(arch))
+        .copied()
+}
+
+/// `name`, the bare name of a classic debugger, in `kits`'s directory for
+/// `arch`, if it's installed there.
+fn find_in(kits: &Path, name: &str, arch: &str) -> Option<PathBuf> {
+    let path = kits
+        .join("Debuggers")
+        .join(arch)
+        .join(format!("{name}.exe"));
+    path.is_file().then_some(path)
+}
+
+/// Where the Windows 10 and 11 SDKs are installed.
+fn kits_root() -> Option<PathBuf> {
+    let program_files = std::env::var_os("ProgramFiles(x86)")?;
+    Some(Path::new(&program_files).join("Windows Kits").join("10"))
+}
+
+/// The classic debugger `debugger` names, if it's one, given by its bare
+/// name rather than a path.
+fn classic_name(debugger: &Debugger) -> Option<String> {
+    let Debugger::Custom(path) = debugger else {
+        return None;
+    };
+    if path.components().count() != 1 {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?.to_ascii_lowercase();
+
+    CLASSIC.contains(&stem.as_str()).then_some(stem)
+}
+
+/// The executable to run for `debugger` when debugging a `triple` binary:
+/// the SDK's build of a classic debugger for its architecture, or `None`
+/// to run the debugger as usual. `explicit` is a `--debugger-path`, which
+/// is only checked against the architecture.
+pub fn debugger(debugger: &Debugger, triple: &str, explicit: Option<&Path>) -> Option<PathBuf> {
+    let wanted = arch(triple)?;
+    if let Some(path) = explicit {
+        if path_arch(path).is_some_and(|arch| arch != wanted) {
+            warn!(
+                "{} is an {} debugger, but {triple} binaries want the {wanted} one",
+                path.display(),
+                path_arch(path).unwrap_or_default()
+            );
+        }
+        return None;
+    }
+
+    let name = classic_name(debugger)?;
+    match kits_root().and_then(|kits| find_in(&kits, &name, wanted)) {
+        Some(path) => {
+            info!("using the {wanted} {name}: {}", path.display());
+            Some(path)
+        }
+        None => {
+            warn!(
+                "the Windows SDK's {wanted} {name} was not found, the {name} on PATH may not \
+                 match {triple}; pass --debugger-path to pick one"
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sdk_arch() {
+        assert_eq!(arch("x86_64-pc-windows-msvc"), Some("x64"));
+        assert_eq!(arch("i686-pc-windows-msvc"), Some("x86"));
+        assert_eq!(arch("i586-pc-windows-msvc"), Some("x86"));
+        assert_eq!(arch("aarch64-pc-windows-msvc"), Some("arm64"));
+        assert_eq!(arch("arm64ec-pc-windows-msvc"), Some("arm64"));
+        assert_eq!(arch("thumbv7a-pc-windows-msvc"), Some("arm"));
+        assert_eq!(arch("riscv64gc-unknown-linux-gnu"), None);
+
+        let kits = std::env::temp_dir().join(format!("cargo-debug-kits-{}", std::process::id()));
+        let x86 = kits.join("Debuggers").join("x86");
+        std::fs::create_dir_all(&x86).unwrap();
+        std::fs::write(x86.join("cdb.exe"), "").unwrap();
+        assert_eq!(find_in(&kits, "cdb", "x86"), Some(x86.join("cdb.exe")));
+        assert_eq!(find_in(&kits, "cdb", "arm64"), None);
+        assert_eq!(path_arch(&x86.join("cdb.exe")), Some("x86"));
+        assert_eq!(path_arch(Path::new("cdb.exe")), None);
+        std::fs::remove_dir_all(&kits).unwrap();
+
+        let cdb = Debugger::Custom(PathBuf::from("CDB.exe"));
+        assert_eq!(classic_name(&cdb), Some("cdb".to_string()));
+        let elsewhere = Debugger::Custom(Path::new("tools").join("cdb.exe"));
+        assert_eq!(classic_name(&elsewhere), None);
+        assert_eq!(classic_name(&Debugger::Windbg), None);
+    }
+}
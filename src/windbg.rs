@@ -0,0 +1,170 @@
+//! Writing WinDbg launch settings (`.debugTarget` files), which reopen a
+//! session when double-clicked, and the symbol path WinDbg is launched with.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use log::info;
+
+use crate::quote;
+
+/// Microsoft's public symbol server, for system DLLs such as ntdll.
+const MICROSOFT_SYMBOL_SERVER: &str = "https://msdl.microsoft.com/download/symbols";
+
+/// The `_NT_SYMBOL_PATH` used when none is set: the binary's directory, for
+/// its fresh PDB, then Microsoft's symbol server, cached under
+/// `%LOCALAPPDATA%` if there is one.
+pub fn default_symbol_path(local_app_data: Option<&str>, bin_dir: &str) -> String {
+    let server = match local_app_data {
+        Some(dir) => format!("cache*{dir}\\cargo-debug\\sym;srv*{MICROSOFT_SYMBOL_SERVER}"),
+        None => format!("srv*{MICROSOFT_SYMBOL_SERVER}"),
+    };
+
+    format!("{bin_dir};{server}")
+}
+
+/// What WinDbg needs to launch the selected binary again.
+struct LaunchSettings<'a> {
+    executable: &'a str,
+    arguments: &'a [String],
+    working_directory: &'a Path,
+    symbol_path: String,
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn render(settings: &LaunchSettings) -> String {
+    let property = |indent: usize, name: &str, value: &str| {
+        format!(
+            "{}<Property name=\"{name}\" value=\"{}\" />\n",
+            " ".repeat(indent),
+            xml_escape(value)
+        )
+    };
+
+    let arguments = settings
+        .arguments
+        .iter()
+        .map(|a| quote::windows(a))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str(&format!(
+        "<TargetConfig Name=\"{}\" LaunchOptions=\"Launch\">\n",
+        xml_escape(settings.executable)
+    ));
+    xml.push_str("  <EngineConfig />\n");
+    xml.push_str("  <EngineOptions>\n");
+    xml.push_str(&property(4, "SymbolPath", &settings.symbol_path));
+    xml.push_str("  </EngineOptions>\n");
+    xml.push_str("  <TargetOptions>\n");
+    xml.push_str("    <Option name=\"LaunchProcess\">\n");
+    xml.push_str(&property(6, "Executable", settings.executable));
+    xml.push_str(&property(6, "Arguments", &arguments));
+    xml.push_str(&property(
+        6,
+        "WorkingDirectory",
+        &settings.working_directory.to_string_lossy(),
+    ));
+    xml.push_str("    </Option>\n");
+    xml.push_str("  </TargetOptions>\n");
+    xml.push_str("</TargetConfig>\n");
+
+    xml
+}
+
+/// Write launch settings for debugging `bin` with `options` to `path`.
+///
+/// Symbols are looked up next to the binary, then in `_NT_SYMBOL_PATH`.
+pub fn emit(path: &Path, bin: &str, options: &[String]) -> Result<()> {
+    let mut symbol_path = Path::new(bin)
+        .parent()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Ok(nt_symbol_path) = env::var("_NT_SYMBOL_PATH") {
+        symbol_path = format!("{symbol_path};{nt_symbol_path}");
+    }
+
+    let settings = LaunchSettings {
+        executable: bin,
+        arguments: options,
+        working_directory: &env::current_dir()?,
+        symbol_path,
+    };
+
+    std::fs::write(path, render(&settings))
+        .with_context(|| format!("Could not write WinDbg settings to {}", path.display()))?;
+
+    info!("wrote WinDbg launch settings to {}", path.display());
+
+    Ok(())
+}
+
+/// Open launch settings in WinDbg, through the `.debugTarget` file association.
+pub fn open(path: &Path) -> Result<()> {
+    let status = Command::new("cmd")
+        .args(["/C", "start", ""])
+        .arg(path)
+        .status()
+        .context("Could not run cmd")?;
+    if !status.success() {
+        bail!("Could not open {}", path.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn symbol_path() {
+        assert_eq!(
+            default_symbol_path(Some(r"C:\Users\me\AppData\Local"), r"C:\foo\target\debug"),
+            r"C:\foo\target\debug;cache*C:\Users\me\AppData\Local\cargo-debug\sym;srv*https://msdl.microsoft.com/download/symbols"
+        );
+        assert_eq!(
+            default_symbol_path(None, r"C:\foo\target\debug"),
+            r"C:\foo\target\debug;srv*https://msdl.microsoft.com/download/symbols"
+        );
+    }
+
+    #[test]
+    fn launch_settings() {
+        let settings = LaunchSettings {
+            executable: r"C:\foo\target\debug\foo.exe",
+            arguments: &["--name".to_string(), "a <b> & c".to_string()],
+            working_directory: Path::new(r"C:\foo"),
+            symbol_path: r"C:\foo\target\debug".to_string(),
+        };
+
+        assert_eq!(
+            render(&settings),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<TargetConfig Name="C:\foo\target\debug\foo.exe" LaunchOptions="Launch">
+  <EngineConfig />
+  <EngineOptions>
+    <Property name="SymbolPath" value="C:\foo\target\debug" />
+  </EngineOptions>
+  <TargetOptions>
+    <Option name="LaunchProcess">
+      <Property name="Executable" value="C:\foo\target\debug\foo.exe" />
+      <Property name="Arguments" value="--name &quot;a &lt;b&gt; &amp; c&quot;" />
+      <Property name="WorkingDirectory" value="C:\foo" />
+    </Option>
+  </TargetOptions>
+</TargetConfig>
+"#
+        );
+    }
+}
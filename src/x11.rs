@@ -0,0 +1,100 @@
+//! Showing GUI debuggers over SSH with X11 forwarding.
+//!
+//! `ssh -X` (or `-Y`) sets `DISPLAY` to the forwarded display and adds a
+//! cookie for it to the X authority file. Both are checked up front, so a
+//! missing display fails with a hint rather than an obscure error from the
+//! debugger.
+
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use log::{info, warn};
+
+use crate::Debugger;
+
+/// Debuggers that aren't in the terminal, run with [`Debugger::Custom`].
+const GUI_DEBUGGERS: [&str; 6] = ["ddd", "gede", "gdbgui", "kdbg", "nemiver", "seer"];
+
+/// Whether `debugger` needs a display.
+pub fn is_gui(debugger: &Debugger) -> bool {
+    match debugger {
+        Debugger::Devenv | Debugger::Windbg => true,
+        Debugger::Custom(path) => path
+            .file_stem()
+            .is_some_and(|name| GUI_DEBUGGERS.iter().any(|gui| name == *gui)),
+        _ => false,
+    }
+}
+
+/// The X authority file: `XAUTHORITY` if set, otherwise `~/.Xauthority`.
+fn authority_file(xauthority: Option<PathBuf>, home: Option<PathBuf>) -> Option<PathBuf> {
+    xauthority.or_else(|| home.map(|home| home.join(".Xauthority")))
+}
+
+/// The environment showing `debugger` on `display` (or `DISPLAY`).
+pub fn environment(debugger: &Debugger, display: Option<&str>) -> Result<Vec<(String, String)>> {
+    if !is_gui(debugger) {
+        warn!(
+            "{} runs in the terminal, ignoring --x11-forward",
+            debugger.name()
+        );
+        return Ok(vec![]);
+    }
+
+    let display = match display {
+        Some(display) => display.to_string(),
+        None => match env::var("DISPLAY") {
+            Ok(display) if !display.is_empty() => display,
+            _ => bail!(
+                "--x11-forward needs a display, but DISPLAY isn't set; \
+                 connect with `ssh -X` (or `-Y`), or pass --display"
+            ),
+        },
+    };
+    info!("showing {} on display {display}", debugger.name());
+
+    let mut vars = vec![("DISPLAY".to_string(), display)];
+    let authority = authority_file(
+        env::var_os("XAUTHORITY").map(PathBuf::from),
+        env::var_os("HOME").map(PathBuf::from),
+    );
+    match authority.filter(|file| file.is_file()) {
+        Some(file) => vars.push((
+            "XAUTHORITY".to_string(),
+            file.to_string_lossy().into_owned(),
+        )),
+        None => warn!(
+            "no X authority file found, the display may refuse the connection; \
+             is `xauth` installed on this machine?"
+        ),
+    }
+
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gui_debuggers() {
+        assert!(is_gui(&Debugger::Devenv));
+        assert!(is_gui(&Debugger::Custom(PathBuf::from("/usr/bin/ddd"))));
+        assert!(is_gui(&Debugger::Custom(PathBuf::from("nemiver"))));
+        assert!(!is_gui(&Debugger::Gdb));
+        assert!(!is_gui(&Debugger::Custom(PathBuf::from("cgdb"))));
+
+        assert_eq!(
+            authority_file(None, Some(PathBuf::from("/home/me"))),
+            Some(PathBuf::from("/home/me/.Xauthority"))
+        );
+        assert_eq!(
+            authority_file(
+                Some(PathBuf::from("/tmp/xauth-1000")),
+                Some(PathBuf::from("/home/me"))
+            ),
+            Some(PathBuf::from("/tmp/xauth-1000"))
+        );
+    }
+}
@@ -0,0 +1,8 @@
+# Generated by cargo-debug 0.0.0
+# cargo debug gdb --emit-script repro -- --seed 42
+$ErrorActionPreference = 'Stop'
+
+Set-Location -LiteralPath '/my dir'
+$env:RUST_BACKTRACE = '1'
+& gdb --args '/my dir/foo' --seed 42
+exit $LASTEXITCODE
@@ -0,0 +1,8 @@
+#!/bin/sh
+# Generated by cargo-debug 0.0.0
+# cargo debug gdb --emit-script repro -- --seed 42
+set -e
+
+cd '/my dir'
+export RUST_BACKTRACE=1
+exec gdb --args '/my dir/foo' --seed 42